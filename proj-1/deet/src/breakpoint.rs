@@ -0,0 +1,74 @@
+use crate::dwarf_data::Type;
+use crate::expression::Expression;
+use crate::inferior::WatchKind;
+
+#[derive(Clone)]
+pub struct Breakpoint {
+    pub num: usize,
+    pub addr: usize,
+    /// What the user asked to break on, so the breakpoint can be set again in a new program
+    pub location: String,
+    pub enabled: bool,
+    /// The byte that was replaced with 0xcc, if the breakpoint is installed in the inferior
+    pub orig_byte: Option<u8>,
+    pub hit_count: usize,
+    /// Number of upcoming hits to skip before stopping
+    pub ignore_count: usize,
+    /// Only stop if this is true in the innermost frame
+    pub condition: Option<Expression>,
+    /// Command lines to run each time the breakpoint stops the program
+    pub commands: Vec<String>,
+}
+
+impl Breakpoint {
+    pub fn new(num: usize, addr: usize) -> Breakpoint {
+        Breakpoint {
+            num,
+            addr,
+            location: format!("*{:#x}", addr),
+            enabled: true,
+            orig_byte: None,
+            hit_count: 0,
+            ignore_count: 0,
+            condition: None,
+            commands: Vec::new(),
+        }
+    }
+}
+
+/// A hardware watchpoint on a variable or address. Shares its numbering with breakpoints.
+pub struct Watchpoint {
+    pub num: usize,
+    /// What the user asked to watch, e.g. `counter` or `*0x601040`
+    pub expr: String,
+    pub kind: WatchKind,
+    pub addr: usize,
+    pub entity_type: Type,
+    /// For a local variable, the canonical frame address of the frame it lives in. The
+    /// watchpoint is meaningless once that frame has returned.
+    pub frame: Option<usize>,
+    pub enabled: bool,
+    /// The debug register holding this watchpoint, if it is armed in the inferior
+    pub slot: Option<usize>,
+    pub old_value: Vec<u8>,
+    pub hit_count: usize,
+}
+
+impl Watchpoint {
+    /// Short name of the watchpoint type, for breakpoint listings.
+    pub fn description(&self) -> &'static str {
+        match self.kind {
+            WatchKind::Write => "hw watchpoint",
+            WatchKind::Read => "read watchpoint",
+            WatchKind::Access => "acc watchpoint",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.kind {
+            WatchKind::Write => "Hardware watchpoint",
+            WatchKind::Read => "Hardware read watchpoint",
+            WatchKind::Access => "Hardware access (read/write) watchpoint",
+        }
+    }
+}
@@ -0,0 +1,737 @@
+//! Post-mortem debugging from an ELF core file.
+//!
+//! A core file is an ELF file of type ET_CORE. Its PT_LOAD segments hold the memory of the
+//! process, one per mapping, and a PT_NOTE segment holds everything else the kernel saved: the
+//! registers of each thread (NT_PRSTATUS, NT_FPREGSET), the command line (NT_PRPSINFO), the
+//! auxiliary vector (NT_AUXV), the signal that killed the process (NT_SIGINFO) and the files that
+//! were mapped (NT_FILE). The kernel leaves out memory it can read back from files, like code, so
+//! that is read from the mapped files themselves.
+//!
+//! `gcore` writes the same kind of file from a live inferior, laid out the way the kernel does it.
+
+use crate::breakpoint::Breakpoint;
+use crate::inferior::Inferior;
+use crate::process::Process;
+use crate::target::{self, Mapping, Region, Target};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PAGE_SIZE: usize = 4096;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_SIGINFO: u32 = 0x53494749;
+const NT_FILE: u32 = 0x46494c45;
+
+/// Offsets into `struct elf_prstatus`, `struct elf_prpsinfo` and `siginfo_t` on x86-64
+const PR_CURSIG: usize = 12;
+const PR_PID: usize = 32;
+const PR_REG: usize = 112;
+const PR_FPVALID: usize = 328;
+const PRSTATUS_SIZE: usize = 336;
+const PR_SNAME: usize = 1;
+const PR_UID: usize = 16;
+const PS_PID: usize = 24;
+const PR_FNAME: usize = 40;
+const PR_FNAME_LEN: usize = 16;
+const PR_PSARGS: usize = 56;
+const PR_PSARGS_LEN: usize = 80;
+const PRPSINFO_SIZE: usize = 136;
+const SI_ADDR: usize = 16;
+
+/// Memory of the process saved in the core file, as a PT_LOAD segment describes it.
+struct Segment {
+    addr: usize,
+    /// Where the saved bytes are in the core file
+    offset: usize,
+    /// How many bytes were saved. The rest of the mapping was left out.
+    file_size: usize,
+}
+
+pub struct CoreFile {
+    data: memmap::Mmap,
+    pid: Pid,
+    segments: Vec<Segment>,
+    /// Registers of the thread that was killed
+    regs: libc::user_regs_struct,
+    fpregs: Option<libc::user_fpregs_struct>,
+    signal: Option<Signal>,
+    siginfo: Option<Vec<u8>>,
+    command: String,
+    auxv: Vec<u8>,
+    mappings: Vec<Mapping>,
+}
+
+impl CoreFile {
+    pub fn open(path: &str) -> Result<CoreFile, String> {
+        let file = fs::File::open(path).map_err(|e| format!("{}: {}.", path, e))?;
+        let data = unsafe { memmap::Mmap::map(&file) }.map_err(|e| format!("{}: {}.", path, e))?;
+        let not_core = || format!("\"{}\" is not a core dump: file format not recognized", path);
+        if data.len() < 64 || &data[..4] != b"\x7fELF" || data[4] != 2 || data[5] != 1 {
+            return Err(not_core());
+        }
+        if read_u16(&data, 16) != Some(ET_CORE) {
+            return Err(not_core());
+        }
+        if read_u16(&data, 18) != Some(EM_X86_64) {
+            return Err(format!("\"{}\" is not an x86-64 core dump", path));
+        }
+        let phoff = read_u64(&data, 32).ok_or_else(not_core)? as usize;
+        let phentsize = read_u16(&data, 54).ok_or_else(not_core)? as usize;
+        let phnum = read_u16(&data, 56).ok_or_else(not_core)? as usize;
+
+        let mut segments = Vec::new();
+        let mut executable = Vec::new();
+        let mut notes = Vec::new();
+        for i in 0..phnum {
+            // the headers, like everything else in the file, may point anywhere
+            let header = i.checked_mul(phentsize).and_then(|start| start.checked_add(phoff));
+            let header = header.and_then(|start| data.get(start..)).ok_or_else(not_core)?;
+            let field = |offset| read_u64(header, offset).map(|value| value as usize);
+            let (p_type, p_flags) = match (read_u32(header, 0), read_u32(header, 4)) {
+                (Some(p_type), Some(p_flags)) => (p_type, p_flags),
+                _ => return Err(not_core()),
+            };
+            let (offset, addr, file_size) = match (field(8), field(16), field(32)) {
+                (Some(offset), Some(addr), Some(file_size))
+                    if offset.checked_add(file_size).is_some() && addr.checked_add(file_size).is_some() =>
+                {
+                    (offset, addr, file_size)
+                }
+                _ => return Err(not_core()),
+            };
+            match p_type {
+                PT_LOAD => {
+                    if p_flags & PF_X != 0 {
+                        executable.push(addr);
+                    }
+                    segments.push(Segment { addr, offset, file_size });
+                }
+                PT_NOTE => notes.extend(parse_notes(data.get(offset..offset + file_size).ok_or_else(not_core)?)),
+                _ => {}
+            }
+        }
+
+        let mut core = CoreFile {
+            pid: Pid::from_raw(0),
+            segments,
+            regs: unsafe { std::mem::zeroed() },
+            fpregs: None,
+            signal: None,
+            siginfo: None,
+            command: String::new(),
+            auxv: Vec::new(),
+            mappings: Vec::new(),
+            data,
+        };
+        // the thread that caused the dump comes first, followed by the notes that belong to it
+        let mut threads = 0;
+        for (note_type, desc) in notes {
+            match note_type {
+                NT_PRSTATUS => {
+                    threads += 1;
+                    if threads > 1 || desc.len() < PR_REG + std::mem::size_of::<libc::user_regs_struct>() {
+                        continue;
+                    }
+                    core.pid = Pid::from_raw(read_u32(&desc, PR_PID).unwrap_or(0) as i32);
+                    core.signal = read_u16(&desc, PR_CURSIG).and_then(|num| Signal::try_from(num as i32).ok());
+                    core.regs = unsafe { std::ptr::read_unaligned(desc[PR_REG..].as_ptr() as *const libc::user_regs_struct) };
+                }
+                NT_FPREGSET if threads == 1 && desc.len() >= std::mem::size_of::<libc::user_fpregs_struct>() => {
+                    core.fpregs = Some(unsafe { std::ptr::read_unaligned(desc.as_ptr() as *const libc::user_fpregs_struct) });
+                }
+                NT_SIGINFO if threads == 1 => core.siginfo = Some(desc),
+                NT_PRPSINFO => {
+                    let psargs = desc.get(PR_PSARGS..PR_PSARGS + PR_PSARGS_LEN).unwrap_or_default();
+                    let end = psargs.iter().position(|byte| *byte == 0).unwrap_or(psargs.len());
+                    core.command = String::from_utf8_lossy(&psargs[..end]).trim_end().to_string();
+                }
+                NT_AUXV => core.auxv = desc,
+                NT_FILE => core.mappings = parse_file_note(&desc, &executable),
+                _ => {}
+            }
+        }
+        if threads == 0 {
+            return Err(format!("\"{}\" has no registers", path));
+        }
+        Ok(core)
+    }
+
+    /// Returns the command line of the process, as far as the kernel kept it.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Returns the signal that killed the process.
+    pub fn signal(&self) -> Option<Signal> {
+        self.signal
+    }
+
+    /// Returns the address that caused the fatal signal, for faults such as SIGSEGV.
+    pub fn fault_address(&self) -> Option<usize> {
+        read_u64(self.siginfo.as_ref()?, SI_ADDR).map(|addr| addr as usize)
+    }
+
+    /// Reads memory from a single segment or mapped file, returning at most `len` bytes.
+    fn read_chunk(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        if let Some(segment) = self
+            .segments
+            .iter()
+            .find(|segment| addr >= segment.addr && addr < segment.addr + segment.file_size)
+        {
+            let len = len.min(segment.addr + segment.file_size - addr);
+            let offset = segment.offset + addr - segment.addr;
+            return self.data.get(offset..offset + len).map(|bytes| bytes.to_vec());
+        }
+        let mapping = self.mappings.iter().find(|mapping| addr >= mapping.start && addr < mapping.end)?;
+        let mut bytes = vec![0; len.min(mapping.end - addr)];
+        let file = fs::File::open(&mapping.path).ok()?;
+        file.read_exact_at(&mut bytes, mapping.offset.checked_add(addr - mapping.start)? as u64).ok()?;
+        Some(bytes)
+    }
+}
+
+impl Target for CoreFile {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let chunk = self.read_chunk(addr + bytes.len(), len - bytes.len()).ok_or(nix::Error::EIO)?;
+            bytes.extend(chunk);
+        }
+        Ok(bytes)
+    }
+
+    fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        Ok(self.regs)
+    }
+
+    fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        self.fpregs.ok_or(nix::Error::ENODATA)
+    }
+
+    fn auxv(&self) -> Option<Vec<u8>> {
+        Some(self.auxv.clone())
+    }
+
+    fn mappings(&self) -> Vec<Mapping> {
+        self.mappings.clone()
+    }
+}
+
+/// Splits the contents of a PT_NOTE segment into (type, description) pairs. Names and
+/// descriptions are padded to 4 bytes.
+fn parse_notes(data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut notes = Vec::new();
+    let mut offset = 0;
+    while let (Some(namesz), Some(descsz), Some(note_type)) =
+        (read_u32(data, offset), read_u32(data, offset + 4), read_u32(data, offset + 8))
+    {
+        let desc_start = offset + 12 + align4(namesz as usize);
+        let desc_end = desc_start + descsz as usize;
+        match data.get(desc_start..desc_end) {
+            Some(desc) => notes.push((note_type, desc.to_vec())),
+            None => break,
+        }
+        offset = desc_start + align4(descsz as usize);
+    }
+    notes
+}
+
+/// Reads the NT_FILE note: a count and page size, then (start, end, offset in pages) for each
+/// mapping, then their NUL-separated paths. Whether a mapping is executable comes from the
+/// flags of its PT_LOAD segment.
+fn parse_file_note(desc: &[u8], executable: &[usize]) -> Vec<Mapping> {
+    let (count, page_size) = match (read_u64(desc, 0), read_u64(desc, 8)) {
+        (Some(count), Some(page_size)) => (count as usize, page_size as usize),
+        _ => return Vec::new(),
+    };
+    let names_start = match count.checked_mul(24).and_then(|size| size.checked_add(16)) {
+        Some(names_start) => names_start,
+        None => return Vec::new(),
+    };
+    // each path ends in a NUL; one that is cut short is left out, along with its mapping
+    let names = desc.get(names_start..).unwrap_or_default();
+    let names = match names.iter().rposition(|byte| *byte == 0) {
+        Some(end) => names[..end].split(|byte| *byte == 0),
+        None => return Vec::new(),
+    };
+    let mut mappings = Vec::new();
+    for (i, name) in (0..count).zip(names) {
+        let entry = 16 + i * 24;
+        let offset = read_u64(desc, entry + 16).and_then(|pages| (pages as usize).checked_mul(page_size));
+        if let (Some(start), Some(end), Some(offset)) = (read_u64(desc, entry), read_u64(desc, entry + 8), offset) {
+            mappings.push(Mapping {
+                start: start as usize,
+                end: end as usize,
+                offset,
+                executable: executable.contains(&(start as usize)),
+                path: String::from_utf8_lossy(name).into_owned(),
+            });
+        }
+    }
+    mappings
+}
+
+/// Writes a core file of a stopped inferior, with the registers of all its threads and the
+/// contents of its memory. Memory that can be read back from the mapped files, like code, is left
+/// out, and the original bytes of our breakpoints are put back in the rest.
+pub fn write_core_file(inferior: &Inferior, breakpoints: &HashMap<usize, Breakpoint>, path: &str) -> Result<(), String> {
+    let pid = inferior.pid();
+    let io_error = |e: std::io::Error| format!("{}: {}.", path, e);
+    let maps = fs::read_to_string(format!("/proc/{}/smaps", pid)).map_err(io_error)?;
+    let mem = fs::File::open(format!("/proc/{}/mem", pid)).map_err(io_error)?;
+
+    let segments: Vec<LoadSegment> = target::parse_regions(&maps).iter().map(load_segment).collect();
+    let notes = process_notes(inferior, &target::parse_maps(&maps));
+
+    let mut file = fs::File::create(path).map_err(io_error)?;
+    write_core(&mut file, &notes, &segments, |addr, page| {
+        // e.g. [vvar] can't be read
+        if mem.read_exact_at(page, addr as u64).is_err() {
+            return false;
+        }
+        for breakpoint in breakpoints.values() {
+            if let Some(orig_byte) = breakpoint.orig_byte {
+                if breakpoint.addr >= addr && breakpoint.addr - addr < page.len() {
+                    page[breakpoint.addr - addr] = orig_byte;
+                }
+            }
+        }
+        true
+    })
+    .map_err(io_error)
+}
+
+/// Memory of the process to be saved as a PT_LOAD segment.
+struct LoadSegment {
+    start: usize,
+    end: usize,
+    flags: u32,
+    /// Whether the contents go in the file, rather than only the size
+    saved: bool,
+}
+
+/// Works out the PT_LOAD segment of a region. Like the kernel, it saves the contents of memory
+/// that can't be read back from a file: anonymous memory, writable mappings, and the pages of
+/// mapped files that have been written to since, such as the relocated GOT of a library that
+/// the loader has made read-only again.
+fn load_segment(region: &Region) -> LoadSegment {
+    let mut flags = 0;
+    for (set, flag) in [(region.readable, PF_R), (region.writable, PF_W), (region.executable, PF_X)] {
+        if set {
+            flags |= flag;
+        }
+    }
+    LoadSegment {
+        start: region.start,
+        end: region.end,
+        flags,
+        saved: region.readable && (region.writable || region.anonymous > 0 || !region.path.starts_with('/')),
+    }
+}
+
+/// Writes the ELF header, the program headers, the notes and then the contents of the saved
+/// segments, which `read_page` fills in a page at a time. A page it can't read is left as a hole
+/// in the file, so it reads back as zeros, as the kernel does it.
+fn write_core(
+    file: &mut fs::File,
+    notes: &[u8],
+    segments: &[LoadSegment],
+    mut read_page: impl FnMut(usize, &mut [u8]) -> bool,
+) -> std::io::Result<()> {
+    let notes_offset = EHDR_SIZE + (segments.len() + 1) * PHDR_SIZE;
+    let mut data_offset = align_page(notes_offset + notes.len());
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\x7fELF");
+    out.extend_from_slice(&[2, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&ET_CORE.to_le_bytes());
+    out.extend_from_slice(&EM_X86_64.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    out.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&((segments.len() + 1) as u16).to_le_bytes());
+    out.extend_from_slice(&[0; 6]); // no section headers
+
+    push_program_header(&mut out, PT_NOTE, 0, notes_offset, 0, notes.len(), 0, 4);
+    for segment in segments {
+        let size = segment.end - segment.start;
+        let file_size = if segment.saved { size } else { 0 };
+        push_program_header(&mut out, PT_LOAD, segment.flags, data_offset, segment.start, file_size, size, PAGE_SIZE);
+        data_offset += file_size;
+    }
+    out.extend_from_slice(notes);
+    out.resize(align_page(out.len()), 0);
+    file.write_all(&out)?;
+
+    let mut page = vec![0; PAGE_SIZE];
+    for segment in segments.iter().filter(|segment| segment.saved) {
+        for addr in (segment.start..segment.end).step_by(PAGE_SIZE) {
+            let page = &mut page[..PAGE_SIZE.min(segment.end - addr)];
+            if read_page(addr, page) {
+                file.write_all(page)?;
+            } else {
+                file.seek(SeekFrom::Current(page.len() as i64))?;
+            }
+        }
+    }
+    // a hole at the very end is only there once the file is extended over it
+    let len = file.stream_position()?;
+    file.set_len(len)
+}
+
+/// Builds the contents of the PT_NOTE segment. As in the kernel's core files, the current thread
+/// comes first, followed by the notes about the whole process, then the other threads.
+fn process_notes(inferior: &Inferior, files: &[Mapping]) -> Vec<u8> {
+    let pid = inferior.pid();
+    // the fields after the command name, which is in parentheses and may contain spaces
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+    let stat: Vec<&str> = stat.rsplit_once(')').map_or(Vec::new(), |(_, rest)| rest.split_whitespace().collect());
+    let state = stat.first().and_then(|state| state.bytes().next()).unwrap_or(b'R');
+    // parent, process group and session
+    let ids: Vec<i32> = (1..4).map(|i| stat.get(i).and_then(|id| id.parse().ok()).unwrap_or(0)).collect();
+
+    let mut threads: Vec<Pid> = inferior.threads().iter().map(|thread| thread.tid).collect();
+    let current = inferior.threads().iter().find(|thread| thread.num == inferior.current_thread_num()).map(|thread| thread.tid);
+    threads.sort_by_key(|tid| Some(*tid) != current);
+
+    let mut notes = Vec::new();
+    for (i, tid) in threads.into_iter().enumerate() {
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        for (j, id) in [tid.as_raw()].iter().chain(&ids).enumerate() {
+            prstatus[PR_PID + j * 4..PR_PID + j * 4 + 4].copy_from_slice(&id.to_le_bytes());
+        }
+        if let Ok(regs) = inferior.thread_registers(tid) {
+            prstatus[PR_REG..PR_FPVALID].copy_from_slice(&struct_bytes(&regs)[..PR_FPVALID - PR_REG]);
+        }
+        let fpregs = inferior.thread_fp_registers(tid).ok();
+        prstatus[PR_FPVALID] = fpregs.is_some() as u8;
+        push_note(&mut notes, NT_PRSTATUS, &prstatus);
+
+        if i == 0 {
+            push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid, state, &ids));
+            push_note(&mut notes, NT_AUXV, &inferior.auxv().unwrap_or_default());
+            push_note(&mut notes, NT_FILE, &file_note(files));
+        }
+        if let Some(fpregs) = fpregs {
+            push_note(&mut notes, NT_FPREGSET, struct_bytes(&fpregs));
+        }
+    }
+    notes
+}
+
+/// Fills in a `struct elf_prpsinfo` with the state, ids, name and command line of the process.
+fn prpsinfo(pid: Pid, state: u8, ids: &[i32]) -> Vec<u8> {
+    let mut prpsinfo = vec![0; PRPSINFO_SIZE];
+    prpsinfo[PR_SNAME] = state;
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    for (i, key) in ["Uid:", "Gid:"].iter().enumerate() {
+        let id: u32 = status
+            .lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|ids| ids.split_whitespace().next()?.parse().ok())
+            .unwrap_or(0);
+        prpsinfo[PR_UID + i * 4..PR_UID + i * 4 + 4].copy_from_slice(&id.to_le_bytes());
+    }
+    for (i, id) in [pid.as_raw()].iter().chain(ids).enumerate() {
+        prpsinfo[PS_PID + i * 4..PS_PID + i * 4 + 4].copy_from_slice(&id.to_le_bytes());
+    }
+    // both strings keep a NUL at the end
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let comm = comm.trim_end().as_bytes();
+    let len = comm.len().min(PR_FNAME_LEN - 1);
+    prpsinfo[PR_FNAME..PR_FNAME + len].copy_from_slice(&comm[..len]);
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let args: Vec<u8> = cmdline.iter().map(|byte| if *byte == 0 { b' ' } else { *byte }).collect();
+    let args = String::from_utf8_lossy(&args).trim_end().to_string().into_bytes();
+    let len = args.len().min(PR_PSARGS_LEN - 1);
+    prpsinfo[PR_PSARGS..PR_PSARGS + len].copy_from_slice(&args[..len]);
+    prpsinfo
+}
+
+/// Builds the NT_FILE note from the file-backed mappings.
+fn file_note(files: &[Mapping]) -> Vec<u8> {
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&(files.len() as u64).to_le_bytes());
+    desc.extend_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+    for mapping in files {
+        for value in [mapping.start, mapping.end, mapping.offset / PAGE_SIZE] {
+            desc.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+    for mapping in files {
+        desc.extend_from_slice(mapping.path.as_bytes());
+        desc.push(0);
+    }
+    desc
+}
+
+fn push_note(notes: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    let name = b"CORE\0";
+    notes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&note_type.to_le_bytes());
+    notes.extend_from_slice(name);
+    notes.resize(align4(notes.len()), 0);
+    notes.extend_from_slice(desc);
+    notes.resize(align4(notes.len()), 0);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn push_program_header(out: &mut Vec<u8>, p_type: u32, flags: u32, offset: usize, addr: usize, file_size: usize, mem_size: usize, align: usize) {
+    out.extend_from_slice(&p_type.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    for value in [offset, addr, 0, file_size, mem_size, align] {
+        out.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+}
+
+/// Returns the bytes of a plain C struct such as `user_regs_struct`.
+fn struct_bytes<T>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>()) }
+}
+
+fn align_page(offset: usize) -> usize {
+    (offset + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset.checked_add(8)?)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns a path for a scratch file, unique to this process and test.
+    fn scratch_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("deet-{}-{}", std::process::id(), name)).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut notes = Vec::new();
+        push_note(&mut notes, NT_PRSTATUS, &[1, 2, 3, 4, 5]);
+        push_note(&mut notes, NT_AUXV, &[]);
+        push_note(&mut notes, NT_SIGINFO, &[6, 7, 8]);
+        assert_eq!(notes.len() % 4, 0);
+        assert_eq!(
+            parse_notes(&notes),
+            [(NT_PRSTATUS, vec![1, 2, 3, 4, 5]), (NT_AUXV, vec![]), (NT_SIGINFO, vec![6, 7, 8])]
+        );
+        // a note cut short ends the list
+        assert_eq!(parse_notes(&notes[..notes.len() - 4]), [(NT_PRSTATUS, vec![1, 2, 3, 4, 5]), (NT_AUXV, vec![])]);
+        assert!(parse_notes(&[]).is_empty());
+        assert!(parse_notes(&[5, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn test_file_note() {
+        let files = [
+            Mapping { start: 0x400000, end: 0x401000, offset: 0, executable: false, path: "/tmp/t/prog".to_string() },
+            Mapping { start: 0x401000, end: 0x403000, offset: 0x1000, executable: true, path: "/tmp/t/prog".to_string() },
+            Mapping {
+                start: 0x7f1e2a828000,
+                end: 0x7f1e2a9bd000,
+                offset: 0x28000,
+                executable: true,
+                path: "/opt/my libs/libfoo.so (deleted)".to_string(),
+            },
+        ];
+        let desc = file_note(&files);
+        assert!(parse_file_note(&desc, &[0x401000, 0x7f1e2a828000]) == files);
+        // only the mappings that are all there are read
+        assert!(parse_file_note(&desc[..16 + 24], &[]).is_empty());
+        assert!(parse_file_note(&desc[..desc.len() - 40], &[0x401000, 0x7f1e2a828000]) == files[..1]);
+        assert!(parse_file_note(&desc[..desc.len() - 1], &[0x401000, 0x7f1e2a828000]) == files[..2]);
+        assert!(parse_file_note(&[], &[]).is_empty());
+
+        // counts and offsets too big for the address space
+        for count in [u64::MAX, 1 << 60, 0x0aaa_aaaa_aaaa_aaab] {
+            let mut huge = desc.clone();
+            huge[..8].copy_from_slice(&count.to_le_bytes());
+            assert!(parse_file_note(&huge, &[]).is_empty());
+        }
+        let mut huge = desc.clone();
+        huge[16 + 16..16 + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_file_note(&huge, &[0x401000, 0x7f1e2a828000]) == files[1..]);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut notes = Vec::new();
+        push_note(&mut notes, NT_PRSTATUS, &vec![0; PRSTATUS_SIZE]);
+        push_note(&mut notes, NT_FILE, &file_note(&[]));
+        let segments = [LoadSegment { start: 0x600000, end: 0x601000, flags: PF_R | PF_W, saved: true }];
+        let path = scratch_path("corrupt");
+        let mut file = fs::File::create(&path).unwrap();
+        write_core(&mut file, &notes, &segments, |_, _| true).unwrap();
+        drop(file);
+        let core = fs::read(&path).unwrap();
+        assert!(CoreFile::open(&path).is_ok());
+
+        // the offset of a field of the ELF header or the program headers, and what to put there
+        let note_header = EHDR_SIZE;
+        let load_header = EHDR_SIZE + PHDR_SIZE;
+        let corruptions = [
+            (32, u64::MAX - 8),
+            (32, 1 << 62),
+            (32, core.len() as u64 - 8),
+            (note_header + 8, u64::MAX),
+            (note_header + 32, u64::MAX - 0x100),
+            (load_header + 8, u64::MAX - 0x10),
+            (load_header + 16, u64::MAX - 0x10),
+            (load_header + 32, u64::MAX),
+        ];
+        for (offset, value) in corruptions {
+            let mut corrupt = core.clone();
+            corrupt[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            fs::write(&path, &corrupt).unwrap();
+            match CoreFile::open(&path) {
+                Err(e) => assert!(e.contains("is not a core dump"), "{}", e),
+                Ok(_) => panic!("{:#x} at {} makes a core file", value, offset),
+            }
+        }
+        // a huge number of huge program headers
+        let mut corrupt = core.clone();
+        corrupt[54..58].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        fs::write(&path, &corrupt).unwrap();
+        assert!(CoreFile::open(&path).is_err());
+        // cut off in the middle of the headers
+        fs::write(&path, &core[..EHDR_SIZE + 20]).unwrap();
+        assert!(CoreFile::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // a file mapped at 0x400000, whose code isn't saved, but whose page at 0x401000 has been
+        // written to since, like a relocated GOT
+        let mapped_path = scratch_path("mapped");
+        let mapped: Vec<u8> = (0..2 * PAGE_SIZE).map(|i| (i / 7) as u8).collect();
+        fs::write(&mapped_path, &mapped).unwrap();
+        let smaps = format!(
+            "\
+00400000-00401000 r-xp 00001000 08:01 1835 {0}
+Anonymous:             0 kB
+00401000-00402000 r--p 00000000 08:01 1835 {0}
+Anonymous:             4 kB
+00600000-00603000 rw-p 00000000 00:00 0
+Anonymous:            12 kB
+7ffd0000000-7ffd0002000 rw-p 00000000 00:00 0 [stack]
+Anonymous:             8 kB
+",
+            mapped_path
+        );
+        let files = target::parse_maps(&smaps);
+        assert_eq!(files.len(), 2);
+
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        regs.rip = 0x400123;
+        regs.rsp = 0x7ffd0000ff0;
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        prstatus[PR_CURSIG] = Signal::SIGSEGV as u8;
+        prstatus[PR_PID..PR_PID + 4].copy_from_slice(&4242u32.to_le_bytes());
+        prstatus[PR_REG..PR_FPVALID].copy_from_slice(struct_bytes(&regs));
+        let mut prpsinfo = vec![0; PRPSINFO_SIZE];
+        prpsinfo[PR_PSARGS..PR_PSARGS + 14].copy_from_slice(b"./prog a b    ");
+        let mut siginfo = vec![0; 128];
+        siginfo[SI_ADDR..SI_ADDR + 8].copy_from_slice(&0xdeadu64.to_le_bytes());
+        let mut notes = Vec::new();
+        push_note(&mut notes, NT_PRSTATUS, &prstatus);
+        push_note(&mut notes, NT_PRPSINFO, &prpsinfo);
+        push_note(&mut notes, NT_AUXV, &[6, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0]);
+        push_note(&mut notes, NT_FILE, &file_note(&files));
+        push_note(&mut notes, NT_SIGINFO, &siginfo);
+        // a second thread, which is left out
+        push_note(&mut notes, NT_PRSTATUS, &vec![0; PRSTATUS_SIZE]);
+
+        // the middle page of the heap can't be read, nor the last of the stack, which has to be a
+        // hole at the end of the file
+        let segments: Vec<LoadSegment> = target::parse_regions(&smaps).iter().map(load_segment).collect();
+        let saved: Vec<bool> = segments.iter().map(|segment| segment.saved).collect();
+        assert_eq!(saved, [false, true, true, true]);
+        let core_path = scratch_path("core");
+        let mut file = fs::File::create(&core_path).unwrap();
+        let mut pages = Vec::new();
+        write_core(&mut file, &notes, &segments, |addr, page| {
+            pages.push(addr);
+            if addr == 0x601000 || addr == 0x7ffd0001000 {
+                return false;
+            }
+            for (i, byte) in page.iter_mut().enumerate() {
+                *byte = (addr >> 12) as u8 ^ i as u8;
+            }
+            true
+        })
+        .unwrap();
+        drop(file);
+        assert_eq!(pages, [0x401000, 0x600000, 0x601000, 0x602000, 0x7ffd0000000, 0x7ffd0001000]);
+
+        let core = CoreFile::open(&core_path).unwrap();
+        assert_eq!(core.pid(), Pid::from_raw(4242));
+        assert_eq!(core.signal(), Some(Signal::SIGSEGV));
+        assert_eq!(core.fault_address(), Some(0xdead));
+        assert_eq!(core.command(), "./prog a b");
+        assert_eq!(core.get_registers().unwrap().rip, 0x400123);
+        assert!(core.get_fp_registers().is_err());
+        assert_eq!(core.auxv().unwrap().len(), 16);
+        assert!(core.mappings() == files);
+
+        assert_eq!(core.read_bytes(0x600000, 4).unwrap(), [0, 1, 2, 3]);
+        assert_eq!(core.read_bytes(0x600ffe, 4).unwrap(), [0xfe, 0xff, 0, 0]);
+        assert_eq!(core.read_bytes(0x602000, 2).unwrap(), [2, 3]);
+        let stack: Vec<u8> = (0xf0..=0xff).chain([0; 0x10]).collect();
+        assert_eq!(core.read_bytes(0x7ffd0000ff0, 0x20).unwrap(), stack);
+        assert_eq!(core.read_bytes(0x7ffd0001ff8, 8).unwrap(), [0; 8]);
+        assert!(core.read_bytes(0x603000, 1).is_err());
+        // from the mapped file, except where it was written to
+        assert_eq!(core.read_bytes(0x400123, 3).unwrap(), mapped[PAGE_SIZE + 0x123..PAGE_SIZE + 0x126]);
+        assert_eq!(core.read_bytes(0x401010, 3).unwrap(), [0x11, 0x10, 0x13]);
+        assert_eq!(core.read_bytes(0x400fff, 2).unwrap(), [mapped[2 * PAGE_SIZE - 1], 1]);
+        assert!(core.read_bytes(0x401fff, 2).is_err());
+        // a page of headers and notes, then six of memory
+        assert_eq!(fs::metadata(&core_path).unwrap().len() as usize, 7 * PAGE_SIZE);
+
+        fs::remove_file(&core_path).unwrap();
+        fs::remove_file(&mapped_path).unwrap();
+    }
+}
@@ -0,0 +1,589 @@
+//! A Debug Adapter Protocol server, so that deet can be used from VS Code and other editors that
+//! speak it. Messages are JSON objects, each preceded by a `Content-Length` header, exchanged over
+//! stdio or a TCP connection. Requests are carried out by the same `Debugger` the command line
+//! uses, and what happens to the program comes back from it as events, which are translated
+//! into DAP events.
+
+use crate::debugger::Debugger;
+use crate::debugger_command::DebuggerCommand;
+use crate::json::Json;
+use crate::signals;
+use crate::utils;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+/// The longest message a client may send; anything longer is skipped unread
+const MAX_MESSAGE_LEN: usize = 1 << 24;
+
+/// Serves one client on standard input and output. The program's own output goes to standard
+/// error, and its input is /dev/null.
+pub fn serve_stdio() -> Result<(), String> {
+    let input = utils::take_stdin();
+    let output = utils::take_stdout();
+    DapServer::new(Box::new(input), Box::new(output)).run()
+}
+
+/// Waits for one client to connect to `port` on the loopback interface, and serves it.
+pub fn serve_tcp(port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("Could not listen on port {}: {}", port, e))?;
+    println!("Listening for a DAP client on 127.0.0.1:{}", port);
+    let (stream, peer) = listener.accept().map_err(|e| e.to_string())?;
+    println!("DAP client connected from {}", peer);
+    let output = stream.try_clone().map_err(|e| e.to_string())?;
+    DapServer::new(Box::new(stream), Box::new(output)).run()
+}
+
+struct DapServer {
+    input: BufReader<Box<dyn Read>>,
+    output: Box<dyn Write>,
+    /// Sequence number of the next message we send
+    seq: usize,
+    /// Created by `launch` or `attach`, from the program's debugging symbols
+    debugger: Option<Debugger>,
+    /// How to start the program once the client is done configuring it: `run` or `attach`
+    start: Option<DebuggerCommand>,
+    /// The breakpoints the client has set in each source file, by number, so that the next
+    /// `setBreakpoints` for the file can replace them
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    /// Events from the debugger, sent once the response to the request that caused them is
+    events: Vec<Json>,
+}
+
+impl DapServer {
+    fn new(input: Box<dyn Read>, output: Box<dyn Write>) -> DapServer {
+        DapServer {
+            input: BufReader::new(input),
+            output,
+            seq: 1,
+            debugger: None,
+            start: None,
+            source_breakpoints: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Answers requests until the client disconnects or goes away.
+    fn run(&mut self) -> Result<(), String> {
+        while let Some(request) = self.read_message()? {
+            let command = request.get("command").and_then(Json::as_str).unwrap_or_default().to_string();
+            let args = request.get("arguments").cloned().unwrap_or(Json::Null);
+            let result = self.handle(&command, &args);
+            let mut response = vec![
+                ("seq", self.next_seq()),
+                ("type", "response".into()),
+                ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+                ("command", command.as_str().into()),
+            ];
+            match result {
+                Ok(body) => {
+                    response.push(("success", true.into()));
+                    if body != Json::Null {
+                        response.push(("body", body));
+                    }
+                }
+                Err(message) => {
+                    response.push(("success", false.into()));
+                    response.push(("message", message.into()));
+                }
+            }
+            self.send(Json::object(response))?;
+            match command.as_str() {
+                // the client configures breakpoints once it hears we're ready for them
+                "launch" | "attach" if self.debugger.is_some() => self.send_event("initialized", Json::Null)?,
+                "disconnect" => return Ok(()),
+                _ => {}
+            }
+            for event in std::mem::take(&mut self.events) {
+                self.send_debugger_event(&event)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out a request, returning the body of the response or why it failed.
+    fn handle(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+            ])),
+            "launch" => {
+                let program = args.get("program").and_then(Json::as_str).ok_or("No program to launch")?;
+                let program_args = args
+                    .get("args")
+                    .and_then(Json::as_array)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|arg| arg.as_str().map_or_else(|| arg.to_string(), str::to_string))
+                    .collect();
+                self.open(program)?;
+                self.start = Some(DebuggerCommand::Run(program_args));
+                Ok(Json::Null)
+            }
+            "attach" => {
+                let pid = args.get("pid").and_then(Json::as_usize).ok_or("No pid to attach to")?;
+                self.open(&format!("/proc/{}/exe", pid))?;
+                self.start = Some(DebuggerCommand::Attach(pid as i32));
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => self.set_breakpoints(args),
+            "configurationDone" => match self.start.take() {
+                Some(start) => {
+                    self.execute(start)?;
+                    Ok(Json::Null)
+                }
+                None => Ok(Json::Null),
+            },
+            "threads" => {
+                let threads: Vec<Json> = self
+                    .debugger()?
+                    .threads()
+                    .into_iter()
+                    .map(|(num, tid)| {
+                        Json::object(vec![
+                            ("id", num.into()),
+                            ("name", format!("Thread {} (LWP {})", num, tid).into()),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object(vec![("threads", threads.into())]))
+            }
+            "stackTrace" => self.stack_trace(args),
+            "scopes" => {
+                // each frame has two scopes, whose variablesReference encodes the frame level
+                let level = args.get("frameId").and_then(Json::as_usize).unwrap_or(0);
+                let scopes = vec![
+                    Json::object(vec![
+                        ("name", "Arguments".into()),
+                        ("variablesReference", (level * 2 + 1).into()),
+                        ("expensive", false.into()),
+                    ]),
+                    Json::object(vec![
+                        ("name", "Locals".into()),
+                        ("variablesReference", (level * 2 + 2).into()),
+                        ("expensive", false.into()),
+                    ]),
+                ];
+                Ok(Json::object(vec![("scopes", scopes.into())]))
+            }
+            "variables" => {
+                let reference = args.get("variablesReference").and_then(Json::as_usize).unwrap_or(0);
+                if reference == 0 {
+                    return Err("Invalid variablesReference".to_string());
+                }
+                let variables: Vec<Json> = self
+                    .debugger()?
+                    .frame_variables((reference - 1) / 2, reference % 2 == 1)
+                    .into_iter()
+                    .map(|(name, value)| {
+                        Json::object(vec![
+                            ("name", name.into()),
+                            ("value", value.into()),
+                            ("variablesReference", 0.into()),
+                        ])
+                    })
+                    .collect();
+                Ok(Json::object(vec![("variables", variables.into())]))
+            }
+            "evaluate" => {
+                let expr = args.get("expression").and_then(Json::as_str).ok_or("No expression")?;
+                let level = args.get("frameId").and_then(Json::as_usize).unwrap_or(0);
+                let value = self.debugger()?.evaluate(level, expr)?;
+                Ok(Json::object(vec![("result", value.into()), ("variablesReference", 0.into())]))
+            }
+            "continue" => {
+                self.execute(DebuggerCommand::Continue)?;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" => self.execute(DebuggerCommand::Next).map(|_| Json::Null),
+            "stepIn" => self.execute(DebuggerCommand::Step).map(|_| Json::Null),
+            "stepOut" => self.execute(DebuggerCommand::Finish).map(|_| Json::Null),
+            "disconnect" => {
+                // kills a program we started, and leaves one we attached to running
+                if let Some(debugger) = self.debugger.as_mut() {
+                    debugger.execute(DebuggerCommand::Quit);
+                }
+                Ok(Json::Null)
+            }
+            _ => Err(format!("Unsupported request {}", command)),
+        }
+    }
+
+    /// Loads the debugging symbols of the program to debug.
+    fn open(&mut self, program: &str) -> Result<(), String> {
+        if self.debugger.is_some() {
+            return Err("A program is already being debugged".to_string());
+        }
+        let mut debugger = Debugger::open(program)?;
+        debugger.queue_events();
+        self.debugger = Some(debugger);
+        Ok(())
+    }
+
+    fn debugger(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger.as_mut().ok_or_else(|| "No program is being debugged".to_string())
+    }
+
+    /// Runs a command, keeping the events it caused for after the response. Fails with the
+    /// first error the command reported.
+    fn execute(&mut self, command: DebuggerCommand) -> Result<(), String> {
+        let debugger = self.debugger()?;
+        debugger.execute(command);
+        let mut error = None;
+        for event in debugger.take_events() {
+            match event_kind(&event) {
+                "error" if error.is_none() => {
+                    error = event.get("message").and_then(Json::as_str).map(str::to_string);
+                }
+                _ => self.events.push(event),
+            }
+        }
+        match error {
+            Some(message) => Err(message),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the breakpoints in a source file with the lines the client asks for.
+    fn set_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let source = args.get("source").cloned().unwrap_or(Json::Null);
+        let path = source.get("path").and_then(Json::as_str).ok_or("No source path")?.to_string();
+        let requested = args.get("breakpoints").and_then(Json::as_array).unwrap_or_default().to_vec();
+        let old = self.source_breakpoints.remove(&path).unwrap_or_default();
+        let debugger = self.debugger()?;
+        if !old.is_empty() {
+            debugger.execute(DebuggerCommand::Delete(old));
+        }
+
+        let mut nums = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint.get("line").and_then(Json::as_usize).unwrap_or(0);
+            let condition = breakpoint.get("condition").and_then(Json::as_str);
+            debugger.set_breakpoint(&format!("{}:{}", path, line), condition);
+            let events = debugger.take_events();
+            let result = match events.iter().find(|event| matches!(event_kind(event), "breakpoint-created" | "error")) {
+                Some(created) if event_kind(created) == "breakpoint-created" => {
+                    let num = created.get("number").and_then(Json::as_usize).unwrap_or(0);
+                    nums.push(num);
+                    vec![
+                        ("id", num.into()),
+                        ("verified", true.into()),
+                        ("line", created.get("line").cloned().unwrap_or_else(|| line.into())),
+                        ("source", source.clone()),
+                    ]
+                }
+                Some(error) => vec![
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", error.get("message").cloned().unwrap_or(Json::Null)),
+                ],
+                None => vec![("verified", false.into()), ("line", line.into())],
+            };
+            breakpoints.push(Json::object(result));
+        }
+        self.source_breakpoints.insert(path, nums);
+        Ok(Json::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    /// Returns the frames of a thread, selecting it first if it isn't the current one. Frames are
+    /// identified by their level.
+    fn stack_trace(&mut self, args: &Json) -> Result<Json, String> {
+        let debugger = self.debugger()?;
+        if let Some(thread) = args.get("threadId").and_then(Json::as_usize) {
+            if matches!(debugger.current_thread(), Some(current) if current != thread) {
+                debugger.execute(DebuggerCommand::Thread(Some(thread)));
+            }
+        }
+        let frames = debugger.stack_frames();
+        let total = frames.len();
+        let start = args.get("startFrame").and_then(Json::as_usize).unwrap_or(0);
+        let count = match args.get("levels").and_then(Json::as_usize) {
+            Some(levels) if levels > 0 => levels,
+            _ => total,
+        };
+        let frames: Vec<Json> = frames
+            .into_iter()
+            .skip(start)
+            .take(count)
+            .map(|frame| {
+                let mut members = vec![
+                    ("id", frame.level.into()),
+                    ("name", frame.function.unwrap_or_else(|| "??".to_string()).into()),
+                    ("line", frame.line.as_ref().map_or(0, |line| line.number).into()),
+                    ("column", 0.into()),
+                    ("instructionPointerReference", format!("{:#x}", frame.pc).into()),
+                ];
+                if let Some(line) = frame.line {
+                    let name = line.file.rsplit('/').next().unwrap_or_default().to_string();
+                    members.push(("source", Json::object(vec![("name", name.into()), ("path", line.file.into())])));
+                }
+                Json::object(members)
+            })
+            .collect();
+        Ok(Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())]))
+    }
+
+    /// Translates an event from the debugger into the DAP events that mean the same.
+    fn send_debugger_event(&mut self, event: &Json) -> Result<(), String> {
+        match event_kind(event) {
+            "stopped" => {
+                let thread = event.get("thread").and_then(Json::as_usize).unwrap_or(1);
+                let mut body = vec![("threadId", thread.into()), ("allThreadsStopped", true.into())];
+                match event.get("reason").and_then(Json::as_str) {
+                    Some("breakpoint-hit") => {
+                        body.push(("reason", "breakpoint".into()));
+                        let num = event.get("breakpoint").cloned().unwrap_or(Json::Null);
+                        body.push(("hitBreakpointIds", vec![num].into()));
+                    }
+                    Some("watchpoint-trigger") => body.push(("reason", "data breakpoint".into())),
+                    Some("signal-received") => {
+                        let signal = event.get("signal").and_then(Json::as_str).unwrap_or_default();
+                        body.push(("reason", "exception".into()));
+                        body.push(("description", format!("Signal {}", signal).into()));
+                        body.push(("text", signal.into()));
+                    }
+                    Some("attached") => body.push(("reason", "pause".into())),
+                    Some("exec") => body.push(("reason", "entry".into())),
+                    _ => body.push(("reason", "step".into())),
+                }
+                self.send_event("stopped", Json::object(body))
+            }
+            "exited" => {
+                let exit_code = match (event.get("exit-code"), event.get("signal").and_then(Json::as_str)) {
+                    (Some(Json::Number(code)), _) => *code as i32,
+                    (_, Some(signal)) => signals::parse_signal(signal).map_or(128, |signal| 128 + signal as i32),
+                    _ => 0,
+                };
+                self.send_event("exited", Json::object(vec![("exitCode", exit_code.into())]))?;
+                self.send_event("terminated", Json::Null)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn next_seq(&mut self) -> Json {
+        self.seq += 1;
+        (self.seq - 1).into()
+    }
+
+    fn send_event(&mut self, event: &str, body: Json) -> Result<(), String> {
+        let mut message = vec![("seq", self.next_seq()), ("type", "event".into()), ("event", event.into())];
+        if body != Json::Null {
+            message.push(("body", body));
+        }
+        self.send(Json::object(message))
+    }
+
+    fn send(&mut self, message: Json) -> Result<(), String> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+            .and_then(|_| self.output.flush())
+            .map_err(|e| format!("Error writing to DAP client: {}", e))
+    }
+
+    /// Reads the next message, or returns None once the client has closed the connection. A
+    /// message that isn't JSON, or is too long to be a request, is skipped so that one bad
+    /// message doesn't end the session.
+    fn read_message(&mut self) -> Result<Option<Json>, String> {
+        let read_error = |e: std::io::Error| format!("Error reading from DAP client: {}", e);
+        loop {
+            let length = match self.read_headers()? {
+                Some(length) => length,
+                None => return Ok(None),
+            };
+            if length > MAX_MESSAGE_LEN {
+                std::io::copy(&mut (&mut self.input).take(length as u64), &mut std::io::sink()).map_err(read_error)?;
+                println!("Skipped a DAP message of {} bytes", length);
+                continue;
+            }
+            let mut body = vec![0; length];
+            self.input.read_exact(&mut body).map_err(read_error)?;
+            let message = String::from_utf8(body)
+                .map_err(|_| "it is not UTF-8".to_string())
+                .and_then(|text| Json::parse(&text));
+            match message {
+                Ok(message) => return Ok(Some(message)),
+                Err(e) => println!("Skipped a DAP message: {}", e),
+            }
+        }
+    }
+
+    /// Reads the headers of a message up to the blank line after them, and returns its
+    /// `Content-Length`, or None once the client has closed the connection.
+    fn read_headers(&mut self) -> Result<Option<usize>, String> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            match self.input.read_line(&mut header) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(e) => return Err(format!("Error reading from DAP client: {}", e)),
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                // the blank line after the headers
+                if length.is_some() {
+                    return Ok(length);
+                }
+                continue;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+}
+
+/// Returns what kind of event the debugger emitted, such as `stopped`.
+fn event_kind(event: &Json) -> &str {
+    event.get("event").and_then(Json::as_str).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    /// What the server writes, kept for the test to look at.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut message = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        message.extend_from_slice(body);
+        message
+    }
+
+    /// Runs a server over the given requests and returns the messages it sent.
+    fn serve(input: Vec<u8>) -> Vec<Json> {
+        let output = Output::default();
+        DapServer::new(Box::new(Cursor::new(input)), Box::new(output.clone())).run().unwrap();
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let mut messages = Vec::new();
+        let mut rest = output.as_str();
+        while let Some(header) = rest.strip_prefix("Content-Length: ") {
+            let (length, body) = header.split_once("\r\n\r\n").unwrap();
+            let length: usize = length.parse().unwrap();
+            messages.push(Json::parse(&body[..length]).unwrap());
+            rest = &body[length..];
+        }
+        assert_eq!(rest, "");
+        messages
+    }
+
+    fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+        messages
+            .iter()
+            .find(|message| {
+                message.get("type").and_then(Json::as_str) == Some("response")
+                    && message.get("command").and_then(Json::as_str) == Some(command)
+            })
+            .unwrap_or_else(|| panic!("no response to {}", command))
+    }
+
+    fn success(message: &Json) -> Option<bool> {
+        match message.get("success") {
+            Some(Json::Bool(success)) => Some(*success),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_bad_messages() {
+        let mut input = frame(b"{\"seq\": 1, \"type\": \"request\", \"command\": \"initialize\"}");
+        input.extend(frame(b"{\"seq\": 2, \"type\": \"req"));
+        input.extend(frame(b"\xff\xfe"));
+        input.extend(frame(&vec![b' '; MAX_MESSAGE_LEN + 1]));
+        input.extend(frame(b"{\"seq\": 3, \"type\": \"request\", \"command\": \"frobnicate\"}"));
+        input.extend(frame(b"{\"seq\": 4, \"type\": \"request\", \"command\": \"threads\"}"));
+        let messages = serve(input);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(success(response(&messages, "initialize")), Some(true));
+        let unsupported = response(&messages, "frobnicate");
+        assert_eq!(success(unsupported), Some(false));
+        assert_eq!(unsupported.get("request_seq").and_then(Json::as_usize), Some(3));
+        assert_eq!(unsupported.get("message").and_then(Json::as_str), Some("Unsupported request frobnicate"));
+        assert_eq!(
+            response(&messages, "threads").get("message").and_then(Json::as_str),
+            Some("No program is being debugged")
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        // function_calls, built the way the Makefile builds the samples, but with DWARF 4 line
+        // tables, since breakpoints by file and line can't yet be resolved in DWARF 5 ones
+        let source = format!("{}/samples/function_calls.c", env!("CARGO_MANIFEST_DIR"));
+        let program = std::env::temp_dir().join(format!("deet-{}-function_calls", std::process::id()));
+        let program = program.to_string_lossy().into_owned();
+        let built = std::process::Command::new("cc")
+            .args(["-gdwarf-4", "-O0", "-g", "-no-pie", "-fno-omit-frame-pointer", "-o", &program, &source])
+            .status()
+            .expect("could not run cc");
+        assert!(built.success());
+
+        let requests = [
+            r#"{"seq": 1, "type": "request", "command": "initialize", "arguments": {"adapterID": "deet"}}"#.to_string(),
+            format!(r#"{{"seq": 2, "type": "request", "command": "launch", "arguments": {{"program": "{}"}}}}"#, program),
+            format!(
+                r#"{{"seq": 3, "type": "request", "command": "setBreakpoints", "arguments": {{"source": {{"path": "{}"}}, "breakpoints": [{{"line": 11}}, {{"line": 1000}}]}}}}"#,
+                source
+            ),
+            r#"{"seq": 4, "type": "request", "command": "configurationDone"}"#.to_string(),
+            r#"{"seq": 5, "type": "request", "command": "evaluate", "arguments": {"expression": "a + b", "frameId": 0}}"#.to_string(),
+            r#"{"seq": 6, "type": "request", "command": "disconnect"}"#.to_string(),
+        ];
+        let input: Vec<u8> = requests.iter().flat_map(|request| frame(request.as_bytes())).collect();
+        let messages = serve(input);
+        std::fs::remove_file(&program).unwrap();
+
+        let initialize = response(&messages, "initialize");
+        assert_eq!(success(initialize), Some(true));
+        assert_eq!(
+            initialize.get("body").and_then(|body| body.get("supportsConfigurationDoneRequest")),
+            Some(&Json::Bool(true))
+        );
+        assert_eq!(success(response(&messages, "launch")), Some(true));
+        // the client waits for this before setting breakpoints
+        let kinds: Vec<&str> = messages
+            .iter()
+            .map(|message| message.get("command").or_else(|| message.get("event")).and_then(Json::as_str).unwrap_or_default())
+            .collect();
+        assert_eq!(&kinds[..3], ["initialize", "launch", "initialized"]);
+
+        let set_breakpoints = response(&messages, "setBreakpoints");
+        assert_eq!(success(set_breakpoints), Some(true));
+        let breakpoints = set_breakpoints.get("body").and_then(|body| body.get("breakpoints")).and_then(Json::as_array).unwrap();
+        assert_eq!(breakpoints.len(), 2);
+        assert_eq!(breakpoints[0].get("verified"), Some(&Json::Bool(true)));
+        assert_eq!(breakpoints[0].get("line").and_then(Json::as_usize), Some(11));
+        assert_eq!(breakpoints[1].get("verified"), Some(&Json::Bool(false)));
+
+        assert_eq!(success(response(&messages, "configurationDone")), Some(true));
+        let stopped = messages
+            .iter()
+            .find(|message| message.get("event").and_then(Json::as_str) == Some("stopped"))
+            .and_then(|message| message.get("body"))
+            .expect("the program didn't stop");
+        assert_eq!(stopped.get("reason").and_then(Json::as_str), Some("breakpoint"));
+        let evaluate = response(&messages, "evaluate");
+        assert_eq!(evaluate.get("body").and_then(|body| body.get("result")).and_then(Json::as_str), Some("47"));
+        assert_eq!(success(response(&messages, "disconnect")), Some(true));
+    }
+}
@@ -1,34 +1,159 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::inferior::Inferior;
-use crate::inferior::Status;
+use crate::breakpoint::{Breakpoint, Watchpoint};
+use crate::core_file::{self, CoreFile};
+use crate::debugger_command::{DebuggerCommand, ExamineFormat};
+use crate::disassembler::{self, Instruction};
+use crate::expression::{self, Expression, Place, Scope};
+use crate::json::Json;
+use crate::inferior::{ForkPolicy, Inferior, Status, WatchKind, NUM_HW_WATCHPOINTS};
 use crate::dwarf_data:: {
+    bit_field_len,
+    bit_mask,
     DwarfData,
+    Encoding,
     Error as DwarfError,
+    Line,
+    Type,
+    TypeKind,
+    Types,
+    Variable,
 };
+use crate::location::{Location, Storage};
+use crate::process::Process;
+use crate::remote::Remote;
+use crate::registers::{self, RegisterKind, REGISTERS};
+use crate::shared_library::SharedLibraries;
+use crate::signals::{self, SignalTable};
+use crate::source::SourceFiles;
+use crate::target::Target;
+use crate::unwind::{Frame, Unwinder};
 use crate::utils;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
 
-#[derive(Clone)]
-pub struct Breakpoint {
-    pub addr: usize,
-    pub orig_byte: u8,
-}
+/// How deeply user commands may call each other, as GDB's `max-user-call-depth` allows by
+/// default
+const MAX_USER_CALL_DEPTH: usize = 1024;
 
 pub struct Debugger {
     target: String,
     history_path: String,
     readline: Editor<()>, // Line Editor
-    inferior: Option<Inferior>,
+    inferior: Option<Box<dyn Process>>,
+    /// The core file being looked at, when there is no live process
+    core: Option<CoreFile>,
     debug_data: DwarfData,
-    // breakpoints: Vec<usize>,
-    breakpoints: HashMap<usize, Option<Breakpoint>>,
+    shared_libraries: SharedLibraries,
+    breakpoints: HashMap<usize, Breakpoint>, // keyed by address
+    watchpoints: Vec<Watchpoint>,
+    next_breakpoint_num: usize,
+    fork_policy: ForkPolicy,
+    signal_policy: SignalTable,
+    unwinder: Option<Unwinder>,
+    /// Level of the frame that `print` and `info locals` look at, as chosen with `frame`, `up`
+    /// and `down`. Every stop selects the innermost frame again.
+    selected_frame: usize,
+    /// Format and unit size of the last `x` command, used when the next one leaves them out
+    examine_format: char,
+    examine_unit: usize,
+    /// Where a bare `x` continues: just past the memory the last one printed
+    next_examine_addr: Option<usize>,
+    sources: SourceFiles,
+    /// The file and range of lines the last `list` printed, which a bare `list` continues from.
+    /// Forgotten whenever the program stops or another frame is selected, so that the next
+    /// `list` shows the new location.
+    listing: Option<(String, usize, usize)>,
+    /// Expressions `display` prints after every stop, with their numbers
+    displays: Vec<(usize, Expression)>,
+    next_display_num: usize,
+    /// Lines from scripts, user commands and breakpoint commands, run before anything else is
+    /// read from the terminal, each with how deeply it is nested in user commands
+    pending_lines: VecDeque<(String, usize)>,
+    /// How deeply nested in user commands the line being run is
+    command_depth: usize,
+    /// Exit once the pending lines are done, rather than reading from the terminal
+    batch: bool,
+    /// Commands made with `define`, by name
+    user_commands: HashMap<String, Vec<String>>,
+    /// How the program last ended or died: its exit code, or 128 plus the signal that killed or
+    /// stopped it. Batch mode exits with it.
+    exit_status: Option<i32>,
+    /// Where events about what happens go, when a front end is driving deet
+    events: Option<EventSink>,
+    /// Where a JSON client's requests come from
+    requests: Option<BufReader<File>>,
+    /// The `id` of the JSON request being carried out, until its `done` event is sent
+    request_id: Option<Json>,
+}
+
+/// Where events go: to a JSON client, as lines on a stream, or into a queue for the DAP server to
+/// translate.
+enum EventSink {
+    Stream(File),
+    Queue(RefCell<Vec<Json>>),
+}
+
+/// A stack frame, for front ends that lay out backtraces themselves.
+pub struct FrameInfo {
+    pub level: usize,
+    pub pc: usize,
+    /// The function, or else the closest symbol
+    pub function: Option<String>,
+    pub line: Option<Line>,
+}
+
+/// What an expression sees from a frame of the stopped program: the frame's variables and
+/// registers, the globals and memory. Without a frame, only the globals.
+struct FrameScope<'a> {
+    debugger: &'a Debugger,
+    frame: Option<Frame>,
+}
+
+impl Scope for FrameScope<'_> {
+    fn types(&self) -> &Types {
+        self.debugger.types_for(self.frame.as_ref())
+    }
+
+    fn variable(&self, name: &str) -> Option<Result<Place, String>> {
+        let code_addr = self.frame.as_ref().map_or(0, |frame| frame.code_addr());
+        let var = self.debugger.debug_data_for(code_addr).get_variable(code_addr, name)?;
+        Some(self.debugger.variable_place(var, self.frame.as_ref()))
+    }
+
+    fn register(&self, name: &str) -> Result<u64, String> {
+        let index = registers::find(name).ok_or_else(|| format!("Invalid register \"{}\".", name))?;
+        self.debugger.registers_in(self.frame.as_ref())?[index]
+            .ok_or_else(|| format!("${} was not saved in this frame.", name))
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.debugger
+            .target()
+            .and_then(|target| target.read_bytes(addr, len).ok())
+            .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+    }
 }
 
 impl Debugger {
     /// Initializes the debugger.
     pub fn new(target: &str) -> Debugger {
+        match Debugger::open(target) {
+            Ok(debugger) => debugger,
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Initializes the debugger, or says why the target's debugging symbols can't be loaded.
+    pub fn open(target: &str) -> Result<Debugger, String> {
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         let mut readline = Editor::<()>::new().expect("Create Editor fail");
         // Attempt to load history from ~/.deet_history if it exists
@@ -36,92 +161,714 @@ impl Debugger {
 
         let debug_data = match DwarfData::from_file(target) {
             Ok(data) => data,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Counld not open file {}", target);
-                std::process::exit(1);
-            }
+            Err(DwarfError::ErrorOpeningFile) => return Err(format!("Counld not open file {}", target)),
             Err(DwarfError::DwarfFormatError(e)) => {
-                println!("Could not debugging symbols from {} : {:?}", target, e);
-                std::process::exit(1);
+                return Err(format!("Could not debugging symbols from {} : {:?}", target, e))
             }
-        }; 
+        };
 
-        Debugger {
+        Ok(Debugger {
             target: target.to_string(),
             history_path,
             readline,
             inferior: None,
+            core: None,
             debug_data,
+            shared_libraries: SharedLibraries::new(target),
             breakpoints: HashMap::new(),
+            watchpoints: Vec::new(),
+            next_breakpoint_num: 1,
+            fork_policy: ForkPolicy::default(),
+            signal_policy: SignalTable::default(),
+            unwinder: None,
+            selected_frame: 0,
+            examine_format: 'x',
+            examine_unit: 4,
+            next_examine_addr: None,
+            sources: SourceFiles::new(),
+            listing: None,
+            displays: Vec::new(),
+            next_display_num: 1,
+            pending_lines: VecDeque::new(),
+            command_depth: 0,
+            batch: false,
+            user_commands: HashMap::new(),
+            exit_status: None,
+            events: None,
+            requests: None,
+            request_id: None,
+        })
+    }
+
+    /// Reads and runs commands until the user quits or, in batch mode, the scripts are done.
+    /// Returns the status deet should exit with: in batch mode, how the program ended.
+    pub fn run(&mut self) -> i32 {
+        loop {
+            let command = self.get_next_command();
+            let keep_going = self.execute(command);
+            if self.pending_lines.is_empty() {
+                self.finish_request();
+            }
+            if !keep_going {
+                break;
+            }
+        }
+        if self.batch {
+            self.exit_status.unwrap_or(0)
+        } else {
+            0
         }
     }
 
-    pub fn run(&mut self) {
-        // test
-        self.debug_data.print(); // to be removed
+    /// Carries out one command. Returns false if deet should exit.
+    pub fn execute(&mut self, command: DebuggerCommand) -> bool {
+        match command {
+            DebuggerCommand::Run(args) => {
+                // kill the inferior if it is already running
+                self.kill_inferior();
+                self.exit_status = None;
+                if let Some(mut inferior) = Inferior::new(&self.target, &args) {
+                    // Create the inferior
+                    inferior.set_fork_policy(self.fork_policy);
+                    inferior.set_signal_policy(self.signal_policy);
+                    self.inferior = Some(Box::new(inferior));
+                    self.relocate_program();
+                    self.rearm_watchpoints();
+                    // start
+                    self.continue_inferior();
 
-        loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    // kill the inferior if it is already running
+                } else {
+                    self.report_error("Error starting subprocess");
+                }
+            }
+            DebuggerCommand::Continue => {
+                // Continue to run
+                self.continue_inferior();
+            }
+            DebuggerCommand::Step => {
+                self.step_inferior(true);
+            }
+            DebuggerCommand::Next => {
+                self.step_inferior(false);
+            }
+            DebuggerCommand::StepInstruction(count) => {
+                self.step_instructions(count, false);
+            }
+            DebuggerCommand::NextInstruction(count) => {
+                self.step_instructions(count, true);
+            }
+            DebuggerCommand::Finish => {
+                self.finish_inferior();
+            }
+            DebuggerCommand::Print(name) => {
+                self.print_variable(&name);
+            }
+            DebuggerCommand::Ptype(expr) => {
+                self.print_type(&expr, true);
+            }
+            DebuggerCommand::Whatis(expr) => {
+                self.print_type(&expr, false);
+            }
+            DebuggerCommand::InfoLocals => {
+                self.print_frame_variables(false);
+            }
+            DebuggerCommand::InfoArgs => {
+                self.print_frame_variables(true);
+            }
+            DebuggerCommand::Attach(pid) => {
+                self.attach_inferior(pid);
+            }
+            DebuggerCommand::TargetRemote(address) => {
+                self.connect_remote(&address);
+            }
+            DebuggerCommand::Detach => {
+                if self.inferior.is_some() {
+                    self.detach_inferior();
+                } else {
+                    println!("No inferior running");
+                }
+            }
+            DebuggerCommand::InfoRegisters(names) => {
+                self.print_registers(&names, false);
+            }
+            DebuggerCommand::InfoAllRegisters => {
+                self.print_registers(&[], true);
+            }
+            DebuggerCommand::InfoSharedLibrary => {
+                self.print_shared_libraries();
+            }
+            DebuggerCommand::InfoThreads => {
+                self.print_threads();
+            }
+            DebuggerCommand::Thread(num) => {
+                self.select_thread(num);
+            }
+            DebuggerCommand::Set(name, value) => {
+                self.set_option(&name, &value);
+            }
+            DebuggerCommand::SetVariable(lhs, value) => {
+                self.set_variable(&lhs, &value);
+            }
+            DebuggerCommand::Examine(examine_format, expr) => {
+                self.examine_memory(examine_format, expr);
+            }
+            DebuggerCommand::Disassemble(raw, spec) => {
+                self.disassemble(raw, spec);
+            }
+            DebuggerCommand::List(spec) => {
+                self.list_source(spec.as_deref());
+            }
+            DebuggerCommand::Directory(dirs) => {
+                self.set_source_directories(&dirs);
+            }
+            DebuggerCommand::Display(expr) => {
+                self.add_display(expr);
+            }
+            DebuggerCommand::Undisplay(nums) => {
+                self.remove_displays(&nums);
+            }
+            DebuggerCommand::InfoDisplay => {
+                self.print_displays();
+            }
+            DebuggerCommand::Handle(args) => {
+                self.handle_signals(&args);
+            }
+            DebuggerCommand::InfoSignals(signal) => {
+                self.print_signals(signal.as_deref());
+            }
+            DebuggerCommand::Gcore(path) => {
+                self.generate_core(path);
+            }
+            DebuggerCommand::Source(path) => {
+                self.source_file(&path);
+            }
+            DebuggerCommand::Define(name) => {
+                if self.interactive() {
+                    println!("Type commands for definition of \"{}\".", name);
+                    println!("End with a line saying just \"end\".");
+                }
+                let body = self.read_block();
+                self.user_commands.insert(name, body);
+            }
+            DebuggerCommand::Commands(num) => {
+                let num = num.unwrap_or(self.next_breakpoint_num - 1);
+                if let Some(addr) = self.find_breakpoint(num) {
+                    if self.interactive() {
+                        println!("Type commands for breakpoint(s) {}, one per line.", num);
+                        println!("End with a line saying just \"end\".");
+                    }
+                    let commands = self.read_block();
+                    self.breakpoints.get_mut(&addr).unwrap().commands = commands;
+                }
+            }
+            DebuggerCommand::Quit => {
+                // leave a process we attached to running, as we found it
+                if self.inferior.as_ref().map_or(false, |inferior| inferior.is_attached()) {
+                    self.detach_inferior();
+                } else {
                     self.kill_inferior();
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &mut self.breakpoints) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        // start
-                        self.continue_inferior();
-
-                    } else {
-                        println!("Error starting subprocess");
+                }
+                return false;
+            }
+            DebuggerCommand::Backtrace => {
+                if self.target().is_some() {
+                    self.print_backtrace();
+                } else {
+                    println!("No inferior running");
+                }
+            }
+            DebuggerCommand::Frame(level) => {
+                self.select_frame(level.unwrap_or(self.selected_frame));
+            }
+            DebuggerCommand::Up(count) => {
+                let depth = self.frames().len();
+                if depth == 0 {
+                    self.report_error("No stack.");
+                } else if self.selected_frame + 1 >= depth {
+                    println!("Initial frame selected; you cannot go up.");
+                } else {
+                    self.select_frame(std::cmp::min(self.selected_frame + count, depth - 1));
+                }
+            }
+            DebuggerCommand::Down(count) => {
+                if self.target().is_none() {
+                    self.report_error("No stack.");
+                } else if self.selected_frame == 0 {
+                    println!("Bottom (innermost) frame selected; you cannot go down.");
+                } else {
+                    self.select_frame(self.selected_frame.saturating_sub(count));
+                }
+            }
+            DebuggerCommand::Breakpoint(break_target, condition) => {
+                self.set_breakpoint(&break_target, condition.as_deref());
+            }
+            DebuggerCommand::InfoBreakpoints => {
+                self.print_breakpoints();
+            }
+            DebuggerCommand::Delete(nums) => {
+                let nums = if nums.is_empty() { self.breakpoint_nums() } else { nums };
+                for num in nums {
+                    if let Some(index) = self.watchpoint_index(num) {
+                        self.disarm_watchpoint(index);
+                        self.watchpoints.remove(index);
+                    } else if let Some(addr) = self.find_breakpoint(num) {
+                        self.uninstall_breakpoint(addr);
+                        self.breakpoints.remove(&addr);
                     }
                 }
-                DebuggerCommand::Continue => {
-                    // Continue to run
-                    self.continue_inferior();
+            }
+            DebuggerCommand::Disable(nums) => {
+                let nums = if nums.is_empty() { self.breakpoint_nums() } else { nums };
+                for num in nums {
+                    if let Some(index) = self.watchpoint_index(num) {
+                        self.disarm_watchpoint(index);
+                        self.watchpoints[index].enabled = false;
+                    } else if let Some(addr) = self.find_breakpoint(num) {
+                        self.uninstall_breakpoint(addr);
+                        self.breakpoints.get_mut(&addr).unwrap().enabled = false;
+                    }
                 }
-                DebuggerCommand::Quit => {
-                    self.kill_inferior();
-                    return;
+            }
+            DebuggerCommand::Enable(nums) => {
+                let nums = if nums.is_empty() { self.breakpoint_nums() } else { nums };
+                for num in nums {
+                    if let Some(index) = self.watchpoint_index(num) {
+                        self.watchpoints[index].enabled = self.arm_watchpoint(index);
+                    } else if let Some(addr) = self.find_breakpoint(num) {
+                        self.breakpoints.get_mut(&addr).unwrap().enabled = true;
+                        self.install_breakpoint(addr);
+                    }
                 }
-                DebuggerCommand::Backtrace => {
-                    if let Some(inferior) = &self.inferior {
-                        inferior.backtrace(&self.debug_data).ok();
+            }
+            DebuggerCommand::Ignore(num, count) => {
+                if let Some(addr) = self.find_breakpoint(num) {
+                    self.breakpoints.get_mut(&addr).unwrap().ignore_count = count;
+                    if count == 0 {
+                        println!("Will stop next time breakpoint {} is reached.", num);
                     } else {
-                        println!("No inferior running");
+                        println!("Will ignore next {} crossings of breakpoint {}.", count, num);
                     }
                 }
-                DebuggerCommand::Breakpoint(break_target) => {
-                    let addr: usize;
-                    if break_target.starts_with("*") {
-                        if let Some(tmp_addr) = utils::parse_address(&break_target[1..]) {
-                            addr = tmp_addr;
-                            println!("Set breakpoint at address {:#x}", addr);
-                            self.breakpoints.insert(addr, None);
-                        }
-                        else {
-                            println!("Invalid address");
-                            continue;
+            }
+            DebuggerCommand::Watch(kind, expr) => {
+                self.set_watchpoint(kind, &expr);
+            }
+            DebuggerCommand::Condition(num, text) => {
+                if let Some(addr) = self.find_breakpoint(num) {
+                    let condition = match text {
+                        Some(text) => match Expression::parse(&text, self.debug_data_for(addr).types()) {
+                            Ok(condition) => Some(condition),
+                            Err(e) => {
+                                self.report_error(&e);
+                                return true;
+                            }
+                        },
+                        None => {
+                            println!("Breakpoint {} now unconditional.", num);
+                            None
                         }
-                    }
-                    else {
-                        println!("Invalid breakpoint");
-                        continue;
-                    }
+                    };
+                    self.breakpoints.get_mut(&addr).unwrap().condition = condition;
+                }
+            }
+            _ => {
+                println!("Unknown command");
+            }
+        }
+        true
+    }
 
-                    if let Some(inferior) = &mut self.inferior {
-                        if let Ok(orig_byte) = inferior.set_breakpoint(addr) {
-                            self.breakpoints.insert(addr, Some(Breakpoint { addr, orig_byte}));
-                        }
-                    }
-                    else {
-                        self.breakpoints.insert(addr, None);
+    /// Creates a new numbered breakpoint, installing it right away if the inferior is running.
+    pub fn set_breakpoint(&mut self, target: &str, condition: Option<&str>) {
+        // a register is read once, and the breakpoint stays at the address it held
+        let target = match target.strip_prefix("*$") {
+            Some(name) => match self.register_value(name) {
+                Ok(addr) => format!("*{:#x}", addr),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            },
+            None => target.to_string(),
+        };
+        let target = target.as_str();
+        let addr = match self.resolve_breakpoint_target(target) {
+            Some(addr) => addr,
+            None => {
+                self.report_error(&format!("Invalid breakpoint {}", target));
+                return;
+            }
+        };
+        if let Some(existing) = self.breakpoints.get(&addr) {
+            self.report_error(&format!("Breakpoint {} already set at {:#x}", existing.num, addr));
+            return;
+        }
+        let condition = match condition.map(|text| Expression::parse(text, self.debug_data_for(addr).types())) {
+            Some(Ok(condition)) => Some(condition),
+            Some(Err(e)) => {
+                self.report_error(&e);
+                return;
+            }
+            None => None,
+        };
+
+        let mut breakpoint = Breakpoint::new(self.next_breakpoint_num, addr);
+        breakpoint.location = target.to_string();
+        breakpoint.condition = condition;
+        if let Some(inferior) = &mut self.inferior {
+            match inferior.set_breakpoint(addr) {
+                Ok(orig_byte) => breakpoint.orig_byte = Some(orig_byte),
+                Err(e) => {
+                    println!("Error setting breakpoint: {:?}", e);
+                    return;
+                }
+            }
+        }
+        let line = self.debug_data.get_line_from_addr(addr);
+        match &line {
+            Some(line) => println!("Breakpoint {} at {:#x}: {}", breakpoint.num, addr, line),
+            None => println!("Breakpoint {} at {:#x}", breakpoint.num, addr),
+        }
+        self.emit(
+            "breakpoint-created",
+            vec![
+                ("number", breakpoint.num.into()),
+                ("addr", format!("{:#x}", addr).into()),
+                ("location", target.into()),
+                ("file", line.as_ref().map(|line| line.file.clone()).into()),
+                ("line", line.map(|line| line.number).into()),
+            ],
+        );
+        self.next_breakpoint_num += 1;
+        self.breakpoints.insert(addr, breakpoint);
+    }
+
+    /// Returns the numbers of all breakpoints and watchpoints, in order.
+    fn breakpoint_nums(&self) -> Vec<usize> {
+        let mut nums: Vec<usize> = self.breakpoints.values().map(|bp| bp.num).collect();
+        nums.extend(self.watchpoints.iter().map(|wp| wp.num));
+        nums.sort_unstable();
+        nums
+    }
+
+    /// Returns the address of breakpoint `num`, or reports that it doesn't exist.
+    fn find_breakpoint(&self, num: usize) -> Option<usize> {
+        let addr = self.breakpoints.values().find(|bp| bp.num == num).map(|bp| bp.addr);
+        if addr.is_none() {
+            self.report_error(&format!("No breakpoint number {}.", num));
+        }
+        addr
+    }
+
+    /// Writes the 0xcc for the breakpoint at `addr` into the running inferior.
+    fn install_breakpoint(&mut self, addr: usize) {
+        let breakpoint = self.breakpoints.get_mut(&addr).unwrap();
+        if let (Some(inferior), None) = (&mut self.inferior, breakpoint.orig_byte) {
+            match inferior.set_breakpoint(addr) {
+                Ok(orig_byte) => breakpoint.orig_byte = Some(orig_byte),
+                Err(e) => println!("Error setting breakpoint: {:?}", e),
+            }
+        }
+    }
+
+    /// Restores the original byte under the breakpoint at `addr` in the running inferior.
+    fn uninstall_breakpoint(&mut self, addr: usize) {
+        let breakpoint = self.breakpoints.get_mut(&addr).unwrap();
+        if let (Some(inferior), Some(orig_byte)) = (&mut self.inferior, breakpoint.orig_byte) {
+            if let Err(e) = inferior.remove_breakpoint(addr, orig_byte) {
+                println!("Error removing breakpoint: {:?}", e);
+            }
+        }
+        breakpoint.orig_byte = None;
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        println!("Num     Type            Enb Address            What");
+        for num in self.breakpoint_nums() {
+            let (description, enabled, addr, what, hit_count) =
+                match self.watchpoints.iter().find(|wp| wp.num == num) {
+                    Some(watchpoint) => (
+                        watchpoint.description(),
+                        watchpoint.enabled,
+                        watchpoint.addr,
+                        watchpoint.expr.clone(),
+                        watchpoint.hit_count,
+                    ),
+                    None => {
+                        let breakpoint = self.breakpoints.values().find(|bp| bp.num == num).unwrap();
+                        let what = match (
+                            self.debug_data.get_function_from_addr(breakpoint.addr),
+                            self.debug_data.get_line_from_addr(breakpoint.addr),
+                        ) {
+                            (Some(func), Some(line)) => format!("in {} at {}", func, line),
+                            (Some(func), None) => format!("in {}", func),
+                            _ => String::new(),
+                        };
+                        ("breakpoint", breakpoint.enabled, breakpoint.addr, what, breakpoint.hit_count)
                     }
+                };
+            println!(
+                "{:<7} {:<15} {:<3} {:#018x} {}",
+                num,
+                description,
+                if enabled { "y" } else { "n" },
+                addr,
+                what
+            );
+            let breakpoint = self.breakpoints.values().find(|bp| bp.num == num);
+            if let Some(condition) = breakpoint.and_then(|bp| bp.condition.as_ref()) {
+                println!("\tstop only if {}", condition);
+            }
+            if hit_count > 0 {
+                println!(
+                    "\tbreakpoint already hit {} time{}",
+                    hit_count,
+                    if hit_count == 1 { "" } else { "s" }
+                );
+            }
+            if let Some(ignore_count) = breakpoint.map(|bp| bp.ignore_count).filter(|count| *count > 0) {
+                println!("\twill ignore next {} crossings", ignore_count);
+            }
+        }
+    }
+
+    /// Sets a hardware watchpoint on the value of an expression, which has to be in memory, or on
+    /// the int at `*ADDR`.
+    fn set_watchpoint(&mut self, kind: WatchKind, expr: &str) {
+        if self.inferior.is_none() {
+            self.report_error("The program is not being run.");
+            return;
+        }
+        let (addr, entity_type, frame) = if let Some(addr) = expr.strip_prefix('*') {
+            let addr = addr.trim();
+            let value = match addr.strip_prefix('$') {
+                Some(name) => self.register_value(name).ok().map(|value| value as usize),
+                None => utils::parse_address(addr),
+            };
+            match value {
+                Some(addr) => (addr, Type::new("int".to_string(), 4, Encoding::Signed), None),
+                None => {
+                    self.report_error(&format!("Invalid address {}", addr));
+                    return;
+                }
+            }
+        } else {
+            let frame = match self.selected_frame() {
+                Some(frame) => frame,
+                None => {
+                    self.report_error("No stack.");
+                    return;
+                }
+            };
+            let scope = FrameScope {
+                debugger: self,
+                frame: Some(frame.clone()),
+            };
+            let result = Expression::parse(expr, scope.types())
+                .and_then(|expression| Ok((expression.evaluate(&scope)?, expression)));
+            let (place, expression) = match result {
+                Ok((place, expression)) if place.value.is_none() && place.bits.is_none() => (place, expression),
+                Ok(_) => {
+                    self.report_error(&format!("Cannot watch \"{}\": it is not in memory here", expr));
+                    return;
                 }
-                _ => {
-                    println!("Unknown command");
+                Err(e) => {
+                    self.report_error(&e);
+                    return;
                 }
+            };
+            // a watchpoint on anything involving a local goes away with its frame, as in GDB
+            let code_addr = frame.code_addr();
+            let func = self.debug_data_for(code_addr).get_function_containing(code_addr);
+            let local = expression.names().iter().any(|name| {
+                func.map_or(false, |func| {
+                    func.variables
+                        .iter()
+                        .any(|var| var.name == *name && !matches!(var.location, Location::Address(_)))
+                })
+            });
+            (place.addr, place.entity_type, if local { Some(frame.cfa) } else { None })
+        };
+        let size = entity_type.size;
+        if ![1, 2, 4, 8].contains(&size) || addr % size != 0 {
+            self.report_error(&format!(
+                "Cannot watch {} bytes at {:#x}: the debug registers only cover 1, 2, 4 or 8 aligned bytes",
+                size, addr
+            ));
+            return;
+        }
+
+        self.watchpoints.push(Watchpoint {
+            num: self.next_breakpoint_num,
+            expr: expr.to_string(),
+            kind,
+            addr,
+            entity_type,
+            frame,
+            enabled: true,
+            slot: None,
+            old_value: Vec::new(),
+            hit_count: 0,
+        });
+        if !self.arm_watchpoint(self.watchpoints.len() - 1) {
+            self.watchpoints.pop();
+            return;
+        }
+        let watchpoint = self.watchpoints.last().unwrap();
+        println!("{} {}: {}", watchpoint.label(), watchpoint.num, watchpoint.expr);
+        self.next_breakpoint_num += 1;
+    }
+
+    /// Returns the index of watchpoint `num` in `self.watchpoints`.
+    fn watchpoint_index(&self, num: usize) -> Option<usize> {
+        self.watchpoints.iter().position(|wp| wp.num == num)
+    }
+
+    /// Programs a watchpoint into a free debug register of the running inferior and records its
+    /// current value. Returns false if that isn't possible.
+    fn arm_watchpoint(&mut self, index: usize) -> bool {
+        let watchpoint = &mut self.watchpoints[index];
+        let inferior = match &mut self.inferior {
+            Some(inferior) => inferior,
+            None => return false,
+        };
+        if watchpoint.slot.is_some() {
+            return true;
+        }
+        let size = watchpoint.entity_type.size;
+        let error = match inferior.set_hw_watchpoint(watchpoint.addr, size, watchpoint.kind) {
+            Ok(Some(slot)) => {
+                watchpoint.slot = Some(slot);
+                watchpoint.old_value = inferior.read_bytes(watchpoint.addr, size).unwrap_or_default();
+                return true;
+            }
+            Ok(None) => format!(
+                "All {} hardware watchpoint slots are in use; delete or disable one first.",
+                NUM_HW_WATCHPOINTS
+            ),
+            Err(e) => format!("Could not set watchpoint: {:?}", e),
+        };
+        self.report_error(&error);
+        false
+    }
+
+    /// Frees the debug register used by a watchpoint.
+    fn disarm_watchpoint(&mut self, index: usize) {
+        let watchpoint = &mut self.watchpoints[index];
+        if let (Some(inferior), Some(slot)) = (&mut self.inferior, watchpoint.slot) {
+            if let Err(e) = inferior.remove_hw_watchpoint(slot) {
+                println!("Error removing watchpoint: {:?}", e);
             }
         }
+        watchpoint.slot = None;
+    }
+
+    /// Arms the watchpoints again in a freshly started inferior. Watchpoints on local variables
+    /// point into a frame that no longer exists, so they are deleted.
+    fn rearm_watchpoints(&mut self) {
+        self.watchpoints.retain(|wp| {
+            if wp.frame.is_some() {
+                println!("Watchpoint {} deleted because the program has left the frame of {}.", wp.num, wp.expr);
+            }
+            wp.frame.is_none()
+        });
+        for index in 0..self.watchpoints.len() {
+            self.watchpoints[index].slot = None;
+            if self.watchpoints[index].enabled {
+                self.watchpoints[index].enabled = self.arm_watchpoint(index);
+            }
+        }
+    }
+
+    /// Checks whether the last trap came from a hardware watchpoint, and if so prints the old
+    /// and new values and returns whether to stay stopped. Writes that leave the value unchanged
+    /// are passed over, as are writes seen by read watchpoints (x86 can only trap on any access).
+    fn watchpoint_hit(&mut self) -> Option<bool> {
+        let slot = self.inferior.as_mut()?.take_hw_watchpoint_hit()?;
+        let index = self.watchpoints.iter().position(|wp| wp.slot == Some(slot))?;
+
+        // the stack slot of a local may be reused once its function has returned
+        if let Some(cfa) = self.watchpoints[index].frame {
+            if !self.frames().iter().any(|frame| frame.cfa == cfa) {
+                println!(
+                    "\nWatchpoint {} deleted because the program has left the frame of {}.",
+                    self.watchpoints[index].num, self.watchpoints[index].expr
+                );
+                self.disarm_watchpoint(index);
+                self.watchpoints.remove(index);
+                return Some(true);
+            }
+        }
+
+        let inferior = self.inferior.as_mut()?;
+        let watchpoint = &mut self.watchpoints[index];
+        let new_value = inferior
+            .read_bytes(watchpoint.addr, watchpoint.entity_type.size)
+            .ok()?;
+        let changed = new_value != watchpoint.old_value;
+        let stop = match watchpoint.kind {
+            WatchKind::Write => changed,
+            WatchKind::Read => !changed,
+            WatchKind::Access => true,
+        };
+        if stop {
+            watchpoint.hit_count += 1;
+            println!("\n{} {}: {}\n", watchpoint.label(), watchpoint.num, watchpoint.expr);
+            if changed {
+                println!("Old value = {}", watchpoint.entity_type.format_value(&watchpoint.old_value));
+                println!("New value = {}", watchpoint.entity_type.format_value(&new_value));
+            } else {
+                println!("Value = {}", watchpoint.entity_type.format_value(&new_value));
+            }
+        }
+        watchpoint.old_value = new_value;
+        Some(stop)
+    }
+
+    /// Checks whether the inferior stopped at an enabled breakpoint. If so, records the hit and
+    /// returns the breakpoint number along with whether its condition and ignore count say to
+    /// stay stopped.
+    fn breakpoint_hit(&mut self, rip: usize) -> Option<(usize, bool)> {
+        let breakpoint = self.breakpoints.get(&rip).filter(|bp| bp.enabled)?;
+        let num = breakpoint.num;
+        if breakpoint.condition.is_some() {
+            let frame = self.frames().into_iter().next();
+            let scope = FrameScope { debugger: self, frame };
+            match self.breakpoints[&rip].condition.as_ref().unwrap().is_true(&scope) {
+                Ok(true) => {}
+                Ok(false) => return Some((num, false)),
+                Err(e) => println!("Error in testing condition for breakpoint {}: {}", num, e),
+            }
+        }
+        let breakpoint = self.breakpoints.get_mut(&rip).unwrap();
+        breakpoint.hit_count += 1;
+        if breakpoint.ignore_count > 0 {
+            breakpoint.ignore_count -= 1;
+            return Some((num, false));
+        }
+        Some((num, true))
+    }
+
+    /// Resolves a breakpoint target to an address. Accepts `*ADDR`, `LINE`, `FUNC`, `FILE:LINE`
+    /// and `FILE:FUNC`. Function breakpoints are placed after the prologue.
+    fn resolve_breakpoint_target(&self, target: &str) -> Option<usize> {
+        if let Some(addr) = target.strip_prefix('*') {
+            return utils::parse_address(addr);
+        }
+        let (file, location) = match target.rsplit_once(':') {
+            Some((file, location)) => (Some(file), location),
+            None => (None, target),
+        };
+        if let Ok(line_number) = location.parse::<usize>() {
+            self.debug_data.get_addr_for_line(file, line_number)
+        } else {
+            let func_addr = self.debug_data.get_addr_for_function(file, location)?;
+            Some(self.debug_data.get_addr_after_prologue(func_addr))
+        }
     }
 
     pub fn kill_inferior(&mut self) {
@@ -129,74 +876,2017 @@ impl Debugger {
             self.inferior.as_mut().unwrap().kill();
             self.inferior = None;
         }
+        self.core = None;
     }
 
-    pub fn continue_inferior(&mut self) {
-        if let Some(inferior) = self.inferior.as_mut() {
-            match inferior.cont(&self.breakpoints) {
-                Ok(status) => match status {
-                    Status::Exited(exit_code) => {
-                        println!("Inferior exited with code {}", exit_code);
-                        self.inferior = None
-                    }
-                    Status::Signaled(signal) => {
-                        println!("Inferior was killed by signal {}", signal);
-                        self.kill_inferior();
-                    }
-                    Status::Stopped(signal, rip) => {
-                        println!("Inferior stopped due to signal {}", signal);
-                        let line = self.debug_data.get_line_from_addr(rip).unwrap();
-                        println!("Stopped at {}", line);
-                    }
-                },
+    /// Saves a core file of the stopped inferior, named core.<pid> unless a path is given.
+    fn generate_core(&self, path: Option<String>) {
+        let inferior = match self.inferior.as_ref().map(|inferior| inferior.as_inferior()) {
+            Some(Some(inferior)) => inferior,
+            Some(None) => {
+                println!("Can't write a core file of a remote target.");
+                return;
+            }
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let path = path.unwrap_or_else(|| format!("core.{}", inferior.pid()));
+        match core_file::write_core_file(inferior, &self.breakpoints, &path) {
+            Ok(()) => println!("Saved corefile {}", path),
+            Err(e) => println!("Failed to write corefile: {}", e),
+        }
+    }
+
+    /// Returns what `print`, `x` and `backtrace` look at: the live process, or else the core file.
+    fn target(&self) -> Option<&dyn Target> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => Some(inferior.as_ref()),
+            (None, Some(core)) => Some(core),
+            (None, None) => None,
+        }
+    }
+
+    /// Opens a core file for post-mortem debugging, in place of any live process, and shows
+    /// where the program died.
+    pub fn load_core(&mut self, path: &str) {
+        let core = match CoreFile::open(path) {
+            Ok(core) => core,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        self.kill_inferior();
+        if !core.command().is_empty() {
+            println!("Core was generated by `{}'.", core.command());
+        }
+        match (core.signal(), core.fault_address()) {
+            (Some(signal), Some(addr)) if signals::is_fault(signal) => {
+                println!("Program terminated with signal {} (fault address {:#x})", signal, addr)
+            }
+            (Some(signal), _) => println!("Program terminated with signal {}", signal),
+            (None, _) => {}
+        }
+        let rip = core.rip();
+        self.core = Some(core);
+        self.selected_frame = 0;
+        self.listing = None;
+        self.relocate_program();
+        if let Some(rip) = rip {
+            self.print_stop_location(rip);
+            let core = self.core.as_ref().unwrap();
+            let fault_addr = core.fault_address().map(|addr| format!("{:#x}", addr));
+            self.emit_stopped(
+                rip,
+                vec![
+                    ("reason", "core".into()),
+                    ("signal", core.signal().map(|signal| signal.as_str()).into()),
+                    ("fault-address", fault_addr.into()),
+                ],
+            );
+        }
+    }
+
+    /// Attaches to a running process and stops it. If the process is running a different program
+    /// than the current target, its debugging symbols are loaded and breakpoints set on the old
+    /// target are dropped.
+    pub fn attach_inferior(&mut self, pid: i32) {
+        self.kill_inferior();
+        let exe = format!("/proc/{}/exe", pid);
+        let same_target = match (std::fs::canonicalize(&exe), std::fs::canonicalize(&self.target)) {
+            (Ok(exe_path), Ok(target_path)) => exe_path == target_path,
+            (Err(_), _) => {
+                self.report_error(&format!("No such process {}", pid));
+                return;
+            }
+            _ => false,
+        };
+        if !same_target {
+            match DwarfData::from_file(&exe) {
+                Ok(debug_data) => self.debug_data = debug_data,
                 Err(e) => {
-                    println!("Error: {:?}", e);
+                    self.report_error(&format!("Could not load debugging symbols from {}: {:?}", exe, e));
+                    return;
                 }
             }
+            if !self.breakpoints.is_empty() || !self.watchpoints.is_empty() {
+                println!("Deleting breakpoints set on {}", self.target);
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+            }
+            self.shared_libraries = SharedLibraries::new(&exe);
+            self.target = exe;
         }
-        else {
-            println!("No inferior to continue");
+
+        match Inferior::attach(Pid::from_raw(pid)) {
+            Ok(mut inferior) => {
+                println!("Attaching to process {}", pid);
+                inferior.set_fork_policy(self.fork_policy);
+                inferior.set_signal_policy(self.signal_policy);
+                let rip = inferior.rip();
+                self.inferior = Some(Box::new(inferior));
+                self.selected_frame = 0;
+                self.listing = None;
+                self.relocate_program();
+                self.rearm_watchpoints();
+                if let Some(rip) = rip {
+                    self.print_stop_location(rip);
+                    self.emit_stopped(rip, vec![("reason", "attached".into())]);
+                }
+            }
+            Err(e) => self.report_error(&format!("Could not attach to process {}: {}", pid, e)),
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
+    /// Takes over a program that a gdbserver controls, in place of any local process. The
+    /// program is assumed to be the current target; its symbols are read from the local copy.
+    pub fn connect_remote(&mut self, address: &str) {
+        self.kill_inferior();
+        match Remote::connect(address) {
+            Ok(mut remote) => {
+                println!("Remote debugging using {}", address);
+                remote.set_signal_policy(self.signal_policy);
+                let rip = remote.rip();
+                self.inferior = Some(Box::new(remote));
+                self.selected_frame = 0;
+                self.listing = None;
+                self.relocate_program();
+                self.rearm_watchpoints();
+                if let Some(rip) = rip {
+                    self.print_stop_location(rip);
+                    self.emit_stopped(rip, vec![("reason", "remote".into())]);
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+            }
+            Err(e) => self.report_error(&e),
+        }
+    }
+
+    /// Removes all breakpoints and watchpoints from the inferior and lets it run on its own.
+    pub fn detach_inferior(&mut self) {
+        for index in 0..self.watchpoints.len() {
+            self.disarm_watchpoint(index);
+        }
+        let mut inferior = self.inferior.take().unwrap();
+        match inferior.detach(&mut self.breakpoints) {
+            Ok(()) => println!("Detaching from process {}", inferior.pid()),
+            Err(e) => println!("Error detaching from process {}: {}", inferior.pid(), e),
+        }
+    }
+
+    pub fn continue_inferior(&mut self) {
+        loop {
+            let inferior = match self.inferior.as_mut() {
+                Some(inferior) => inferior,
+                None => {
+                    self.report_error("No inferior to continue");
+                    return;
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+            };
+            let result = match inferior.cont(&self.breakpoints) {
+                // the new program runs on, stopping at its own breakpoints
+                Ok(Status::Exec) => {
+                    self.follow_exec();
+                    continue;
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                result => result,
+            };
+            match self.handle_status(result) {
+                // keep going if a breakpoint's condition or ignore count says so
+                Some((signal, rip)) => {
+                    if self.report_stop(signal, rip, false) {
+                        return;
                     }
                 }
+                None => return,
+            }
+        }
+    }
+
+    /// Steps the inferior to the next source line, entering calls if `step_into` is set. Code
+    /// without line information is left by running until it returns, or by continuing in the
+    /// outermost frame.
+    fn step_inferior(&mut self, step_into: bool) {
+        let rip = match self.inferior.as_ref() {
+            Some(inferior) => inferior.rip(),
+            None => {
+                self.report_error("No inferior running");
+                return;
+            }
+        };
+        let in_line_info = match rip {
+            Some(rip) => self.debug_data.get_line_from_addr(rip).is_some(),
+            None => true,
+        };
+        let return_point = if in_line_info { None } else { self.return_point(0) };
+        if !in_line_info {
+            let symbol = rip.and_then(|rip| self.unwinder.as_ref()?.symbol_for(rip));
+            if let Some((name, _)) = symbol {
+                println!("Single stepping until exit from function {},\nwhich has no line number information.", name);
+            }
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        let result = match return_point {
+            Some((ret_addr, cfa)) => inferior.finish(ret_addr, cfa, &self.breakpoints).map(|(status, _)| status),
+            // the outermost frame: there is nowhere to return to
+            None if !in_line_info => inferior.cont(&self.breakpoints),
+            None => inferior.step_line(&self.debug_data, &self.breakpoints, step_into),
+        };
+        if let Some((signal, rip)) = self.handle_status(result) {
+            self.report_stop(signal, rip, true);
+        }
+    }
+
+    /// Executes `count` machine instructions in the current thread. With `over`, a call counts as
+    /// one instruction. Breakpoints and signals end the stepping early.
+    fn step_instructions(&mut self, count: usize, over: bool) {
+        for i in 0..count {
+            let inferior = match self.inferior.as_mut() {
+                Some(inferior) => inferior,
+                None => {
+                    self.report_error("No inferior running");
+                    return;
+                }
+            };
+            let result = if over {
+                inferior.next_instruction(&self.breakpoints)
+            } else {
+                inferior.step_instruction(&self.breakpoints)
+            };
+            let (signal, rip) = match self.handle_status(result) {
+                Some(stop) => stop,
+                None => return,
+            };
+            let at_breakpoint = self.breakpoints.get(&rip).map_or(false, |bp| bp.enabled);
+            if i + 1 == count || signal != Signal::SIGTRAP || at_breakpoint {
+                self.report_stop(signal, rip, true);
+                return;
+            }
+        }
+    }
+
+    /// Runs the inferior until the function of the selected frame returns, then prints the
+    /// returned value.
+    fn finish_inferior(&mut self) {
+        if self.inferior.is_none() {
+            self.report_error("No inferior running");
+            return;
+        }
+        let (ret_addr, cfa) = match self.return_point(self.selected_frame) {
+            Some(return_point) => return_point,
+            None => {
+                self.report_error("\"finish\" not meaningful in the outermost frame.");
+                return;
+            }
+        };
+        let code_addr = self.selected_frame().map(|frame| frame.code_addr());
+        if let Some(func) = code_addr.and_then(|addr| self.debug_data.get_function_from_addr(addr)) {
+            println!("Run till exit from {}", func);
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        let result = inferior.finish(ret_addr, cfa, &self.breakpoints);
+        let returned = matches!(result, Ok((_, true)));
+        if let Some((signal, rip)) = self.handle_status(result.map(|(status, _)| status)) {
+            self.report_stop(signal, rip, true);
+            if let Some(value) = code_addr.filter(|_| returned).and_then(|addr| self.return_value(addr)) {
+                println!("Value returned is {}", value);
+            }
+        }
+    }
+
+    /// Formats the value the function around `code_addr` has just returned, from where the
+    /// x86-64 ABI puts it: rax and rdx for integers and pointers, xmm0 and xmm1 for floating
+    /// point, st0 for a long double, and memory that rax points to for a struct that takes more
+    /// than two registers. Returns None for a void function.
+    fn return_value(&self, code_addr: usize) -> Option<String> {
+        let debug_data = self.debug_data_for(code_addr);
+        let types = debug_data.types();
+        let ty = types.get(debug_data.get_function_containing(code_addr)?.return_type)?;
+        let target = self.target()?;
+        let regs = target.get_registers().ok()?;
+        let fpregs = target.get_fp_registers().ok();
+        let fp_bytes = |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|word| word.to_le_bytes()).collect() };
+
+        let resolved = types.resolve(ty);
+        let mut scalars = Vec::new();
+        types.scalars(resolved, 0, &mut scalars);
+        let bytes = if matches!(resolved.kind, TypeKind::Base) && resolved.encoding == Encoding::Float && resolved.size > 8 {
+            fp_bytes(&fpregs?.st_space[..4])[..10].to_vec()
+        } else if resolved.size > 16 || scalars.iter().any(|(_, size, float)| *float && *size > 8) {
+            target.read_bytes(regs.rax as usize, resolved.size).ok()?
+        } else {
+            // each eightbyte goes in the next SSE register if it holds only floating point,
+            // and in the next general-purpose one otherwise
+            let mut integer = [regs.rax, regs.rdx].into_iter().map(|reg| reg.to_le_bytes().to_vec());
+            let mut sse = (0..2).map(|num| fpregs.map_or(vec![0; 8], |fpregs| fp_bytes(&fpregs.xmm_space[num * 4..num * 4 + 2])));
+            let mut bytes = Vec::new();
+            for start in (0..resolved.size).step_by(8) {
+                let mut overlapping = scalars.iter().filter(|(offset, size, _)| *offset < start + 8 && offset + size > start).peekable();
+                let only_float = overlapping.peek().is_some() && overlapping.all(|(_, _, float)| *float);
+                bytes.extend(if only_float { sse.next()? } else { integer.next()? });
             }
+            bytes.truncate(resolved.size);
+            bytes
+        };
+        let memory = |addr, len| target.read_bytes(addr, len).ok();
+        let value = types.format_value(ty, &bytes, &memory);
+        // with its type for a pointer, the way `print` shows one
+        if matches!(resolved.kind, TypeKind::Pointer(_)) && !value.contains('"') {
+            Some(format!("({}) {}", ty.name, value))
+        } else {
+            Some(value)
         }
     }
+
+    /// Loads the debugging symbols of the program the inferior has just exec'd, and sets the
+    /// breakpoints again in it. Watchpoints refer to the old program, so they are deleted.
+    fn follow_exec(&mut self) {
+        let pid = match &self.inferior {
+            Some(inferior) => inferior.pid(),
+            None => return,
+        };
+        let exe = format!("/proc/{}/exe", pid);
+        let path = std::fs::read_link(&exe)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(exe);
+        println!("process {} is executing new program: {}", pid, path);
+        let loaded = match DwarfData::from_file(&path) {
+            Ok(debug_data) => {
+                self.debug_data = debug_data;
+                true
+            }
+            Err(e) => {
+                println!("Could not load debugging symbols from {}: {:?}", path, e);
+                false
+            }
+        };
+        self.shared_libraries = SharedLibraries::new(&path);
+        self.target = path;
+
+        for watchpoint in self.watchpoints.drain(..) {
+            println!("Watchpoint {} deleted because the program was replaced.", watchpoint.num);
+        }
+        if !loaded {
+            let mut deleted: Vec<(usize, String)> = self.breakpoints.drain().map(|(_, bp)| (bp.num, bp.location)).collect();
+            deleted.sort();
+            for (num, location) in deleted {
+                println!("Breakpoint {} deleted: no location {} in the new program.", num, location);
+            }
+        }
+        self.relocate_program();
+    }
+
+    /// Moves the debugging info, breakpoints and global watchpoints to where the program is loaded
+    /// in a freshly started, attached or exec'd inferior, installs the breakpoints, and picks up
+    /// the shared libraries loaded so far.
+    fn relocate_program(&mut self) {
+        let target: &dyn Target = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.as_ref(),
+            (None, Some(core)) => core,
+            (None, None) => return,
+        };
+        let old_bias = self.debug_data.load_bias();
+        let bias = self.shared_libraries.executable_bias(target).unwrap_or(old_bias);
+        self.debug_data.set_load_bias(bias);
+        self.shared_libraries.update(target, bias);
+        for watchpoint in &mut self.watchpoints {
+            if watchpoint.frame.is_none() && !watchpoint.expr.starts_with('*') {
+                watchpoint.addr = watchpoint.addr.wrapping_sub(old_bias).wrapping_add(bias);
+            }
+        }
+        self.reset_breakpoints();
+    }
+
+    /// Resolves the location of every breakpoint again and installs the enabled ones in the
+    /// inferior. Breakpoints whose location no longer exists are deleted.
+    fn reset_breakpoints(&mut self) {
+        let mut breakpoints: Vec<Breakpoint> = self.breakpoints.drain().map(|(_, bp)| bp).collect();
+        breakpoints.sort_by_key(|bp| bp.num);
+        for mut breakpoint in breakpoints {
+            let addr = match self.resolve_breakpoint_target(&breakpoint.location) {
+                Some(addr) => addr,
+                None => {
+                    println!("Breakpoint {} deleted: no location {} in the new program.", breakpoint.num, breakpoint.location);
+                    continue;
+                }
+            };
+            breakpoint.addr = addr;
+            breakpoint.orig_byte = None;
+            let enabled = breakpoint.enabled;
+            self.breakpoints.insert(addr, breakpoint);
+            if enabled {
+                self.install_breakpoint(addr);
+            }
+        }
+    }
+
+    /// Reports an exit or error from resuming the inferior, dropping the inferior if it is gone.
+    /// Returns the signal and instruction pointer if the inferior is stopped.
+    fn handle_status(&mut self, result: Result<Status, nix::Error>) -> Option<(Signal, usize)> {
+        self.selected_frame = 0;
+        self.listing = None;
+        if let (Ok(Status::Stopped(..)), Some(inferior)) = (&result, &self.inferior) {
+            self.shared_libraries.update(inferior.as_ref(), self.debug_data.load_bias());
+        }
+        match result {
+            Ok(Status::Exited(exit_code)) => {
+                println!("Inferior exited with code {}", exit_code);
+                self.emit("exited", vec![("exit-code", exit_code.into())]);
+                self.exit_status = Some(exit_code);
+                self.inferior = None;
+                None
+            }
+            Ok(Status::Signaled(signal)) => {
+                println!("Inferior was killed by signal {}", signal);
+                self.emit("exited", vec![("signal", signal.as_str().into())]);
+                self.exit_status = Some(128 + signal as i32);
+                self.inferior = None;
+                None
+            }
+            Ok(Status::Stopped(signal, rip)) => Some((signal, rip)),
+            Ok(Status::Exec) => {
+                self.follow_exec();
+                if let Some(rip) = self.inferior.as_ref().and_then(|inferior| inferior.rip()) {
+                    self.print_stop_location(rip);
+                    self.emit_stopped(rip, vec![("reason", "exec".into())]);
+                }
+                None
+            }
+            Err(e) => {
+                self.report_error(&format!("Error: {:?}", e));
+                None
+            }
+        }
+    }
+
+    /// Prints why and where the inferior stopped. Traps from stepping are not announced. Returns
+    /// false, without printing anything, if a `continue` stopped at a breakpoint that should be
+    /// passed over.
+    fn report_stop(&mut self, signal: Signal, rip: usize, stepping: bool) -> bool {
+        if signal == Signal::SIGTRAP {
+            match self.watchpoint_hit() {
+                Some(true) => {
+                    self.announce_thread_switch();
+                    self.print_stop_location(rip);
+                    self.emit_stopped(rip, vec![("reason", "watchpoint-trigger".into())]);
+                    self.show_displays();
+                    return true;
+                }
+                Some(false) if !stepping => return false,
+                _ => {}
+            }
+        }
+        let hit = self.breakpoint_hit(rip);
+        if let (Signal::SIGTRAP, Some((_, false)), false) = (signal, hit, stepping) {
+            return false;
+        }
+        self.announce_thread_switch();
+        if signal != Signal::SIGTRAP {
+            self.exit_status = Some(128 + signal as i32);
+        }
+        let reason = match (signal, hit) {
+            (Signal::SIGTRAP, Some((num, true))) => {
+                println!("Hit breakpoint {}", num);
+                vec![("reason", "breakpoint-hit".into()), ("breakpoint", num.into())]
+            }
+            (Signal::SIGTRAP, _) if stepping => vec![("reason", "end-stepping-range".into())],
+            _ => {
+                let fault_addr = if signals::is_fault(signal) {
+                    self.inferior.as_ref().and_then(|inferior| inferior.fault_address())
+                } else {
+                    None
+                };
+                match fault_addr {
+                    Some(addr) => println!("Inferior stopped due to signal {} (fault address {:#x})", signal, addr),
+                    None => println!("Inferior stopped due to signal {}", signal),
+                }
+                vec![
+                    ("reason", "signal-received".into()),
+                    ("signal", signal.as_str().into()),
+                    ("fault-address", fault_addr.map(|addr| format!("{:#x}", addr)).into()),
+                ]
+            }
+        };
+        self.print_stop_location(rip);
+        self.emit_stopped(rip, reason);
+        self.show_displays();
+        if let (Signal::SIGTRAP, Some((num, true))) = (signal, hit) {
+            self.run_breakpoint_commands(num);
+        }
+        true
+    }
+
+    /// Changes one of the `set` settings.
+    fn set_option(&mut self, name: &str, value: &str) {
+        match (name, value) {
+            ("follow-fork-mode", "parent") => self.fork_policy.follow_child = false,
+            ("follow-fork-mode", "child") => self.fork_policy.follow_child = true,
+            ("follow-fork-mode", _) => println!("Undefined item: \"{}\".", value),
+            ("detach-on-fork", "on") => self.fork_policy.detach_on_fork = true,
+            ("detach-on-fork", "off") => self.fork_policy.detach_on_fork = false,
+            ("detach-on-fork", _) => println!("\"on\" or \"off\" expected."),
+            _ => println!("Undefined set command: \"{}\".", name),
+        }
+        if let Some(inferior) = self.inferior.as_mut() {
+            inferior.set_fork_policy(self.fork_policy);
+        }
+    }
+
+    /// Changes how signals are handled, as in `handle SIGUSR1 nostop noprint`, and shows the
+    /// new settings. The words can come in any order.
+    fn handle_signals(&mut self, args: &[String]) {
+        let mut changed = Vec::new();
+        let mut keywords = Vec::new();
+        for arg in args {
+            match signals::parse_signal(arg) {
+                Some(signal) => changed.push(signal),
+                None => keywords.push(arg.as_str()),
+            }
+        }
+        if changed.is_empty() {
+            println!("Argument required (signal to handle).");
+            return;
+        }
+        let mut policies = Vec::new();
+        for signal in &changed {
+            let mut policy = self.signal_policy.get(*signal);
+            for keyword in &keywords {
+                if !policy.apply(keyword) {
+                    println!("Unrecognized or ambiguous flag word: \"{}\".", keyword);
+                    return;
+                }
+            }
+            policies.push(policy);
+        }
+        if !keywords.is_empty() && changed.contains(&Signal::SIGTRAP) {
+            println!("SIGTRAP is used by the debugger.");
+            return;
+        }
+        for (signal, policy) in changed.iter().zip(policies) {
+            *self.signal_policy.get_mut(*signal) = policy;
+        }
+        if let Some(inferior) = self.inferior.as_mut() {
+            inferior.set_signal_policy(self.signal_policy);
+        }
+        self.print_signal_header();
+        for signal in changed {
+            self.print_signal(signal);
+        }
+    }
+
+    /// Lists how every signal is handled, or just the one asked about.
+    fn print_signals(&self, name: Option<&str>) {
+        let signal = match name.map(signals::parse_signal) {
+            Some(Some(signal)) => Some(signal),
+            Some(None) => {
+                println!("Only signals 1-31 are valid as numeric signals.");
+                return;
+            }
+            None => None,
+        };
+        self.print_signal_header();
+        match signal {
+            Some(signal) => self.print_signal(signal),
+            None => {
+                for signal in Signal::iterator() {
+                    self.print_signal(signal);
+                }
+                println!();
+                println!("Use the \"handle\" command to change these tables.");
+            }
+        }
+    }
+
+    fn print_signal_header(&self) {
+        println!("{:<14}{:<8}{:<8}{:<16}{}", "Signal", "Stop", "Print", "Pass to program", "Description");
+    }
+
+    fn print_signal(&self, signal: Signal) {
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        let policy = self.signal_policy.get(signal);
+        println!(
+            "{:<14}{:<8}{:<8}{:<16}{}",
+            signal.as_str(),
+            yes_no(policy.stop),
+            yes_no(policy.print),
+            yes_no(policy.pass),
+            signals::description(signal)
+        );
+    }
+
+    /// Tells the user if the stop happened in a different thread than the last one.
+    fn announce_thread_switch(&mut self) {
+        if let Some((num, tid)) = self.inferior.as_mut().and_then(|inferior| inferior.take_thread_switch()) {
+            println!("[Switching to thread {} (LWP {})]", num, tid);
+        }
+    }
+
+    /// Prints the threads of the inferior, marking the current one.
+    fn print_threads(&self) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                println!("No threads.");
+                return;
+            }
+        };
+        println!("  Id   Target Id                 Frame");
+        for thread in inferior.threads() {
+            let marker = if thread.num == inferior.current_thread_num() { '*' } else { ' ' };
+            let target_id = match inferior.thread_name(thread.tid) {
+                Some(name) => format!("LWP {} \"{}\"", thread.tid, name),
+                None => format!("LWP {}", thread.tid),
+            };
+            let frame = match inferior.thread_registers(thread.tid) {
+                Ok(regs) => self.describe_location(regs.rip as usize),
+                Err(_) => "(running)".to_string(),
+            };
+            println!("{} {:<4} {:<25} {}", marker, thread.num, target_id, frame);
+        }
+    }
+
+    /// Switches to thread `num`, or says which thread is current if `num` is None.
+    fn select_thread(&mut self, num: Option<usize>) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No inferior running");
+                return;
+            }
+        };
+        match num {
+            Some(num) if inferior.select_thread(num) => {
+                let tid = inferior.threads().iter().find(|thread| thread.num == num).unwrap().tid;
+                println!("[Switching to thread {} (LWP {})]", num, tid);
+                self.selected_frame = 0;
+                self.listing = None;
+                if let Some(rip) = inferior.rip() {
+                    self.print_stop_location(rip);
+                }
+            }
+            Some(num) => println!("Invalid thread ID: {}", num),
+            None => {
+                let num = inferior.current_thread_num();
+                let tid = inferior.threads().iter().find(|thread| thread.num == num).unwrap().tid;
+                println!("[Current thread is {} (LWP {})]", num, tid);
+            }
+        }
+    }
+
+    /// Describes a code address as `func (file:line)`, the way backtraces show frames.
+    fn describe_location(&self, addr: usize) -> String {
+        let debug_data = self.debug_data_for(addr);
+        match (debug_data.get_function_from_addr(addr), debug_data.get_line_from_addr(addr)) {
+            (Some(func), Some(line)) => format!("{} ({})", func, line),
+            (Some(func), None) => format!("{:#x} in {}", addr, func),
+            _ => format!("{:#x}", addr),
+        }
+    }
+
+    fn print_stop_location(&self, rip: usize) {
+        let debug_data = self.debug_data_for(rip);
+        match debug_data.get_line_from_addr(rip) {
+            // e.g. after stepi, mention the address if it is in the middle of a line
+            Some(line) if !debug_data.is_line_start(rip) => println!("Stopped at {:#x} ({})", rip, line),
+            Some(line) => println!("Stopped at {}", line),
+            None => println!("Stopped at {:#x}", rip),
+        }
+    }
+
+    /// Returns the debugging info covering code at `addr`: that of the shared library the address
+    /// is in, if the library has any, or else the program's own.
+    fn debug_data_for(&self, addr: usize) -> &DwarfData {
+        self.shared_libraries
+            .find(addr)
+            .and_then(|lib| lib.debug_data())
+            .unwrap_or(&self.debug_data)
+    }
+
+    fn print_shared_libraries(&mut self) {
+        if let Some(inferior) = &self.inferior {
+            self.shared_libraries.update(inferior.as_ref(), self.debug_data.load_bias());
+        }
+        let libraries = self.shared_libraries.libraries();
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!("From                To                  Syms Read   Shared Object Library");
+        for lib in libraries {
+            let syms_read = if !lib.has_debug_info() {
+                "Yes (*)"
+            } else if lib.symbols_loaded() {
+                "Yes"
+            } else {
+                "No"
+            };
+            println!("{:#018x}  {:#018x}  {:<11} {}", lib.from, lib.to, syms_read, lib.path);
+        }
+        if libraries.iter().any(|lib| !lib.has_debug_info()) {
+            println!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Unwinds the stack of the current thread, innermost frame first. The unwinder is reused
+    /// until the inferior maps or unmaps object files.
+    fn frames(&mut self) -> Vec<Frame> {
+        let target: &dyn Target = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.as_ref(),
+            (None, Some(core)) => core,
+            (None, None) => return Vec::new(),
+        };
+        if !self.unwinder.as_ref().map_or(false, |unwinder| unwinder.is_current(target)) {
+            self.unwinder = Some(Unwinder::new(target));
+        }
+        self.unwinder.as_ref().unwrap().frames(target)
+    }
+
+    /// Returns where the function of frame `level` returns to in its caller, and the CFA of the
+    /// frame, which is where the stack pointer is once it has returned.
+    fn return_point(&mut self, level: usize) -> Option<(usize, usize)> {
+        let frames = self.frames();
+        let frame = frames.get(level)?;
+        let caller = frames.get(level + 1)?;
+        Some((caller.pc, frame.cfa))
+    }
+
+    /// Returns the frame chosen with `frame`, `up` or `down`.
+    fn selected_frame(&mut self) -> Option<Frame> {
+        self.frames().into_iter().nth(self.selected_frame)
+    }
+
+    /// Selects frame `level` of the current thread's stack and prints it.
+    fn select_frame(&mut self, level: usize) {
+        let frames = self.frames();
+        if frames.is_empty() {
+            println!("No stack.");
+            return;
+        }
+        match frames.get(level) {
+            Some(frame) => {
+                self.selected_frame = level;
+                self.listing = None;
+                println!("{}", self.describe_frame(frame));
+            }
+            None => println!("No frame at level {}.", level),
+        }
+    }
+
+    fn print_backtrace(&mut self) {
+        for frame in self.frames() {
+            println!("{}", self.describe_frame(&frame));
+        }
+    }
+
+    /// Returns the stack of the current thread, innermost frame first.
+    pub fn stack_frames(&mut self) -> Vec<FrameInfo> {
+        self.frames()
+            .iter()
+            .map(|frame| {
+                let addr = frame.code_addr();
+                let debug_data = self.debug_data_for(addr);
+                let function = match debug_data.get_function_containing(addr) {
+                    Some(func) => Some(func.name.clone()),
+                    None => self
+                        .unwinder
+                        .as_ref()
+                        .and_then(|unwinder| unwinder.symbol_for(addr))
+                        .map(|(name, _)| name.to_string()),
+                };
+                FrameInfo {
+                    level: frame.level,
+                    pc: frame.pc,
+                    function,
+                    line: debug_data.get_line_from_addr(addr),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the names and values of the arguments, or else the local variables, of frame
+    /// `level` of the current thread.
+    pub fn frame_variables(&mut self, level: usize, args: bool) -> Vec<(String, String)> {
+        let frame = match self.frames().into_iter().nth(level) {
+            Some(frame) => frame,
+            None => return Vec::new(),
+        };
+        match self.debug_data_for(frame.code_addr()).get_function_containing(frame.code_addr()) {
+            Some(func) => func
+                .variables
+                .iter()
+                .filter(|var| var.is_parameter == args)
+                .map(|var| (var.name.clone(), self.read_variable(var, &frame)))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the number and thread ID of each thread of the inferior.
+    pub fn threads(&self) -> Vec<(usize, Pid)> {
+        match &self.inferior {
+            Some(inferior) => inferior.threads().iter().map(|thread| (thread.num, thread.tid)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the number of the thread that `backtrace` and `print` look at.
+    pub fn current_thread(&self) -> Option<usize> {
+        self.inferior.as_ref().map(|inferior| inferior.current_thread_num())
+    }
+
+    /// Describes a stack frame the way backtraces show it, e.g.
+    /// `#1  0x0000000000401196 in main (argc=1, argv=0x7ffe3c9e6ac8) at hello.c:12`. Frames in
+    /// code without debugging info are named after the closest symbol, if there is one.
+    fn describe_frame(&self, frame: &Frame) -> String {
+        let addr = frame.code_addr();
+        let debug_data = self.debug_data_for(addr);
+        let func = debug_data.get_function_containing(addr);
+        let pc = if frame.level > 0 || func.is_none() {
+            format!("{:#018x} in ", frame.pc)
+        } else {
+            String::new()
+        };
+        match (func, self.target()) {
+            (Some(func), Some(_)) => {
+                // like GDB, only scalars are shown in full
+                let args: Vec<String> = func
+                    .variables
+                    .iter()
+                    .filter(|var| var.is_parameter)
+                    .map(|var| match debug_data.types().resolve(&var.entity_type).kind {
+                        TypeKind::Struct(_) | TypeKind::Array { .. } => format!("{}=...", var.name),
+                        _ => format!("{}={}", var.name, self.read_variable(var, frame)),
+                    })
+                    .collect();
+                let line = match debug_data.get_line_from_addr(addr) {
+                    Some(line) => format!(" at {}", line),
+                    None => String::new(),
+                };
+                format!("#{:<3}{}{} ({}){}", frame.level, pc, func.name, args.join(", "), line)
+            }
+            _ => {
+                let symbol = self.unwinder.as_ref().and_then(|unwinder| unwinder.symbol_for(addr));
+                format!("#{:<3}{}{} ()", frame.level, pc, symbol.map_or("??", |(name, _)| name))
+            }
+        }
+    }
+
+    /// Reads the value of a variable in a frame and formats it according to its type.
+    fn read_variable(&self, var: &Variable, frame: &Frame) -> String {
+        let scope = FrameScope {
+            debugger: self,
+            frame: Some(frame.clone()),
+        };
+        match self.variable_place(var, Some(frame)).and_then(|place| expression::format_place(&scope, &place)) {
+            Ok(value) => value,
+            Err(e) => format!("<error: {}>", e),
+        }
+    }
+
+    /// Finds where a variable is as seen from a frame, by evaluating its DWARF location. Without
+    /// a running program only static variables can be found, and the rest are placed at 0, which
+    /// is enough for `ptype`.
+    fn variable_place(&self, var: &Variable, frame: Option<&Frame>) -> Result<Place, String> {
+        let storage = match (self.target(), frame) {
+            (Some(target), Some(frame)) => {
+                let frame_base = self.debug_data_for(frame.code_addr()).get_frame_base(frame.code_addr());
+                var.location.evaluate(target, frame, frame_base)?
+            }
+            _ => Storage::Memory(var.location.static_address().unwrap_or(0)),
+        };
+        let mut place = Place::in_memory(0, var.entity_type.clone());
+        match storage {
+            Storage::Memory(addr) => place.addr = addr,
+            Storage::Value(bytes) => place.value = Some(bytes),
+            Storage::OptimizedOut => place.value = Some(Vec::new()),
+        }
+        Ok(place)
+    }
+
+    /// Prints the value of an expression as seen from the selected frame. A `$register` on its
+    /// own is shown the way `info registers` does.
+    fn print_variable(&mut self, expr: &str) {
+        if let Some(register) = expr.strip_prefix('$').filter(|name| registers::find(name).is_some()) {
+            match self.register_value(register) {
+                Ok(value) => {
+                    let kind = REGISTERS[registers::find(register).unwrap()].kind;
+                    self.print_value(expr, self.format_register(kind, value));
+                }
+                Err(e) => self.report_error(&e),
+            }
+            return;
+        }
+        match self.evaluate(self.selected_frame, expr) {
+            Ok(value) => self.print_value(expr, value),
+            Err(e) => self.report_error(&e),
+        }
+    }
+
+    /// Evaluates an expression as seen from frame `level` of the current thread, and formats its
+    /// value the way `print` shows it.
+    pub fn evaluate(&mut self, level: usize, expr: &str) -> Result<String, String> {
+        let frame = self.frames().into_iter().nth(level);
+        let expression = Expression::parse(expr, self.types_for(frame.as_ref()))?;
+        self.format_expression(frame, &expression)
+    }
+
+    fn print_value(&self, expr: &str, value: String) {
+        println!("{} = {}", expr, value);
+        self.emit("value", vec![("expression", expr.into()), ("value", value.into())]);
+    }
+
+    /// Evaluates an expression in a frame and formats its value. Like GDB, pointers are shown
+    /// with their type, unless they point to a string.
+    fn format_expression(&self, frame: Option<Frame>, expression: &Expression) -> Result<String, String> {
+        let scope = FrameScope { debugger: self, frame };
+        let place = expression.evaluate(&scope)?;
+        let value = expression::format_place(&scope, &place)?;
+        let pointer = matches!(scope.types().resolve(&place.entity_type).kind, TypeKind::Pointer(_));
+        if pointer && !value.contains('"') && !place.is_optimized_out() {
+            Ok(format!("({}) {}", place.entity_type.name, value))
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Adds an expression to print after every stop, and prints it now if the program is
+    /// running. Without one, prints them all.
+    fn add_display(&mut self, expr: Option<String>) {
+        let expr = match expr {
+            Some(expr) => expr,
+            None => {
+                self.show_displays();
+                return;
+            }
+        };
+        let frame = self.selected_frame();
+        let expression = match Expression::parse(&expr, self.types_for(frame.as_ref())) {
+            Ok(expression) => expression,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if self.target().is_some() {
+            self.show_display(frame, self.next_display_num, &expression);
+        }
+        self.displays.push((self.next_display_num, expression));
+        self.next_display_num += 1;
+    }
+
+    /// Deletes the numbered displays, or all of them.
+    fn remove_displays(&mut self, nums: &[usize]) {
+        if nums.is_empty() {
+            self.displays.clear();
+        }
+        for num in nums {
+            match self.displays.iter().position(|(display, _)| display == num) {
+                Some(index) => {
+                    self.displays.remove(index);
+                }
+                None => println!("No display number {}.", num),
+            }
+        }
+    }
+
+    fn print_displays(&self) {
+        if self.displays.is_empty() {
+            println!("There are no auto-display expressions now.");
+            return;
+        }
+        println!("Auto-display expressions now in effect:");
+        println!("Num Enb Expression");
+        for (num, expression) in &self.displays {
+            println!("{}:   y  {}", num, expression);
+        }
+    }
+
+    /// Prints the `display` expressions as seen from the selected frame.
+    fn show_displays(&mut self) {
+        if self.displays.is_empty() {
+            return;
+        }
+        let frame = self.selected_frame();
+        for (num, expression) in &self.displays {
+            self.show_display(frame.clone(), *num, expression);
+        }
+    }
+
+    fn show_display(&self, frame: Option<Frame>, num: usize, expression: &Expression) {
+        match self.format_expression(frame, expression) {
+            Ok(value) => println!("{}: {} = {}", num, expression, value),
+            Err(e) => println!("{}: {} = <error: {}>", num, expression, e),
+        }
+    }
+
+    /// Prints the type of an expression, or spells out a type given by name, for `ptype` (with
+    /// `expand`) and `whatis`.
+    fn print_type(&mut self, expr: &str, expand: bool) {
+        let frame = self.selected_frame();
+        let named = self.all_debug_data().find_map(|debug_data| Some((debug_data, debug_data.types().find(expr)?)));
+        let types = self.types_for(frame.as_ref());
+        let (types, ty) = match named {
+            Some((debug_data, ty)) => (debug_data.types(), Ok(ty.clone())),
+            None => match expression::parse_type(expr, types) {
+                Some(ty) => (types, ty),
+                None => {
+                    let scope = FrameScope { debugger: self, frame };
+                    (types, Expression::parse(expr, types).and_then(|expression| expression.evaluate_type(&scope)))
+                }
+            },
+        };
+        let ty = match ty {
+            Ok(ty) => ty,
+            Err(e) => {
+                self.report_error(&e);
+                return;
+            }
+        };
+        if expand {
+            println!("type = {}", types.ptype(&ty));
+        } else if let (Some(_), TypeKind::Typedef(target)) = (named, &ty.kind) {
+            // like GDB, `whatis` on a typedef's name unrolls one level of it
+            println!("type = {}", types.declaration(types.get(*target), ""));
+        } else {
+            println!("type = {}", ty.name);
+        }
+    }
+
+    /// Returns the types of the code the given frame is in, or the program's own without one.
+    fn types_for(&self, frame: Option<&Frame>) -> &Types {
+        self.debug_data_for(frame.map_or(0, |frame| frame.code_addr())).types()
+    }
+
+    /// Prints the arguments (if `args` is set) or the local variables of the selected frame.
+    fn print_frame_variables(&mut self, args: bool) {
+        if self.target().is_none() {
+            println!("No inferior running");
+            return;
+        }
+        let frame = match self.selected_frame() {
+            Some(frame) => frame,
+            None => {
+                println!("No stack.");
+                return;
+            }
+        };
+        let func = match self.debug_data_for(frame.code_addr()).get_function_containing(frame.code_addr()) {
+            Some(func) => func,
+            None => {
+                println!("No symbol table info available.");
+                return;
+            }
+        };
+        let mut found = false;
+        for var in func.variables.iter().filter(|var| var.is_parameter == args) {
+            println!("{} = {}", var.name, self.read_variable(var, &frame));
+            found = true;
+        }
+        if !found {
+            println!("{}", if args { "No arguments." } else { "No locals." });
+        }
+    }
+
+    /// Works out the address an `x` or `set *ADDR` command refers to: the value of an expression,
+    /// where an array stands for the address of its first element, as in C.
+    fn evaluate_address(&mut self, expr: &str) -> Result<usize, String> {
+        if self.target().is_none() {
+            return Err("The program is not being run.".to_string());
+        }
+        let frame = self.selected_frame();
+        let scope = FrameScope { debugger: self, frame };
+        let place = Expression::parse(expr, scope.types())?.evaluate(&scope)?;
+        expression::address_value(&scope, &place)
+    }
+
+    /// Prints memory in the format of an `x/Nfu` command. Without an address, carries on from
+    /// where the last `x` stopped.
+    fn examine_memory(&mut self, examine_format: ExamineFormat, expr: Option<String>) {
+        if let Some(format) = examine_format.format {
+            self.examine_format = format;
+        }
+        if let Some(unit) = examine_format.unit {
+            self.examine_unit = unit;
+        }
+        let addr = match (expr, self.next_examine_addr) {
+            (Some(expr), _) => match self.evaluate_address(&expr) {
+                Ok(addr) => addr,
+                Err(e) => {
+                    self.report_error(&e);
+                    return;
+                }
+            },
+            (None, Some(addr)) => addr,
+            (None, None) => {
+                self.report_error("Argument required (starting display address).");
+                return;
+            }
+        };
+        if self.examine_format == 'i' {
+            self.next_examine_addr = Some(self.examine_instructions(addr, examine_format.count));
+            return;
+        }
+        let target = match self.target() {
+            Some(target) => target,
+            None => {
+                self.report_error("The program is not being run.");
+                return;
+            }
+        };
+        let next_addr = match self.examine_format {
+            's' => examine_strings(target, addr, examine_format.count),
+            format => examine_units(target, addr, examine_format.count, format, self.examine_unit),
+        };
+        self.next_examine_addr = Some(next_addr);
+    }
+
+    /// Prints `count` instructions starting at `addr`, marking the one the selected frame is
+    /// executing. Returns the address after the last one.
+    fn examine_instructions(&mut self, addr: usize, count: usize) -> usize {
+        let pc = self.selected_frame().map(|frame| frame.pc);
+        let mut addr = addr;
+        for _ in 0..count {
+            let instruction = match self.decode_instruction(addr) {
+                Some(instruction) => instruction,
+                None => {
+                    self.report_error(&format!("Cannot access memory at address {:#x}", addr));
+                    return addr;
+                }
+            };
+            let marker = if pc == Some(addr) { "=> " } else { "   " };
+            let location = match self.symbolize(addr) {
+                Some(symbol) => format!("{:#x} <{}>", addr, symbol),
+                None => format!("{:#x}", addr),
+            };
+            println!("{}{}:\t{}", marker, location, self.format_instruction(&instruction));
+            addr += instruction.len;
+        }
+        addr
+    }
+
+    /// Reads code from the program, with the original bytes in place of the 0xcc of installed
+    /// breakpoints.
+    fn read_code(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let target = self.target()?;
+        let mut bytes = target
+            .read_bytes(addr, len)
+            // the code may end less than `len` bytes before the end of its mapping
+            .or_else(|_| target.read_bytes(addr, len.min((addr | 0xfff) + 1 - addr)))
+            .ok()?;
+        for breakpoint in self.breakpoints.values() {
+            if let Some(orig_byte) = breakpoint.orig_byte {
+                if breakpoint.addr >= addr && breakpoint.addr < addr + bytes.len() {
+                    bytes[breakpoint.addr - addr] = orig_byte;
+                }
+            }
+        }
+        Some(bytes)
+    }
+
+    fn decode_instruction(&self, addr: usize) -> Option<Instruction> {
+        let bytes = self.read_code(addr, disassembler::MAX_LEN)?;
+        Some(disassembler::decode(&bytes, addr))
+    }
+
+    /// Formats an instruction with the names of the functions its operands point into, e.g.
+    /// `call   0x401126 <add>` or `lea    0xec8(%rip),%rax        # 0x402004`.
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        let mut text = instruction.text.clone();
+        if let Some(symbol) = instruction.target.and_then(|target| self.symbolize(target)) {
+            text.push_str(&format!(" <{}>", symbol));
+        }
+        if let Some(reference) = instruction.reference {
+            text.push_str(&format!("        # {:#x}", reference));
+            if let Some(symbol) = self.symbolize(reference) {
+                text.push_str(&format!(" <{}>", symbol));
+            }
+        }
+        text
+    }
+
+    /// Returns the name and address range of the function containing `addr`, from the debugging
+    /// info or else the symbol table.
+    fn function_bounds(&self, addr: usize) -> Option<(String, usize, usize)> {
+        if let Some(func) = self.debug_data_for(addr).get_function_containing(addr) {
+            return Some((func.name.clone(), func.address, func.address + func.text_length));
+        }
+        let (name, start, end) = self.unwinder.as_ref()?.symbol_bounds(addr)?;
+        if end > start {
+            Some((name.to_string(), start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Works out a code address given as a function name, or as anything `x` accepts.
+    fn code_address(&mut self, expr: &str) -> Result<usize, String> {
+        let func_addr = self
+            .all_debug_data()
+            .find_map(|debug_data| debug_data.get_addr_for_function(None, expr));
+        match func_addr {
+            Some(addr) => Ok(addr),
+            None => self.evaluate_address(expr),
+        }
+    }
+
+    /// Works out what a `disassemble` command covers: the function around the selected frame's
+    /// pc, a function named by `spec` or containing the address it gives, or a `START,END` or
+    /// `START,+LENGTH` range. Returns the range along with the function name, if it is one.
+    fn disassembly_range(&mut self, spec: Option<&str>, pc: Option<usize>) -> Result<(usize, usize, Option<String>), String> {
+        let spec = match spec {
+            Some(spec) => spec,
+            None => {
+                let pc = pc.ok_or_else(|| "No frame selected.".to_string())?;
+                let (name, start, end) = self
+                    .function_bounds(pc)
+                    .ok_or_else(|| "No function contains program counter for selected frame.".to_string())?;
+                return Ok((start, end, Some(name)));
+            }
+        };
+        if let Some((start, end)) = spec.split_once(',') {
+            let start = self.code_address(start.trim())?;
+            let end = match end.trim().strip_prefix('+') {
+                Some(len) => start + utils::parse_integer(len.trim()).ok_or_else(|| format!("Invalid number \"{}\".", len))?,
+                None => self.code_address(end.trim())?,
+            };
+            return Ok((start, end, None));
+        }
+        let addr = self.code_address(spec)?;
+        let (name, start, end) = self
+            .function_bounds(addr)
+            .ok_or_else(|| "No function contains specified address.".to_string())?;
+        Ok((start, end, Some(name)))
+    }
+
+    /// Prints the instructions of a function or address range along with the source lines they
+    /// were compiled from, marking the instruction the selected frame is executing with `=>`.
+    /// With `raw`, the bytes of each instruction are shown too.
+    fn disassemble(&mut self, raw: bool, spec: Option<String>) {
+        if self.target().is_none() {
+            self.report_error("The program is not being run.");
+            return;
+        }
+        let pc = self.selected_frame().map(|frame| frame.pc);
+        let (start, end, function) = match self.disassembly_range(spec.as_deref(), pc) {
+            Ok(range) => range,
+            Err(e) => {
+                self.report_error(&e);
+                return;
+            }
+        };
+        match &function {
+            Some(name) => println!("Dump of assembler code for function {}:", name),
+            None => println!("Dump of assembler code from {:#x} to {:#x}:", start, end),
+        }
+        let mut last_line: Option<(String, usize)> = None;
+        let mut addr = start;
+        while addr < end {
+            if let Some(line) = self.debug_data_for(start).get_line_from_addr(addr) {
+                if last_line.as_ref() != Some(&(line.file.clone(), line.number)) {
+                    if last_line.as_ref().map(|(file, _)| file) != Some(&line.file) {
+                        println!("{}:", line.file);
+                    }
+                    println!("{}\t{}", line.number, self.sources.line(&line.file, line.number).unwrap_or(""));
+                    last_line = Some((line.file, line.number));
+                }
+            }
+            let instruction = match self.decode_instruction(addr) {
+                Some(instruction) => instruction,
+                None => {
+                    println!("Cannot access memory at address {:#x}", addr);
+                    return;
+                }
+            };
+            let marker = if pc == Some(addr) { "=> " } else { "   " };
+            let location = match (&function, self.symbolize(addr)) {
+                (Some(_), _) => format!("<+{}>", addr - start),
+                (None, Some(symbol)) => format!("<{}>", symbol),
+                (None, None) => String::new(),
+            };
+            let bytes = if raw {
+                let bytes = self.read_code(addr, instruction.len).unwrap_or_default();
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("{}\t", hex.join(" "))
+            } else {
+                String::new()
+            };
+            println!("{}{:#018x} {}:\t{}{}", marker, addr, location, bytes, self.format_instruction(&instruction));
+            addr += instruction.len;
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// The program's debugging info followed by that of each loaded shared library.
+    fn all_debug_data(&self) -> impl Iterator<Item = &DwarfData> {
+        std::iter::once(&self.debug_data)
+            .chain(self.shared_libraries.libraries().iter().filter_map(|lib| lib.debug_data()))
+    }
+
+    /// Returns the source line `list` starts from when it isn't told where: that of the selected
+    /// frame, or the start of main before the program runs.
+    fn default_source_line(&mut self) -> Result<Line, String> {
+        if let Some(frame) = self.selected_frame() {
+            let addr = frame.code_addr();
+            if let Some(line) = self.debug_data_for(addr).get_line_from_addr(addr) {
+                return Ok(line);
+            }
+        }
+        self.debug_data
+            .get_function_line(None, "main")
+            .ok_or_else(|| "No default source file.".to_string())
+    }
+
+    /// Works out which file and lines a `list` command shows. Accepts `LINE`, `FUNC`,
+    /// `FILE:LINE` and `FILE:FUNC`, which list the ten lines around that line, `-`, which lists
+    /// the ten before the last listing, and nothing, which continues after it.
+    fn source_range(&mut self, spec: Option<&str>) -> Result<(String, usize, usize), String> {
+        let around = |number: usize| {
+            let first = number.saturating_sub(5).max(1);
+            (first, first + 9)
+        };
+        let (file, number) = match spec {
+            None => {
+                if let Some((file, _, last)) = &self.listing {
+                    return Ok((file.clone(), last + 1, last + 10));
+                }
+                let line = self.default_source_line()?;
+                (line.file, line.number)
+            }
+            Some("-") => {
+                let (file, first) = match &self.listing {
+                    Some((file, first, _)) => (file.clone(), *first),
+                    None => {
+                        let line = self.default_source_line()?;
+                        (line.file, around(line.number).0)
+                    }
+                };
+                if first <= 1 {
+                    return Err(format!("Already at the start of {}.", file));
+                }
+                return Ok((file, first.saturating_sub(10).max(1), first - 1));
+            }
+            Some(spec) => {
+                let (file, location) = match spec.rsplit_once(':') {
+                    Some((file, location)) => (Some(file), location),
+                    None => (None, spec),
+                };
+                if let Ok(number) = location.parse::<usize>() {
+                    let path = match (file, &self.listing) {
+                        (Some(file), _) => self
+                            .all_debug_data()
+                            .find_map(|debug_data| debug_data.get_source_path(file))
+                            .ok_or_else(|| format!("No source file named {}.", file))?,
+                        (None, Some((path, _, _))) => path.clone(),
+                        (None, None) => self.default_source_line()?.file,
+                    };
+                    (path, number)
+                } else {
+                    let line = self
+                        .all_debug_data()
+                        .find_map(|debug_data| debug_data.get_function_line(file, location))
+                        .ok_or_else(|| format!("Function \"{}\" not defined.", location))?;
+                    (line.file, line.number)
+                }
+            }
+        };
+        let (first, last) = around(number);
+        Ok((file, first, last))
+    }
+
+    /// Prints source lines for a `list` command. The line the selected frame is at is marked
+    /// with `>`, and lines with breakpoints with `B`, or `b` if they are all disabled.
+    fn list_source(&mut self, spec: Option<&str>) {
+        let (file, first, last) = match self.source_range(spec) {
+            Ok(range) => range,
+            Err(e) => {
+                self.report_error(&e);
+                return;
+            }
+        };
+        let current = self
+            .selected_frame()
+            .and_then(|frame| {
+                let addr = frame.code_addr();
+                self.debug_data_for(addr).get_line_from_addr(addr)
+            })
+            .filter(|line| line.file == file)
+            .map(|line| line.number);
+        // line number -> whether any of its breakpoints is enabled
+        let mut breakpoint_lines: HashMap<usize, bool> = HashMap::new();
+        for breakpoint in self.breakpoints.values() {
+            if let Some(line) = self.debug_data_for(breakpoint.addr).get_line_from_addr(breakpoint.addr) {
+                if line.file == file {
+                    *breakpoint_lines.entry(line.number).or_insert(false) |= breakpoint.enabled;
+                }
+            }
+        }
+        let lines = match self.sources.lines(&file) {
+            Some(lines) => lines,
+            None => {
+                self.report_error(&format!("{}: No such file or directory.", file));
+                return;
+            }
+        };
+        if first > lines.len() {
+            let message = format!("Line number {} out of range; \"{}\" has {} lines.", first, file, lines.len());
+            self.report_error(&message);
+            return;
+        }
+        let last = last.min(lines.len());
+        for number in first..=last {
+            let breakpoint = match breakpoint_lines.get(&number) {
+                Some(true) => 'B',
+                Some(false) => 'b',
+                None => ' ',
+            };
+            let marker = if current == Some(number) { '>' } else { ' ' };
+            println!("{}{} {}\t{}", breakpoint, marker, number, lines[number - 1]);
+        }
+        self.listing = Some((file, first, last));
+    }
+
+    /// Adds directories to search for source files in, or with none, goes back to searching
+    /// only where the debugging info says the files are.
+    fn set_source_directories(&mut self, dirs: &[String]) {
+        if dirs.is_empty() {
+            self.sources.reset_directories();
+        } else {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let dirs: Vec<PathBuf> = dirs.iter().map(|dir| cwd.join(dir)).collect();
+            for dir in dirs.iter().filter(|dir| !dir.is_dir()) {
+                println!("Warning: {}: No such file or directory.", dir.display());
+            }
+            self.sources.add_directories(&dirs);
+        }
+        println!("Source directories searched: {}", self.sources.search_path());
+    }
+
+    /// Returns the general-purpose registers as seen from the selected frame, in the order of
+    /// `REGISTERS`.
+    fn frame_registers(&mut self) -> Result<Vec<Option<u64>>, String> {
+        if self.selected_frame == 0 {
+            return self.registers_in(None);
+        }
+        let frame = self.selected_frame().ok_or_else(|| "No stack.".to_string())?;
+        self.registers_in(Some(&frame))
+    }
+
+    /// Returns the general-purpose registers as seen from a frame, or the innermost one. Outer
+    /// frames only have the registers the unwinder could recover.
+    fn registers_in(&self, frame: Option<&Frame>) -> Result<Vec<Option<u64>>, String> {
+        let target = match self.target() {
+            Some(target) => target,
+            None => return Err("The program has no registers now.".to_string()),
+        };
+        match frame {
+            Some(frame) if frame.level > 0 => Ok(REGISTERS
+                .iter()
+                .map(|register| register.dwarf.and_then(|num| frame.register(num)))
+                .collect()),
+            _ => {
+                let regs = target.get_registers().map_err(|e| format!("Error: {:?}", e))?;
+                Ok(REGISTERS.iter().map(|register| Some((register.get)(&regs))).collect())
+            }
+        }
+    }
+
+    /// Reads a register by name, e.g. `rax` or `pc`, as seen from the selected frame.
+    fn register_value(&mut self, name: &str) -> Result<u64, String> {
+        let index = registers::find(name).ok_or_else(|| format!("Invalid register \"{}\".", name))?;
+        self.frame_registers()?[index].ok_or_else(|| format!("${} was not saved in this frame.", name))
+    }
+
+    /// Changes a register of the current thread. Only the innermost frame's registers are real;
+    /// those of outer frames are only reconstructed.
+    fn set_register(&mut self, name: &str, value: &str) {
+        let index = match registers::find(name) {
+            Some(index) => index,
+            None => {
+                println!("Invalid register \"{}\".", name);
+                return;
+            }
+        };
+        if self.selected_frame != 0 {
+            println!("Registers can only be changed in the innermost frame.");
+            return;
+        }
+        let value = match utils::parse_integer(value)
+            .map(|value| value as u64)
+            .or_else(|| utils::parse_number(value).map(|value| value as i64 as u64))
+        {
+            Some(value) => value,
+            None => {
+                println!("Invalid number \"{}\".", value);
+                return;
+            }
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let result = inferior.get_registers().and_then(|mut regs| {
+            (REGISTERS[index].set)(&mut regs, value);
+            inferior.set_registers(regs)
+        });
+        if let Err(e) = result {
+            println!("Could not set ${}: {:?}", name, e);
+        }
+    }
+
+    /// Formats a register's value the way `info registers` shows it next to the hex value.
+    fn format_register(&self, kind: RegisterKind, value: u64) -> String {
+        match kind {
+            RegisterKind::Integer => (value as i64).to_string(),
+            RegisterKind::DataPointer => format!("{:#x}", value),
+            RegisterKind::CodePointer => match self.symbolize(value as usize) {
+                Some(symbol) => format!("{:#x} <{}>", value, symbol),
+                None => format!("{:#x}", value),
+            },
+            RegisterKind::Flags => registers::format_eflags(value),
+        }
+    }
+
+    /// Describes a code address as `func+offset`.
+    fn symbolize(&self, addr: usize) -> Option<String> {
+        let (name, start) = match self.debug_data_for(addr).get_function_containing(addr) {
+            Some(func) => (func.name.as_str(), func.address),
+            None => self.unwinder.as_ref()?.symbol_for(addr)?,
+        };
+        Some(if addr == start { name.to_string() } else { format!("{}+{}", name, addr - start) })
+    }
+
+    /// Prints the named registers, or all general-purpose ones, as seen from the selected frame.
+    /// With `all`, the x87 and SSE registers follow.
+    fn print_registers(&mut self, names: &[String], all: bool) {
+        let values = match self.frame_registers() {
+            Ok(values) => values,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let indices: Vec<usize> = if names.is_empty() {
+            (0..REGISTERS.len()).collect()
+        } else {
+            match names.iter().map(|name| registers::find(name).ok_or(name)).collect() {
+                Ok(indices) => indices,
+                Err(name) => {
+                    println!("Invalid register \"{}\".", name);
+                    return;
+                }
+            }
+        };
+        for index in indices {
+            let register = &REGISTERS[index];
+            match values[index] {
+                Some(value) => println!(
+                    "{:<15}{:<19}{}",
+                    register.name,
+                    format!("{:#x}", value),
+                    self.format_register(register.kind, value)
+                ),
+                None => println!("{:<15}<not saved>", register.name),
+            }
+        }
+        if !all {
+            return;
+        }
+        if self.selected_frame != 0 {
+            println!("The floating point registers are only available in the innermost frame.");
+            return;
+        }
+        match self.target().unwrap().get_fp_registers() {
+            Ok(fpregs) => {
+                for (name, value, natural) in registers::fp_registers(&fpregs) {
+                    println!("{:<15}{:<19}{}", name, value, natural);
+                }
+            }
+            Err(e) => println!("Could not read the floating point registers: {:?}", e),
+        }
+    }
+
+    /// Assigns the value of an expression to an lvalue of the selected frame, to the int at
+    /// `*ADDR`, or to a `$register`, converting it to the type of what it is assigned to.
+    fn set_variable(&mut self, lhs: &str, value: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        if let Some(name) = lhs.strip_prefix('$') {
+            self.set_register(name, value);
+            return;
+        }
+        let frame = self.selected_frame();
+        let (place, mut bytes) = match self.evaluate_assignment(frame, lhs, value) {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let addr = place.addr;
+        if let Some((shift, width)) = place.bits {
+            // a bit field shares its bytes with its neighbours, which have to be kept
+            let len = bit_field_len((shift, width));
+            let old = match self.inferior.as_ref().unwrap().read_bytes(addr, len) {
+                Ok(old) => Type::decode_integer(&old).0,
+                Err(_) => {
+                    println!("Cannot access memory at address {:#x}", addr);
+                    return;
+                }
+            };
+            let mask = bit_mask(width) << shift;
+            let new = ((Type::decode_integer(&bytes).0 << shift) & mask) | (old & !mask);
+            bytes = new.to_le_bytes()[..len].to_vec();
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        if inferior.write_bytes(addr, &bytes).is_err() {
+            println!("Cannot access memory at address {:#x}", addr);
+            return;
+        }
+        // the debug registers don't see our own writes, so watchpoints would report a stale old value
+        for watchpoint in &mut self.watchpoints {
+            if watchpoint.addr < addr + bytes.len() && addr < watchpoint.addr + watchpoint.entity_type.size {
+                watchpoint.old_value = inferior.read_bytes(watchpoint.addr, watchpoint.entity_type.size).unwrap_or_default();
+            }
+        }
+    }
+
+    /// Works out where an assignment writes and the bytes it writes there.
+    fn evaluate_assignment(&self, frame: Option<Frame>, lhs: &str, value: &str) -> Result<(Place, Vec<u8>), String> {
+        let scope = FrameScope { debugger: self, frame };
+        let types = scope.types();
+        // `*ADDR` writes an int, like GDB does for addresses without a type
+        let raw_address = lhs
+            .strip_prefix('*')
+            .map(|addr| addr.trim())
+            .filter(|addr| utils::parse_integer(addr).is_some() || addr.starts_with('$'));
+        let place = match raw_address {
+            Some(addr) => {
+                let addr = expression::address_value(&scope, &Expression::parse(addr, types)?.evaluate(&scope)?)?;
+                Place::in_memory(addr, Type::new("int".to_string(), 4, Encoding::Signed))
+            }
+            None => Expression::parse(lhs, types)?.evaluate(&scope)?,
+        };
+        if place.value.is_some() {
+            return Err("Left operand of assignment is not an lvalue.".to_string());
+        }
+        let value = Expression::parse(value, types)?.evaluate(&scope)?;
+        let bytes = expression::convert(&scope, value, &place.entity_type)?;
+        Ok((place, bytes))
+    }
+
+    /// Runs the lines of a script file next, before anything else is read.
+    pub fn source_file(&mut self, path: &str) {
+        match std::fs::read_to_string(path) {
+            Ok(script) => {
+                for line in script.lines().rev() {
+                    self.pending_lines.push_front((line.to_string(), 0));
+                }
+            }
+            Err(e) => println!("{}: {}.", path, e),
+        }
+    }
+
+    /// Makes deet exit once the scripts it was given have run, instead of prompting.
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+    }
+
+    /// True if the next line will come from the user at the terminal.
+    fn interactive(&self) -> bool {
+        self.pending_lines.is_empty() && !self.batch && self.events.is_none()
+    }
+
+    /// Returns the next line to run: a pending script line if there is one, or else a line the
+    /// user types. Returns None at the end of the input, which in batch mode is the end of the
+    /// scripts.
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        if let Some((line, depth)) = self.pending_lines.pop_front() {
+            self.command_depth = depth;
+            return Some(line);
+        }
+        self.command_depth = 0;
+        if self.batch {
+            return None;
+        }
+        if self.events.is_some() {
+            return self.read_request();
+        }
+        loop {
+            match self.readline.readline(prompt) {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return None;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        self.readline.add_history_entry(line.as_str());
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
+                    }
+                    return Some(line);
+                }
+            }
+        }
+    }
+
+    /// Switches to `--interpreter=json`: requests are read as JSON objects, one per line, and
+    /// events about what happens are written to standard output as JSON objects, one per line.
+    /// The usual messages, and the output of the program, go to standard error, and the program
+    /// reads from /dev/null so that it can't take the client's requests.
+    pub fn use_json_interpreter(&mut self) {
+        self.requests = Some(BufReader::new(utils::take_stdin()));
+        self.events = Some(EventSink::Stream(utils::take_stdout()));
+    }
+
+    /// Keeps events for the caller to collect with `take_events`, rather than writing them out.
+    pub fn queue_events(&mut self) {
+        self.events = Some(EventSink::Queue(RefCell::new(Vec::new())));
+    }
+
+    /// Returns the events queued since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<Json> {
+        match &self.events {
+            Some(EventSink::Queue(queue)) => queue.take(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Reads the next JSON request from the client and returns its command line. A request
+    /// is an object like `{"id": 1, "command": "break", "args": ["main"]}`; `args` is optional,
+    /// and `command` can hold the whole line, or several lines. Returns None at the end of input.
+    fn read_request(&mut self) -> Option<String> {
+        loop {
+            let mut text = String::new();
+            match self.requests.as_mut()?.read_line(&mut text) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => panic!("Unexpected I/O error: {:?}", err),
+            }
+            if text.trim().is_empty() {
+                continue;
+            }
+            let request = match Json::parse(&text) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.report_error(&format!("Invalid request: {}", e));
+                    continue;
+                }
+            };
+            self.request_id = Some(request.get("id").cloned().unwrap_or(Json::Null));
+            let mut line = match request.get("command").and_then(Json::as_str) {
+                Some(command) => command.to_string(),
+                None => {
+                    self.report_error("Request has no command");
+                    self.finish_request();
+                    continue;
+                }
+            };
+            match request.get("args") {
+                Some(Json::Array(args)) => {
+                    for arg in args {
+                        match arg {
+                            Json::String(text) => line = format!("{} {}", line, text),
+                            value => line = format!("{} {}", line, value),
+                        }
+                    }
+                }
+                Some(_) => {
+                    self.report_error("Request args must be an array");
+                    self.finish_request();
+                    continue;
+                }
+                None => {}
+            }
+            let mut lines = line.lines().map(str::to_string);
+            let first = lines.next().unwrap_or_default();
+            for (i, line) in lines.enumerate() {
+                self.pending_lines.insert(i, (line, 0));
+            }
+            return Some(first);
+        }
+    }
+
+    /// Writes an event for `--interpreter=json`, tagged with the id of the request that caused
+    /// it. Does nothing otherwise.
+    fn emit(&self, event: &str, fields: Vec<(&str, Json)>) {
+        let events = match &self.events {
+            Some(events) => events,
+            None => return,
+        };
+        let mut members = vec![("event", event.into())];
+        if let Some(id) = &self.request_id {
+            members.push(("id", id.clone()));
+        }
+        members.extend(fields);
+        match events {
+            // the client may be gone, which is no reason to stop debugging
+            EventSink::Stream(stream) => {
+                let mut stream: &File = stream;
+                writeln!(stream, "{}", Json::object(members)).ok();
+            }
+            EventSink::Queue(queue) => queue.borrow_mut().push(Json::object(members)),
+        }
+    }
+
+    /// Emits a `stopped` event, with the thread and the frame the program stopped in.
+    fn emit_stopped(&self, rip: usize, mut fields: Vec<(&str, Json)>) {
+        if self.events.is_none() {
+            return;
+        }
+        let debug_data = self.debug_data_for(rip);
+        let line = debug_data.get_line_from_addr(rip);
+        let frame = Json::object(vec![
+            ("addr", format!("{:#x}", rip).into()),
+            ("func", debug_data.get_function_from_addr(rip).into()),
+            ("file", line.as_ref().map(|line| line.file.clone()).into()),
+            ("line", line.map(|line| line.number).into()),
+        ]);
+        let thread = self.inferior.as_ref().map(|inferior| inferior.current_thread_num());
+        fields.push(("thread", thread.into()));
+        fields.push(("frame", frame));
+        self.emit("stopped", fields);
+    }
+
+    /// Prints an error message, and sends it as an `error` event to a JSON client.
+    fn report_error(&self, message: &str) {
+        println!("{}", message);
+        self.emit("error", vec![("message", message.into())]);
+    }
+
+    /// Tells a JSON client that its request has been carried out, with everything it caused.
+    fn finish_request(&mut self) {
+        if self.request_id.is_some() {
+            self.emit("done", Vec::new());
+            self.request_id = None;
+        }
+    }
+
+    /// Reads the body of a `define` or `commands`, up to the matching `end`. Blocks can nest.
+    fn read_block(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut depth = 0;
+        while let Some(line) = self.read_line(">") {
+            let line = line.trim();
+            match line.split_whitespace().next() {
+                Some("end") if depth == 0 => break,
+                Some("end") => depth -= 1,
+                Some("define") | Some("commands") => depth += 1,
+                _ => {}
+            }
+            lines.push(line.to_string());
+        }
+        lines
+    }
+
+    /// Queues the commands of a breakpoint that just stopped the program.
+    fn run_breakpoint_commands(&mut self, num: usize) {
+        if let Some(breakpoint) = self.breakpoints.values().find(|bp| bp.num == num) {
+            for line in breakpoint.commands.iter().rev() {
+                self.pending_lines.push_front((line.clone(), 0));
+            }
+        }
+    }
+
+    /// Returns the next command to run, or Quit at the end of the input. Lines come from the
+    /// pending ones first (scripts, the `commands` of a breakpoint that was just hit, the rest of
+    /// a multi-line request) and then from the terminal or the JSON client. Blank lines and `#`
+    /// comments are skipped, and a command made with `define` is replaced by its body, with
+    /// `$arg0`, `$arg1`... and `$argc` standing for its arguments. A request that doesn't end in
+    /// a command, such as an unknown one, is finished here.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // a request that came to nothing, such as an unknown command, is done too
+            if self.pending_lines.is_empty() {
+                self.finish_request();
+            }
+            let line = match self.read_line("(deet) ") {
+                Some(line) => line,
+                None => return DebuggerCommand::Quit,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                return cmd;
+            }
+            let body = match self.user_commands.get(tokens[0]) {
+                Some(body) => body.clone(),
+                None => {
+                    self.report_error("Unrecognized command.");
+                    continue;
+                }
+            };
+            let depth = self.command_depth + 1;
+            if depth > MAX_USER_CALL_DEPTH {
+                // the outermost user command is abandoned, with everything it has yet to run
+                while matches!(self.pending_lines.front(), Some((_, depth)) if *depth > 0) {
+                    self.pending_lines.pop_front();
+                }
+                self.report_error("Max user call depth exceeded -- command aborted.");
+                continue;
+            }
+            // $arg0, $arg1... stand for the arguments, and $argc for how many there are
+            let args = &tokens[1..];
+            for line in body.iter().rev() {
+                let mut line = line.replace("$argc", &args.len().to_string());
+                for (i, arg) in args.iter().enumerate().rev() {
+                    line = line.replace(&format!("$arg{}", i), arg);
+                }
+                self.pending_lines.push_front((line, depth));
+            }
+        }
+    }
+}
+
+/// Prints `count` units of `unit` bytes starting at `addr`, several to a line, as hex (x),
+/// signed (d) or unsigned (u) integers, or chars (c). Returns the address after the last unit.
+fn examine_units(target: &dyn Target, addr: usize, count: usize, format: char, unit: usize) -> usize {
+    let unit = if format == 'c' { 1 } else { unit };
+    let per_line = match (format, unit) {
+        ('c', _) => 8,
+        (_, 8) => 2,
+        (_, 4) => 4,
+        _ => 8,
+    };
+    let encoding = match format {
+        'd' => Encoding::Signed,
+        'u' => Encoding::Unsigned,
+        'c' => Encoding::SignedChar,
+        _ => Encoding::Address,
+    };
+    let unit_type = Type::new(String::new(), unit, encoding);
+    let mut addr = addr;
+    let mut remaining = count;
+    while remaining > 0 {
+        let units = remaining.min(per_line);
+        let bytes = match target.read_bytes(addr, units * unit) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                println!("Cannot access memory at address {:#x}", addr);
+                return addr;
+            }
+        };
+        let values: Vec<String> = bytes
+            .chunks(unit)
+            .map(|chunk| match format {
+                // hex is padded to the width of the unit
+                'x' => format!("0x{:0>width$}", &unit_type.format_value(chunk)[2..], width = 2 * unit),
+                _ => unit_type.format_value(chunk),
+            })
+            .collect();
+        println!("{:#x}:\t{}", addr, values.join("\t"));
+        addr += units * unit;
+        remaining -= units;
+    }
+    addr
+}
+
+/// Prints `count` NUL-terminated strings starting at `addr`. Returns the address after the last
+/// one.
+fn examine_strings(target: &dyn Target, addr: usize, count: usize) -> usize {
+    // like GDB, long strings are cut off after 200 characters
+    const MAX_STRING: usize = 200;
+    const PAGE_SIZE: usize = 0x1000;
+    let mut addr = addr;
+    for _ in 0..count {
+        let mut text = Vec::new();
+        let terminated = loop {
+            if text.len() == MAX_STRING {
+                break false;
+            }
+            // no further than the end of the page, so that a string ending just before unreadable
+            // memory can still be read
+            let start = addr + text.len();
+            let len = (PAGE_SIZE - start % PAGE_SIZE).min(MAX_STRING - text.len());
+            let chunk = match target.read_bytes(start, len) {
+                Ok(chunk) => chunk,
+                Err(_) if text.is_empty() => {
+                    println!("Cannot access memory at address {:#x}", addr);
+                    return addr;
+                }
+                Err(_) => break false,
+            };
+            if let Some(end) = chunk.iter().position(|byte| *byte == 0) {
+                text.extend_from_slice(&chunk[..end]);
+                break true;
+            }
+            text.extend(chunk);
+        };
+        println!("{:#x}:\t{}{}", addr, utils::format_string(&text), if terminated { "" } else { "..." });
+        addr += if terminated { text.len() + 1 } else { text.len() };
+    }
+    addr
 }
@@ -1,9 +1,94 @@
+use crate::inferior::WatchKind;
+
+#[derive(Debug, PartialEq)]
 pub enum DebuggerCommand {
     Quit,
     Continue,
     Run(Vec<String>),
     Backtrace,
-    Breakpoint(String),
+    Frame(Option<usize>),
+    Up(usize),
+    Down(usize),
+    /// A location, and the condition that follows `if`
+    Breakpoint(String, Option<String>),
+    Step,
+    Next,
+    StepInstruction(usize),
+    NextInstruction(usize),
+    Finish,
+    Print(String),
+    Ptype(String),
+    Whatis(String),
+    InfoLocals,
+    InfoArgs,
+    InfoBreakpoints,
+    Delete(Vec<usize>),
+    Disable(Vec<usize>),
+    Enable(Vec<usize>),
+    Ignore(usize, usize),
+    Condition(usize, Option<String>),
+    Watch(WatchKind, String),
+    Attach(i32),
+    /// Debug a program that a gdbserver controls, at [host]:port
+    TargetRemote(String),
+    Detach,
+    InfoThreads,
+    InfoSharedLibrary,
+    InfoRegisters(Vec<String>),
+    InfoAllRegisters,
+    Thread(Option<usize>),
+    Set(String, String),
+    SetVariable(String, String),
+    Examine(ExamineFormat, Option<String>),
+    Disassemble(bool, Option<String>),
+    List(Option<String>),
+    Directory(Vec<String>),
+    Display(Option<String>),
+    Undisplay(Vec<usize>),
+    InfoDisplay,
+    /// Signals and the `stop`/`print`/`pass` keywords to apply to them, in any order
+    Handle(Vec<String>),
+    InfoSignals(Option<String>),
+    /// Write a core file, to the given path or core.<pid>
+    Gcore(Option<String>),
+    Source(String),
+    /// Define a user command; its body follows on the next lines, up to `end`
+    Define(String),
+    /// Set the commands of a breakpoint, by default the last one created
+    Commands(Option<usize>),
+}
+
+/// The `/Nfu` suffix of an `x` command. Whatever is left out is taken from the previous `x`.
+#[derive(Debug, PartialEq)]
+pub struct ExamineFormat {
+    pub count: usize,
+    /// One of x (hex), d (signed decimal), u (unsigned decimal), c (char), s (string) and
+    /// i (instruction)
+    pub format: Option<char>,
+    /// Size of each unit in bytes: b, h, w and g stand for 1, 2, 4 and 8
+    pub unit: Option<usize>,
+}
+
+impl ExamineFormat {
+    fn parse(spec: &str) -> Option<ExamineFormat> {
+        let digits = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+        let mut examine_format = ExamineFormat {
+            count: if digits == 0 { 1 } else { spec[..digits].parse().ok()? },
+            format: None,
+            unit: None,
+        };
+        for letter in spec[digits..].chars() {
+            match letter {
+                'x' | 'd' | 'u' | 'c' | 's' | 'i' => examine_format.format = Some(letter),
+                'b' => examine_format.unit = Some(1),
+                'h' => examine_format.unit = Some(2),
+                'w' => examine_format.unit = Some(4),
+                'g' => examine_format.unit = Some(8),
+                _ => return None,
+            }
+        }
+        Some(examine_format)
+    }
 }
 
 impl DebuggerCommand {
@@ -22,9 +107,198 @@ impl DebuggerCommand {
             "bt" | "back" | "backtrace" => {
                 Some(DebuggerCommand::Backtrace)
             }
+            "f" | "frame" => match tokens.get(1) {
+                Some(level) => Some(DebuggerCommand::Frame(Some(level.parse().ok()?))),
+                None => Some(DebuggerCommand::Frame(None)),
+            },
+            "up" | "down" => {
+                let count = match tokens.get(1) {
+                    Some(count) => count.parse().ok()?,
+                    None => 1,
+                };
+                if tokens[0] == "up" {
+                    Some(DebuggerCommand::Up(count))
+                } else {
+                    Some(DebuggerCommand::Down(count))
+                }
+            }
             "b" | "break" | "breakpoint" => {
+                match tokens.get(2) {
+                    None if tokens.len() == 2 => Some(DebuggerCommand::Breakpoint(tokens[1].to_string(), None)),
+                    Some(&"if") if tokens.len() > 3 => Some(DebuggerCommand::Breakpoint(
+                        tokens[1].to_string(),
+                        Some(tokens[3..].join(" ")),
+                    )),
+                    _ => None,
+                }
+            }
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "si" | "stepi" | "ni" | "nexti" => {
+                let count = match tokens.get(1) {
+                    Some(count) => count.parse().ok()?,
+                    None => 1,
+                };
+                if tokens[0].starts_with('s') {
+                    Some(DebuggerCommand::StepInstruction(count))
+                } else {
+                    Some(DebuggerCommand::NextInstruction(count))
+                }
+            }
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "p" | "print" => {
+                if tokens.len() >= 2 {
+                    Some(DebuggerCommand::Print(tokens[1..].join(" ")))
+                } else {
+                    None
+                }
+            }
+            "ptype" | "whatis" => {
+                if tokens.len() >= 2 {
+                    let expr = tokens[1..].join(" ");
+                    if tokens[0] == "ptype" {
+                        Some(DebuggerCommand::Ptype(expr))
+                    } else {
+                        Some(DebuggerCommand::Whatis(expr))
+                    }
+                } else {
+                    None
+                }
+            }
+            "i" | "info" => match tokens.get(1) {
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"args") => Some(DebuggerCommand::InfoArgs),
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                Some(&"r") | Some(&"reg") | Some(&"registers") => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..].iter().map(|name| name.trim_start_matches('$').to_string()).collect(),
+                )),
+                Some(&"all-registers") => Some(DebuggerCommand::InfoAllRegisters),
+                Some(&"display") => Some(DebuggerCommand::InfoDisplay),
+                Some(&"signals") | Some(&"handle") => {
+                    Some(DebuggerCommand::InfoSignals(tokens.get(2).map(|signal| signal.to_string())))
+                }
+                Some(&"sharedlibrary") | Some(&"shared") | Some(&"dll") => {
+                    Some(DebuggerCommand::InfoSharedLibrary)
+                }
+                _ => None,
+            },
+            "d" | "delete" => Some(DebuggerCommand::Delete(parse_numbers(&tokens[1..])?)),
+            "disable" => Some(DebuggerCommand::Disable(parse_numbers(&tokens[1..])?)),
+            "enable" => Some(DebuggerCommand::Enable(parse_numbers(&tokens[1..])?)),
+            "ignore" => {
+                if tokens.len() == 3 {
+                    Some(DebuggerCommand::Ignore(tokens[1].parse().ok()?, tokens[2].parse().ok()?))
+                } else {
+                    None
+                }
+            }
+            "condition" => {
+                if tokens.len() >= 2 {
+                    let condition = if tokens.len() > 2 { Some(tokens[2..].join(" ")) } else { None };
+                    Some(DebuggerCommand::Condition(tokens[1].parse().ok()?, condition))
+                } else {
+                    None
+                }
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match tokens[0] {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                if tokens.len() >= 2 {
+                    Some(DebuggerCommand::Watch(kind, tokens[1..].join(" ")))
+                } else {
+                    None
+                }
+            }
+            "display" => Some(DebuggerCommand::Display(
+                Some(tokens[1..].join(" ")).filter(|expr| !expr.is_empty()),
+            )),
+            "undisplay" => Some(DebuggerCommand::Undisplay(parse_numbers(&tokens[1..])?)),
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|token| token.to_string()).collect(),
+            )),
+            "source" => {
                 if tokens.len() == 2 {
-                    Some(DebuggerCommand::Breakpoint(tokens[1].to_string()))
+                    Some(DebuggerCommand::Source(tokens[1].to_string()))
+                } else {
+                    None
+                }
+            }
+            "define" => {
+                if tokens.len() == 2 {
+                    Some(DebuggerCommand::Define(tokens[1].to_string()))
+                } else {
+                    None
+                }
+            }
+            "commands" => match tokens.get(1) {
+                Some(num) => Some(DebuggerCommand::Commands(Some(num.parse().ok()?))),
+                None => Some(DebuggerCommand::Commands(None)),
+            },
+            "gcore" => Some(DebuggerCommand::Gcore(tokens.get(1).map(|path| path.to_string()))),
+            "attach" => {
+                if tokens.len() == 2 {
+                    Some(DebuggerCommand::Attach(tokens[1].parse().ok()?))
+                } else {
+                    None
+                }
+            }
+            "target" => match tokens.get(1) {
+                Some(&"remote") if tokens.len() == 3 => Some(DebuggerCommand::TargetRemote(tokens[2].to_string())),
+                _ => None,
+            },
+            "detach" => Some(DebuggerCommand::Detach),
+            "thread" => match tokens.get(1) {
+                Some(num) => Some(DebuggerCommand::Thread(Some(num.parse().ok()?))),
+                None => Some(DebuggerCommand::Thread(None)),
+            },
+            "x" => Some(DebuggerCommand::Examine(
+                ExamineFormat::parse("")?,
+                Some(tokens[1..].join(" ")).filter(|addr| !addr.is_empty()),
+            )),
+            "disas" | "disassemble" => {
+                // /r shows the raw bytes of each instruction
+                let raw = tokens.get(1) == Some(&"/r");
+                let start = if raw { 2 } else { 1 };
+                Some(DebuggerCommand::Disassemble(
+                    raw,
+                    Some(tokens[start..].join(" ")).filter(|spec| !spec.is_empty()),
+                ))
+            }
+            "l" | "list" => Some(DebuggerCommand::List(
+                Some(tokens[1..].join(" ")).filter(|spec| !spec.is_empty()),
+            )),
+            "dir" | "directory" => Some(DebuggerCommand::Directory(
+                tokens[1..]
+                    .iter()
+                    .flat_map(|dirs| dirs.split(':'))
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| dir.to_string())
+                    .collect(),
+            )),
+            x if x.starts_with("x/") => Some(DebuggerCommand::Examine(
+                ExamineFormat::parse(&x[2..])?,
+                Some(tokens[1..].join(" ")).filter(|addr| !addr.is_empty()),
+            )),
+            "set" if tokens.len() > 1
+                && (tokens[1] == "var" || tokens[1] == "variable" || tokens[1].starts_with(['*', '$'])) =>
+            {
+                let start = if tokens[1].starts_with(['*', '$']) { 1 } else { 2 };
+                let assignment = tokens[start..].join(" ");
+                let (lhs, value) = assignment.split_once('=')?;
+                if lhs.trim().is_empty() || value.trim().is_empty() {
+                    return None;
+                }
+                Some(DebuggerCommand::SetVariable(lhs.trim().to_string(), value.trim().to_string()))
+            }
+            "set" => {
+                if tokens.len() == 3 {
+                    Some(DebuggerCommand::Set(tokens[1].to_string(), tokens[2].to_string()))
                 } else {
                     None
                 }
@@ -34,3 +308,158 @@ impl DebuggerCommand {
         }
     }
 }
+
+/// Parses a list of breakpoint numbers.
+fn parse_numbers(tokens: &[&str]) -> Option<Vec<usize>> {
+    tokens.iter().map(|token| token.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(line: &str) -> Option<DebuggerCommand> {
+        DebuggerCommand::from_tokens(&line.split_whitespace().collect())
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let breakpoint = |location: &str, condition: Option<&str>| {
+            Some(DebuggerCommand::Breakpoint(location.to_string(), condition.map(str::to_string)))
+        };
+        let cases = [
+            ("break main", breakpoint("main", None)),
+            ("b prog.c:12", breakpoint("prog.c:12", None)),
+            ("break *0x401136", breakpoint("*0x401136", None)),
+            ("break main if n > 1", breakpoint("main", Some("n > 1"))),
+            ("b loop.c:7 if i == 3 && done", breakpoint("loop.c:7", Some("i == 3 && done"))),
+            ("break", None),
+            ("break main if", None),
+            ("break main n > 1", None),
+            ("condition 2 n == 1", Some(DebuggerCommand::Condition(2, Some("n == 1".to_string())))),
+            ("condition 2", Some(DebuggerCommand::Condition(2, None))),
+            ("condition n == 1", None),
+            ("condition", None),
+            ("ignore 1 5", Some(DebuggerCommand::Ignore(1, 5))),
+            ("ignore 1", None),
+            ("ignore 1 5 6", None),
+            ("ignore one 5", None),
+            ("ignore 1 -5", None),
+            ("delete 1 3", Some(DebuggerCommand::Delete(vec![1, 3]))),
+            ("disable", Some(DebuggerCommand::Disable(Vec::new()))),
+            ("enable 1 x", None),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_examine_format() {
+        let format = |count, format, unit| Some(ExamineFormat { count, format, unit });
+        let cases = [
+            ("", format(1, None, None)),
+            ("4", format(4, None, None)),
+            ("x", format(1, Some('x'), None)),
+            ("4xw", format(4, Some('x'), Some(4))),
+            ("2g", format(2, None, Some(8))),
+            ("16bx", format(16, Some('x'), Some(1))),
+            ("3dh", format(3, Some('d'), Some(2))),
+            ("s", format(1, Some('s'), None)),
+            ("10i", format(10, Some('i'), None)),
+            // the last letter of each kind wins
+            ("xdbg", format(1, Some('d'), Some(8))),
+            ("4q", None),
+            ("x4", None),
+            ("-1x", None),
+            ("99999999999999999999x", None),
+        ];
+        for (spec, examine_format) in cases {
+            assert_eq!(ExamineFormat::parse(spec), examine_format, "{}", spec);
+        }
+
+        let examine = |count, format, unit, addr: Option<&str>| {
+            Some(DebuggerCommand::Examine(ExamineFormat { count, format, unit }, addr.map(str::to_string)))
+        };
+        let cases = [
+            ("x", examine(1, None, None, None)),
+            ("x &n", examine(1, None, None, Some("&n"))),
+            ("x/4xw &n", examine(4, Some('x'), Some(4), Some("&n"))),
+            ("x/2g $sp + 8", examine(2, None, Some(8), Some("$sp + 8"))),
+            ("x/s", examine(1, Some('s'), None, None)),
+            ("x/z &n", None),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let set_variable = |lhs: &str, value: &str| Some(DebuggerCommand::SetVariable(lhs.to_string(), value.to_string()));
+        let cases = [
+            ("set var n = 3", set_variable("n", "3")),
+            ("set variable p.x=-4", set_variable("p.x", "-4")),
+            ("set var arr[1] = n * 2", set_variable("arr[1]", "n * 2")),
+            ("set *0x404028 = 5", set_variable("*0x404028", "5")),
+            ("set *(int *) &n = 1", set_variable("*(int *) &n", "1")),
+            ("set $rax = 0x10", set_variable("$rax", "0x10")),
+            ("set var n", None),
+            ("set var = 3", None),
+            ("set var n =", None),
+            ("set *0x404028", None),
+            ("set listsize 20", Some(DebuggerCommand::Set("listsize".to_string(), "20".to_string()))),
+            ("set listsize", None),
+            ("set", None),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let disassemble = |raw, spec: Option<&str>| Some(DebuggerCommand::Disassemble(raw, spec.map(str::to_string)));
+        let cases = [
+            ("disas", disassemble(false, None)),
+            ("disas /r", disassemble(true, None)),
+            ("disassemble main", disassemble(false, Some("main"))),
+            ("disassemble /r main", disassemble(true, Some("main"))),
+            ("disas 0x401126,0x401136", disassemble(false, Some("0x401126,0x401136"))),
+            ("disas /r 0x401126, +16", disassemble(true, Some("0x401126, +16"))),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_directory() {
+        let directory = |dirs: &[&str]| Some(DebuggerCommand::Directory(dirs.iter().map(|dir| dir.to_string()).collect()));
+        let cases = [
+            ("dir a:b", directory(&["a", "b"])),
+            ("directory /src /other:/more", directory(&["/src", "/other", "/more"])),
+            ("dir :a::b:", directory(&["a", "b"])),
+            // with no directories, the search path is reset
+            ("dir", directory(&[])),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_handle() {
+        let handle = |args: &[&str]| Some(DebuggerCommand::Handle(args.iter().map(|arg| arg.to_string()).collect()));
+        let cases = [
+            ("handle SIGUSR1 nostop noprint", handle(&["SIGUSR1", "nostop", "noprint"])),
+            ("handle pass SIGALRM 14", handle(&["pass", "SIGALRM", "14"])),
+            ("handle", handle(&[])),
+            ("info signals", Some(DebuggerCommand::InfoSignals(None))),
+            ("info handle SIGINT", Some(DebuggerCommand::InfoSignals(Some("SIGINT".to_string())))),
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse(line), command, "{}", line);
+        }
+    }
+}
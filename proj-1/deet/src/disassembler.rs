@@ -0,0 +1,1608 @@
+//! Decodes x86-64 machine code into the AT&T syntax that GDB and objdump print, e.g.
+//! `mov    %rsp,%rbp`.
+//!
+//! The decoder knows the general-purpose, x87 and SSE instructions along with the AVX and BMI
+//! instructions that show up in compiled code and libc. Anything else decodes as `(bad)`, which
+//! takes up a single byte unless the length of the instruction could still be worked out.
+
+/// x86 instructions are at most 15 bytes long, prefixes included
+pub const MAX_LEN: usize = 15;
+
+/// One decoded instruction.
+pub struct Instruction {
+    pub len: usize,
+    /// Mnemonic and operands, e.g. `call   0x401126`
+    pub text: String,
+    /// Destination of a direct jump or call
+    pub target: Option<usize>,
+    /// Address of a %rip-relative memory operand
+    pub reference: Option<usize>,
+}
+
+const REG64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
+const REG32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d",
+    "r15d",
+];
+const REG16: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w", "r14w", "r15w",
+];
+const REG8: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b",
+];
+/// Byte registers 4-7 without a REX prefix
+const REG8_HIGH: [&str; 4] = ["ah", "ch", "dh", "bh"];
+const SEGMENTS: [&str; 6] = ["es", "cs", "ss", "ds", "fs", "gs"];
+
+/// Condition codes of jcc, setcc and cmovcc, in encoding order
+const CONDITIONS: [&str; 16] = ["o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g"];
+const ARITHMETIC: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFTS: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+/// Predicates of cmpps and friends, which objdump folds into the mnemonic
+const COMPARISONS: [&str; 8] = ["eq", "lt", "le", "unord", "neq", "nlt", "nle", "ord"];
+/// Type suffixes of the packed and scalar SSE floating-point instructions, by mandatory prefix
+const FLOAT_SUFFIXES: [&str; 4] = ["ps", "pd", "ss", "sd"];
+
+/// x87 instructions with a memory operand, by opcode (d8-df) and the reg field of the ModRM
+const X87_MEMORY: [[&str; 8]; 8] = [
+    ["fadds", "fmuls", "fcoms", "fcomps", "fsubs", "fsubrs", "fdivs", "fdivrs"],
+    ["flds", "", "fsts", "fstps", "fldenv", "fldcw", "fnstenv", "fnstcw"],
+    ["fiaddl", "fimull", "ficoml", "ficompl", "fisubl", "fisubrl", "fidivl", "fidivrl"],
+    ["fildl", "fisttpl", "fistl", "fistpl", "", "fldt", "", "fstpt"],
+    ["faddl", "fmull", "fcoml", "fcompl", "fsubl", "fsubrl", "fdivl", "fdivrl"],
+    ["fldl", "fisttpll", "fstl", "fstpl", "frstor", "", "fnsave", "fnstsw"],
+    ["fiadds", "fimuls", "ficoms", "ficomps", "fisubs", "fisubrs", "fidivs", "fidivrs"],
+    ["filds", "fisttps", "fists", "fistps", "fbld", "fildll", "fbstp", "fistpll"],
+];
+const X87_ARITHMETIC: [&str; 8] = ["fadd", "fmul", "fcom", "fcomp", "fsub", "fsubr", "fdiv", "fdivr"];
+/// The operand-less d9 e0-ff instructions
+const X87_D9: [&str; 32] = [
+    "fchs", "fabs", "", "", "ftst", "fxam", "", "", "fld1", "fldl2t", "fldl2e", "fldpi", "fldlg2", "fldln2", "fldz",
+    "", "f2xm1", "fyl2x", "fptan", "fpatan", "fxtract", "fprem1", "fdecstp", "fincstp", "fprem", "fyl2xp1", "fsqrt",
+    "fsincos", "frndint", "fscale", "fsin", "fcos",
+];
+
+/// MMX and SSE integer instructions that take the same operands in both forms: MMX registers
+/// without a prefix and XMM registers with 0x66.
+fn packed_integer(op: u8) -> Option<&'static str> {
+    Some(match op {
+        0x60 => "punpcklbw",
+        0x61 => "punpcklwd",
+        0x62 => "punpckldq",
+        0x63 => "packsswb",
+        0x64 => "pcmpgtb",
+        0x65 => "pcmpgtw",
+        0x66 => "pcmpgtd",
+        0x67 => "packuswb",
+        0x68 => "punpckhbw",
+        0x69 => "punpckhwd",
+        0x6a => "punpckhdq",
+        0x6b => "packssdw",
+        0x74 => "pcmpeqb",
+        0x75 => "pcmpeqw",
+        0x76 => "pcmpeqd",
+        0xd1 => "psrlw",
+        0xd2 => "psrld",
+        0xd3 => "psrlq",
+        0xd4 => "paddq",
+        0xd5 => "pmullw",
+        0xd8 => "psubusb",
+        0xd9 => "psubusw",
+        0xda => "pminub",
+        0xdb => "pand",
+        0xdc => "paddusb",
+        0xdd => "paddusw",
+        0xde => "pmaxub",
+        0xdf => "pandn",
+        0xe0 => "pavgb",
+        0xe1 => "psraw",
+        0xe2 => "psrad",
+        0xe3 => "pavgw",
+        0xe4 => "pmulhuw",
+        0xe5 => "pmulhw",
+        0xe8 => "psubsb",
+        0xe9 => "psubsw",
+        0xea => "pminsw",
+        0xeb => "por",
+        0xec => "paddsb",
+        0xed => "paddsw",
+        0xee => "pmaxsw",
+        0xef => "pxor",
+        0xf1 => "psllw",
+        0xf2 => "pslld",
+        0xf3 => "psllq",
+        0xf4 => "pmuludq",
+        0xf5 => "pmaddwd",
+        0xf6 => "psadbw",
+        0xf8 => "psubb",
+        0xf9 => "psubw",
+        0xfa => "psubd",
+        0xfb => "psubq",
+        0xfc => "paddb",
+        0xfd => "paddw",
+        0xfe => "paddd",
+        _ => return None,
+    })
+}
+
+/// Instructions of the 0f 38 map that operate on vector registers, along with whether the VEX
+/// form takes a second source register.
+fn map38_vector(op: u8) -> Option<(&'static str, bool)> {
+    Some(match op {
+        0x00 => ("pshufb", true),
+        0x01 => ("phaddw", true),
+        0x02 => ("phaddd", true),
+        0x04 => ("pmaddubsw", true),
+        0x08 => ("psignb", true),
+        0x0b => ("pmulhrsw", true),
+        0x17 => ("ptest", false),
+        0x1c => ("pabsb", false),
+        0x1d => ("pabsw", false),
+        0x1e => ("pabsd", false),
+        0x20 => ("pmovsxbw", false),
+        0x21 => ("pmovsxbd", false),
+        0x23 => ("pmovsxwd", false),
+        0x25 => ("pmovsxdq", false),
+        0x29 => ("pcmpeqq", true),
+        0x2b => ("packusdw", true),
+        0x30 => ("pmovzxbw", false),
+        0x31 => ("pmovzxbd", false),
+        0x32 => ("pmovzxbq", false),
+        0x33 => ("pmovzxwd", false),
+        0x34 => ("pmovzxwq", false),
+        0x35 => ("pmovzxdq", false),
+        0x37 => ("pcmpgtq", true),
+        0x38 => ("pminsb", true),
+        0x39 => ("pminsd", true),
+        0x3a => ("pminuw", true),
+        0x3b => ("pminud", true),
+        0x3c => ("pmaxsb", true),
+        0x3d => ("pmaxsd", true),
+        0x3e => ("pmaxuw", true),
+        0x3f => ("pmaxud", true),
+        0x40 => ("pmulld", true),
+        _ => return None,
+    })
+}
+
+/// Decodes the instruction at the start of `bytes`, which were read from `addr`. Undecodable
+/// bytes come out as a one-byte `(bad)` instruction.
+pub fn decode(bytes: &[u8], addr: usize) -> Instruction {
+    let mut decoder = Decoder {
+        bytes: &bytes[..bytes.len().min(MAX_LEN)],
+        addr,
+        pos: 0,
+        operand_prefixes: 0,
+        address_size: false,
+        rep: None,
+        lock: false,
+        segment: None,
+        rex: 0,
+        vex: None,
+        rip_displacement: None,
+        target: None,
+    };
+    match decoder.instruction() {
+        Some(text) => Instruction {
+            len: decoder.pos,
+            text,
+            target: decoder.target,
+            reference: decoder
+                .rip_displacement
+                .map(|disp| (addr + decoder.pos).wrapping_add(disp as usize)),
+        },
+        None => Instruction {
+            len: 1,
+            text: "(bad)".to_string(),
+            target: None,
+            reference: None,
+        },
+    }
+}
+
+/// The fields of a VEX prefix that aren't folded into `rex` or the mandatory prefix.
+struct Vex {
+    /// 256-bit (ymm) operands
+    long: bool,
+    /// The extra source register
+    reg: usize,
+    /// The mandatory prefix it stands for: none, 66, f3 or f2
+    prefix: usize,
+}
+
+enum Rm {
+    Reg(usize),
+    Mem(String),
+}
+
+impl Rm {
+    fn is_mem(&self) -> bool {
+        matches!(self, Rm::Mem(_))
+    }
+}
+
+struct ModRm {
+    /// The reg field, extended by REX.R
+    reg: usize,
+    rm: Rm,
+}
+
+#[derive(Clone, Copy)]
+enum VectorRegs {
+    Mmx,
+    Xmm,
+}
+
+/// Mnemonic and operands, in AT&T order
+type Decoded = Option<(String, Vec<String>)>;
+
+fn decoded(mnemonic: &str, operands: Vec<String>) -> Decoded {
+    Some((mnemonic.to_string(), operands))
+}
+
+fn suffix(size: usize) -> &'static str {
+    match size {
+        1 => "b",
+        2 => "w",
+        4 => "l",
+        _ => "q",
+    }
+}
+
+/// Formats an immediate truncated to the operand size, the way objdump shows it.
+fn immediate(value: i64, size: usize) -> String {
+    let value = if size >= 8 { value as u64 } else { value as u64 & ((1 << (8 * size)) - 1) };
+    format!("$0x{:x}", value)
+}
+
+fn signed_hex(value: i64) -> String {
+    if value < 0 {
+        format!("-0x{:x}", value.unsigned_abs())
+    } else {
+        format!("0x{:x}", value)
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    addr: usize,
+    pos: usize,
+    /// Number of 0x66 prefixes. The first selects 16-bit operands or an SSE variant; objdump
+    /// shows the others as `data16`.
+    operand_prefixes: usize,
+    /// 0x67, for 32-bit addressing
+    address_size: bool,
+    /// The last 0xf2 or 0xf3 prefix
+    rep: Option<u8>,
+    lock: bool,
+    segment: Option<usize>,
+    /// The REX prefix, or the equivalent bits of a VEX prefix
+    rex: u8,
+    vex: Option<Vex>,
+    rip_displacement: Option<i64>,
+    target: Option<usize>,
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn read(&mut self, size: usize) -> Option<i64> {
+        let bytes = self.bytes.get(self.pos..self.pos + size)?;
+        self.pos += size;
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(bytes);
+        // sign-extend from the top byte that was read
+        let shift = 64 - 8 * size as u32;
+        Some(((u64::from_le_bytes(raw) << shift) as i64) >> shift)
+    }
+
+    fn imm8(&mut self) -> Option<i64> {
+        self.read(1)
+    }
+
+    /// Reads an immediate of the operand size, which stays 32 bits for 64-bit operands.
+    fn imm(&mut self, size: usize) -> Option<i64> {
+        self.read(size.min(4))
+    }
+
+    /// Reads a relative displacement and returns the address it points to.
+    fn branch(&mut self, size: usize) -> Option<String> {
+        let rel = self.read(size)?;
+        let target = (self.addr + self.pos).wrapping_add(rel as usize);
+        self.target = Some(target);
+        Some(format!("0x{:x}", target))
+    }
+
+    fn rex_w(&self) -> bool {
+        self.rex & 8 != 0
+    }
+
+    fn rex_r(&self) -> usize {
+        (self.rex as usize & 4) << 1
+    }
+
+    fn rex_x(&self) -> usize {
+        (self.rex as usize & 2) << 2
+    }
+
+    fn rex_b(&self) -> usize {
+        (self.rex as usize & 1) << 3
+    }
+
+    /// Size of an operand that defaults to 32 bits.
+    fn operand_size(&self) -> usize {
+        if self.rex_w() {
+            8
+        } else if self.operand_prefixes > 0 {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// Size of an operand that defaults to 64 bits, as for push, pop and indirect branches.
+    fn stack_size(&self) -> usize {
+        if self.operand_prefixes > 0 && !self.rex_w() {
+            2
+        } else {
+            8
+        }
+    }
+
+    /// Which mandatory prefix selects the variant of an SSE instruction: 0 for none, then 66, f3
+    /// and f2. A repeat prefix takes precedence over 66.
+    fn sse_prefix(&self) -> usize {
+        if let Some(vex) = &self.vex {
+            return vex.prefix;
+        }
+        match self.rep {
+            Some(0xf3) => 2,
+            Some(0xf2) => 3,
+            _ if self.operand_prefixes > 0 => 1,
+            _ => 0,
+        }
+    }
+
+    fn gpr(&self, num: usize, size: usize) -> String {
+        let name = match size {
+            1 if self.rex == 0 && (4..8).contains(&num) => REG8_HIGH[num - 4],
+            1 => REG8[num],
+            2 => REG16[num],
+            4 => REG32[num],
+            _ => REG64[num],
+        };
+        format!("%{}", name)
+    }
+
+    fn vector(&self, num: usize, regs: VectorRegs) -> String {
+        match regs {
+            VectorRegs::Mmx => format!("%mm{}", num & 7),
+            VectorRegs::Xmm if self.vex.as_ref().map_or(false, |vex| vex.long) => format!("%ymm{}", num),
+            VectorRegs::Xmm => format!("%xmm{}", num),
+        }
+    }
+
+    fn rm_gpr(&self, rm: &Rm, size: usize) -> String {
+        match rm {
+            Rm::Reg(num) => self.gpr(*num, size),
+            Rm::Mem(text) => text.clone(),
+        }
+    }
+
+    fn rm_vector(&self, rm: &Rm, regs: VectorRegs) -> String {
+        match rm {
+            Rm::Reg(num) => self.vector(*num, regs),
+            Rm::Mem(text) => text.clone(),
+        }
+    }
+
+    /// Appends the operand-size suffix to instructions whose only sized operand is in memory,
+    /// e.g. `movl   $0x0,-0x4(%rbp)`.
+    fn sized(&self, mnemonic: &str, rm: &Rm, size: usize) -> String {
+        if rm.is_mem() {
+            format!("{}{}", mnemonic, suffix(size))
+        } else {
+            mnemonic.to_string()
+        }
+    }
+
+    fn modrm(&mut self) -> Option<ModRm> {
+        let byte = self.byte()?;
+        let mode = byte >> 6;
+        let reg = ((byte as usize >> 3) & 7) | self.rex_r();
+        let rm = byte as usize & 7;
+        if mode == 3 {
+            return Some(ModRm {
+                reg,
+                rm: Rm::Reg(rm | self.rex_b()),
+            });
+        }
+
+        let mut base = Some(rm | self.rex_b());
+        let mut index = None;
+        let mut rip_relative = false;
+        let mut displacement_size = match mode {
+            1 => 1,
+            2 => 4,
+            _ => 0,
+        };
+        if rm == 4 {
+            let sib = self.byte()?;
+            let scale = 1 << (sib >> 6);
+            let index_num = ((sib as usize >> 3) & 7) | self.rex_x();
+            if index_num != 4 {
+                index = Some((index_num, scale));
+            }
+            base = Some((sib as usize & 7) | self.rex_b());
+            if sib & 7 == 5 && mode == 0 {
+                base = None;
+                displacement_size = 4;
+            }
+        } else if rm == 5 && mode == 0 {
+            base = None;
+            rip_relative = true;
+            displacement_size = 4;
+        }
+        let displacement = if displacement_size > 0 { self.read(displacement_size)? } else { 0 };
+
+        let address_regs = if self.address_size { &REG32 } else { &REG64 };
+        let mut text = match self.segment {
+            Some(segment) if segment >= 4 => format!("%{}:", SEGMENTS[segment]),
+            _ => String::new(),
+        };
+        if rip_relative {
+            self.rip_displacement = Some(displacement);
+            let pc = if self.address_size { "eip" } else { "rip" };
+            text.push_str(&format!("{}(%{})", signed_hex(displacement), pc));
+        } else if base.is_none() && index.is_none() {
+            let addr = if self.address_size { displacement as u32 as u64 } else { displacement as u64 };
+            text.push_str(&format!("0x{:x}", addr));
+        } else {
+            if displacement_size > 0 {
+                text.push_str(&signed_hex(displacement));
+            }
+            text.push('(');
+            if let Some(base) = base {
+                text.push_str(&format!("%{}", address_regs[base]));
+            }
+            if let Some((index, scale)) = index {
+                text.push_str(&format!(",%{},{}", address_regs[index], scale));
+            }
+            text.push(')');
+        }
+        Some(ModRm { reg, rm: Rm::Mem(text) })
+    }
+
+    /// Decodes the prefixes and the instruction that follows them into its text.
+    fn instruction(&mut self) -> Option<String> {
+        loop {
+            match *self.bytes.get(self.pos)? {
+                0x66 => self.operand_prefixes += 1,
+                0x67 => self.address_size = true,
+                0xf0 => self.lock = true,
+                byte @ (0xf2 | 0xf3) => self.rep = Some(byte),
+                byte @ (0x26 | 0x2e | 0x36 | 0x3e) => self.segment = Some((byte as usize >> 3) & 3),
+                byte @ (0x64 | 0x65) => self.segment = Some(byte as usize - 0x60),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        let mut op = self.byte()?;
+        let (mnemonic, operands) = match op {
+            0x40..=0x4f => {
+                self.rex = op;
+                op = self.byte()?;
+                match op {
+                    0x0f => self.two_byte()?,
+                    _ => self.one_byte(op)?,
+                }
+            }
+            0xc4 | 0xc5 => self.vex_instruction(op)?,
+            0x62 => self.evex_instruction()?,
+            0x0f => self.two_byte()?,
+            _ => self.one_byte(op)?,
+        };
+
+        let mut words: Vec<&str> = Vec::new();
+        words.extend(std::iter::repeat_n("data16", self.operand_prefixes.saturating_sub(1)));
+        if let Some(segment) = self.segment.filter(|segment| *segment < 4) {
+            words.push(SEGMENTS[segment]);
+        }
+        if self.lock {
+            words.push("lock");
+        }
+        words.push(&mnemonic);
+        let mnemonic = words.join(" ");
+        Some(if operands.is_empty() {
+            mnemonic
+        } else {
+            format!("{:<6} {}", mnemonic, operands.join(","))
+        })
+    }
+
+    /// Applies the prefix words that only mean something for branches: `bnd` for f2 and, on
+    /// indirect branches, `notrack` for the ds segment prefix.
+    fn branch_mnemonic(&mut self, mnemonic: &str, indirect: bool) -> String {
+        let mut words = Vec::new();
+        if self.rep == Some(0xf2) {
+            words.push("bnd");
+        }
+        if indirect && self.segment == Some(3) {
+            self.segment = None;
+            words.push("notrack");
+        }
+        words.push(mnemonic);
+        words.join(" ")
+    }
+
+    fn one_byte(&mut self, op: u8) -> Decoded {
+        let size = self.operand_size();
+        match op {
+            0x00..=0x3f if op & 7 < 6 => {
+                let mnemonic = ARITHMETIC[op as usize >> 3];
+                match op & 7 {
+                    0..=3 => {
+                        let size = if op & 1 == 0 { 1 } else { size };
+                        let m = self.modrm()?;
+                        let reg = self.gpr(m.reg, size);
+                        let rm = self.rm_gpr(&m.rm, size);
+                        if op & 2 == 0 {
+                            decoded(mnemonic, vec![reg, rm])
+                        } else {
+                            decoded(mnemonic, vec![rm, reg])
+                        }
+                    }
+                    4 => decoded(mnemonic, vec![immediate(self.imm8()?, 1), "%al".to_string()]),
+                    _ => decoded(mnemonic, vec![immediate(self.imm(size)?, size), self.gpr(0, size)]),
+                }
+            }
+            0x50..=0x57 => decoded("push", vec![self.gpr((op as usize & 7) | self.rex_b(), self.stack_size())]),
+            0x58..=0x5f => decoded("pop", vec![self.gpr((op as usize & 7) | self.rex_b(), self.stack_size())]),
+            0x63 => {
+                let m = self.modrm()?;
+                let mnemonic = if self.rex_w() { "movslq" } else { "movsxd" };
+                decoded(mnemonic, vec![self.rm_gpr(&m.rm, 4), self.gpr(m.reg, size)])
+            }
+            0x68 => decoded("push", vec![immediate(self.imm(4)?, self.stack_size())]),
+            0x6a => decoded("push", vec![immediate(self.imm8()?, self.stack_size())]),
+            0x69 | 0x6b => {
+                let m = self.modrm()?;
+                let imm = if op == 0x69 { self.imm(size)? } else { self.imm8()? };
+                decoded("imul", vec![immediate(imm, size), self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0x70..=0x7f => {
+                let mnemonic = format!("j{}", CONDITIONS[op as usize & 15]);
+                let target = self.branch(1)?;
+                decoded(&self.branch_mnemonic(&mnemonic, false), vec![target])
+            }
+            0x80 | 0x81 | 0x83 => {
+                let size = if op == 0x80 { 1 } else { size };
+                let m = self.modrm()?;
+                let imm = if op == 0x81 { self.imm(size)? } else { self.imm8()? };
+                let mnemonic = self.sized(ARITHMETIC[m.reg & 7], &m.rm, size);
+                decoded(&mnemonic, vec![immediate(imm, size), self.rm_gpr(&m.rm, size)])
+            }
+            0x84..=0x8b => {
+                let size = if op & 1 == 0 { 1 } else { size };
+                let mnemonic = match op {
+                    0x84 | 0x85 => "test",
+                    0x86 | 0x87 => "xchg",
+                    _ => "mov",
+                };
+                let m = self.modrm()?;
+                let reg = self.gpr(m.reg, size);
+                let rm = self.rm_gpr(&m.rm, size);
+                if op >= 0x8a {
+                    decoded(mnemonic, vec![rm, reg])
+                } else {
+                    decoded(mnemonic, vec![reg, rm])
+                }
+            }
+            0x8c | 0x8e => {
+                let m = self.modrm()?;
+                let segment = format!("%{}", SEGMENTS.get(m.reg & 7)?);
+                let rm = self.rm_gpr(&m.rm, if m.rm.is_mem() { 2 } else { size });
+                if op == 0x8c {
+                    decoded("mov", vec![segment, rm])
+                } else {
+                    decoded("mov", vec![rm, segment])
+                }
+            }
+            0x8d => {
+                let m = self.modrm()?;
+                if !m.rm.is_mem() {
+                    return None;
+                }
+                decoded("lea", vec![self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0x8f => {
+                let m = self.modrm()?;
+                decoded("pop", vec![self.rm_gpr(&m.rm, self.stack_size())])
+            }
+            0x90 if self.rex_b() != 0 => decoded("xchg", vec![self.gpr(8, size), self.gpr(0, size)]),
+            0x90 if self.rep == Some(0xf3) => decoded("pause", vec![]),
+            0x90 if self.operand_prefixes > 0 => decoded("xchg", vec!["%ax".to_string(), "%ax".to_string()]),
+            0x90 => decoded("nop", vec![]),
+            0x91..=0x97 => decoded("xchg", vec![self.gpr(0, size), self.gpr((op as usize & 7) | self.rex_b(), size)]),
+            0x98 => decoded(["cbtw", "cwtl", "cltq"][size.trailing_zeros() as usize - 1], vec![]),
+            0x99 => decoded(["cwtd", "cltd", "cqto"][size.trailing_zeros() as usize - 1], vec![]),
+            0x9b => {
+                // fwait before a non-waiting x87 control instruction makes its waiting form,
+                // e.g. fwait; fnstcw is fstcw
+                let start = self.pos;
+                if let Some(next @ 0xd8..=0xdf) = self.bytes.get(self.pos).copied() {
+                    self.pos += 1;
+                    if let Some((mnemonic, operands)) = self.x87(next).filter(|(mnemonic, _)| mnemonic.starts_with("fn") && mnemonic != "fnop") {
+                        return decoded(&format!("f{}", &mnemonic[2..]), operands);
+                    }
+                }
+                self.pos = start;
+                self.rip_displacement = None;
+                decoded("fwait", vec![])
+            }
+            0x9c => decoded("pushf", vec![]),
+            0x9d => decoded("popf", vec![]),
+            0x9e => decoded("sahf", vec![]),
+            0x9f => decoded("lahf", vec![]),
+            0xa0..=0xa3 => {
+                let size = if op & 1 == 0 { 1 } else { size };
+                let offset = self.read(if self.address_size { 4 } else { 8 })?;
+                let segment = match self.segment {
+                    Some(segment) if segment >= 4 => format!("%{}:", SEGMENTS[segment]),
+                    _ => String::new(),
+                };
+                let memory = format!("{}0x{:x}", segment, offset as u64);
+                if op < 0xa2 {
+                    decoded("movabs", vec![memory, self.gpr(0, size)])
+                } else {
+                    decoded("movabs", vec![self.gpr(0, size), memory])
+                }
+            }
+            0xa4..=0xa7 | 0xaa..=0xaf => {
+                let size = if op & 1 == 0 { 1 } else { size };
+                let (si, di) = if self.address_size { ("esi", "edi") } else { ("rsi", "rdi") };
+                let source = format!("%ds:(%{})", si);
+                let destination = format!("%es:(%{})", di);
+                let accumulator = self.gpr(0, size);
+                let (mnemonic, operands) = match op {
+                    0xa4 | 0xa5 => (format!("movs{}", suffix(size)), vec![source, destination]),
+                    0xa6 | 0xa7 => (format!("cmps{}", suffix(size)), vec![destination, source]),
+                    0xaa | 0xab => ("stos".to_string(), vec![accumulator, destination]),
+                    0xac | 0xad => ("lods".to_string(), vec![source, accumulator]),
+                    _ => ("scas".to_string(), vec![destination, accumulator]),
+                };
+                let compares = matches!(op, 0xa6 | 0xa7 | 0xae | 0xaf);
+                let mnemonic = match self.rep {
+                    Some(0xf3) if compares => format!("repz {}", mnemonic),
+                    Some(0xf3) => format!("rep {}", mnemonic),
+                    Some(_) => format!("repnz {}", mnemonic),
+                    None => mnemonic,
+                };
+                decoded(&mnemonic, operands)
+            }
+            0xa8 => decoded("test", vec![immediate(self.imm8()?, 1), "%al".to_string()]),
+            0xa9 => decoded("test", vec![immediate(self.imm(size)?, size), self.gpr(0, size)]),
+            0xb0..=0xb7 => {
+                let reg = self.gpr((op as usize & 7) | self.rex_b(), 1);
+                decoded("mov", vec![immediate(self.imm8()?, 1), reg])
+            }
+            0xb8..=0xbf => {
+                let reg = self.gpr((op as usize & 7) | self.rex_b(), size);
+                if size == 8 {
+                    decoded("movabs", vec![immediate(self.read(8)?, 8), reg])
+                } else {
+                    decoded("mov", vec![immediate(self.imm(size)?, size), reg])
+                }
+            }
+            0xc0 | 0xc1 | 0xd0..=0xd3 => {
+                let size = if op & 1 == 0 { 1 } else { size };
+                let m = self.modrm()?;
+                let mnemonic = self.sized(SHIFTS[m.reg & 7], &m.rm, size);
+                let rm = self.rm_gpr(&m.rm, size);
+                match op {
+                    0xc0 | 0xc1 => decoded(&mnemonic, vec![immediate(self.imm8()?, 1), rm]),
+                    0xd0 | 0xd1 => decoded(&mnemonic, vec![rm]),
+                    _ => decoded(&mnemonic, vec!["%cl".to_string(), rm]),
+                }
+            }
+            0xc2 => {
+                let imm = self.read(2)?;
+                decoded(&self.branch_mnemonic("ret", false), vec![immediate(imm, 2)])
+            }
+            0xc3 if self.rep == Some(0xf3) => decoded("repz ret", vec![]),
+            0xc3 => decoded(&self.branch_mnemonic("ret", false), vec![]),
+            0xc6 if self.bytes.get(self.pos) == Some(&0xf8) => {
+                self.pos += 1;
+                decoded("xabort", vec![immediate(self.imm8()?, 1)])
+            }
+            0xc7 if self.bytes.get(self.pos) == Some(&0xf8) => {
+                self.pos += 1;
+                decoded("xbegin", vec![self.branch(4)?])
+            }
+            0xc6 | 0xc7 => {
+                let size = if op == 0xc6 { 1 } else { size };
+                let m = self.modrm()?;
+                if m.reg & 7 != 0 {
+                    return None;
+                }
+                let imm = self.imm(size)?;
+                decoded(&self.sized("mov", &m.rm, size), vec![immediate(imm, size), self.rm_gpr(&m.rm, size)])
+            }
+            0xc8 => {
+                let frame_size = self.read(2)?;
+                let level = self.imm8()?;
+                decoded("enter", vec![immediate(frame_size, 2), immediate(level, 1)])
+            }
+            0xc9 => decoded("leave", vec![]),
+            0xca => decoded("lret", vec![immediate(self.read(2)?, 2)]),
+            0xcb => decoded("lret", vec![]),
+            0xcc => decoded("int3", vec![]),
+            0xcd => decoded("int", vec![immediate(self.imm8()?, 1)]),
+            0xcf => decoded(if self.rex_w() { "iretq" } else { "iret" }, vec![]),
+            0xd8..=0xdf => self.x87(op),
+            0xe0..=0xe3 => {
+                let mnemonic = ["loopne", "loope", "loop", "jrcxz"][op as usize & 3];
+                decoded(mnemonic, vec![self.branch(1)?])
+            }
+            0xe4 | 0xe5 => {
+                let port = immediate(self.imm8()?, 1);
+                decoded("in", vec![port, self.gpr(0, if op == 0xe4 { 1 } else { size.min(4) })])
+            }
+            0xe6 | 0xe7 => {
+                let port = immediate(self.imm8()?, 1);
+                decoded("out", vec![self.gpr(0, if op == 0xe6 { 1 } else { size.min(4) }), port])
+            }
+            0xec | 0xed => decoded("in", vec!["(%dx)".to_string(), self.gpr(0, if op == 0xec { 1 } else { size.min(4) })]),
+            0xee | 0xef => decoded("out", vec![self.gpr(0, if op == 0xee { 1 } else { size.min(4) }), "(%dx)".to_string()]),
+            0xe8 => {
+                let target = self.branch(4)?;
+                decoded(&self.branch_mnemonic("call", false), vec![target])
+            }
+            0xe9 | 0xeb => {
+                let target = self.branch(if op == 0xe9 { 4 } else { 1 })?;
+                decoded(&self.branch_mnemonic("jmp", false), vec![target])
+            }
+            0xf1 => decoded("int1", vec![]),
+            0xf4 => decoded("hlt", vec![]),
+            0xf5 => decoded("cmc", vec![]),
+            0xf6 | 0xf7 => {
+                let size = if op == 0xf6 { 1 } else { size };
+                let m = self.modrm()?;
+                let rm = self.rm_gpr(&m.rm, size);
+                match m.reg & 7 {
+                    0 | 1 => {
+                        let imm = self.imm(size)?;
+                        decoded(&self.sized("test", &m.rm, size), vec![immediate(imm, size), rm])
+                    }
+                    reg => {
+                        let mnemonic = ["", "", "not", "neg", "mul", "imul", "div", "idiv"][reg];
+                        decoded(&self.sized(mnemonic, &m.rm, size), vec![rm])
+                    }
+                }
+            }
+            0xf8 => decoded("clc", vec![]),
+            0xf9 => decoded("stc", vec![]),
+            0xfa => decoded("cli", vec![]),
+            0xfb => decoded("sti", vec![]),
+            0xfc => decoded("cld", vec![]),
+            0xfd => decoded("std", vec![]),
+            0xfe => {
+                let m = self.modrm()?;
+                let mnemonic = match m.reg & 7 {
+                    0 => "inc",
+                    1 => "dec",
+                    _ => return None,
+                };
+                decoded(&self.sized(mnemonic, &m.rm, 1), vec![self.rm_gpr(&m.rm, 1)])
+            }
+            0xff => {
+                let m = self.modrm()?;
+                match m.reg & 7 {
+                    0 | 1 => {
+                        let mnemonic = if m.reg & 7 == 0 { "inc" } else { "dec" };
+                        decoded(&self.sized(mnemonic, &m.rm, size), vec![self.rm_gpr(&m.rm, size)])
+                    }
+                    2 | 4 => {
+                        let mnemonic = if m.reg & 7 == 2 { "call" } else { "jmp" };
+                        let target = format!("*{}", self.rm_gpr(&m.rm, 8));
+                        decoded(&self.branch_mnemonic(mnemonic, true), vec![target])
+                    }
+                    3 | 5 if m.rm.is_mem() => {
+                        let mnemonic = if m.reg & 7 == 3 { "lcall" } else { "ljmp" };
+                        decoded(mnemonic, vec![format!("*{}", self.rm_gpr(&m.rm, 8))])
+                    }
+                    6 => decoded("push", vec![self.rm_gpr(&m.rm, self.stack_size())]),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn x87(&mut self, op: u8) -> Decoded {
+        let m = self.modrm()?;
+        let group = op as usize - 0xd8;
+        let reg = m.reg & 7;
+        let num = match m.rm {
+            Rm::Mem(text) => {
+                let mnemonic = X87_MEMORY[group][reg];
+                return if mnemonic.is_empty() { None } else { decoded(mnemonic, vec![text]) };
+            }
+            Rm::Reg(num) => num & 7,
+        };
+        // the top of the stack is just %st when it is implied, %st(0) when it is encoded
+        let st = |num: usize| format!("%st({})", num);
+        match (op, reg) {
+            (0xd8, 2 | 3) => decoded(X87_ARITHMETIC[reg], vec![st(num)]),
+            (0xd8, _) => decoded(X87_ARITHMETIC[reg], vec![st(num), "%st".to_string()]),
+            (0xd9, 0) => decoded("fld", vec![st(num)]),
+            (0xd9, 1) => decoded("fxch", vec![st(num)]),
+            (0xd9, 2) if num == 0 => decoded("fnop", vec![]),
+            (0xd9, 4..=7) => {
+                let mnemonic = X87_D9[(reg - 4) * 8 + num];
+                if mnemonic.is_empty() {
+                    None
+                } else {
+                    decoded(mnemonic, vec![])
+                }
+            }
+            (0xda, 0..=3) => decoded(["fcmovb", "fcmove", "fcmovbe", "fcmovu"][reg], vec![st(num), "%st".to_string()]),
+            (0xda, 5) if num == 1 => decoded("fucompp", vec![]),
+            (0xdb, 0..=3) => decoded(["fcmovnb", "fcmovne", "fcmovnbe", "fcmovnu"][reg], vec![st(num), "%st".to_string()]),
+            (0xdb, 4) if num == 2 => decoded("fnclex", vec![]),
+            (0xdb, 4) if num == 3 => decoded("fninit", vec![]),
+            (0xdb, 5) => decoded("fucomi", vec![st(num), "%st".to_string()]),
+            (0xdb, 6) => decoded("fcomi", vec![st(num), "%st".to_string()]),
+            (0xdc, 0 | 1 | 4..=7) => decoded(X87_ARITHMETIC[reg], vec!["%st".to_string(), st(num)]),
+            (0xdd, 0) => decoded("ffree", vec![st(num)]),
+            (0xdd, 2) => decoded("fst", vec![st(num)]),
+            (0xdd, 3) => decoded("fstp", vec![st(num)]),
+            (0xdd, 4) => decoded("fucom", vec![st(num)]),
+            (0xdd, 5) => decoded("fucomp", vec![st(num)]),
+            (0xde, 3) if num == 1 => decoded("fcompp", vec![]),
+            (0xde, 0 | 1 | 4..=7) => decoded(&format!("{}p", X87_ARITHMETIC[reg]), vec!["%st".to_string(), st(num)]),
+            (0xdf, 4) if num == 0 => decoded("fnstsw", vec!["%ax".to_string()]),
+            (0xdf, 5) => decoded("fucomip", vec![st(num), "%st".to_string()]),
+            (0xdf, 6) => decoded("fcomip", vec![st(num), "%st".to_string()]),
+            _ => None,
+        }
+    }
+
+    /// Decodes an instruction of the 0f map, the opcode byte after 0x0f being next.
+    fn two_byte(&mut self) -> Decoded {
+        let op = self.byte()?;
+        let size = self.operand_size();
+        match op {
+            0x01 => match self.byte()? {
+                0xd0 => decoded("xgetbv", vec![]),
+                0xd5 => decoded("xend", vec![]),
+                0xd6 => decoded("xtest", vec![]),
+                0xee => decoded("rdpkru", vec![]),
+                0xef => decoded("wrpkru", vec![]),
+                0xf9 => decoded("rdtscp", vec![]),
+                _ => None,
+            },
+            0x05 => decoded("syscall", vec![]),
+            0x0b => decoded("ud2", vec![]),
+            0x0d => {
+                let m = self.modrm()?;
+                let mnemonic = if m.reg & 7 == 1 { "prefetchw" } else { "prefetch" };
+                decoded(mnemonic, vec![self.rm_gpr(&m.rm, 8)])
+            }
+            0x10..=0x17 | 0x28..=0x2f | 0x50..=0x7f | 0xc2 | 0xc4..=0xc6 | 0xd0..=0xff => self.sse(op),
+            0x18 => {
+                let m = self.modrm()?;
+                match (m.reg & 7, &m.rm) {
+                    (reg @ 0..=3, Rm::Mem(text)) => {
+                        decoded(["prefetchnta", "prefetcht0", "prefetcht1", "prefetcht2"][reg], vec![text.clone()])
+                    }
+                    _ => decoded(&self.sized("nop", &m.rm, size), vec![self.rm_gpr(&m.rm, size)]),
+                }
+            }
+            0x19..=0x1f => {
+                if op == 0x1e && self.rep == Some(0xf3) {
+                    match self.bytes.get(self.pos) {
+                        Some(0xfa) => {
+                            self.pos += 1;
+                            return decoded("endbr64", vec![]);
+                        }
+                        Some(0xfb) => {
+                            self.pos += 1;
+                            return decoded("endbr32", vec![]);
+                        }
+                        _ => {}
+                    }
+                }
+                let m = self.modrm()?;
+                decoded(&self.sized("nop", &m.rm, size), vec![self.rm_gpr(&m.rm, size)])
+            }
+            0x31 => decoded("rdtsc", vec![]),
+            0x38 => {
+                let op = self.byte()?;
+                self.map38(op)
+            }
+            0x3a => {
+                let op = self.byte()?;
+                self.map3a(op)
+            }
+            0x40..=0x4f => {
+                let m = self.modrm()?;
+                let mnemonic = format!("cmov{}", CONDITIONS[op as usize & 15]);
+                decoded(&mnemonic, vec![self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0x80..=0x8f => {
+                let mnemonic = format!("j{}", CONDITIONS[op as usize & 15]);
+                let target = self.branch(4)?;
+                decoded(&self.branch_mnemonic(&mnemonic, false), vec![target])
+            }
+            0x90..=0x9f => {
+                let m = self.modrm()?;
+                let mnemonic = format!("set{}", CONDITIONS[op as usize & 15]);
+                decoded(&mnemonic, vec![self.rm_gpr(&m.rm, 1)])
+            }
+            0xa0 => decoded("push", vec!["%fs".to_string()]),
+            0xa1 => decoded("pop", vec!["%fs".to_string()]),
+            0xa8 => decoded("push", vec!["%gs".to_string()]),
+            0xa9 => decoded("pop", vec!["%gs".to_string()]),
+            0xa2 => decoded("cpuid", vec![]),
+            0xa3 | 0xab | 0xb3 | 0xbb => {
+                let mnemonic = match op {
+                    0xa3 => "bt",
+                    0xab => "bts",
+                    0xb3 => "btr",
+                    _ => "btc",
+                };
+                let m = self.modrm()?;
+                decoded(mnemonic, vec![self.gpr(m.reg, size), self.rm_gpr(&m.rm, size)])
+            }
+            0xa4 | 0xa5 | 0xac | 0xad => {
+                let mnemonic = if op < 0xac { "shld" } else { "shrd" };
+                let m = self.modrm()?;
+                let count = if op & 1 == 0 { immediate(self.imm8()?, 1) } else { "%cl".to_string() };
+                decoded(mnemonic, vec![count, self.gpr(m.reg, size), self.rm_gpr(&m.rm, size)])
+            }
+            0xae => {
+                let m = self.modrm()?;
+                match (&m.rm, m.reg & 7) {
+                    (Rm::Mem(text), reg) => {
+                        let mnemonic =
+                            ["fxsave", "fxrstor", "ldmxcsr", "stmxcsr", "xsave", "xrstor", "xsaveopt", "clflush"][reg];
+                        decoded(mnemonic, vec![text.clone()])
+                    }
+                    (Rm::Reg(_), 5) => decoded("lfence", vec![]),
+                    (Rm::Reg(_), 6) => decoded("mfence", vec![]),
+                    (Rm::Reg(_), 7) => decoded("sfence", vec![]),
+                    _ => None,
+                }
+            }
+            0xaf => {
+                let m = self.modrm()?;
+                decoded("imul", vec![self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0xb0 | 0xb1 | 0xc0 | 0xc1 => {
+                let size = if op & 1 == 0 { 1 } else { size };
+                let mnemonic = if op < 0xc0 { "cmpxchg" } else { "xadd" };
+                let m = self.modrm()?;
+                decoded(mnemonic, vec![self.gpr(m.reg, size), self.rm_gpr(&m.rm, size)])
+            }
+            0xb6 | 0xb7 | 0xbe | 0xbf => {
+                let source_size = if op & 1 == 0 { 1 } else { 2 };
+                let extension = if op < 0xbe { "movz" } else { "movs" };
+                let mnemonic = format!("{}{}{}", extension, suffix(source_size), suffix(size));
+                let m = self.modrm()?;
+                decoded(&mnemonic, vec![self.rm_gpr(&m.rm, source_size), self.gpr(m.reg, size)])
+            }
+            0xb8 if self.rep == Some(0xf3) => {
+                let m = self.modrm()?;
+                decoded("popcnt", vec![self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0xba => {
+                let m = self.modrm()?;
+                let mnemonic = match m.reg & 7 {
+                    4 => "bt",
+                    5 => "bts",
+                    6 => "btr",
+                    7 => "btc",
+                    _ => return None,
+                };
+                let imm = self.imm8()?;
+                decoded(&self.sized(mnemonic, &m.rm, size), vec![immediate(imm, 1), self.rm_gpr(&m.rm, size)])
+            }
+            0xbc | 0xbd => {
+                let mnemonic = match (op, self.rep) {
+                    (0xbc, Some(0xf3)) => "tzcnt",
+                    (0xbd, Some(0xf3)) => "lzcnt",
+                    (0xbc, _) => "bsf",
+                    _ => "bsr",
+                };
+                let m = self.modrm()?;
+                decoded(mnemonic, vec![self.rm_gpr(&m.rm, size), self.gpr(m.reg, size)])
+            }
+            0xc7 => {
+                let m = self.modrm()?;
+                match (m.reg & 7, &m.rm) {
+                    (1, Rm::Mem(text)) => {
+                        let mnemonic = if self.rex_w() { "cmpxchg16b" } else { "cmpxchg8b" };
+                        decoded(mnemonic, vec![text.clone()])
+                    }
+                    (6, Rm::Reg(num)) => decoded("rdrand", vec![self.gpr(*num, size)]),
+                    (7, Rm::Reg(num)) => decoded("rdseed", vec![self.gpr(*num, size)]),
+                    _ => None,
+                }
+            }
+            0xc8..=0xcf => {
+                let size = if self.rex_w() { 8 } else { 4 };
+                decoded("bswap", vec![self.gpr((op as usize & 7) | self.rex_b(), size)])
+            }
+            _ => None,
+        }
+    }
+
+    /// Finishes a vector instruction: VEX-encoded forms get their `v` and, if `nds` is set, the
+    /// extra source register, which goes just before the destination.
+    fn vector_decoded(&self, mnemonic: &str, mut operands: Vec<String>, nds: bool) -> Decoded {
+        match &self.vex {
+            Some(vex) => {
+                if nds {
+                    let extra = self.vector(vex.reg, VectorRegs::Xmm);
+                    operands.insert(operands.len() - 1, extra);
+                }
+                decoded(&format!("v{}", mnemonic), operands)
+            }
+            None => decoded(mnemonic, operands),
+        }
+    }
+
+    /// An instruction that reads its ModRM operand into the register of the reg field.
+    fn vector_load(&mut self, mnemonic: &str, regs: VectorRegs, nds: bool) -> Decoded {
+        let m = self.modrm()?;
+        let operands = vec![self.rm_vector(&m.rm, regs), self.vector(m.reg, regs)];
+        self.vector_decoded(mnemonic, operands, nds)
+    }
+
+    /// An instruction that stores the register of the reg field into its ModRM operand.
+    fn vector_store(&mut self, mnemonic: &str, regs: VectorRegs) -> Decoded {
+        let m = self.modrm()?;
+        let operands = vec![self.vector(m.reg, regs), self.rm_vector(&m.rm, regs)];
+        self.vector_decoded(mnemonic, operands, false)
+    }
+
+    /// Like `vector_load`, with an immediate byte after the ModRM operand.
+    fn vector_load_imm(&mut self, mnemonic: &str, regs: VectorRegs, nds: bool) -> Decoded {
+        let m = self.modrm()?;
+        let imm = immediate(self.imm8()?, 1);
+        let operands = vec![imm, self.rm_vector(&m.rm, regs), self.vector(m.reg, regs)];
+        self.vector_decoded(mnemonic, operands, nds)
+    }
+
+    /// Decodes the MMX, SSE and (with a VEX prefix) AVX instructions of the 0f map.
+    fn sse(&mut self, op: u8) -> Decoded {
+        use VectorRegs::{Mmx, Xmm};
+        let prefix = self.sse_prefix();
+        let float = FLOAT_SUFFIXES[prefix];
+        let scalar = prefix >= 2;
+        let gpr_size = if self.rex_w() { 8 } else { 4 };
+        // the MMX form of an integer instruction has no prefix, the SSE one 66
+        let integer_regs = match prefix {
+            0 if self.vex.is_none() => Some(Mmx),
+            1 => Some(Xmm),
+            _ => None,
+        };
+        match op {
+            0x10 | 0x11 => {
+                let mnemonic = ["movups", "movupd", "movss", "movsd"][prefix];
+                let m = self.modrm()?;
+                let (reg, rm) = (self.vector(m.reg, Xmm), self.rm_vector(&m.rm, Xmm));
+                let operands = if op == 0x10 { vec![rm, reg] } else { vec![reg, rm] };
+                // only the VEX scalar moves between registers merge in a second source
+                self.vector_decoded(mnemonic, operands, scalar && !m.rm.is_mem())
+            }
+            0x12 | 0x16 => {
+                let m = self.modrm()?;
+                let high = op == 0x16;
+                let mnemonic = match (prefix, m.rm.is_mem()) {
+                    (0, false) => if high { "movlhps" } else { "movhlps" },
+                    (0, true) => if high { "movhps" } else { "movlps" },
+                    (1, _) => if high { "movhpd" } else { "movlpd" },
+                    (2, _) => if high { "movshdup" } else { "movsldup" },
+                    _ if !high => "movddup",
+                    _ => return None,
+                };
+                let operands = vec![self.rm_vector(&m.rm, Xmm), self.vector(m.reg, Xmm)];
+                self.vector_decoded(mnemonic, operands, prefix < 2)
+            }
+            0x13 | 0x17 if prefix < 2 => {
+                let mnemonic = match (op, prefix) {
+                    (0x13, 0) => "movlps",
+                    (0x13, _) => "movlpd",
+                    (_, 0) => "movhps",
+                    _ => "movhpd",
+                };
+                self.vector_store(mnemonic, Xmm)
+            }
+            0x14 | 0x15 if prefix < 2 => {
+                let mnemonic = format!("unpck{}{}", if op == 0x14 { "l" } else { "h" }, float);
+                self.vector_load(&mnemonic, Xmm, true)
+            }
+            0x28 if prefix < 2 => self.vector_load(&format!("mova{}", float), Xmm, false),
+            0x29 if prefix < 2 => self.vector_store(&format!("mova{}", float), Xmm),
+            0x2b if prefix < 2 => self.vector_store(&format!("movnt{}", float), Xmm),
+            0x2a => {
+                let m = self.modrm()?;
+                if scalar {
+                    let mut mnemonic = format!("cvtsi2{}", float);
+                    if m.rm.is_mem() {
+                        mnemonic.push_str(suffix(gpr_size));
+                    }
+                    let operands = vec![self.rm_gpr(&m.rm, gpr_size), self.vector(m.reg, Xmm)];
+                    self.vector_decoded(&mnemonic, operands, true)
+                } else {
+                    let mnemonic = format!("cvtpi2{}", float);
+                    decoded(&mnemonic, vec![self.rm_vector(&m.rm, Mmx), self.vector(m.reg, Xmm)])
+                }
+            }
+            0x2c | 0x2d => {
+                let truncate = if op == 0x2c { "t" } else { "" };
+                let m = self.modrm()?;
+                if scalar {
+                    let mnemonic = format!("cvt{}{}2si", truncate, float);
+                    let operands = vec![self.rm_vector(&m.rm, Xmm), self.gpr(m.reg, gpr_size)];
+                    self.vector_decoded(&mnemonic, operands, false)
+                } else {
+                    let mnemonic = format!("cvt{}{}2pi", truncate, float);
+                    decoded(&mnemonic, vec![self.rm_vector(&m.rm, Xmm), self.vector(m.reg, Mmx)])
+                }
+            }
+            0x2e | 0x2f if prefix < 2 => {
+                let mnemonic = format!("{}comis{}", if op == 0x2e { "u" } else { "" }, &float[1..]);
+                self.vector_load(&mnemonic, Xmm, false)
+            }
+            0x50 if prefix < 2 => {
+                let m = self.modrm()?;
+                let operands = vec![self.rm_vector(&m.rm, Xmm), self.gpr(m.reg, 4)];
+                self.vector_decoded(&format!("movmsk{}", float), operands, false)
+            }
+            0x51 => self.vector_load(&format!("sqrt{}", float), Xmm, scalar),
+            0x52 | 0x53 if prefix == 0 || prefix == 2 => {
+                let mnemonic = format!("{}{}", if op == 0x52 { "rsqrt" } else { "rcp" }, float);
+                self.vector_load(&mnemonic, Xmm, scalar)
+            }
+            0x54..=0x57 if prefix < 2 => {
+                let mnemonic = format!("{}{}", ["and", "andn", "or", "xor"][op as usize - 0x54], float);
+                self.vector_load(&mnemonic, Xmm, true)
+            }
+            0x58 | 0x59 | 0x5c..=0x5f => {
+                let operation = match op {
+                    0x58 => "add",
+                    0x59 => "mul",
+                    0x5c => "sub",
+                    0x5d => "min",
+                    0x5e => "div",
+                    _ => "max",
+                };
+                self.vector_load(&format!("{}{}", operation, float), Xmm, true)
+            }
+            0x5a => {
+                let mnemonic = ["cvtps2pd", "cvtpd2ps", "cvtss2sd", "cvtsd2ss"][prefix];
+                self.vector_load(mnemonic, Xmm, scalar)
+            }
+            0x5b if prefix < 3 => {
+                let mnemonic = ["cvtdq2ps", "cvtps2dq", "cvttps2dq"][prefix];
+                self.vector_load(mnemonic, Xmm, false)
+            }
+            0x6c | 0x6d if prefix == 1 => {
+                let mnemonic = if op == 0x6c { "punpcklqdq" } else { "punpckhqdq" };
+                self.vector_load(mnemonic, Xmm, true)
+            }
+            0x6e | 0x7e if prefix < 2 => {
+                let regs = integer_regs.unwrap_or(Xmm);
+                let m = self.modrm()?;
+                let mnemonic = if self.rex_w() { "movq" } else { "movd" };
+                let vector = self.vector(m.reg, regs);
+                let gpr = self.rm_gpr(&m.rm, gpr_size);
+                let operands = if op == 0x6e { vec![gpr, vector] } else { vec![vector, gpr] };
+                self.vector_decoded(mnemonic, operands, false)
+            }
+            0x7e if prefix == 2 => self.vector_load("movq", Xmm, false),
+            0x6f | 0x7f => {
+                let (mnemonic, regs) = match prefix {
+                    0 => ("movq", Mmx),
+                    1 => ("movdqa", Xmm),
+                    2 => ("movdqu", Xmm),
+                    _ => return None,
+                };
+                if op == 0x6f {
+                    self.vector_load(mnemonic, regs, false)
+                } else {
+                    self.vector_store(mnemonic, regs)
+                }
+            }
+            0x70 => {
+                let (mnemonic, regs) = match prefix {
+                    0 => ("pshufw", Mmx),
+                    1 => ("pshufd", Xmm),
+                    2 => ("pshufhw", Xmm),
+                    _ => ("pshuflw", Xmm),
+                };
+                self.vector_load_imm(mnemonic, regs, false)
+            }
+            0x71..=0x73 => {
+                let regs = integer_regs?;
+                let m = self.modrm()?;
+                let mnemonic = match (op, m.reg & 7) {
+                    (0x71, 2) => "psrlw",
+                    (0x71, 4) => "psraw",
+                    (0x71, 6) => "psllw",
+                    (0x72, 2) => "psrld",
+                    (0x72, 4) => "psrad",
+                    (0x72, 6) => "pslld",
+                    (0x73, 2) => "psrlq",
+                    (0x73, 3) if prefix == 1 => "psrldq",
+                    (0x73, 6) => "psllq",
+                    (0x73, 7) if prefix == 1 => "pslldq",
+                    _ => return None,
+                };
+                let mut operands = vec![immediate(self.imm8()?, 1), self.rm_vector(&m.rm, regs)];
+                // with VEX, vvvv is the destination rather than a source
+                if let Some(vex) = &self.vex {
+                    operands.push(self.vector(vex.reg, regs));
+                }
+                self.vector_decoded(mnemonic, operands, false)
+            }
+            0x77 => match &self.vex {
+                Some(vex) if vex.long => decoded("vzeroall", vec![]),
+                Some(_) => decoded("vzeroupper", vec![]),
+                None if prefix == 0 => decoded("emms", vec![]),
+                None => None,
+            },
+            0xc2 => {
+                let m = self.modrm()?;
+                let predicate = self.imm8()? as u8;
+                let operands = vec![self.rm_vector(&m.rm, Xmm), self.vector(m.reg, Xmm)];
+                match COMPARISONS.get(predicate as usize) {
+                    Some(name) => self.vector_decoded(&format!("cmp{}{}", name, float), operands, true),
+                    None => {
+                        let mut operands = operands;
+                        operands.insert(0, immediate(predicate as i64, 1));
+                        self.vector_decoded(&format!("cmp{}", float), operands, true)
+                    }
+                }
+            }
+            0xc6 if prefix < 2 => self.vector_load_imm(&format!("shuf{}", float), Xmm, true),
+            0xc4 | 0xc5 if prefix < 2 => {
+                let regs = integer_regs.unwrap_or(Xmm);
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                let operands = if op == 0xc4 {
+                    vec![imm, self.rm_gpr(&m.rm, 4), self.vector(m.reg, regs)]
+                } else {
+                    vec![imm, self.rm_vector(&m.rm, regs), self.gpr(m.reg, 4)]
+                };
+                self.vector_decoded(if op == 0xc4 { "pinsrw" } else { "pextrw" }, operands, op == 0xc4)
+            }
+            0xd6 if prefix == 1 => self.vector_store("movq", Xmm),
+            0xd7 => {
+                let regs = integer_regs?;
+                let m = self.modrm()?;
+                let operands = vec![self.rm_vector(&m.rm, regs), self.gpr(m.reg, 4)];
+                self.vector_decoded("pmovmskb", operands, false)
+            }
+            0xe6 if prefix > 0 => {
+                let mnemonic = ["", "cvttpd2dq", "cvtdq2pd", "cvtpd2dq"][prefix];
+                self.vector_load(mnemonic, Xmm, false)
+            }
+            0xe7 => match integer_regs? {
+                Mmx => self.vector_store("movntq", Mmx),
+                Xmm => self.vector_store("movntdq", Xmm),
+            },
+            0xf0 if prefix == 3 => self.vector_load("lddqu", Xmm, false),
+            _ => {
+                let mnemonic = packed_integer(op)?;
+                self.vector_load(mnemonic, integer_regs?, true)
+            }
+        }
+    }
+
+    /// Decodes an instruction of the 0f 38 map.
+    fn map38(&mut self, op: u8) -> Decoded {
+        let prefix = self.sse_prefix();
+        let gpr_size = if self.rex_w() { 8 } else { 4 };
+        if let Some((mnemonic, nds)) = map38_vector(op) {
+            let regs = match prefix {
+                0 if self.vex.is_none() && nds => VectorRegs::Mmx,
+                1 => VectorRegs::Xmm,
+                _ => return self.unknown_with_modrm(0),
+            };
+            return self.vector_load(mnemonic, regs, nds);
+        }
+        match (op, prefix, self.vex.is_some()) {
+            (0x18 | 0x58 | 0x59 | 0x78 | 0x79, 1, true) => {
+                let mnemonic = match op {
+                    0x18 => "broadcastss",
+                    0x58 => "pbroadcastd",
+                    0x59 => "pbroadcastq",
+                    0x78 => "pbroadcastb",
+                    _ => "pbroadcastw",
+                };
+                // the source is always an xmm register
+                let m = self.modrm()?;
+                let source = match &m.rm {
+                    Rm::Reg(num) => format!("%xmm{}", num),
+                    Rm::Mem(text) => text.clone(),
+                };
+                let operands = vec![source, self.vector(m.reg, VectorRegs::Xmm)];
+                self.vector_decoded(mnemonic, operands, false)
+            }
+            (0xf0 | 0xf1, 0 | 1, false) => {
+                let m = self.modrm()?;
+                if !m.rm.is_mem() {
+                    return None;
+                }
+                let size = self.operand_size();
+                let (reg, rm) = (self.gpr(m.reg, size), self.rm_gpr(&m.rm, size));
+                if op == 0xf0 {
+                    decoded("movbe", vec![rm, reg])
+                } else {
+                    decoded("movbe", vec![reg, rm])
+                }
+            }
+            (0xf0 | 0xf1, 3, false) => {
+                let m = self.modrm()?;
+                let size = if op == 0xf0 { 1 } else { self.operand_size() };
+                let mnemonic = format!("crc32{}", suffix(size));
+                let destination = self.gpr(m.reg, gpr_size);
+                decoded(&mnemonic, vec![self.rm_gpr(&m.rm, size), destination])
+            }
+            (0xf2, 0, true) => self.bmi("andn", false),
+            (0xf3, 0, true) => {
+                let m = self.modrm()?;
+                let mnemonic = match m.reg & 7 {
+                    1 => "blsr",
+                    2 => "blsmsk",
+                    3 => "blsi",
+                    _ => return None,
+                };
+                let destination = self.gpr(self.vex.as_ref()?.reg, gpr_size);
+                decoded(mnemonic, vec![self.rm_gpr(&m.rm, gpr_size), destination])
+            }
+            (0xf5, 0, true) => self.bmi("bzhi", true),
+            (0xf5, 2, true) => self.bmi("pext", false),
+            (0xf5, 3, true) => self.bmi("pdep", false),
+            (0xf7, _, true) => self.bmi(["bextr", "shlx", "sarx", "shrx"][prefix], true),
+            _ => self.unknown_with_modrm(0),
+        }
+    }
+
+    /// A VEX-encoded general-purpose instruction with the registers of the reg field, the
+    /// ModRM operand and VEX.vvvv. `count_last` puts the vvvv register first, as it is for the
+    /// instructions where it is a shift count or index.
+    fn bmi(&mut self, mnemonic: &str, count_last: bool) -> Decoded {
+        let size = if self.rex_w() { 8 } else { 4 };
+        let m = self.modrm()?;
+        let extra = self.gpr(self.vex.as_ref()?.reg, size);
+        let rm = self.rm_gpr(&m.rm, size);
+        let mut operands = if count_last { vec![extra, rm] } else { vec![rm, extra] };
+        operands.push(self.gpr(m.reg, size));
+        decoded(mnemonic, operands)
+    }
+
+    /// Decodes an instruction of the 0f 3a map, all of which end in an immediate byte.
+    fn map3a(&mut self, op: u8) -> Decoded {
+        use VectorRegs::{Mmx, Xmm};
+        let prefix = self.sse_prefix();
+        let vex = self.vex.is_some();
+        let gpr_size = if self.rex_w() { 8 } else { 4 };
+        match (op, prefix) {
+            (0x0f, 0) if !vex => self.vector_load_imm("palignr", Mmx, false),
+            (0x0f, 1) => self.vector_load_imm("palignr", Xmm, true),
+            (0x08..=0x0b, 1) => {
+                let mnemonic = ["roundps", "roundpd", "roundss", "roundsd"][op as usize - 0x08];
+                self.vector_load_imm(mnemonic, Xmm, op >= 0x0a)
+            }
+            (0x0c..=0x0e, 1) => {
+                let mnemonic = ["blendps", "blendpd", "pblendw"][op as usize - 0x0c];
+                self.vector_load_imm(mnemonic, Xmm, true)
+            }
+            (0x14 | 0x16, 1) => {
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                let (mnemonic, size) = match (op, self.rex_w()) {
+                    (0x14, _) => ("pextrb", 4),
+                    (_, true) => ("pextrq", 8),
+                    _ => ("pextrd", 4),
+                };
+                let operands = vec![imm, format!("%xmm{}", m.reg), self.rm_gpr(&m.rm, size)];
+                self.vector_decoded(mnemonic, operands, false)
+            }
+            (0x20 | 0x22, 1) => {
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                let (mnemonic, size) = match (op, self.rex_w()) {
+                    (0x20, _) => ("pinsrb", 4),
+                    (_, true) => ("pinsrq", 8),
+                    _ => ("pinsrd", 4),
+                };
+                let operands = vec![imm, self.rm_gpr(&m.rm, size), format!("%xmm{}", m.reg)];
+                self.vector_decoded(mnemonic, operands, true)
+            }
+            (0x44, 1) => self.vector_load_imm("pclmulqdq", Xmm, true),
+            (0x60..=0x63, 1) => {
+                let mnemonic = ["pcmpestrm", "pcmpestri", "pcmpistrm", "pcmpistri"][op as usize - 0x60];
+                self.vector_load_imm(mnemonic, Xmm, false)
+            }
+            (0x18 | 0x38, 1) if vex => {
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                let mnemonic = if op == 0x18 { "vinsertf128" } else { "vinserti128" };
+                let source = match &m.rm {
+                    Rm::Reg(num) => format!("%xmm{}", num),
+                    Rm::Mem(text) => text.clone(),
+                };
+                let extra = self.vector(self.vex.as_ref()?.reg, Xmm);
+                decoded(mnemonic, vec![imm, source, extra, self.vector(m.reg, Xmm)])
+            }
+            (0x19 | 0x39, 1) if vex => {
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                let mnemonic = if op == 0x19 { "vextractf128" } else { "vextracti128" };
+                let destination = match &m.rm {
+                    Rm::Reg(num) => format!("%xmm{}", num),
+                    Rm::Mem(text) => text.clone(),
+                };
+                decoded(mnemonic, vec![imm, self.vector(m.reg, Xmm), destination])
+            }
+            (0x06 | 0x46, 1) if vex => {
+                let mnemonic = if op == 0x06 { "perm2f128" } else { "perm2i128" };
+                self.vector_load_imm(mnemonic, Xmm, true)
+            }
+            (0xf0, 3) if vex => {
+                let m = self.modrm()?;
+                let imm = immediate(self.imm8()?, 1);
+                decoded("rorx", vec![imm, self.rm_gpr(&m.rm, gpr_size), self.gpr(m.reg, gpr_size)])
+            }
+            _ => self.unknown_with_modrm(1),
+        }
+    }
+
+    /// Skips the ModRM operand and `immediate_size` bytes of an instruction the decoder doesn't
+    /// know, so at least its length is right.
+    fn unknown_with_modrm(&mut self, immediate_size: usize) -> Decoded {
+        self.modrm()?;
+        if immediate_size > 0 {
+            self.read(immediate_size)?;
+        }
+        decoded("(bad)", vec![])
+    }
+
+    /// Skips over an AVX-512 instruction, which the decoder doesn't know but can still find the
+    /// length of: the EVEX prefix, the opcode, a ModRM operand and sometimes an immediate byte.
+    fn evex_instruction(&mut self) -> Decoded {
+        let map = self.byte()? & 3;
+        self.read(2)?;
+        let op = self.byte()?;
+        let has_immediate = map == 3 || (map == 1 && matches!(op, 0x70..=0x73 | 0xc2 | 0xc4..=0xc6));
+        self.unknown_with_modrm(if has_immediate { 1 } else { 0 })
+    }
+
+    /// Decodes a VEX prefix and the instruction it introduces.
+    fn vex_instruction(&mut self, op: u8) -> Decoded {
+        let first = self.byte()?;
+        let (map, last) = if op == 0xc5 {
+            self.rex = 0x40 | ((!first >> 5) & 4);
+            (1, first)
+        } else {
+            let last = self.byte()?;
+            self.rex = 0x40 | ((!first >> 5) & 7) | ((last >> 4) & 8);
+            (first & 0x1f, last)
+        };
+        self.vex = Some(Vex {
+            long: last & 4 != 0,
+            reg: (!last as usize >> 3) & 15,
+            prefix: last as usize & 3,
+        });
+        let op = self.byte()?;
+        match map {
+            1 => match op {
+                0x10..=0x17 | 0x28..=0x2f | 0x50..=0x7f | 0xc2 | 0xc4..=0xc6 | 0xd0..=0xff => self.sse(op),
+                _ => self.unknown_with_modrm(0),
+            },
+            2 => self.map38(op),
+            3 => self.map3a(op),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ADDR: usize = 0x401000;
+
+    /// Decodes an instruction followed by nops, as it would be read out of a function.
+    fn decode_padded(bytes: &[u8]) -> Instruction {
+        let mut padded = bytes.to_vec();
+        padded.resize(MAX_LEN, 0x90);
+        decode(&padded, ADDR)
+    }
+
+    #[test]
+    fn test_decode() {
+        // as objdump -d prints them
+        let cases: [(&[u8], &str); 31] = [
+            (&[0x55], "push   %rbp"),
+            (&[0x41, 0x55], "push   %r13"),
+            (&[0x48, 0x89, 0xe5], "mov    %rsp,%rbp"),
+            (&[0x4c, 0x89, 0xc7], "mov    %r8,%rdi"),
+            (&[0x45, 0x31, 0xc0], "xor    %r8d,%r8d"),
+            (&[0x4d, 0x85, 0xe4], "test   %r12,%r12"),
+            (&[0x40, 0x88, 0xf7], "mov    %sil,%dil"),
+            (&[0x88, 0xe0], "mov    %ah,%al"),
+            (&[0x48, 0x83, 0xec, 0x10], "sub    $0x10,%rsp"),
+            (&[0x89, 0x7d, 0xfc], "mov    %edi,-0x4(%rbp)"),
+            (&[0x8b, 0x44, 0x24, 0x08], "mov    0x8(%rsp),%eax"),
+            (&[0x48, 0x8b, 0x04, 0xc5, 0x00, 0x10, 0x40, 0x00], "mov    0x401000(,%rax,8),%rax"),
+            (&[0x42, 0x8b, 0x04, 0xa8], "mov    (%rax,%r13,4),%eax"),
+            (&[0x4a, 0x8d, 0x0c, 0x2b], "lea    (%rbx,%r13,1),%rcx"),
+            (&[0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00], "nopw   0x0(%rax,%rax,1)"),
+            (&[0xc7, 0x45, 0xf8, 0x01, 0x00, 0x00, 0x00], "movl   $0x1,-0x8(%rbp)"),
+            (&[0x48, 0xc7, 0xc0, 0xff, 0xff, 0xff, 0xff], "mov    $0xffffffffffffffff,%rax"),
+            (&[0x48, 0xb8, 0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01], "movabs $0x123456789abcdef,%rax"),
+            (&[0x48, 0x8d, 0x05, 0xc8, 0x0e, 0x00, 0x00], "lea    0xec8(%rip),%rax"),
+            (&[0xff, 0x15, 0xf2, 0x0f, 0x00, 0x00], "call   *0xff2(%rip)"),
+            (&[0xe8, 0x21, 0x00, 0x00, 0x00], "call   0x401026"),
+            (&[0xe8, 0xf6, 0xff, 0xff, 0xff], "call   0x400ffb"),
+            (&[0xeb, 0xfe], "jmp    0x401000"),
+            (&[0x74, 0x05], "je     0x401007"),
+            (&[0x0f, 0x84, 0x10, 0x00, 0x00, 0x00], "je     0x401016"),
+            (&[0xff, 0xe0], "jmp    *%rax"),
+            (&[0x41, 0xff, 0xd4], "call   *%r12"),
+            (&[0xc3], "ret"),
+            (&[0xf3, 0x0f, 0x1e, 0xfa], "endbr64"),
+            (&[0xf2, 0x0f, 0x10, 0x45, 0xf0], "movsd  -0x10(%rbp),%xmm0"),
+            (&[0x0f, 0x0b], "ud2"),
+        ];
+        for (bytes, text) in cases {
+            let instruction = decode_padded(bytes);
+            assert_eq!(instruction.text, text, "{:02x?}", bytes);
+            assert_eq!(instruction.len, bytes.len(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        let call = decode_padded(&[0xe8, 0xf6, 0xff, 0xff, 0xff]);
+        assert_eq!((call.target, call.reference), (Some(0x400ffb), None));
+        let jump = decode_padded(&[0x74, 0x05]);
+        assert_eq!((jump.target, jump.reference), (Some(0x401007), None));
+        // a %rip-relative operand counts from the end of the instruction
+        let lea = decode_padded(&[0x48, 0x8d, 0x05, 0xc8, 0x0e, 0x00, 0x00]);
+        assert_eq!((lea.target, lea.reference), (None, Some(0x401ecf)));
+        let indirect = decode_padded(&[0xff, 0x15, 0xf2, 0x0f, 0x00, 0x00]);
+        assert_eq!((indirect.target, indirect.reference), (None, Some(0x401ff8)));
+        let register = decode_padded(&[0xff, 0xe0]);
+        assert_eq!((register.target, register.reference), (None, None));
+    }
+
+    #[test]
+    fn test_bad() {
+        // 06 was push %es, which 64-bit mode dropped
+        for bytes in [&[0x06][..], &[0x0f, 0x04]] {
+            let instruction = decode_padded(bytes);
+            assert_eq!(instruction.text, "(bad)", "{:02x?}", bytes);
+            assert_eq!(instruction.len, 1, "{:02x?}", bytes);
+        }
+        // an instruction cut off by the end of readable memory
+        let truncated = decode(&[0xe8, 0x21], ADDR);
+        assert_eq!(truncated.text, "(bad)");
+        assert_eq!(truncated.len, 1);
+        assert_eq!(decode(&[], ADDR).text, "(bad)");
+    }
+}
@@ -1,6 +1,9 @@
 use crate::gimli_wrapper;
+use crate::location::Location;
+use crate::utils;
 use addr2line::Context;
 use object::Object;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
@@ -12,6 +15,9 @@ pub enum Error {
 
 pub struct DwarfData {
     files: Vec<File>,
+    types: Types,
+    /// How far the object file has been moved from its link-time addresses in the inferior
+    bias: usize,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
 }
 
@@ -38,20 +44,59 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
+            files,
+            types,
+            bias: 0,
             addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
         })
     }
 
-    #[allow(dead_code)]
+    /// Moves the addresses of functions, lines and static variables to where the object file is
+    /// loaded in the inferior, `bias` bytes above its link-time addresses. Position-independent
+    /// executables and shared libraries are only placed once they are mapped.
+    pub fn set_load_bias(&mut self, bias: usize) {
+        let delta = bias.wrapping_sub(self.bias);
+        for file in &mut self.files {
+            for func in &mut file.functions {
+                func.address = func.address.wrapping_add(delta);
+                if let Some(frame_base) = &mut func.frame_base {
+                    frame_base.relocate(delta);
+                }
+            }
+            for line in &mut file.lines {
+                line.address = line.address.wrapping_add(delta);
+            }
+            let variables = file
+                .global_variables
+                .iter_mut()
+                .chain(file.functions.iter_mut().flat_map(|func| func.variables.iter_mut()));
+            for var in variables {
+                var.location.relocate(delta);
+            }
+        }
+        self.bias = bias;
+    }
+
+    pub fn load_bias(&self) -> usize {
+        self.bias
+    }
+
+    pub fn types(&self) -> &Types {
+        &self.types
+    }
+
     fn get_target_file(&self, file: &str) -> Option<&File> {
+        // a unit compiled from a relative path also matches the file's full path, as front ends
+        // give it
         self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+            f.name == file
+                || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+                || (!f.name.starts_with('/') && file.ends_with(&format!("/{}", f.name)))
         })
     }
 
-    #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
@@ -66,7 +111,6 @@ impl DwarfData {
         )
     }
 
-    #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
         match file {
             Some(filename) => Some(
@@ -87,11 +131,89 @@ impl DwarfData {
         }
     }
 
+    /// Returns the source file and line a function is declared on.
+    pub fn get_function_line(&self, file: Option<&str>, func_name: &str) -> Option<Line> {
+        let func = match file {
+            Some(filename) => self
+                .get_target_file(filename)?
+                .functions
+                .iter()
+                .find(|func| func.name == func_name)?,
+            None => self
+                .files
+                .iter()
+                .flat_map(|file| file.functions.iter())
+                .find(|func| func.name == func_name)?,
+        };
+        Some(Line {
+            file: self.get_line_from_addr(func.address)?.file,
+            number: func.line_number,
+            address: func.address,
+        })
+    }
+
+    /// Returns the full path of a source file given by name, as the line table records it.
+    pub fn get_source_path(&self, file: &str) -> Option<String> {
+        let target_file = self.get_target_file(file)?;
+        Some(self.get_line_from_addr(target_file.lines.first()?.address)?.file)
+    }
+
+    /// Returns the address of the first line-table row after the start of the function beginning
+    /// at `func_addr`. Stopping there skips the prologue, so the frame is already set up.
+    pub fn get_addr_after_prologue(&self, func_addr: usize) -> usize {
+        for file in &self.files {
+            if let Some(func) = file.functions.iter().find(|func| func.address == func_addr) {
+                return file
+                    .lines
+                    .iter()
+                    .map(|line| line.address)
+                    .filter(|&addr| addr > func.address && addr < func.address + func.text_length)
+                    .min()
+                    .unwrap_or(func_addr);
+            }
+        }
+        func_addr
+    }
+
+    /// Returns true if `addr` begins a row in the line table, i.e. the first instruction of a
+    /// line rather than one in the middle of it.
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.lines.iter().any(|line| line.address == addr))
+    }
+
+    /// Returns the function whose code contains `addr`.
+    pub fn get_function_containing(&self, addr: usize) -> Option<&Function> {
+        self.files.iter().flat_map(|file| file.functions.iter()).find(|func| {
+            addr >= func.address && addr < func.address + func.text_length
+        })
+    }
+
+    /// Returns the frame base of the function containing `addr`, which the locations of its
+    /// variables may refer to.
+    pub fn get_frame_base(&self, addr: usize) -> Option<&Location> {
+        self.get_function_containing(addr)?.frame_base.as_ref()
+    }
+
+    /// Looks up a variable by name as seen from code at `addr`: variables of the enclosing
+    /// function first, then globals.
+    pub fn get_variable(&self, addr: usize, name: &str) -> Option<&Variable> {
+        self.get_function_containing(addr)
+            .and_then(|func| func.variables.iter().find(|var| var.name == name))
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .flat_map(|file| file.global_variables.iter())
+                    .find(|var| var.name == name)
+            })
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(self.bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -104,7 +226,7 @@ impl DwarfData {
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(curr_addr.wrapping_sub(self.bias).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
@@ -148,40 +270,573 @@ impl DwarfData {
     }
 }
 
+/// How the bytes of a base type are interpreted (DW_AT_encoding).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    #[default]
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Float,
+    Boolean,
+    Address,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
+    /// The name C would write the type with, e.g. `struct node *` or `int [4]`
     pub name: String,
     pub size: usize,
+    /// How the bytes are interpreted as a number. Typedefs and qualified types take this and
+    /// their size from the type they stand for.
+    pub encoding: Encoding,
+    pub kind: TypeKind,
+    /// The offset of the type's entry, by which other types refer to it. Types made up by the
+    /// debugger, like the result of `&x` when the program never uses `&x`, have none.
+    pub id: Option<usize>,
+}
+
+/// What a type is made of. Other types are referred to by the offset of their entry in the
+/// debugging info, which `Types::get` looks up, with None standing for void.
+#[derive(Debug, Clone, Default)]
+pub enum TypeKind {
+    #[default]
+    Base,
+    Pointer(Option<usize>),
+    /// A struct, union or class, whose keyword is part of its name
+    Struct(Vec<Member>),
+    /// An array, of unknown length if there is no count. Each dimension of a multidimensional
+    /// array is an array of the next one.
+    Array {
+        element: usize,
+        count: Option<usize>,
+    },
+    /// An enum and its enumerators, whose values are kept as unsigned bytes of the enum's size
+    Enum(Vec<(String, u64)>),
+    Typedef(Option<usize>),
+    /// `const`, `volatile`, `restrict` or `_Atomic`
+    Qualified(&'static str, Option<usize>),
+    Function {
+        return_type: Option<usize>,
+        params: Vec<Option<usize>>,
+        variadic: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Empty for the anonymous structs and unions C11 allows inside others
+    pub name: String,
+    pub entity_type: Option<usize>,
+    /// Byte offset from the start of the struct
+    pub offset: usize,
+    /// For a bit field, the position of its lowest bit counting from `offset`, and its width
+    pub bits: Option<(usize, usize)>,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
+    pub fn new(name: String, size: usize, encoding: Encoding) -> Self {
         Type {
-            name: name,
-            size: size,
+            name,
+            size,
+            encoding,
+            kind: TypeKind::Base,
+            id: None,
+        }
+    }
+
+    /// Returns true for the one-byte character types, whose arrays and pointers are shown as
+    /// strings.
+    fn is_char(&self) -> bool {
+        self.size == 1 && matches!(self.encoding, Encoding::SignedChar | Encoding::UnsignedChar)
+    }
+
+    /// Decodes up to 8 little-endian bytes as an integer, returning it both zero- and
+    /// sign-extended.
+    pub fn decode_integer(bytes: &[u8]) -> (u64, i64) {
+        let mut raw = [0u8; 8];
+        let len = bytes.len().min(8);
+        raw[..len].copy_from_slice(&bytes[..len]);
+        let unsigned = u64::from_le_bytes(raw);
+        let shift = 64 - 8 * len.max(1) as u32;
+        (unsigned, ((unsigned << shift) as i64) >> shift)
+    }
+
+    /// Interprets a value of this type as a number, e.g. for comparisons.
+    pub fn numeric_value(&self, bytes: &[u8]) -> f64 {
+        let (unsigned, signed) = Type::decode_integer(bytes);
+        match self.encoding {
+            Encoding::Signed | Encoding::SignedChar => signed as f64,
+            Encoding::Float => match bytes.len() {
+                4 => f32::from_bits(unsigned as u32) as f64,
+                8 => f64::from_bits(unsigned),
+                10 | 16 => utils::x87_extended_to_f64(&bytes[..10]),
+                _ => unsigned as f64,
+            },
+            _ => unsigned as f64,
+        }
+    }
+
+    /// Formats a value of this type from its little-endian bytes in memory.
+    pub fn format_value(&self, bytes: &[u8]) -> String {
+        let (unsigned, signed) = Type::decode_integer(bytes);
+        match self.encoding {
+            Encoding::Signed => signed.to_string(),
+            Encoding::Unsigned => unsigned.to_string(),
+            Encoding::SignedChar => format!("{} {}", signed, utils::format_char(unsigned as u8)),
+            Encoding::UnsignedChar => format!("{} {}", unsigned, utils::format_char(unsigned as u8)),
+            Encoding::Boolean => (unsigned != 0).to_string(),
+            Encoding::Address => format!("{:#x}", unsigned),
+            Encoding::Float => match bytes.len() {
+                4 => f32::from_bits(unsigned as u32).to_string(),
+                8 | 10 | 16 => self.numeric_value(bytes).to_string(),
+                _ => format!("{:#x}", unsigned),
+            },
         }
     }
 }
 
-#[derive(Clone)]
-pub enum Location {
-    Address(usize),
-    FramePointerOffset(isize),
+/// The types in an object file's debugging info, keyed by the offset of their entries.
+#[derive(Default)]
+pub struct Types(HashMap<usize, Type>);
+
+/// Reads `len` bytes of the inferior's memory at an address, for following pointers.
+pub type ReadMemory<'a> = &'a dyn Fn(usize, usize) -> Option<Vec<u8>>;
+
+/// GDB's default limits on how many array elements and string characters are shown, and how
+/// many repeats of an element are run together
+const PRINT_ELEMENTS: usize = 200;
+const REPEAT_THRESHOLD: usize = 10;
+
+/// Returns the bytes of a value from `len` bytes at `offset` into another one, or as many of
+/// them as there are.
+fn slice(bytes: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(bytes.len());
+    &bytes[start..(offset + len).min(bytes.len())]
 }
 
-impl fmt::Display for Location {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Location::Address(addr) => write!(f, "Address({:#x})", addr),
-            Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+/// Returns how many bytes hold a bit field with the given position and width.
+pub fn bit_field_len((shift, width): (usize, usize)) -> usize {
+    (shift + width + 7) / 8
+}
+
+pub fn bit_mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Puts a declarator after a type name, e.g. `int` and `*p` make `int *p`.
+fn join(base: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        base.to_string()
+    } else {
+        format!("{} {}", base, declarator)
+    }
+}
+
+impl Types {
+    /// Takes the types as they are in the debugging info, and works out the names and sizes of
+    /// those that are made from other types.
+    pub fn new(types: HashMap<usize, Type>) -> Types {
+        let mut types = Types(types);
+        let ids: Vec<usize> = types.0.keys().copied().collect();
+        for id in &ids {
+            let (size, encoding) = types.size_and_encoding(Some(*id), 0);
+            let ty = types.0.get_mut(id).unwrap();
+            ty.size = size;
+            ty.encoding = encoding;
+            ty.id = Some(*id);
+        }
+        for id in &ids {
+            let ty = &types.0[id];
+            if !matches!(ty.kind, TypeKind::Base | TypeKind::Struct(_) | TypeKind::Enum(_) | TypeKind::Typedef(_)) {
+                let name = types.declaration(Some(ty), "");
+                types.0.get_mut(id).unwrap().name = name;
+            }
+        }
+        types
+    }
+
+    /// Works out the size of a type for which the debugging info doesn't give one, and the
+    /// encoding typedefs and qualified types take from the type they stand for.
+    fn size_and_encoding(&self, id: Option<usize>, depth: usize) -> (usize, Encoding) {
+        let ty = match self.get(id) {
+            Some(ty) if depth < 32 => ty,
+            _ => return (0, Encoding::default()),
+        };
+        match ty.kind {
+            TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => self.size_and_encoding(target, depth + 1),
+            TypeKind::Array { element, count } if ty.size == 0 => {
+                let element_size = self.size_and_encoding(Some(element), depth + 1).0;
+                (element_size * count.unwrap_or(0), ty.encoding)
+            }
+            _ => (ty.size, ty.encoding),
         }
     }
+
+    pub fn get(&self, id: Option<usize>) -> Option<&Type> {
+        self.0.get(&id?)
+    }
+
+    /// Finds a named type, e.g. `int` or `struct node`.
+    pub fn find(&self, name: &str) -> Option<&Type> {
+        self.0.values().find(|ty| ty.name == name)
+    }
+
+    /// Returns the type of a pointer to `target`, or to void if it's None. The debugging info
+    /// has one for most types; for the others one is made up.
+    pub fn pointer_to(&self, target: Option<&Type>) -> Type {
+        let target_id = target.and_then(|target| target.id);
+        if target.is_none() || target_id.is_some() {
+            let existing = self.0.values().find(|ty| matches!(ty.kind, TypeKind::Pointer(id) if id == target_id));
+            if let Some(existing) = existing {
+                return existing.clone();
+            }
+        }
+        let mut pointer = Type {
+            name: String::new(),
+            size: std::mem::size_of::<usize>(),
+            encoding: Encoding::Address,
+            kind: TypeKind::Pointer(target_id),
+            id: None,
+        };
+        // the target is needed by id to put parentheses around e.g. `int (*)[4]`
+        pointer.name = match target_id {
+            Some(_) => self.declaration(Some(&pointer), ""),
+            None => self.declaration(target, "*"),
+        };
+        pointer
+    }
+
+    /// Finds an enumerator by name, returning its enum and value.
+    pub fn find_enumerator(&self, name: &str) -> Option<(&Type, u64)> {
+        self.0.values().find_map(|ty| match &ty.kind {
+            TypeKind::Enum(values) => values.iter().find(|(enumerator, _)| enumerator == name).map(|(_, value)| (ty, *value)),
+            _ => None,
+        })
+    }
+
+    /// Looks through typedefs and qualifiers to the type that decides how a value is laid out.
+    pub fn resolve<'a>(&'a self, ty: &'a Type) -> &'a Type {
+        let mut ty = ty;
+        for _ in 0..32 {
+            match ty.kind {
+                TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => match self.get(target) {
+                    Some(target) => ty = target,
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    /// Returns the type a pointer points to or an array holds. Pointers to void have none.
+    pub fn target<'a>(&'a self, ty: &'a Type) -> Option<&'a Type> {
+        match self.resolve(ty).kind {
+            TypeKind::Pointer(target) => self.get(target),
+            TypeKind::Array { element, .. } => self.get(Some(element)),
+            _ => None,
+        }
+    }
+
+    /// Lists the numbers and pointers a value of a type is made of, looking into its members and
+    /// elements, as (offset, size, floating point). The x86-64 ABI passes small structs in
+    /// registers according to what they hold.
+    pub fn scalars(&self, ty: &Type, offset: usize, scalars: &mut Vec<(usize, usize, bool)>) {
+        let ty = self.resolve(ty);
+        match &ty.kind {
+            TypeKind::Struct(members) => {
+                for member in members {
+                    if let Some(member_type) = self.get(member.entity_type) {
+                        self.scalars(member_type, offset + member.offset, scalars);
+                    }
+                }
+            }
+            TypeKind::Array { element, count } => {
+                if let Some(element) = self.get(Some(*element)).filter(|element| element.size > 0) {
+                    for i in 0..count.unwrap_or(0).min(ty.size / element.size) {
+                        self.scalars(element, offset + i * element.size, scalars);
+                    }
+                }
+            }
+            _ => scalars.push((offset, ty.size, ty.encoding == Encoding::Float)),
+        }
+    }
+
+    /// Writes out the declaration of `declarator` (a name, or nothing for just the type) as
+    /// having a type, the way C would, e.g. `char *argv[]`.
+    pub fn declaration(&self, ty: Option<&Type>, declarator: &str) -> String {
+        self.declare(ty, declarator, false)
+    }
+
+    /// Describes a type the way `ptype` does: like `declaration`, but a struct, union or enum
+    /// underneath typedefs, pointers and arrays is spelled out with its members.
+    pub fn ptype(&self, ty: &Type) -> String {
+        self.declare(Some(ty), "", true)
+    }
+
+    fn declare(&self, ty: Option<&Type>, declarator: &str, expand: bool) -> String {
+        let ty = match ty {
+            Some(ty) => ty,
+            None => return join("void", declarator),
+        };
+        match &ty.kind {
+            TypeKind::Pointer(target) => {
+                let target = self.get(*target);
+                let declarator = match target.map(|target| &target.kind) {
+                    // pointers to arrays and functions need parentheses, as in `int (*)[4]`
+                    Some(TypeKind::Array { .. }) | Some(TypeKind::Function { .. }) => format!("(*{})", declarator),
+                    _ => format!("*{}", declarator),
+                };
+                self.declare(target, &declarator, expand)
+            }
+            TypeKind::Array { element, count } => {
+                let count = count.map_or(String::new(), |count| count.to_string());
+                self.declare(self.get(Some(*element)), &format!("{}[{}]", declarator, count), expand)
+            }
+            TypeKind::Function { return_type, params, variadic } => {
+                let mut params: Vec<String> = params.iter().map(|param| self.declaration(self.get(*param), "")).collect();
+                if *variadic {
+                    params.push("...".to_string());
+                } else if params.is_empty() {
+                    params.push("void".to_string());
+                }
+                self.declare(self.get(*return_type), &format!("{}({})", declarator, params.join(", ")), expand)
+            }
+            TypeKind::Qualified(qualifier, target) => {
+                let target = self.get(*target);
+                match target.map(|target| &target.kind) {
+                    // a const pointer is `char *const p`, with the qualifier after the star
+                    Some(TypeKind::Pointer(_)) => self.declare(target, &join(qualifier, declarator), expand),
+                    _ => format!("{} {}", qualifier, self.declare(target, declarator, expand)),
+                }
+            }
+            TypeKind::Typedef(target) if expand => self.declare(self.get(*target), declarator, expand),
+            TypeKind::Struct(_) | TypeKind::Enum(_) if expand => join(&self.body(ty), declarator),
+            _ => join(&ty.name, declarator),
+        }
+    }
+
+    /// Spells out a struct, union or enum with its members or enumerators.
+    fn body(&self, ty: &Type) -> String {
+        let tag = ty.name.trim_end_matches(" {...}");
+        match &ty.kind {
+            TypeKind::Struct(members) => {
+                let mut text = format!("{} {{\n", tag);
+                if members.is_empty() && ty.size == 0 {
+                    text.push_str("    <incomplete type>\n");
+                }
+                for member in members {
+                    let declaration = self.declaration(self.get(member.entity_type), &member.name);
+                    match member.bits {
+                        Some((_, width)) => text.push_str(&format!("    {} : {};\n", declaration, width)),
+                        None => text.push_str(&format!("    {};\n", declaration)),
+                    }
+                }
+                text.push('}');
+                text
+            }
+            TypeKind::Enum(values) => {
+                // values that just count up from the one before are left out, as in the source
+                let mut next = 0;
+                let enumerators: Vec<String> = values
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = self.enum_value(ty, *value);
+                        let text = if value == next { name.clone() } else { format!("{} = {}", name, value) };
+                        next = value.wrapping_add(1);
+                        text
+                    })
+                    .collect();
+                format!("{} {{{}}}", tag, enumerators.join(", "))
+            }
+            _ => ty.name.clone(),
+        }
+    }
+
+    /// Converts the stored bytes of an enumerator to its value, which may be negative.
+    fn enum_value(&self, ty: &Type, value: u64) -> i64 {
+        if ty.encoding == Encoding::Unsigned || ty.size == 0 || ty.size >= 8 {
+            return value as i64;
+        }
+        let shift = 64 - 8 * ty.size as u32;
+        ((value << shift) as i64) >> shift
+    }
+
+    /// Formats a value of a type from its little-endian bytes in memory. Pointers to chars are
+    /// followed, to show the string they point to.
+    pub fn format_value(&self, ty: &Type, bytes: &[u8], memory: ReadMemory) -> String {
+        match &ty.kind {
+            TypeKind::Base | TypeKind::Function { .. } => ty.format_value(bytes),
+            TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => match self.get(*target) {
+                Some(target) => self.format_value(target, bytes, memory),
+                None => ty.format_value(bytes),
+            },
+            TypeKind::Enum(values) => {
+                let (unsigned, _) = Type::decode_integer(bytes);
+                let mask = bit_mask(8 * ty.size);
+                match values.iter().find(|(_, value)| value & mask == unsigned & mask) {
+                    Some((name, _)) => name.clone(),
+                    None => self.enum_value(ty, unsigned).to_string(),
+                }
+            }
+            TypeKind::Pointer(target) => {
+                let (addr, _) = Type::decode_integer(bytes);
+                let pointee = self.get(*target).map(|target| self.resolve(target));
+                if addr != 0 && pointee.map_or(false, |pointee| pointee.is_char()) {
+                    match read_string(addr as usize, memory) {
+                        Some(text) => return format!("{:#x} {}", addr, text),
+                        None => return format!("{:#x} <error: Cannot access memory at address {:#x}>", addr, addr),
+                    }
+                }
+                format!("{:#x}", addr)
+            }
+            TypeKind::Struct(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = self.format_member(member, bytes, memory);
+                        if member.name.is_empty() {
+                            value
+                        } else {
+                            format!("{} = {}", member.name, value)
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array { element, .. } => {
+                let element = match self.get(Some(*element)) {
+                    Some(element) if element.size > 0 => element,
+                    _ => return "{}".to_string(),
+                };
+                if self.resolve(element).is_char() {
+                    return format_char_array(bytes);
+                }
+                let values: Vec<String> = bytes
+                    .chunks(element.size)
+                    .filter(|chunk| chunk.len() == element.size)
+                    .take(PRINT_ELEMENTS + 1)
+                    .map(|chunk| self.format_value(element, chunk, memory))
+                    .collect();
+                let mut items: Vec<String> = Vec::new();
+                let mut i = 0;
+                while i < values.len().min(PRINT_ELEMENTS) {
+                    let run = values[i..].iter().take_while(|value| **value == values[i]).count();
+                    if run >= REPEAT_THRESHOLD {
+                        items.push(format!("{} <repeats {} times>", values[i], run));
+                        i += run;
+                    } else {
+                        items.push(values[i].clone());
+                        i += 1;
+                    }
+                }
+                if bytes.len() / element.size > i {
+                    items.push("...".to_string());
+                }
+                format!("{{{}}}", items.join(", "))
+            }
+        }
+    }
+
+    /// Formats a struct member, given the bytes of the whole struct.
+    fn format_member(&self, member: &Member, bytes: &[u8], memory: ReadMemory) -> String {
+        let ty = match self.get(member.entity_type) {
+            Some(ty) => ty,
+            None => return "<unknown type>".to_string(),
+        };
+        match member.bits {
+            Some(bits) => self.format_bit_field(ty, slice(bytes, member.offset, bit_field_len(bits)), bits, memory),
+            None => self.format_value(ty, slice(bytes, member.offset, ty.size), memory),
+        }
+    }
+
+    /// Extracts a bit field of `width` bits starting `shift` bits into `bytes`, sign-extending
+    /// it if its type is signed.
+    pub fn bit_field_value(&self, ty: &Type, bytes: &[u8], (shift, width): (usize, usize)) -> u64 {
+        let (raw, _) = Type::decode_integer(bytes);
+        let value = (raw >> shift) & bit_mask(width);
+        let signed = matches!(self.resolve(ty).encoding, Encoding::Signed | Encoding::SignedChar);
+        if signed && width > 0 && width < 64 && value >> (width - 1) & 1 == 1 {
+            value | u64::MAX << width
+        } else {
+            value
+        }
+    }
+
+    pub fn format_bit_field(&self, ty: &Type, bytes: &[u8], bits: (usize, usize), memory: ReadMemory) -> String {
+        let value = self.bit_field_value(ty, bytes, bits);
+        self.format_value(ty, &value.to_le_bytes()[..ty.size.min(8)], memory)
+    }
+
+    /// Finds a member of a struct or union by name, looking inside anonymous ones too. Returns
+    /// its offset from the start of `ty` along with it.
+    pub fn find_member<'a>(&'a self, ty: &'a Type, name: &str) -> Option<(usize, &'a Member)> {
+        let members = match &self.resolve(ty).kind {
+            TypeKind::Struct(members) => members,
+            _ => return None,
+        };
+        for member in members {
+            if member.name == name {
+                return Some((member.offset, member));
+            }
+            if member.name.is_empty() {
+                if let Some((offset, inner)) = self.find_member(self.get(member.entity_type)?, name) {
+                    return Some((member.offset + offset, inner));
+                }
+            }
+        }
+        None
+    }
 }
 
-impl fmt::Debug for Location {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Display::fmt(self, f)
+/// Reads the NUL-terminated string at `addr` and quotes it, cutting it off after
+/// `PRINT_ELEMENTS` characters. Returns None if there's no memory there.
+fn read_string(addr: usize, memory: ReadMemory) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < PRINT_ELEMENTS {
+        match memory(addr + bytes.len(), 1) {
+            Some(byte) if byte[0] != 0 => bytes.push(byte[0]),
+            Some(_) => return Some(utils::format_string(&bytes)),
+            None if bytes.is_empty() => return None,
+            None => break,
+        }
+    }
+    Some(format!("{}...", utils::format_string(&bytes)))
+}
+
+/// Formats a char array as a string, the way GDB does: the NUL at the end is left out, and
+/// long runs of one character are shown as repeats, e.g. `"ab", '\000' <repeats 14 times>`.
+fn format_char_array(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+    let limit = bytes.len().min(PRINT_ELEMENTS);
+    let mut items: Vec<String> = Vec::new();
+    let mut text: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < limit {
+        let run = bytes[i..].iter().take_while(|byte| **byte == bytes[i]).count();
+        if run >= REPEAT_THRESHOLD {
+            if !text.is_empty() {
+                items.push(utils::format_string(&text));
+                text.clear();
+            }
+            items.push(format!("{} <repeats {} times>", utils::format_char(bytes[i]), run));
+            i += run;
+        } else {
+            text.push(bytes[i]);
+            i += 1;
+        }
+    }
+    if !text.is_empty() || items.is_empty() {
+        items.push(utils::format_string(&text));
     }
+    let more = if bytes.len() > i { "..." } else { "" };
+    format!("{}{}", items.join(", "), more)
 }
 
 // For variables and formal parameters
@@ -191,6 +846,7 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -199,6 +855,10 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    /// What `DW_OP_fbreg` in the locations of its variables is relative to
+    pub frame_base: Option<Location>,
+    /// The type it returns, by the offset of its entry, or None for void
+    pub return_type: Option<usize>,
     pub variables: Vec<Variable>,
 }
 
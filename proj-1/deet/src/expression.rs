@@ -0,0 +1,1337 @@
+//! C expressions, as `print`, `display`, `watch` and breakpoint conditions take them. The text is
+//! parsed once into a tree, which is evaluated as often as needed against a `Scope`: the
+//! variables, types, registers and memory seen from a frame of the stopped program.
+//!
+//! Evaluation follows C where it matters for debugging: integers are promoted and converted the
+//! usual way, pointer arithmetic counts in elements, and arrays decay to pointers to their first
+//! element. Assignments and function calls aren't supported.
+
+use crate::dwarf_data::{bit_field_len, bit_mask, Encoding, Type, TypeKind, Types};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Where a value is, and its type.
+pub struct Place {
+    /// An address in the inferior's memory, or an offset into `value`
+    pub addr: usize,
+    pub entity_type: Type,
+    /// For a bit field, its position in the bytes at `addr` and its width
+    pub bits: Option<(usize, usize)>,
+    /// The bytes of a value that isn't in memory, e.g. a variable kept in a register or the
+    /// result of arithmetic. Empty if it was optimized out.
+    pub value: Option<Vec<u8>>,
+}
+
+impl Place {
+    pub fn in_memory(addr: usize, entity_type: Type) -> Place {
+        Place {
+            addr,
+            entity_type,
+            bits: None,
+            value: None,
+        }
+    }
+
+    /// A value that only exists in the debugger, given as little-endian bytes.
+    pub fn computed(entity_type: Type, bytes: Vec<u8>) -> Place {
+        Place {
+            addr: 0,
+            entity_type,
+            bits: None,
+            value: Some(bytes),
+        }
+    }
+
+    pub fn is_optimized_out(&self) -> bool {
+        self.value.as_ref().map_or(false, |value| value.is_empty())
+    }
+}
+
+/// What an expression can refer to.
+pub trait Scope {
+    fn types(&self) -> &Types;
+    /// Finds a variable by name. Returns None if there is no such variable, so that the name
+    /// can be tried as an enumerator.
+    fn variable(&self, name: &str) -> Option<Result<Place, String>>;
+    fn register(&self, name: &str) -> Result<u64, String>;
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String>;
+}
+
+#[derive(Clone)]
+pub struct Expression {
+    text: String,
+    root: Expr,
+}
+
+#[derive(Clone)]
+enum Expr {
+    /// A literal, with whether it had a `u` and an `l` suffix
+    Integer(u64, bool, bool),
+    Float(f64),
+    Char(u8),
+    Variable(String),
+    Register(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Cast(TypeName, Box<Expr>),
+    Sizeof(Box<Expr>),
+    SizeofType(TypeName),
+    Index(Box<Expr>, Box<Expr>),
+    /// `.` or `->`, which are interchangeable as in GDB
+    Member(Box<Expr>, String),
+}
+
+/// A type written out in a cast or `sizeof`, e.g. `unsigned char *`.
+#[derive(Clone)]
+struct TypeName {
+    /// The words before the stars, e.g. `unsigned char` or `struct node`
+    base: String,
+    pointers: usize,
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Integer(u64, bool, bool),
+    Float(f64),
+    Char(u8),
+    Name(String),
+    Register(String),
+    Symbol(&'static str),
+}
+
+/// Operators and punctuation, longest first so that `<=` isn't read as `<`
+const SYMBOLS: [&str; 30] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!",
+    "~", "?", ":", "(", ")", "[", "]", ".", ",", "=",
+];
+
+/// Words that start a type name
+const TYPE_WORDS: [&str; 15] = [
+    "struct", "union", "enum", "unsigned", "signed", "char", "short", "int", "long", "float", "double", "void",
+    "_Bool", "const", "volatile",
+];
+
+/// The C base types, by the names compilers give them in the debugging info
+const BASE_TYPES: [(&str, usize, Encoding); 15] = [
+    ("char", 1, Encoding::SignedChar),
+    ("signed char", 1, Encoding::SignedChar),
+    ("unsigned char", 1, Encoding::UnsignedChar),
+    ("short int", 2, Encoding::Signed),
+    ("short unsigned int", 2, Encoding::Unsigned),
+    ("int", 4, Encoding::Signed),
+    ("unsigned int", 4, Encoding::Unsigned),
+    ("long int", 8, Encoding::Signed),
+    ("long unsigned int", 8, Encoding::Unsigned),
+    ("long long int", 8, Encoding::Signed),
+    ("long long unsigned int", 8, Encoding::Unsigned),
+    ("float", 4, Encoding::Float),
+    ("double", 8, Encoding::Float),
+    ("long double", 16, Encoding::Float),
+    ("_Bool", 1, Encoding::Boolean),
+];
+
+impl Expression {
+    /// Parses an expression. The types are needed to tell a cast to a typedef, like
+    /// `(size_t) n`, from a parenthesized variable.
+    pub fn parse(text: &str, types: &Types) -> Result<Expression, String> {
+        let mut parser = Parser {
+            text,
+            tokens: tokenize(text)?,
+            pos: 0,
+            types,
+        };
+        if parser.tokens.is_empty() {
+            return Err("Argument required (expression to compute).".to_string());
+        }
+        let root = parser.expression()?;
+        if parser.peek().is_some() {
+            return Err(parser.syntax_error());
+        }
+        Ok(Expression {
+            text: text.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, scope: &dyn Scope) -> Result<Place, String> {
+        Evaluator::new(scope, false).evaluate(&self.root)
+    }
+
+    /// Works out the type of the expression without reading memory, for `ptype` and `whatis`.
+    pub fn evaluate_type(&self, scope: &dyn Scope) -> Result<Type, String> {
+        Ok(Evaluator::new(scope, true).evaluate(&self.root)?.entity_type)
+    }
+
+    /// Evaluates the expression as a condition: true unless it is zero.
+    pub fn is_true(&self, scope: &dyn Scope) -> Result<bool, String> {
+        let evaluator = Evaluator::new(scope, false);
+        evaluator.is_true(&evaluator.evaluate(&self.root)?)
+    }
+
+    /// Returns the names the expression refers to, which are variables or enumerators.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.root.collect_names(&mut names);
+        names
+    }
+}
+
+impl Expr {
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Variable(name) => names.push(name),
+            Expr::Unary(_, operand) | Expr::Cast(_, operand) | Expr::Sizeof(operand) | Expr::Member(operand, _) => {
+                operand.collect_names(names)
+            }
+            Expr::Binary(_, lhs, rhs) | Expr::Index(lhs, rhs) => {
+                lhs.collect_names(names);
+                rhs.collect_names(names);
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                condition.collect_names(names);
+                then.collect_names(names);
+                otherwise.collect_names(names);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Parses a type written out the way a cast would, e.g. `unsigned long` or `struct node *`, for
+/// `ptype` and `whatis`. Returns None if the text doesn't start like a type.
+pub fn parse_type(text: &str, types: &Types) -> Option<Result<Type, String>> {
+    let mut parser = Parser {
+        text,
+        tokens: tokenize(text).ok()?,
+        pos: 0,
+        types,
+    };
+    if !parser.is_type_at(0) {
+        return None;
+    }
+    let type_name = match parser.type_name() {
+        Ok(type_name) if parser.peek().is_none() => type_name,
+        Ok(_) => return Some(Err(parser.syntax_error())),
+        Err(e) => return Some(Err(e)),
+    };
+    Some(named_type(types, &type_name))
+}
+
+/// Formats a value according to its type, following char pointers to show their strings.
+pub fn format_place(scope: &dyn Scope, place: &Place) -> Result<String, String> {
+    if place.is_optimized_out() {
+        return Ok("<optimized out>".to_string());
+    }
+    let types = scope.types();
+    let len = place.bits.map_or(place.entity_type.size, bit_field_len);
+    let bytes = Evaluator::new(scope, false).bytes(place, len)?;
+    let memory = |addr, len| scope.read_memory(addr, len).ok();
+    Ok(match place.bits {
+        Some(bits) => types.format_bit_field(&place.entity_type, &bytes, bits, &memory),
+        None => types.format_value(&place.entity_type, &bytes, &memory),
+    })
+}
+
+/// Reads a value as an address, for `x`: an array stands for the address of its first element.
+pub fn address_value(scope: &dyn Scope, place: &Place) -> Result<usize, String> {
+    let evaluator = Evaluator::new(scope, false);
+    match scope.types().resolve(&place.entity_type).kind {
+        TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) | TypeKind::Array { .. } => {
+            Ok(evaluator.number(place)?.as_u64() as usize)
+        }
+        _ => Err("Attempt to use a non-scalar value as an address.".to_string()),
+    }
+}
+
+/// Converts a value to a type the way an assignment does, returning the bytes to store.
+pub fn convert(scope: &dyn Scope, place: Place, ty: &Type) -> Result<Vec<u8>, String> {
+    let evaluator = Evaluator::new(scope, false);
+    let place = evaluator.cast(place, ty.clone())?;
+    evaluator.bytes(&place, ty.size)
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let token = if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).map_or(false, u8::is_ascii_digit)) {
+            i = number_end(text, i);
+            parse_number(&text[start..i])?
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            i += 1;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            match text[start..i].strip_prefix('$') {
+                Some(name) => Token::Register(name.to_string()),
+                None => Token::Name(text[start..i].to_string()),
+            }
+        } else if c == b'\'' {
+            let (value, len) = parse_char(&text[i..])?;
+            i += len;
+            Token::Char(value)
+        } else if c == b'"' {
+            return Err("String literals are not supported.".to_string());
+        } else {
+            match SYMBOLS.iter().find(|symbol| text[i..].starts_with(*symbol)) {
+                Some(symbol) => {
+                    i += symbol.len();
+                    Token::Symbol(symbol)
+                }
+                None => return Err(format!("Invalid character '{}' in expression.", text[i..].chars().next().unwrap())),
+            }
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// Finds where a number starting at `start` ends, including a suffix and an exponent's sign.
+fn number_end(text: &str, start: usize) -> usize {
+    let bytes = text.as_bytes();
+    let hex = text[start..].starts_with("0x") || text[start..].starts_with("0X");
+    let mut i = start;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let exponent_sign = (c == b'+' || c == b'-') && !hex && matches!(bytes[i - 1], b'e' | b'E');
+        if c.is_ascii_alphanumeric() || c == b'.' || exponent_sign {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Parses an integer (decimal, hex or octal, with `u` and `l` suffixes) or floating-point literal.
+fn parse_number(text: &str) -> Result<Token, String> {
+    let invalid = || format!("Invalid number \"{}\".", text);
+    let lower = text.to_ascii_lowercase();
+    let hex = lower.starts_with("0x");
+    if !hex && (lower.contains('.') || lower.contains('e')) {
+        let digits = lower.trim_end_matches(['f', 'l']);
+        return digits.parse().map(Token::Float).map_err(|_| invalid());
+    }
+    let digits = lower.trim_end_matches(['u', 'l']);
+    let suffix = &lower[digits.len()..];
+    let value = if hex {
+        u64::from_str_radix(&digits[2..], 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    let value = value.map_err(|_| invalid())?;
+    Ok(Token::Integer(value, suffix.contains('u'), suffix.contains('l')))
+}
+
+/// Parses a character literal such as `'a'`, `'\n'` or `'\0'` at the start of `text`, returning
+/// its value and how many bytes it takes up.
+fn parse_char(text: &str) -> Result<(u8, usize), String> {
+    let bytes = text.as_bytes();
+    let unmatched = || "Unmatched single quote.".to_string();
+    // the length of what is between the quotes
+    let (value, len) = match bytes.get(1) {
+        Some(b'\\') => match *bytes.get(2).ok_or_else(unmatched)? {
+            b'n' => (b'\n', 2),
+            b't' => (b'\t', 2),
+            b'r' => (b'\r', 2),
+            b'a' => (7, 2),
+            b'b' => (8, 2),
+            b'f' => (12, 2),
+            b'v' => (11, 2),
+            b'e' => (27, 2),
+            b'x' => {
+                let digits = bytes[3..].iter().take_while(|c| c.is_ascii_hexdigit()).count();
+                let value = u64::from_str_radix(&text[3..3 + digits], 16).map_err(|_| "\\x escape without a following hex digit".to_string())?;
+                (value as u8, 2 + digits)
+            }
+            b'0'..=b'7' => {
+                let digits = bytes[2..].iter().take(3).take_while(|c| (b'0'..=b'7').contains(*c)).count();
+                (u32::from_str_radix(&text[2..2 + digits], 8).unwrap() as u8, 1 + digits)
+            }
+            other => (other, 2),
+        },
+        Some(b'\'') => return Err("Empty character constant.".to_string()),
+        Some(c) => (*c, 1),
+        None => return Err(unmatched()),
+    };
+    if bytes.get(1 + len) != Some(&b'\'') {
+        return Err(unmatched());
+    }
+    Ok((value, len + 2))
+}
+
+/// How tightly binary operators bind, from `||` up to `*`
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn is_comparison(op: &str) -> bool {
+    ["==", "!=", "<", ">", "<=", ">="].contains(&op)
+}
+
+/// A recursive descent parser, with precedence climbing for the binary operators.
+struct Parser<'a> {
+    text: &'a str,
+    /// Tokens with the offset in `text` they start at
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    types: &'a Types,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn peek_name(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Name(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.peek_symbol() == Some(symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.syntax_error())
+        }
+    }
+
+    /// Reports a syntax error at the current token, the way GDB does.
+    fn syntax_error(&self) -> String {
+        let rest = self.tokens.get(self.pos).map_or("", |(_, start)| &self.text[*start..]);
+        format!("A syntax error in expression, near `{}'.", rest)
+    }
+
+    /// Returns true if the token at `pos` starts a type name rather than an expression.
+    fn is_type_at(&self, pos: usize) -> bool {
+        match self.tokens.get(pos) {
+            Some((Token::Name(word), _)) => {
+                TYPE_WORDS.contains(&word.as_str())
+                    || self.types.find(word).map_or(false, |ty| matches!(ty.kind, TypeKind::Typedef(_)))
+            }
+            _ => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(1)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.expression()?;
+        self.expect(":")?;
+        let otherwise = self.expression()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_symbol() {
+            let precedence = match precedence(op) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek_symbol() {
+            Some(op @ ("-" | "+" | "!" | "~" | "*" | "&")) => {
+                self.pos += 1;
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+            Some("(") if self.is_type_at(self.pos + 1) => {
+                self.pos += 1;
+                let type_name = self.type_name()?;
+                self.expect(")")?;
+                return Ok(Expr::Cast(type_name, Box::new(self.unary()?)));
+            }
+            _ => {}
+        }
+        if self.peek_name() == Some("sizeof") {
+            self.pos += 1;
+            if self.peek_symbol() == Some("(") && self.is_type_at(self.pos + 1) {
+                self.pos += 1;
+                let type_name = self.type_name()?;
+                self.expect(")")?;
+                return Ok(Expr::SizeofType(type_name));
+            }
+            return Ok(Expr::Sizeof(Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.expression()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".") || self.eat("->") {
+                let name = self.peek_name().map(|name| name.to_string()).ok_or_else(|| self.syntax_error())?;
+                self.pos += 1;
+                expr = Expr::Member(Box::new(expr), name);
+            } else if self.peek_symbol() == Some("(") {
+                return Err("Calling functions of the program is not supported.".to_string());
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned().ok_or_else(|| self.syntax_error())?;
+        let expr = match token {
+            Token::Integer(value, unsigned, long) => Expr::Integer(value, unsigned, long),
+            Token::Float(value) => Expr::Float(value),
+            Token::Char(value) => Expr::Char(value),
+            Token::Name(name) => Expr::Variable(name),
+            Token::Register(name) => Expr::Register(name),
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let inner = self.expression()?;
+                self.expect(")")?;
+                return Ok(inner);
+            }
+            Token::Symbol(_) => return Err(self.syntax_error()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+
+    /// Parses the words and stars of a type name.
+    fn type_name(&mut self) -> Result<TypeName, String> {
+        let mut base: Vec<String> = Vec::new();
+        while let Some(word) = self.peek_name().map(|word| word.to_string()) {
+            match word.as_str() {
+                "const" | "volatile" => {}
+                "struct" | "union" | "enum" if base.is_empty() => {
+                    self.pos += 1;
+                    let tag = self.peek_name().ok_or_else(|| self.syntax_error())?;
+                    base.push(format!("{} {}", word, tag));
+                }
+                _ if TYPE_WORDS.contains(&word.as_str()) => base.push(word),
+                // a typedef's name on its own
+                _ if base.is_empty() && self.is_type_at(self.pos) => base.push(word),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if base.is_empty() {
+            return Err(self.syntax_error());
+        }
+        let mut pointers = 0;
+        loop {
+            if self.eat("*") {
+                pointers += 1;
+            } else if matches!(self.peek_name(), Some("const" | "volatile")) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(TypeName {
+            base: base.join(" "),
+            pointers,
+        })
+    }
+}
+
+/// Works out which base type a combination of C keywords like `unsigned long` stands for, by its
+/// name in the debugging info. Returns None for words that aren't all keywords.
+fn base_type_name(words: &str) -> Option<&'static str> {
+    let words: Vec<&str> = words.split(' ').collect();
+    if words.iter().any(|word| !TYPE_WORDS.contains(word)) {
+        return None;
+    }
+    let has = |word: &str| words.contains(&word);
+    let unsigned = has("unsigned");
+    let longs = words.iter().filter(|word| **word == "long").count();
+    Some(if has("_Bool") {
+        "_Bool"
+    } else if has("float") {
+        "float"
+    } else if has("double") {
+        if longs > 0 { "long double" } else { "double" }
+    } else if has("char") {
+        if unsigned {
+            "unsigned char"
+        } else if has("signed") {
+            "signed char"
+        } else {
+            "char"
+        }
+    } else if has("short") {
+        if unsigned { "short unsigned int" } else { "short int" }
+    } else if longs >= 2 {
+        if unsigned { "long long unsigned int" } else { "long long int" }
+    } else if longs == 1 {
+        if unsigned { "long unsigned int" } else { "long int" }
+    } else if unsigned || has("signed") || has("int") {
+        if unsigned { "unsigned int" } else { "int" }
+    } else {
+        return None;
+    })
+}
+
+/// Returns a base type by its name in the debugging info, e.g. `long int`. Programs that never
+/// use one don't describe it, so it is made up then.
+fn base_type(types: &Types, name: &str) -> Type {
+    if let Some(ty) = types.find(name).filter(|ty| matches!(ty.kind, TypeKind::Base)) {
+        return ty.clone();
+    }
+    let (_, size, encoding) = BASE_TYPES.iter().find(|(base, _, _)| *base == name).copied().unwrap_or((name, 0, Encoding::Signed));
+    Type::new(name.to_string(), size, encoding)
+}
+
+/// Looks up a type written out in a cast or `sizeof`. Plain `void` is only allowed as the
+/// target of a pointer.
+fn named_type(types: &Types, type_name: &TypeName) -> Result<Type, String> {
+    let base = type_name.base.as_str();
+    let mut ty = if base == "void" {
+        None
+    } else if let Some(name) = base_type_name(base) {
+        Some(base_type(types, name))
+    } else {
+        let ty = types.find(base).ok_or_else(|| match base.split_once(' ') {
+            Some((tag, name)) => format!("No {} type named {}.", tag, name),
+            None => format!("No symbol \"{}\" in current context.", base),
+        })?;
+        Some(ty.clone())
+    };
+    for _ in 0..type_name.pointers {
+        ty = Some(types.pointer_to(ty.as_ref()));
+    }
+    ty.ok_or_else(|| "Attempt to use a type name as an expression".to_string())
+}
+
+/// An arithmetic value, after reading it out of its place
+#[derive(Clone, Copy)]
+enum Number {
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+impl Number {
+    fn is_zero(self) -> bool {
+        match self {
+            Number::Signed(value) => value == 0,
+            Number::Unsigned(value) => value == 0,
+            Number::Float(value) => value == 0.0,
+        }
+    }
+
+    fn as_i64(self) -> i64 {
+        match self {
+            Number::Signed(value) => value,
+            Number::Unsigned(value) => value as i64,
+            Number::Float(value) => value as i64,
+        }
+    }
+
+    fn as_u64(self) -> u64 {
+        self.as_i64() as u64
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Signed(value) => value as f64,
+            Number::Unsigned(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    scope: &'a dyn Scope,
+    types: &'a Types,
+    /// Only the type of the result is wanted, so memory isn't read and all values are zero
+    types_only: bool,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(scope: &'a dyn Scope, types_only: bool) -> Evaluator<'a> {
+        Evaluator {
+            scope,
+            types: scope.types(),
+            types_only,
+        }
+    }
+
+    fn evaluate(&self, expr: &Expr) -> Result<Place, String> {
+        match expr {
+            Expr::Integer(value, unsigned, long) => {
+                // like C, a literal is an int if it fits in one, and a long otherwise
+                let limit = if *unsigned { u32::MAX as u64 } else { i32::MAX as u64 };
+                let size = if *long || *value > limit { 8 } else { 4 };
+                Ok(self.number_place(self.integer_type(size, *unsigned), Number::Unsigned(*value)))
+            }
+            Expr::Float(value) => Ok(self.number_place(base_type(self.types, "double"), Number::Float(*value))),
+            Expr::Char(value) => Ok(Place::computed(base_type(self.types, "char"), vec![*value])),
+            Expr::Variable(name) => match self.scope.variable(name) {
+                Some(place) => place,
+                None => {
+                    let (ty, value) = self
+                        .types
+                        .find_enumerator(name)
+                        .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+                    Ok(self.number_place(ty.clone(), Number::Unsigned(value)))
+                }
+            },
+            Expr::Register(name) => {
+                let value = if self.types_only { 0 } else { self.scope.register(name)? };
+                Ok(self.number_place(base_type(self.types, "long int"), Number::Unsigned(value)))
+            }
+            Expr::Unary(op, operand) => self.unary(op, operand),
+            Expr::Binary(op, lhs, rhs) => self.binary(op, lhs, rhs),
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.is_true(&self.evaluate(condition)?)? {
+                    self.evaluate(then)
+                } else {
+                    self.evaluate(otherwise)
+                }
+            }
+            Expr::Cast(type_name, operand) => {
+                let ty = named_type(self.types, type_name)?;
+                self.cast(self.evaluate(operand)?, ty)
+            }
+            Expr::Sizeof(operand) => {
+                // the operand isn't evaluated, as in C
+                let ty = Evaluator::new(self.scope, true).evaluate(operand)?.entity_type;
+                Ok(self.sizeof(&ty))
+            }
+            Expr::SizeofType(type_name) => Ok(self.sizeof(&named_type(self.types, type_name)?)),
+            Expr::Index(base, index) => {
+                let (base, index) = (self.evaluate(base)?, self.evaluate(index)?);
+                // `i[array]` is as good as `array[i]` in C
+                let (base, index) = if !self.is_pointer(&base) && self.is_pointer(&index) { (index, base) } else { (base, index) };
+                if !self.is_pointer(&base) {
+                    return Err(format!("cannot subscript something of type `{}'", base.entity_type.name));
+                }
+                let index = self.number(&index)?.as_i64();
+                let element = self.dereference(&base)?;
+                let offset = index.wrapping_mul(element.entity_type.size as i64);
+                Ok(Place {
+                    addr: element.addr.wrapping_add(offset as usize),
+                    ..element
+                })
+            }
+            Expr::Member(base, name) => {
+                let base = self.evaluate(base)?;
+                let base = match self.types.resolve(&base.entity_type).kind {
+                    TypeKind::Pointer(_) => self.dereference(&base)?,
+                    TypeKind::Struct(_) => base,
+                    _ => return Err("Attempt to extract a component of a value that is not a structure.".to_string()),
+                };
+                let (offset, member) = self
+                    .types
+                    .find_member(&base.entity_type, name)
+                    .ok_or_else(|| format!("There is no member named {}.", name))?;
+                Ok(Place {
+                    addr: base.addr + offset,
+                    entity_type: self.types.get(member.entity_type).cloned().unwrap_or_default(),
+                    bits: member.bits,
+                    value: base.value,
+                })
+            }
+        }
+    }
+
+    fn unary(&self, op: &str, operand: &Expr) -> Result<Place, String> {
+        let value = self.evaluate(operand)?;
+        match op {
+            "*" => self.dereference(&value),
+            "&" => {
+                if value.bits.is_some() || value.value.is_some() {
+                    return Err("Attempt to take address of value not located in memory.".to_string());
+                }
+                let ty = self.types.pointer_to(Some(&value.entity_type));
+                Ok(self.number_place(ty, Number::Unsigned(value.addr as u64)))
+            }
+            "!" => Ok(self.boolean(!self.is_true(&value)?)),
+            _ => {
+                if self.is_pointer(&value) {
+                    return Err("Argument to arithmetic operation not a number or boolean.".to_string());
+                }
+                let number = self.number(&value)?;
+                if self.is_float(&value) {
+                    return match op {
+                        "-" => Ok(self.number_place(value.entity_type, Number::Float(-number.as_f64()))),
+                        "+" => Ok(self.number_place(value.entity_type, number)),
+                        _ => Err("Argument to complement operation not an integer or boolean.".to_string()),
+                    };
+                }
+                let (size, unsigned) = self.promoted(&value);
+                let number = match op {
+                    "-" => Number::Unsigned(number.as_u64().wrapping_neg()),
+                    "~" => Number::Unsigned(!number.as_u64()),
+                    _ => number,
+                };
+                Ok(self.number_place(self.integer_type(size, unsigned), number))
+            }
+        }
+    }
+
+    fn binary(&self, op: &str, lhs: &Expr, rhs: &Expr) -> Result<Place, String> {
+        // the right operand of `&&` and `||` is only evaluated if it decides the result
+        if op == "&&" || op == "||" {
+            let lhs = self.is_true(&self.evaluate(lhs)?)?;
+            let result = if lhs == (op == "||") { lhs } else { self.is_true(&self.evaluate(rhs)?)? };
+            return Ok(self.boolean(result));
+        }
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        match (self.is_pointer(&lhs), self.is_pointer(&rhs)) {
+            (true, false) if op == "+" || op == "-" => return self.pointer_offset(&lhs, self.number(&rhs)?, op == "-"),
+            (false, true) if op == "+" => return self.pointer_offset(&rhs, self.number(&lhs)?, false),
+            (true, true) if op == "-" => {
+                let size = self.target_size(&self.decayed(&lhs)) as i64;
+                let difference = self.number(&lhs)?.as_i64().wrapping_sub(self.number(&rhs)?.as_i64()) / size;
+                return Ok(self.number_place(base_type(self.types, "long int"), Number::Signed(difference)));
+            }
+            (true, _) | (_, true) if is_comparison(op) => {
+                let ordering = self.number(&lhs)?.as_u64().cmp(&self.number(&rhs)?.as_u64());
+                return Ok(self.boolean(compare(op, Some(ordering))));
+            }
+            (true, _) | (_, true) => return Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+            _ => {}
+        }
+        let (l, r) = (self.number(&lhs)?, self.number(&rhs)?);
+
+        if self.is_float(&lhs) || self.is_float(&rhs) {
+            let (l, r) = (l.as_f64(), r.as_f64());
+            let result = match op {
+                "+" => l + r,
+                "-" => l - r,
+                "*" => l * r,
+                "/" => l / r,
+                _ if is_comparison(op) => return Ok(self.boolean(compare(op, l.partial_cmp(&r)))),
+                _ => return Err(format!("Integer only operation {}.", op)),
+            };
+            return Ok(self.number_place(base_type(self.types, "double"), Number::Float(result)));
+        }
+
+        // the usual arithmetic conversions, except that shifts take the type of the left operand
+        let (lsize, lunsigned) = self.promoted(&lhs);
+        let (rsize, runsigned) = self.promoted(&rhs);
+        let (size, unsigned) = if op == "<<" || op == ">>" {
+            (lsize, lunsigned)
+        } else {
+            let size = lsize.max(rsize);
+            (size, (lunsigned && lsize == size) || (runsigned && rsize == size))
+        };
+        let result = if unsigned {
+            let mask = bit_mask(8 * size);
+            let (l, r) = (l.as_u64() & mask, r.as_u64() & mask);
+            if is_comparison(op) {
+                return Ok(self.boolean(compare(op, Some(l.cmp(&r)))));
+            }
+            Number::Unsigned(match op {
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "*" => l.wrapping_mul(r),
+                "/" | "%" if r == 0 => return Err("Division by zero".to_string()),
+                "/" => l / r,
+                "%" => l % r,
+                "<<" => l.wrapping_shl(r as u32),
+                ">>" => l.wrapping_shr(r as u32),
+                "&" => l & r,
+                "|" => l | r,
+                _ => l ^ r,
+            })
+        } else {
+            let (l, r) = (l.as_i64(), r.as_i64());
+            if is_comparison(op) {
+                return Ok(self.boolean(compare(op, Some(l.cmp(&r)))));
+            }
+            Number::Signed(match op {
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "*" => l.wrapping_mul(r),
+                "/" | "%" if r == 0 => return Err("Division by zero".to_string()),
+                "/" => l.wrapping_div(r),
+                "%" => l.wrapping_rem(r),
+                "<<" => l.wrapping_shl(r as u32),
+                ">>" => l.wrapping_shr(r as u32),
+                "&" => l & r,
+                "|" => l | r,
+                _ => l ^ r,
+            })
+        };
+        Ok(self.number_place(self.integer_type(size, unsigned), result))
+    }
+
+    /// Converts a value to another type, the way a cast does. Structs can only be "converted"
+    /// to their own type.
+    fn cast(&self, value: Place, ty: Type) -> Result<Place, String> {
+        let target = self.types.resolve(&ty);
+        match target.kind {
+            TypeKind::Struct(_) | TypeKind::Array { .. } | TypeKind::Function { .. } => {
+                if self.types.resolve(&value.entity_type).name == target.name {
+                    Ok(Place { entity_type: ty, ..value })
+                } else {
+                    Err("Invalid cast.".to_string())
+                }
+            }
+            _ => {
+                let number = match (target.encoding, self.number(&value)?) {
+                    (Encoding::Float, number) => Number::Float(number.as_f64()),
+                    // floats become integers by dropping the fraction
+                    (_, Number::Float(value)) => Number::Signed(value as i64),
+                    (_, number) => number,
+                };
+                Ok(self.number_place(ty, number))
+            }
+        }
+    }
+
+    /// Follows a pointer to the value it points to. An array stands for its first element.
+    fn dereference(&self, place: &Place) -> Result<Place, String> {
+        let target = self.types.target(&place.entity_type).cloned();
+        match (&self.types.resolve(&place.entity_type).kind, target) {
+            (TypeKind::Array { .. }, Some(target)) => Ok(Place {
+                addr: place.addr,
+                entity_type: target,
+                bits: None,
+                value: place.value.clone(),
+            }),
+            (TypeKind::Pointer(_), Some(target)) => {
+                let addr = self.number(place)?.as_u64() as usize;
+                Ok(Place::in_memory(addr, target))
+            }
+            _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+        }
+    }
+
+    /// Adds `offset` elements to a pointer, or subtracts them.
+    fn pointer_offset(&self, pointer: &Place, offset: Number, subtract: bool) -> Result<Place, String> {
+        let ty = self.decayed(pointer);
+        let delta = offset.as_i64().wrapping_mul(self.target_size(&ty) as i64) as u64;
+        let addr = self.number(pointer)?.as_u64();
+        let addr = if subtract { addr.wrapping_sub(delta) } else { addr.wrapping_add(delta) };
+        Ok(self.number_place(ty, Number::Unsigned(addr)))
+    }
+
+    /// Returns the type of a pointer or array used as a value: an array becomes a pointer to its
+    /// first element.
+    fn decayed(&self, place: &Place) -> Type {
+        match self.types.resolve(&place.entity_type).kind {
+            TypeKind::Array { element, .. } => self.types.pointer_to(self.types.get(Some(element))),
+            _ => place.entity_type.clone(),
+        }
+    }
+
+    /// Returns the size of what a pointer points to, taking void and functions to be one byte as
+    /// GDB does.
+    fn target_size(&self, pointer_type: &Type) -> usize {
+        self.types.target(pointer_type).map_or(1, |target| target.size).max(1)
+    }
+
+    fn sizeof(&self, ty: &Type) -> Place {
+        self.number_place(base_type(self.types, "long unsigned int"), Number::Unsigned(ty.size as u64))
+    }
+
+    fn boolean(&self, value: bool) -> Place {
+        self.number_place(base_type(self.types, "int"), Number::Signed(value as i64))
+    }
+
+    fn integer_type(&self, size: usize, unsigned: bool) -> Type {
+        let name = match (size, unsigned) {
+            (8, false) => "long int",
+            (8, true) => "long unsigned int",
+            (_, false) => "int",
+            (_, true) => "unsigned int",
+        };
+        base_type(self.types, name)
+    }
+
+    /// Returns the size and signedness an integer operand has after C's integer promotions.
+    fn promoted(&self, place: &Place) -> (usize, bool) {
+        let ty = self.types.resolve(&place.entity_type);
+        if ty.size < 4 {
+            return (4, false);
+        }
+        let unsigned = matches!(ty.kind, TypeKind::Base)
+            && matches!(ty.encoding, Encoding::Unsigned | Encoding::UnsignedChar | Encoding::Boolean);
+        (ty.size.min(8), unsigned)
+    }
+
+    fn is_pointer(&self, place: &Place) -> bool {
+        matches!(self.types.resolve(&place.entity_type).kind, TypeKind::Pointer(_) | TypeKind::Array { .. })
+    }
+
+    fn is_float(&self, place: &Place) -> bool {
+        let ty = self.types.resolve(&place.entity_type);
+        matches!(ty.kind, TypeKind::Base) && ty.encoding == Encoding::Float
+    }
+
+    fn is_true(&self, place: &Place) -> Result<bool, String> {
+        Ok(!self.number(place)?.is_zero())
+    }
+
+    /// Makes a value of a number, stored in the bytes of the given type.
+    fn number_place(&self, ty: Type, number: Number) -> Place {
+        let resolved = self.types.resolve(&ty);
+        let bytes = match (resolved.encoding, resolved.size) {
+            (Encoding::Float, 4) => (number.as_f64() as f32).to_le_bytes().to_vec(),
+            (Encoding::Float, 8) => number.as_f64().to_le_bytes().to_vec(),
+            // there's no producing an x87 long double, so a double has to do
+            (Encoding::Float, _) => return self.number_place(base_type(self.types, "double"), number),
+            (Encoding::Boolean, size) => {
+                let value = !number.is_zero() as u8;
+                (0..size).map(|i| if i == 0 { value } else { 0 }).collect()
+            }
+            (_, size) => {
+                let value = number.as_u64().to_le_bytes();
+                (0..size).map(|i| value.get(i).copied().unwrap_or(0)).collect()
+            }
+        };
+        Place::computed(ty, bytes)
+    }
+
+    /// Reads a value as a number. An array in memory reads as the address of its first element.
+    fn number(&self, place: &Place) -> Result<Number, String> {
+        let ty = self.types.resolve(&place.entity_type);
+        match ty.kind {
+            TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) => {}
+            TypeKind::Array { .. } if place.value.is_none() => return Ok(Number::Unsigned(place.addr as u64)),
+            _ => return Err("Argument to arithmetic operation not a number or boolean.".to_string()),
+        }
+        let len = place.bits.map_or(ty.size, bit_field_len);
+        let bytes = self.bytes(place, len)?;
+        let signed = matches!(ty.encoding, Encoding::Signed | Encoding::SignedChar);
+        if let Some(bits) = place.bits {
+            let value = self.types.bit_field_value(ty, &bytes, bits);
+            return Ok(if signed { Number::Signed(value as i64) } else { Number::Unsigned(value) });
+        }
+        let (unsigned_value, signed_value) = Type::decode_integer(&bytes);
+        Ok(match ty.encoding {
+            Encoding::Float => Number::Float(ty.numeric_value(&bytes)),
+            _ if signed => Number::Signed(signed_value),
+            _ => Number::Unsigned(unsigned_value),
+        })
+    }
+
+    /// Reads the bytes of a value, from memory or from the value itself if it isn't in memory.
+    fn bytes(&self, place: &Place, len: usize) -> Result<Vec<u8>, String> {
+        match &place.value {
+            _ if self.types_only => Ok(vec![0; len]),
+            Some(value) => value
+                .get(place.addr..place.addr.saturating_add(len))
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(|| "value has been optimized out".to_string()),
+            None => self.scope.read_memory(place.addr, len),
+        }
+    }
+}
+
+/// Applies a comparison operator to the ordering of its operands. Nothing compares equal to NaN.
+fn compare(op: &str, ordering: Option<Ordering>) -> bool {
+    match ordering {
+        Some(ordering) => match op {
+            "==" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            "<" => ordering == Ordering::Less,
+            ">" => ordering == Ordering::Greater,
+            "<=" => ordering != Ordering::Greater,
+            _ => ordering != Ordering::Less,
+        },
+        None => op == "!=",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dwarf_data::Member;
+    use std::collections::HashMap;
+
+    const INT: usize = 1;
+    const POINT: usize = 2;
+    const POINT_PTR: usize = 3;
+    const INT_ARRAY: usize = 4;
+    const SIZE_T: usize = 5;
+    const ULONG: usize = 6;
+    const COLOR: usize = 7;
+
+    /// Memory starts at BASE and holds `int n = 7`, `struct point p = {1, 2}`,
+    /// `struct point *pp = &p`, `int arr[3] = {10, 20, 30}` and `enum color c = GREEN`.
+    const BASE: usize = 0x1000;
+
+    struct TestScope {
+        types: Types,
+        memory: Vec<u8>,
+    }
+
+    impl TestScope {
+        fn new() -> TestScope {
+            let mut types = HashMap::new();
+            types.insert(INT, Type::new("int".to_string(), 4, Encoding::Signed));
+            types.insert(ULONG, Type::new("long unsigned int".to_string(), 8, Encoding::Unsigned));
+            let member = |name: &str, offset| Member { name: name.to_string(), entity_type: Some(INT), offset, bits: None };
+            let mut point = Type::new("struct point".to_string(), 8, Encoding::Signed);
+            point.kind = TypeKind::Struct(vec![member("x", 0), member("y", 4)]);
+            types.insert(POINT, point);
+            let mut point_ptr = Type::new(String::new(), 8, Encoding::Address);
+            point_ptr.kind = TypeKind::Pointer(Some(POINT));
+            types.insert(POINT_PTR, point_ptr);
+            let mut int_array = Type::new(String::new(), 0, Encoding::Signed);
+            int_array.kind = TypeKind::Array { element: INT, count: Some(3) };
+            types.insert(INT_ARRAY, int_array);
+            let mut size_t = Type::new("size_t".to_string(), 0, Encoding::Signed);
+            size_t.kind = TypeKind::Typedef(Some(ULONG));
+            types.insert(SIZE_T, size_t);
+            let mut color = Type::new("enum color".to_string(), 4, Encoding::Unsigned);
+            color.kind = TypeKind::Enum(vec![("RED".to_string(), 0), ("GREEN".to_string(), 1)]);
+            types.insert(COLOR, color);
+
+            let mut memory = Vec::new();
+            memory.extend_from_slice(&7i32.to_le_bytes());
+            memory.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+            memory.extend_from_slice(&(BASE as u64 + 4).to_le_bytes());
+            for value in [10i32, 20, 30] {
+                memory.extend_from_slice(&value.to_le_bytes());
+            }
+            memory.extend_from_slice(&1u32.to_le_bytes());
+            TestScope { types: Types::new(types), memory }
+        }
+
+        fn variable_at(&self, addr: usize, id: usize) -> Place {
+            Place::in_memory(addr, self.types.get(Some(id)).unwrap().clone())
+        }
+    }
+
+    impl Scope for TestScope {
+        fn types(&self) -> &Types {
+            &self.types
+        }
+
+        fn variable(&self, name: &str) -> Option<Result<Place, String>> {
+            let place = match name {
+                "n" => self.variable_at(BASE, INT),
+                "p" => self.variable_at(BASE + 4, POINT),
+                "pp" => self.variable_at(BASE + 12, POINT_PTR),
+                "arr" => self.variable_at(BASE + 20, INT_ARRAY),
+                "c" => self.variable_at(BASE + 32, COLOR),
+                _ => return None,
+            };
+            Some(Ok(place))
+        }
+
+        fn register(&self, name: &str) -> Result<u64, String> {
+            match name {
+                "rip" => Ok(0x401000),
+                _ => Err(format!("Invalid register `{}'", name)),
+            }
+        }
+
+        fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+            addr.checked_sub(BASE)
+                .and_then(|offset| self.memory.get(offset..offset + len))
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+        }
+    }
+
+    fn print(scope: &TestScope, text: &str) -> Result<String, String> {
+        let expression = Expression::parse(text, &scope.types)?;
+        format_place(scope, &expression.evaluate(scope)?)
+    }
+
+    fn type_of(scope: &TestScope, text: &str) -> Result<String, String> {
+        Ok(Expression::parse(text, &scope.types)?.evaluate_type(scope)?.name)
+    }
+
+    #[test]
+    fn test_precedence() {
+        let scope = TestScope::new();
+        let cases = [
+            ("1 + 2 * 3", "7"),
+            ("(1 + 2) * 3", "9"),
+            ("10 - 4 - 3", "3"),
+            ("100 / 10 / 5", "2"),
+            ("7 % 3 * 2", "2"),
+            ("1 << 2 + 1", "8"),
+            ("1 + 1 < 3", "1"),
+            ("1 < 2 == 1", "1"),
+            ("6 & 3 ^ 1 | 8", "11"),
+            ("0 || 1 && 0", "0"),
+            ("1 ? 2 : 0 ? 3 : 4", "2"),
+            ("0 ? 2 : 0 ? 3 : 4", "4"),
+            ("-2 * -3", "6"),
+            ("!0 + ~0", "0"),
+            ("- -1", "1"),
+            ("n * 2 + p.y", "16"),
+            ("7 / 2", "3"),
+            ("7.0 / 2", "3.5"),
+            ("-7 >> 1", "-4"),
+            ("0x10 + 010 + 'a'", "121"),
+            ("1u - 2", "4294967295"),
+            ("c == GREEN", "1"),
+        ];
+        for (text, value) in cases {
+            assert_eq!(print(&scope, text), Ok(value.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_casts() {
+        let scope = TestScope::new();
+        let cases = [
+            ("(char) 321", "65 'A'"),
+            ("(unsigned char) -1", "255 '\\377'"),
+            ("(short) 65537", "1"),
+            ("(double) 1 / 2", "0.5"),
+            ("(int) 2.9", "2"),
+            ("(size_t) -1", "18446744073709551615"),
+            ("(unsigned long) n * 2", "14"),
+            ("(long) (char) 200", "-56"),
+            ("(_Bool) 5", "true"),
+            ("(enum color) 1", "GREEN"),
+            ("(struct point *) pp == pp", "1"),
+        ];
+        for (text, value) in cases {
+            assert_eq!(print(&scope, text), Ok(value.to_string()), "{}", text);
+        }
+        assert_eq!(type_of(&scope, "(size_t) n"), Ok("size_t".to_string()));
+        assert_eq!(type_of(&scope, "(unsigned char *) pp"), Ok("unsigned char *".to_string()));
+    }
+
+    #[test]
+    fn test_sizeof() {
+        let scope = TestScope::new();
+        let cases = [
+            ("sizeof(int)", "4"),
+            ("sizeof (char)", "1"),
+            ("sizeof(long double)", "16"),
+            ("sizeof(char *)", "8"),
+            ("sizeof(struct point)", "8"),
+            ("sizeof(size_t)", "8"),
+            ("sizeof n", "4"),
+            ("sizeof arr", "12"),
+            ("sizeof arr / sizeof arr[0]", "3"),
+            ("sizeof pp->x", "4"),
+            ("sizeof(n + 1L)", "8"),
+        ];
+        for (text, value) in cases {
+            assert_eq!(print(&scope, text), Ok(value.to_string()), "{}", text);
+        }
+        assert_eq!(type_of(&scope, "sizeof n"), Ok("long unsigned int".to_string()));
+    }
+
+    #[test]
+    fn test_memory_operators() {
+        let scope = TestScope::new();
+        let cases = [
+            ("pp->y", "2"),
+            ("(*pp).x", "1"),
+            ("pp.x", "1"),
+            ("p->y", "2"),
+            ("p", "{x = 1, y = 2}"),
+            ("*pp", "{x = 1, y = 2}"),
+            ("arr", "{10, 20, 30}"),
+            ("arr[1]", "20"),
+            ("arr[n - 5]", "30"),
+            ("*arr", "10"),
+            ("*(arr + 2)", "30"),
+            ("*&n", "7"),
+            ("&arr[1]", "0x1018"),
+            ("&p", "0x1004"),
+            ("&pp->y", "0x1008"),
+            ("&arr[2] - &arr[0]", "2"),
+            ("*(int *) 0x1000", "7"),
+            ("pp + 1", "0x100c"),
+            ("$rip", "4198400"),
+        ];
+        for (text, value) in cases {
+            assert_eq!(print(&scope, text), Ok(value.to_string()), "{}", text);
+        }
+        assert_eq!(type_of(&scope, "&arr[1]"), Ok("int *".to_string()));
+        assert_eq!(type_of(&scope, "arr"), Ok("int [3]".to_string()));
+        assert_eq!(type_of(&scope, "*pp"), Ok("struct point".to_string()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let scope = TestScope::new();
+        let cases = [
+            ("", "Argument required (expression to compute)."),
+            ("1 +", "A syntax error in expression, near `'."),
+            ("(1", "A syntax error in expression, near `'."),
+            ("n n", "A syntax error in expression, near `n'."),
+            ("1 + * / 2", "A syntax error in expression, near `/ 2'."),
+            ("arr[1", "A syntax error in expression, near `'."),
+            ("p.", "A syntax error in expression, near `'."),
+            ("foo", "No symbol \"foo\" in current context."),
+            ("p.z", "There is no member named z."),
+            ("*n", "Attempt to take contents of a non-pointer value."),
+            ("1 / 0", "Division by zero"),
+            ("n % 0", "Division by zero"),
+            ("(struct nosuch *) 0", "No struct type named nosuch."),
+            ("*(int *) 0", "Cannot access memory at address 0x0"),
+            ("$nosuch", "Invalid register `nosuch'"),
+        ];
+        for (text, error) in cases {
+            assert_eq!(print(&scope, text), Err(error.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        let scope = TestScope::new();
+        let expression = Expression::parse("pp->x + arr[n] * (c ? sizeof p : 1)", &scope.types).unwrap();
+        assert_eq!(expression.names(), ["pp", "arr", "n", "c", "p"]);
+        assert_eq!(expression.to_string(), "pp->x + arr[n] * (c ? sizeof p : 1)");
+    }
+}
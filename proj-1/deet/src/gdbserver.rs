@@ -0,0 +1,759 @@
+//! A stub for the GDB remote serial protocol, so that gdb or lldb can drive a program through
+//! deet's process control: `deet --gdbserver :2345 <program>`. Breakpoints are deet's own, and
+//! continuing and stepping go through `Inferior` as they do at the `(deet)` prompt; the client
+//! does the symbolic side.
+
+use crate::breakpoint::Breakpoint;
+use crate::inferior::{Inferior, Status};
+use crate::process::Process;
+use crate::registers::REGISTERS;
+use crate::rsp::{self, escape_binary, from_hex, parse_hex, register_bytes, register_size, set_register_bytes, to_hex, NUM_REGISTERS, ST0};
+use crate::signals::{self, SignalPolicy, SignalTable};
+use crate::target::Target;
+use nix::sys::signal::{self as nix_signal, Signal};
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileExt;
+use std::sync::mpsc::{self, Receiver};
+
+/// The largest packet we take, as told to the client in `qSupported`.
+const PACKET_SIZE: usize = 0x4000;
+
+/// The x87 control registers, in gdb's order from FCTRL.
+const X87_CONTROL: [&str; 8] = ["fctrl", "fstat", "ftag", "fiseg", "fioff", "foseg", "fooff", "fop"];
+
+/// Waits for one client on `address`, e.g. `:2345` or `0.0.0.0:2345`, and lets it debug the
+/// inferior until it kills or detaches from it. A bare port listens on the loopback interface
+/// only, since the client can make the program do anything.
+pub fn serve(address: &str, inferior: Inferior) -> Result<(), String> {
+    let address = match address.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => address.to_string(),
+    };
+    let listener = TcpListener::bind(&address).map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+    println!("Listening on {}", address);
+    serve_listener(listener, inferior)
+}
+
+/// Waits for one client on a listener that is already bound, and serves it as `serve` does.
+pub fn serve_listener(listener: TcpListener, inferior: Inferior) -> Result<(), String> {
+    let (stream, peer) = listener.accept().map_err(|e| e.to_string())?;
+    println!("Remote debugging from {}", peer);
+    GdbServer::new(stream, inferior)?.run()
+}
+
+/// What the connection reader hands over.
+enum Input {
+    Packet(String),
+    /// A packet that arrived damaged and should be sent again
+    BadChecksum,
+}
+
+struct GdbServer {
+    stream: TcpStream,
+    packets: Receiver<Input>,
+    /// None once the program has exited
+    inferior: Option<Inferior>,
+    /// The client's breakpoints, keyed by address
+    breakpoints: HashMap<usize, Breakpoint>,
+    /// Acknowledge every packet with `+`, until the client turns that off
+    ack: bool,
+    /// The reply to `?`: why the program last stopped
+    stop_reply: String,
+    /// Set once the client has killed or detached from the program
+    done: bool,
+    /// Files the client has opened through host I/O, by descriptor
+    files: HashMap<usize, File>,
+    next_fd: usize,
+}
+
+impl GdbServer {
+    fn new(stream: TcpStream, mut inferior: Inferior) -> Result<GdbServer, String> {
+        // every signal stops the program and is the client's to deliver or not
+        let mut policy = SignalTable::default();
+        for signal in Signal::iterator() {
+            *policy.get_mut(signal) = SignalPolicy { stop: true, print: false, pass: true };
+        }
+        inferior.set_signal_policy(policy);
+        let stop_reply = format!("T05thread:{:x};", inferior.current_tid().as_raw());
+
+        // a thread of its own reads the connection, so that a ^C can interrupt the program
+        // while this one waits for it
+        let (sender, packets) = mpsc::channel();
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let pid = inferior.pid();
+        std::thread::spawn(move || read_packets(reader, pid, sender));
+
+        Ok(GdbServer {
+            stream,
+            packets,
+            inferior: Some(inferior),
+            breakpoints: HashMap::new(),
+            ack: true,
+            stop_reply,
+            done: false,
+            files: HashMap::new(),
+            next_fd: 1,
+        })
+    }
+
+    /// Answers packets until the client goes away or is done with the program.
+    fn run(&mut self) -> Result<(), String> {
+        while let Ok(input) = self.packets.recv() {
+            let packet = match input {
+                Input::Packet(packet) => packet,
+                Input::BadChecksum => {
+                    self.write(b"-")?;
+                    continue;
+                }
+            };
+            if self.ack {
+                self.write(b"+")?;
+            }
+            let reply = self.handle(&packet);
+            if let Some(reply) = reply {
+                self.send(&reply)?;
+            }
+            if self.done {
+                break;
+            }
+        }
+        if let Some(mut inferior) = self.inferior.take() {
+            // the client went away without saying what to do with the program
+            if inferior.is_attached() {
+                inferior.detach(&mut self.breakpoints).ok();
+            } else {
+                inferior.kill();
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out one packet and returns the reply, if it has one. An empty reply tells the
+    /// client the packet isn't supported.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let reply = match packet.as_bytes().first()? {
+            b'?' => self.stop_reply.clone(),
+            b'g' => self.read_registers(),
+            b'G' => self.write_registers(&packet[1..]),
+            b'p' => self.read_register(&packet[1..]),
+            b'P' => self.write_register(&packet[1..]),
+            b'm' => self.read_memory(&packet[1..]),
+            b'M' => self.write_memory(&packet[1..]),
+            b'Z' => self.insert_breakpoint(&packet[1..]),
+            b'z' => self.remove_breakpoint(&packet[1..]),
+            b'c' | b's' => {
+                let addr = parse_hex(&packet[1..]);
+                self.resume_all(packet.starts_with('s'), None, addr)
+            }
+            b'C' | b'S' => {
+                // C sig[;addr]
+                let (signal, addr) = match packet[1..].split_once(';') {
+                    Some((signal, addr)) => (signal, parse_hex(addr)),
+                    None => (&packet[1..], None),
+                };
+                let signal = parse_hex(signal).and_then(|num| signals::from_gdb(num as u8));
+                self.resume_all(packet.starts_with('S'), signal, addr)
+            }
+            b'H' => match packet.get(2..) {
+                Some(tid) => self.set_thread(tid),
+                None => "E01".to_string(),
+            },
+            b'T' => match self.find_thread(&packet[1..]) {
+                Some(_) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            b'k' => {
+                self.kill();
+                return None;
+            }
+            b'D' => {
+                self.detach();
+                "OK".to_string()
+            }
+            b'q' | b'Q' => self.handle_query(packet),
+            b'v' => self.handle_v(packet),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn handle_query(&mut self, packet: &str) -> String {
+        let (name, args) = packet.split_once(':').unwrap_or((packet, ""));
+        match name {
+            "qSupported" => format!(
+                "PacketSize={:x};QStartNoAckMode+;qXfer:features:read+;qXfer:auxv:read+;qXfer:exec-file:read+;swbreak+;vContSupported+",
+                PACKET_SIZE
+            ),
+            "QStartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            }
+            "qAttached" => match &self.inferior {
+                Some(inferior) if inferior.is_attached() => "1".to_string(),
+                _ => "0".to_string(),
+            },
+            "qC" => match &self.inferior {
+                Some(inferior) => format!("QC{:x}", inferior.current_tid().as_raw()),
+                None => "E01".to_string(),
+            },
+            "qfThreadInfo" => match &self.inferior {
+                Some(inferior) => {
+                    let tids: Vec<String> = inferior.threads().iter().map(|thread| format!("{:x}", thread.tid.as_raw())).collect();
+                    format!("m{}", tids.join(","))
+                }
+                None => "l".to_string(),
+            },
+            "qsThreadInfo" => "l".to_string(),
+            "qXfer" => self.transfer(args),
+            _ => String::new(),
+        }
+    }
+
+    fn handle_v(&mut self, packet: &str) -> String {
+        if packet == "vCont?" {
+            return "vCont;c;C;s;S".to_string();
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            return self.resume_actions(actions);
+        }
+        if packet.starts_with("vKill") {
+            self.kill();
+            return "OK".to_string();
+        }
+        if let Some(request) = packet.strip_prefix("vFile:") {
+            return self.file_request(request);
+        }
+        String::new()
+    }
+
+    /// Carries out the part of gdb's host I/O that lets a client read files on this machine,
+    /// such as /proc/<pid>/maps. Files are only ever opened for reading.
+    fn file_request(&mut self, request: &str) -> String {
+        let (operation, args) = request.split_once(':').unwrap_or((request, ""));
+        let args: Vec<&str> = args.split(',').collect();
+        let result = match (operation, args.as_slice()) {
+            // the client means files as this process sees them, whichever one it names
+            ("setfs", _) => Ok("F0".to_string()),
+            ("open", [path, ..]) => {
+                let path = from_hex(path).map(|path| String::from_utf8_lossy(&path).into_owned()).unwrap_or_default();
+                File::open(path).map(|file| {
+                    let fd = self.next_fd;
+                    self.next_fd += 1;
+                    self.files.insert(fd, file);
+                    format!("F{:x}", fd)
+                })
+            }
+            ("pread", [fd, count, offset]) => match (parse_hex(fd).and_then(|fd| self.files.get(&fd)), parse_hex(count), parse_hex(offset)) {
+                (Some(file), Some(count), Some(offset)) => {
+                    let mut data = vec![0; count.min(0x1000)];
+                    file.read_at(&mut data, offset as u64).map(|len| format!("F{:x};{}", len, escape_binary(&data[..len])))
+                }
+                _ => Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+            },
+            ("close", [fd]) => match parse_hex(fd).and_then(|fd| self.files.remove(&fd)) {
+                Some(_) => Ok("F0".to_string()),
+                None => Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+            },
+            _ => return String::new(),
+        };
+        // gdb's errno values agree with Linux for the errors opening and reading can give
+        result.unwrap_or_else(|e| format!("F-1,{:x}", e.raw_os_error().unwrap_or(libc::EIO)))
+    }
+
+    /// Answers `qXfer:object:read:annex:offset,length` with that much of the object.
+    fn transfer(&self, args: &str) -> String {
+        let parts: Vec<&str> = args.splitn(4, ':').collect();
+        let (object, annex, range) = match parts.as_slice() {
+            [object, "read", annex, range] => (*object, *annex, *range),
+            _ => return String::new(),
+        };
+        let (offset, length) = match range.split_once(',').map(|(offset, length)| (parse_hex(offset), parse_hex(length))) {
+            Some((Some(offset), Some(length))) => (offset, length),
+            _ => return "E01".to_string(),
+        };
+        let data = match (object, annex) {
+            ("features", "target.xml") => target_xml().into_bytes(),
+            ("auxv", "") => match self.inferior.as_ref().and_then(|inferior| inferior.auxv()) {
+                Some(auxv) => auxv,
+                None => return "E01".to_string(),
+            },
+            ("exec-file", _) => match self.inferior.as_ref() {
+                Some(inferior) => match std::fs::read_link(format!("/proc/{}/exe", inferior.pid())) {
+                    Ok(path) => path.to_string_lossy().into_owned().into_bytes(),
+                    Err(_) => return "E01".to_string(),
+                },
+                None => return "E01".to_string(),
+            },
+            _ => return "E00".to_string(),
+        };
+        let chunk = data.get(offset..).unwrap_or_default();
+        let chunk = &chunk[..chunk.len().min(length)];
+        // m means there is more to come, l that this is the last of it
+        let more = if offset + chunk.len() < data.len() { "m" } else { "l" };
+        format!("{}{}", more, escape_binary(chunk))
+    }
+
+    /// Makes the thread that `Hg` or `Hc` names current. 0 and -1 leave it as is.
+    fn set_thread(&mut self, tid: &str) -> String {
+        if tid == "0" || tid == "-1" {
+            return "OK".to_string();
+        }
+        match self.find_thread(tid) {
+            Some(num) => {
+                self.inferior.as_mut().unwrap().select_thread(num);
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    /// Returns the number deet gives the thread with a hex thread ID.
+    fn find_thread(&self, tid: &str) -> Option<usize> {
+        let tid = Pid::from_raw(i32::from_str_radix(tid, 16).ok()?);
+        let inferior = self.inferior.as_ref()?;
+        inferior.threads().iter().find(|thread| thread.tid == tid).map(|thread| thread.num)
+    }
+
+    fn read_registers(&self) -> String {
+        match self.registers() {
+            Some((regs, fpregs)) => (0..NUM_REGISTERS).map(|num| to_hex(&register_bytes(num, &regs, &fpregs))).collect(),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let (mut regs, mut fpregs) = match self.registers() {
+            Some(registers) => registers,
+            None => return "E01".to_string(),
+        };
+        let bytes = match from_hex(hex) {
+            Some(bytes) => bytes,
+            None => return "E01".to_string(),
+        };
+        let mut offset = 0;
+        for num in 0..NUM_REGISTERS {
+            let size = register_size(num);
+            // a short packet leaves the registers past its end alone
+            if offset + size > bytes.len() {
+                break;
+            }
+            set_register_bytes(num, &bytes[offset..offset + size], &mut regs, &mut fpregs);
+            offset += size;
+        }
+        self.set_registers(regs, fpregs)
+    }
+
+    fn read_register(&self, num: &str) -> String {
+        match (parse_hex(num), self.registers()) {
+            (Some(num), Some((regs, fpregs))) if num < NUM_REGISTERS => to_hex(&register_bytes(num, &regs, &fpregs)),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let (num, value) = match args.split_once('=') {
+            Some((num, value)) => (parse_hex(num), from_hex(value)),
+            None => return "E01".to_string(),
+        };
+        match (num, value, self.registers()) {
+            (Some(num), Some(value), Some((mut regs, mut fpregs))) if num < NUM_REGISTERS && value.len() == register_size(num) => {
+                set_register_bytes(num, &value, &mut regs, &mut fpregs);
+                self.set_registers(regs, fpregs)
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Returns the registers of the current thread.
+    fn registers(&self) -> Option<(libc::user_regs_struct, libc::user_fpregs_struct)> {
+        let inferior = self.inferior.as_ref()?;
+        Some((inferior.get_registers().ok()?, inferior.get_fp_registers().ok()?))
+    }
+
+    fn set_registers(&mut self, regs: libc::user_regs_struct, fpregs: libc::user_fpregs_struct) -> String {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return "E01".to_string(),
+        };
+        match inferior.set_registers(regs).and_then(|_| inferior.set_fp_registers(fpregs)) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    /// Reads memory as the program sees it, without the 0xcc of our breakpoints. A client may
+    /// ask for more than fits in a packet, in which case it gets the start of it.
+    fn read_memory(&self, args: &str) -> String {
+        let (addr, len) = match parse_range(args) {
+            Some((addr, len)) => (addr, len.min(PACKET_SIZE / 2)),
+            None => return "E01".to_string(),
+        };
+        let mut bytes = match self.inferior.as_ref().map(|inferior| inferior.read_bytes(addr, len)) {
+            Some(Ok(bytes)) => bytes,
+            _ => return "E01".to_string(),
+        };
+        for breakpoint in self.breakpoints.values() {
+            if let (true, Some(orig_byte)) = (breakpoint.addr >= addr && breakpoint.addr - addr < len, breakpoint.orig_byte) {
+                bytes[breakpoint.addr - addr] = orig_byte;
+            }
+        }
+        to_hex(&bytes)
+    }
+
+    /// Writes memory. Bytes under our breakpoints become the ones restored when the breakpoint
+    /// is removed.
+    fn write_memory(&mut self, args: &str) -> String {
+        let (range, hex) = match args.split_once(':') {
+            Some(parts) => parts,
+            None => return "E01".to_string(),
+        };
+        let (addr, mut bytes) = match (parse_range(range), from_hex(hex)) {
+            (Some((addr, len)), Some(bytes)) if bytes.len() == len => (addr, bytes),
+            _ => return "E01".to_string(),
+        };
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return "E01".to_string(),
+        };
+        for breakpoint in self.breakpoints.values_mut() {
+            if breakpoint.addr >= addr && breakpoint.addr - addr < bytes.len() {
+                breakpoint.orig_byte = Some(bytes[breakpoint.addr - addr]);
+                bytes[breakpoint.addr - addr] = 0xcc;
+            }
+        }
+        match inferior.write_bytes(addr, &bytes) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    /// Sets a software breakpoint with `Z0,addr,kind`. Other kinds aren't supported.
+    fn insert_breakpoint(&mut self, args: &str) -> String {
+        let addr = match breakpoint_address(args) {
+            Some(addr) => addr,
+            None => return String::new(),
+        };
+        if self.breakpoints.contains_key(&addr) {
+            return "OK".to_string();
+        }
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return "E01".to_string(),
+        };
+        match inferior.set_breakpoint(addr) {
+            Ok(orig_byte) => {
+                let mut breakpoint = Breakpoint::new(self.breakpoints.len() + 1, addr);
+                breakpoint.orig_byte = Some(orig_byte);
+                self.breakpoints.insert(addr, breakpoint);
+                "OK".to_string()
+            }
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> String {
+        let addr = match breakpoint_address(args) {
+            Some(addr) => addr,
+            None => return String::new(),
+        };
+        let breakpoint = match self.breakpoints.remove(&addr) {
+            Some(breakpoint) => breakpoint,
+            None => return "OK".to_string(),
+        };
+        match (self.inferior.as_mut(), breakpoint.orig_byte) {
+            (Some(inferior), Some(orig_byte)) => match inferior.remove_breakpoint(addr, orig_byte) {
+                Ok(()) => "OK".to_string(),
+                Err(_) => "E01".to_string(),
+            },
+            _ => "OK".to_string(),
+        }
+    }
+
+    /// Carries out `vCont;action[:tid];...`. A step for a particular thread steps that thread
+    /// alone; otherwise every thread continues, each with the signal its action gives it.
+    fn resume_actions(&mut self, actions: &str) -> String {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return "E01".to_string(),
+        };
+        let tids: Vec<Pid> = inferior.threads().iter().map(|thread| thread.tid).collect();
+        for tid in &tids {
+            inferior.set_resume_signal(*tid, None);
+        }
+        let mut step = false;
+        let mut given = Vec::new();
+        for action in actions.split(';') {
+            let (action, thread) = match action.split_once(':') {
+                Some((action, thread)) => (action, i32::from_str_radix(thread, 16).ok().filter(|tid| *tid > 0)),
+                None => (action, None),
+            };
+            let signal = match action.as_bytes().first() {
+                Some(b'C' | b'S') => parse_hex(&action[1..]).and_then(|num| signals::from_gdb(num as u8)),
+                _ => None,
+            };
+            // the first action that applies to a thread is the one it takes
+            let applies: Vec<Pid> = match thread {
+                Some(tid) => vec![Pid::from_raw(tid)],
+                None => tids.clone(),
+            };
+            for tid in applies {
+                if given.contains(&tid) {
+                    continue;
+                }
+                if action.starts_with(['s', 'S']) && thread.is_some() {
+                    let num = inferior.threads().iter().find(|t| t.tid == tid).map(|t| t.num);
+                    if let Some(num) = num {
+                        inferior.select_thread(num);
+                        step = true;
+                    }
+                }
+                inferior.set_resume_signal(tid, signal);
+                given.push(tid);
+            }
+        }
+        self.resume(step, None)
+    }
+
+    /// Resumes the program from `c`, `s`, `C` or `S`: the current thread takes the signal, if
+    /// any, and the others none.
+    fn resume_all(&mut self, step: bool, signal: Option<Signal>, addr: Option<usize>) -> String {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return "E01".to_string(),
+        };
+        let tids: Vec<Pid> = inferior.threads().iter().map(|thread| thread.tid).collect();
+        for tid in tids {
+            inferior.set_resume_signal(tid, None);
+        }
+        let current = inferior.current_tid();
+        inferior.set_resume_signal(current, signal);
+        self.resume(step, addr)
+    }
+
+    /// Steps the current thread or continues the program, from `addr` if given, and returns the
+    /// stop reply.
+    fn resume(&mut self, step: bool, addr: Option<usize>) -> String {
+        let inferior = self.inferior.as_mut().unwrap();
+        if let Some(addr) = addr {
+            if let Ok(mut regs) = inferior.get_registers() {
+                regs.rip = addr as u64;
+                inferior.set_registers(regs).ok();
+            }
+        }
+        let result = if step {
+            inferior.step_instruction(&self.breakpoints)
+        } else {
+            inferior.cont(&self.breakpoints)
+        };
+        let reply = match result {
+            Ok(Status::Stopped(signal, rip)) => {
+                // tells the client that the pc has already been moved back onto the breakpoint
+                let swbreak = !step && signal == Signal::SIGTRAP && self.breakpoints.contains_key(&rip);
+                format!(
+                    "T{:02x}thread:{:x};{}",
+                    signals::to_gdb(signal),
+                    inferior.current_tid().as_raw(),
+                    if swbreak { "swbreak:;" } else { "" }
+                )
+            }
+            Ok(Status::Exec) => format!("T05thread:{:x};", inferior.current_tid().as_raw()),
+            Ok(Status::Exited(code)) => {
+                self.inferior = None;
+                format!("W{:02x}", code as u8)
+            }
+            Ok(Status::Signaled(signal)) => {
+                self.inferior = None;
+                format!("X{:02x}", signals::to_gdb(signal))
+            }
+            Err(_) => return "E01".to_string(),
+        };
+        self.stop_reply = reply.clone();
+        reply
+    }
+
+    fn kill(&mut self) {
+        if let Some(mut inferior) = self.inferior.take() {
+            inferior.kill();
+        }
+        self.done = true;
+    }
+
+    fn detach(&mut self) {
+        if let Some(mut inferior) = self.inferior.take() {
+            if let Err(e) = inferior.detach(&mut self.breakpoints) {
+                println!("Error detaching from process {}: {}", inferior.pid(), e);
+            }
+        }
+        self.done = true;
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), String> {
+        self.write(&rsp::packet(data))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.stream.write_all(bytes).map_err(|e| format!("Error writing to client: {}", e))
+    }
+}
+
+/// Reads packets from the client and passes them on, until the connection closes. A ^C byte
+/// interrupts the program with SIGINT, which stops it and answers the packet that resumed it.
+fn read_packets(stream: TcpStream, pid: Pid, sender: mpsc::Sender<Input>) {
+    let mut bytes = BufReader::new(stream).bytes().map_while(Result::ok);
+    while let Some(byte) = bytes.next() {
+        match byte {
+            0x03 => {
+                nix_signal::kill(pid, Signal::SIGINT).ok();
+            }
+            b'$' => {
+                let data: Vec<u8> = bytes.by_ref().take_while(|byte| *byte != b'#').collect();
+                let checksum: Vec<u8> = bytes.by_ref().take(2).collect();
+                let input = match rsp::packet_data(&data, &checksum) {
+                    Some(packet) => Input::Packet(packet),
+                    None => Input::BadChecksum,
+                };
+                if sender.send(input).is_err() {
+                    break;
+                }
+            }
+            // acknowledgements, which we don't resend on
+            _ => {}
+        }
+    }
+}
+
+/// Describes the registers to the client in the order `g` packets hold them, as gdb's own
+/// amd64 Linux description does.
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\">\
+         <architecture>i386:x86-64</architecture><osabi>GNU/Linux</osabi><feature name=\"org.gnu.gdb.i386.core\">",
+    );
+    let mut reg = |name: &str, bits: usize, kind: &str| {
+        xml.push_str(&format!("<reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>", name, bits, kind));
+    };
+    for (num, register) in REGISTERS.iter().enumerate().take(ST0) {
+        let kind = match register.name {
+            "rbp" | "rsp" => "data_ptr",
+            "rip" => "code_ptr",
+            _ if num > 16 => "int32",
+            _ => "int64",
+        };
+        reg(register.name, register_size(num) * 8, kind);
+    }
+    for i in 0..8 {
+        reg(&format!("st{}", i), 80, "i387_ext");
+    }
+    for name in X87_CONTROL {
+        reg(name, 32, "int");
+    }
+    xml.push_str(
+        "</feature><feature name=\"org.gnu.gdb.i386.sse\">\
+         <vector id=\"v4f\" type=\"ieee_single\" count=\"4\"/><vector id=\"v2d\" type=\"ieee_double\" count=\"2\"/>\
+         <vector id=\"v16i8\" type=\"int8\" count=\"16\"/><vector id=\"v8i16\" type=\"int16\" count=\"8\"/>\
+         <vector id=\"v4i32\" type=\"int32\" count=\"4\"/><vector id=\"v2i64\" type=\"int64\" count=\"2\"/>\
+         <union id=\"vec128\"><field name=\"v4_float\" type=\"v4f\"/><field name=\"v2_double\" type=\"v2d\"/>\
+         <field name=\"v16_int8\" type=\"v16i8\"/><field name=\"v8_int16\" type=\"v8i16\"/>\
+         <field name=\"v4_int32\" type=\"v4i32\"/><field name=\"v2_int64\" type=\"v2i64\"/>\
+         <field name=\"uint128\" type=\"uint128\"/></union>",
+    );
+    for i in 0..16 {
+        xml.push_str(&format!("<reg name=\"xmm{}\" bitsize=\"128\" type=\"vec128\"/>", i));
+    }
+    xml.push_str("<reg name=\"mxcsr\" bitsize=\"32\" type=\"int\"/></feature>");
+    xml.push_str("<feature name=\"org.gnu.gdb.i386.linux\"><reg name=\"orig_rax\" bitsize=\"64\" type=\"int\"/></feature>");
+    xml.push_str(
+        "<feature name=\"org.gnu.gdb.i386.segments\">\
+         <reg name=\"fs_base\" bitsize=\"64\" type=\"int\"/><reg name=\"gs_base\" bitsize=\"64\" type=\"int\"/></feature>",
+    );
+    xml.push_str("</target>");
+    xml
+}
+
+/// Returns the address of a `Z0,addr,kind` or `z0,addr,kind` breakpoint.
+fn breakpoint_address(args: &str) -> Option<usize> {
+    let mut parts = args.split(',');
+    match (parts.next(), parts.next()) {
+        (Some("0"), Some(addr)) => parse_hex(addr),
+        _ => None,
+    }
+}
+
+/// Parses the `addr,length` of an `m` or `M` packet.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server(inferior: Option<Inferior>) -> GdbServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (_, packets) = mpsc::channel();
+        GdbServer {
+            stream,
+            packets,
+            inferior,
+            breakpoints: HashMap::new(),
+            ack: true,
+            stop_reply: "S05".to_string(),
+            done: false,
+            files: HashMap::new(),
+            next_fd: 1,
+        }
+    }
+
+    #[test]
+    fn test_handle_malformed() {
+        let mut server = server(None);
+        let packets = [
+            "H", "Hg", "Hgzz", "H\u{e9}", "T", "Tzz", "m", "m1", "mzz,1", "m1,zz", "M1,2", "M1,2:zz", "M1,1:0", "Z", "Z0",
+            "Z0,zz,1", "z0,zz", "p", "pzz", "P", "P1=", "Pzz=00", "G", "Gz", "g", "c", "czz", "C", "Czz;1", "vCont;",
+            "vCont;C", "vFile:open:", "vFile:open:zz,0,0", "vFile:pread:1", "vFile:pread:zz,1,0", "vFile:close:",
+            "qXfer", "qXfer:features:read:target.xml:", "qXfer:features:read:target.xml:zz,1", "qXfer:auxv:read::0,10",
+        ];
+        for packet in packets {
+            match server.handle(packet) {
+                Some(reply) => assert!(reply.is_empty() || reply.starts_with('E') || reply.starts_with("F-1"), "{}: {}", packet, reply),
+                None => panic!("{} got no reply", packet),
+            }
+        }
+        assert_eq!(server.handle(""), None);
+        assert_eq!(server.handle("\u{e9}"), Some(String::new()));
+        assert!(!server.done);
+    }
+
+    #[test]
+    fn test_handle_queries() {
+        let mut server = server(None);
+        assert_eq!(server.handle("?"), Some("S05".to_string()));
+        assert!(server.handle("qSupported:swbreak+").unwrap().starts_with("PacketSize=4000;"));
+        assert_eq!(server.handle("qsThreadInfo"), Some("l".to_string()));
+        assert_eq!(server.handle("qNoSuchQuery"), Some(String::new()));
+        let features = server.handle("qXfer:features:read:target.xml:0,a").unwrap();
+        assert_eq!(features, "m<?xml vers");
+        assert_eq!(server.handle("QStartNoAckMode"), Some("OK".to_string()));
+        assert!(!server.ack);
+    }
+
+    #[test]
+    fn test_read_memory_clamped() {
+        let inferior = Inferior::new("/bin/true", &Vec::new()).expect("could not start /bin/true");
+        let rip = inferior.get_registers().unwrap().rip;
+        let mut server = server(Some(inferior));
+        assert_eq!(server.handle("H"), Some("E01".to_string()));
+        let reply = server.handle(&format!("m{:x},ffffffffffffffff", rip)).unwrap();
+        assert_eq!(reply.len(), PACKET_SIZE);
+        assert!(from_hex(&reply).is_some());
+        assert_eq!(server.handle(&format!("m{:x},4", rip)), Some(reply[..8].to_string()));
+        assert_eq!(server.handle("k"), None);
+        assert!(server.done);
+    }
+}
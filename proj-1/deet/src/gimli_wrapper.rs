@@ -10,13 +10,14 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{Encoding, File, Function, Line, Member, Type, TypeKind, Types, Variable};
+use crate::location::{Expression, Location};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<(Vec<File>, Types), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -39,8 +40,15 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
+    // Types may be referenced before they are defined, so they are all collected before any
+    // variables
     let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = dwarf.unit(header)?;
+        load_types(&unit, &dwarf, &mut offset_to_type)?;
+    }
+    let types = Types::new(offset_to_type);
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -51,10 +59,13 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
 
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        let mut inlined_depth = None;
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
-            // Update the offset_to_type mapping for types
+            if inlined_depth.map_or(false, |inlined_depth| depth <= inlined_depth) {
+                inlined_depth = None;
+            }
             // Update the variable list for formal params/variables
             match entry.tag() {
                 gimli::DW_TAG_compile_unit => {
@@ -74,35 +85,15 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
+                // the variables of inlined functions don't belong to the function they were
+                // inlined into
+                _ if inlined_depth.map_or(false, |inlined_depth| depth > inlined_depth) => {}
+                gimli::DW_TAG_inlined_subroutine => inlined_depth = Some(depth),
                 gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
+                    let mut func = Function {
+                        return_type: type_ref(entry, &unit),
+                        ..Default::default()
+                    };
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -129,9 +120,23 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_frame_base => {
+                                func.frame_base = get_location(&attr, &unit, &dwarf);
+                            }
                             _ => {}
                         }
                     }
+                    // an out-of-line copy of a function that was also inlined describes itself
+                    // through the abstract one
+                    if let Some(origin) = abstract_origin(entry, &unit) {
+                        if func.name.is_empty() {
+                            func.name = entry_name(&origin, &unit, &dwarf).unwrap_or_default();
+                            func.line_number = decl_line(&origin).unwrap_or(0);
+                        }
+                        if func.return_type.is_none() {
+                            func.return_type = type_ref(&origin, &unit);
+                        }
+                    }
                     compilation_units.last_mut().unwrap().functions.push(func);
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
@@ -151,15 +156,14 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
+                                    entity_type = types.get(Some(offset)).cloned();
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                                location = get_location(&attr, &unit, &dwarf);
+                            }
+                            gimli::DW_AT_const_value => {
+                                location = const_value(attr.value()).map(Location::Constant);
                             }
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
@@ -169,12 +173,25 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
+                    if let Some(origin) = abstract_origin(entry, &unit) {
+                        if name.is_empty() {
+                            name = entry_name(&origin, &unit, &dwarf).unwrap_or_default();
+                            line_number = decl_line(&origin).unwrap_or(0) as u64;
+                        }
+                        if entity_type.is_none() {
+                            entity_type = types.get(type_ref(&origin, &unit)).cloned();
+                        }
+                    }
+                    // a declaration only says the variable is defined somewhere else, while a
+                    // definition without a location has been optimized away
+                    let declaration = matches!(entry.attr_value(gimli::DW_AT_declaration)?, Some(gimli::AttributeValue::Flag(true)));
+                    if entity_type.is_some() && !name.is_empty() && !declaration {
                         let var = Variable {
                             name,
                             entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
+                            location: location.unwrap_or(Location::OptimizedOut),
                             line_number: line_number.try_into().unwrap(),
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
                         };
                         if depth == 1 {
                             compilation_units
@@ -220,10 +237,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
+                    // Get the File. The unit name may be relative to the compilation
+                    // directory, while the line program path is usually absolute.
                     let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                        .last_mut()
+                        .filter(|f| path.ends_with(&f.name));
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -240,7 +258,270 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, types))
+}
+
+/// Returns the offset of an entry in .debug_info, which is how types are keyed.
+fn section_offset<R: Reader>(unit: &gimli::Unit<R>, offset: UnitOffset) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
+/// Returns the offset of the type an entry's DW_AT_type refers to, or None for void.
+fn type_ref<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>, unit: &gimli::Unit<R>) -> Option<usize> {
+    match entry.attr_value(gimli::DW_AT_type).ok()?? {
+        gimli::AttributeValue::UnitRef(offset) => Some(section_offset(unit, offset)),
+        gimli::AttributeValue::DebugInfoRef(offset) => Some(offset.0),
+        _ => None,
+    }
+}
+
+fn entry_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Collects the types a unit defines, keyed by their offset in .debug_info. The names and sizes
+/// of types made from others are filled in by `Types::new` once all of them are known.
+fn load_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    types: &mut HashMap<usize, Type>,
+) -> Result<(), Error> {
+    // Members, dimensions, enumerators and parameters come right after the type they belong to,
+    // one level deeper
+    let mut parents: Vec<(isize, usize)> = Vec::new();
+    let mut dimensions: HashMap<usize, Vec<(usize, Option<usize>)>> = HashMap::new();
+    // Enumerator values may be stored in fewer bytes than the enum has, and need sign-extending
+    // if its underlying type is signed, which isn't known until the end
+    let mut enumerators: Vec<(usize, String, u64, u32)> = Vec::new();
+    let mut enums: Vec<(usize, Option<usize>)> = Vec::new();
+
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while parents.last().map_or(false, |(parent_depth, _)| *parent_depth >= depth) {
+            parents.pop();
+        }
+        let parent = parents
+            .last()
+            .filter(|(parent_depth, _)| *parent_depth == depth - 1)
+            .map(|(_, parent)| *parent);
+        let offset = section_offset(unit, entry.offset());
+        let name = entry_name(entry, unit, dwarf);
+        let byte_size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|value| value.udata_value())
+            .map(|size| size as usize);
+        let target = type_ref(entry, unit);
+        let derived = |kind: TypeKind| Type {
+            kind,
+            ..Default::default()
+        };
+        let ty = match entry.tag() {
+            gimli::DW_TAG_base_type => {
+                let encoding = match entry.attr_value(gimli::DW_AT_encoding) {
+                    Ok(Some(gimli::AttributeValue::Encoding(ate))) => match ate {
+                        gimli::DW_ATE_unsigned | gimli::DW_ATE_UTF => Encoding::Unsigned,
+                        gimli::DW_ATE_signed_char => Encoding::SignedChar,
+                        gimli::DW_ATE_unsigned_char => Encoding::UnsignedChar,
+                        gimli::DW_ATE_float => Encoding::Float,
+                        gimli::DW_ATE_boolean => Encoding::Boolean,
+                        gimli::DW_ATE_address => Encoding::Address,
+                        _ => Encoding::Signed,
+                    },
+                    _ => Encoding::Signed,
+                };
+                Type::new(name.unwrap_or_else(|| "<unknown>".to_string()), byte_size.unwrap_or(0), encoding)
+            }
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => Type {
+                size: byte_size.unwrap_or(std::mem::size_of::<usize>()),
+                encoding: Encoding::Address,
+                ..derived(TypeKind::Pointer(target))
+            },
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+                let keyword = match entry.tag() {
+                    gimli::DW_TAG_structure_type => "struct",
+                    gimli::DW_TAG_union_type => "union",
+                    _ => "class",
+                };
+                Type {
+                    name: format!("{} {}", keyword, name.as_deref().unwrap_or("{...}")),
+                    size: byte_size.unwrap_or(0),
+                    ..derived(TypeKind::Struct(Vec::new()))
+                }
+            }
+            gimli::DW_TAG_array_type => match target {
+                Some(element) => Type {
+                    size: byte_size.unwrap_or(0),
+                    ..derived(TypeKind::Array { element, count: None })
+                },
+                None => continue,
+            },
+            gimli::DW_TAG_enumeration_type => {
+                enums.push((offset, target));
+                Type {
+                    name: format!("enum {}", name.as_deref().unwrap_or("{...}")),
+                    size: byte_size.unwrap_or(4),
+                    ..derived(TypeKind::Enum(Vec::new()))
+                }
+            }
+            gimli::DW_TAG_typedef => Type {
+                name: name.unwrap_or_default(),
+                ..derived(TypeKind::Typedef(target))
+            },
+            gimli::DW_TAG_const_type => derived(TypeKind::Qualified("const", target)),
+            gimli::DW_TAG_volatile_type => derived(TypeKind::Qualified("volatile", target)),
+            gimli::DW_TAG_restrict_type => derived(TypeKind::Qualified("restrict", target)),
+            gimli::DW_TAG_atomic_type => derived(TypeKind::Qualified("_Atomic", target)),
+            gimli::DW_TAG_subroutine_type => Type {
+                // like GDB, functions are one byte long, so pointer arithmetic on them works
+                size: 1,
+                ..derived(TypeKind::Function {
+                    return_type: target,
+                    params: Vec::new(),
+                    variadic: false,
+                })
+            },
+            gimli::DW_TAG_member => {
+                let mut member = Member {
+                    name: name.unwrap_or_default(),
+                    entity_type: target,
+                    offset: 0,
+                    bits: None,
+                };
+                match entry.attr_value(gimli::DW_AT_data_member_location)? {
+                    Some(gimli::AttributeValue::Udata(offset)) => member.offset = offset as usize,
+                    // DWARF 2 writes it as an expression adding the offset to the struct's address
+                    Some(gimli::AttributeValue::Exprloc(expression)) => {
+                        let mut pc = expression.0.clone();
+                        if let Ok(gimli::Operation::PlusConstant { value }) =
+                            gimli::Operation::parse(&mut pc, unit.encoding())
+                        {
+                            member.offset = value as usize;
+                        }
+                    }
+                    _ => {}
+                }
+                let bit_size = entry.attr_value(gimli::DW_AT_bit_size)?.and_then(|value| value.udata_value());
+                if let Some(width) = bit_size.map(|width| width as usize) {
+                    let data_bit_offset = entry.attr_value(gimli::DW_AT_data_bit_offset)?.and_then(|value| value.udata_value());
+                    let bit_offset = entry.attr_value(gimli::DW_AT_bit_offset)?.and_then(|value| value.udata_value());
+                    if let Some(bit) = data_bit_offset.map(|bit| bit as usize) {
+                        member.offset = bit / 8;
+                        member.bits = Some((bit % 8, width));
+                    } else if let Some(bit_offset) = bit_offset {
+                        // DWARF 2 and 3 count from the most significant bit of a storage unit
+                        // of the member's byte size
+                        let storage = 8 * byte_size.unwrap_or(4);
+                        let bit = storage.saturating_sub(bit_offset as usize + width);
+                        member.offset += bit / 8;
+                        member.bits = Some((bit % 8, width));
+                    }
+                }
+                if let Some(TypeKind::Struct(members)) = parent.and_then(|parent| types.get_mut(&parent)).map(|ty| &mut ty.kind) {
+                    members.push(member);
+                }
+                continue;
+            }
+            gimli::DW_TAG_subrange_type => {
+                let count = match entry.attr_value(gimli::DW_AT_count)?.and_then(|value| value.udata_value()) {
+                    Some(count) => Some(count as usize),
+                    // variable-length arrays have an expression for a bound, and flexible
+                    // array members have none
+                    None => entry
+                        .attr_value(gimli::DW_AT_upper_bound)?
+                        .and_then(|value| value.udata_value())
+                        .map(|bound| bound as usize + 1),
+                };
+                if let Some(parent) = parent {
+                    dimensions.entry(parent).or_default().push((offset, count));
+                }
+                continue;
+            }
+            gimli::DW_TAG_enumerator => {
+                let (value, width) = match entry.attr_value(gimli::DW_AT_const_value)? {
+                    Some(gimli::AttributeValue::Data1(value)) => (value as u64, 1),
+                    Some(gimli::AttributeValue::Data2(value)) => (value as u64, 2),
+                    Some(gimli::AttributeValue::Data4(value)) => (value as u64, 4),
+                    Some(gimli::AttributeValue::Sdata(value)) => (value as u64, 8),
+                    Some(value) => (value.udata_value().unwrap_or(0), 8),
+                    None => (0, 8),
+                };
+                if let Some(parent) = parent {
+                    enumerators.push((parent, name.unwrap_or_default(), value, width));
+                }
+                continue;
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_unspecified_parameters => {
+                let function = parent.and_then(|parent| types.get_mut(&parent)).map(|ty| &mut ty.kind);
+                if let Some(TypeKind::Function { params, variadic, .. }) = function {
+                    if entry.tag() == gimli::DW_TAG_formal_parameter {
+                        params.push(target);
+                    } else {
+                        *variadic = true;
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if entry.has_children() {
+            parents.push((depth, offset));
+        }
+        types.insert(offset, ty);
+    }
+
+    // Each dimension of an array after the first is an array of the next one, which is keyed
+    // by the offset of its subrange entry
+    for (array, dimensions) in dimensions {
+        let element = match types.get(&array).map(|ty| &ty.kind) {
+            Some(TypeKind::Array { element, .. }) => *element,
+            _ => continue,
+        };
+        let mut outer = array;
+        for (i, (subrange, count)) in dimensions.into_iter().enumerate() {
+            let id = if i == 0 { array } else { subrange };
+            if i > 0 {
+                types.insert(id, Type::default());
+                if let Some(TypeKind::Array { element, .. }) = types.get_mut(&outer).map(|ty| &mut ty.kind) {
+                    *element = id;
+                }
+            }
+            types.get_mut(&id).unwrap().kind = TypeKind::Array { element, count };
+            outer = id;
+        }
+    }
+
+    for (offset, underlying) in enums {
+        if let Some(encoding) = underlying.and_then(|underlying| types.get(&underlying)).map(|ty| ty.encoding) {
+            types.get_mut(&offset).unwrap().encoding = encoding;
+        }
+    }
+    for (parent, name, value, width) in enumerators {
+        if let Some(ty) = types.get_mut(&parent) {
+            let signed = ty.encoding != Encoding::Unsigned;
+            let value = if signed && width < 8 {
+                let shift = 64 - 8 * width;
+                (((value << shift) as i64) >> shift) as u64
+            } else {
+                value
+            };
+            if let TypeKind::Enum(values) = &mut ty.kind {
+                values.push((name, value));
+            }
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -291,25 +572,67 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// Reads a DW_AT_location or DW_AT_frame_base, which is either an expression or a location list.
+/// The expressions are kept to be evaluated once the program runs, except for the plain address
+/// of a static variable.
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
+    let encoding = unit.encoding();
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
+        if data.0.is_empty() {
+            return Some(Location::OptimizedOut);
+        }
         let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
-                    }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
-                    }
-                    _ => {}
-                }
+        if let Ok(gimli::Operation::Address { address }) = gimli::Operation::parse(&mut pc, encoding) {
+            if pc.is_empty() {
+                return Some(Location::Address(address.try_into().unwrap()));
             }
         }
+        return Some(Location::Expression(Expression::new(data.0.to_slice().ok()?.to_vec(), encoding)));
+    }
+    let mut entries = dwarf.attr_locations(unit, attr.value()).ok()??;
+    let mut list = Vec::new();
+    while let Ok(Some(entry)) = entries.next() {
+        let expression = Expression::new(entry.data.0.to_slice().ok()?.to_vec(), encoding);
+        list.push((entry.range.begin as usize, entry.range.end as usize, expression));
+    }
+    Some(Location::List(list))
+}
+
+/// Returns the bytes of a DW_AT_const_value, for a variable the compiler replaced with a constant.
+fn const_value<R: Reader>(value: gimli::AttributeValue<R>) -> Option<Vec<u8>> {
+    Some(match value {
+        gimli::AttributeValue::Data1(value) => vec![value],
+        gimli::AttributeValue::Data2(value) => value.to_le_bytes().to_vec(),
+        gimli::AttributeValue::Data4(value) => value.to_le_bytes().to_vec(),
+        gimli::AttributeValue::Data8(value) => value.to_le_bytes().to_vec(),
+        gimli::AttributeValue::Sdata(value) => value.to_le_bytes().to_vec(),
+        gimli::AttributeValue::Udata(value) => value.to_le_bytes().to_vec(),
+        gimli::AttributeValue::Block(block) => block.to_slice().ok()?.to_vec(),
+        _ => return None,
+    })
+}
+
+/// Returns the entry an inlined or out-of-line copy of a function, or one of its variables,
+/// was made from. The name and type are only given there.
+fn abstract_origin<'abbrev, 'unit, R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &'unit gimli::Unit<R>,
+) -> Option<gimli::DebuggingInformationEntry<'abbrev, 'unit, R>>
+where
+    'unit: 'abbrev,
+{
+    match entry.attr_value(gimli::DW_AT_abstract_origin).ok()?? {
+        gimli::AttributeValue::UnitRef(offset) => unit.entry(offset).ok(),
+        _ => None,
     }
-    None
+}
+
+fn decl_line<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Option<usize> {
+    entry.attr_value(gimli::DW_AT_decl_line).ok()??.udata_value().map(|line| line as usize)
 }
 
 // based on dwarf_dump.rs
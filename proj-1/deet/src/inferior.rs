@@ -1,14 +1,17 @@
 use nix::sys::ptrace;
 use nix::sys::signal;
+use nix::sys::uio::{process_vm_readv, process_vm_writev, RemoteIoVec};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::process::Child;
 use std::process::Command;
 use std::os::unix::process::CommandExt;
 use std::collections::HashMap;
-use crate::dwarf_data:: DwarfData;
+use std::io::{IoSlice, IoSliceMut};
 use crate::utils::align_addr_to_word;
-use crate::debugger::Breakpoint;
+use crate::breakpoint::Breakpoint;
+use crate::process::Process;
+use crate::signals::{self, SignalTable};
+use crate::target::{self, Mapping, Target};
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -21,6 +24,9 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior exec'd a new program and is stopped at its entry point.
+    Exec,
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -32,14 +38,110 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// Thread creation, forks and execs are reported to us as ptrace events.
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+/// Offset of `u_debugreg` in `struct user`, for reaching the x86 debug registers through
+/// PTRACE_PEEKUSER/PTRACE_POKEUSER.
+const DEBUG_REGS_OFFSET: usize = 848;
+
+/// x86 has four debug address registers, DR0-DR3.
+pub const NUM_HW_WATCHPOINTS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+/// What to do when the inferior forks.
+#[derive(Clone, Copy)]
+pub struct ForkPolicy {
+    /// Follow the child instead of the parent
+    pub follow_child: bool,
+    /// Let go of the process that is not followed, rather than keeping it stopped
+    pub detach_on_fork: bool,
+}
+
+impl Default for ForkPolicy {
+    fn default() -> ForkPolicy {
+        ForkPolicy {
+            follow_child: false,
+            detach_on_fork: true,
+        }
+    }
+}
+
+/// A thread of the inferior. Threads are numbered in the order deet first sees them.
+pub struct Thread {
+    pub num: usize,
+    pub tid: Pid,
+    /// True from when the thread is resumed until it reports a stop
+    running: bool,
+    /// A SIGSTOP we sent is still queued for this thread and should be swallowed when it arrives
+    stop_expected: bool,
+    /// A stop the thread reported while the other threads were being stopped, with the
+    /// watchpoint slot it triggered. The next `cont` reports it instead of resuming.
+    pending: Option<(signal::Signal, Option<usize>)>,
+    /// The signal the thread last stopped with, to be delivered when it resumes if the signal
+    /// is passed to the program
+    signal: Option<signal::Signal>,
+}
+
+impl Thread {
+    pub fn new(num: usize, tid: Pid) -> Thread {
+        Thread {
+            num,
+            tid,
+            running: false,
+            stop_expected: false,
+            pending: None,
+            signal: None,
+        }
+    }
+}
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// True if we attached to an existing process rather than starting it ourselves
+    attached: bool,
+    threads: Vec<Thread>,
+    next_thread_num: usize,
+    /// The thread whose registers are used and which gets stepped
+    current: Pid,
+    /// The thread the user was last told about
+    announced: Pid,
+    /// The address watched through each of DR0-DR3, if the slot is in use
+    hw_slots: [Option<usize>; NUM_HW_WATCHPOINTS],
+    /// DR7 as it is set in every thread
+    dr7: u64,
+    /// A watchpoint slot that DR6 reported as triggered and nobody has looked at yet
+    hw_watch_hit: Option<usize>,
+    fork_policy: ForkPolicy,
+    signal_policy: SignalTable,
+    /// Processes that forked off (or from) this one and are kept stopped, with detach-on-fork off
+    held: Vec<Inferior>,
+    /// The parent of a vfork we followed into the child. It shares the child's memory until the
+    /// child execs or exits, so it can only be cleaned up and let go then.
+    vfork_parent: Option<Box<Inferior>>,
+    /// Forked children whose first stop arrived before their parent reported the fork
+    stopped_children: Vec<Pid>,
+    /// Ptrace events that threads reported while being stopped, to be handled on the next `cont`
+    deferred_events: Vec<WaitStatus>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: &mut HashMap<usize, Option<Breakpoint>>) -> Option<Inferior> {
+    /// an error is encountered. The process is stopped right after exec; breakpoints are left for
+    /// the caller to install once it knows where the program was loaded.
+    pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
         let mut cmd = Command::new(target);
         cmd.args(args);
         unsafe {
@@ -48,107 +150,679 @@ impl Inferior {
         
         match cmd.spawn() {
             Ok(child) => {
-                let mut inferior = Inferior { child };
-                breakpoints.iter_mut().for_each(|(addr, breakpoint)| {
-                    match inferior.set_breakpoint(*addr) {
-                        Ok(orig_byte) => {
-                            breakpoint.replace(Breakpoint { addr: *addr, orig_byte });
-                        }
-                        Err(e) => println!("Error setting breakpoint: {:?}", e),
-                    }
-                });
+                let pid = Pid::from_raw(child.id() as i32);
+                // The child stops with SIGTRAP once it has exec'd the target
+                match waitpid(pid, None) {
+                    Ok(WaitStatus::Stopped(_, signal::Signal::SIGTRAP)) => {}
+                    _ => return None,
+                }
+                ptrace::setoptions(pid, trace_options()).ok()?;
+                let mut inferior = Inferior::from_pid(pid, false);
+                inferior.add_thread(pid);
                 Some(inferior)
             }
             Err(_) => None,
         }
     }
 
-    /// Returns the pid of this inferior.
-    pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+    /// Attaches to every thread of a running process with PTRACE_ATTACH, which stops them with
+    /// SIGSTOP.
+    pub fn attach(pid: Pid) -> Result<Inferior, nix::Error> {
+        let mut tids: Vec<Pid> = std::fs::read_dir(format!("/proc/{}/task", pid))
+            .map_err(|_| nix::Error::ESRCH)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .map(Pid::from_raw)
+            .collect();
+        // the main thread comes first so that it gets number 1
+        tids.sort_by_key(|tid| (*tid != pid, tid.as_raw()));
+
+        let mut inferior = Inferior::from_pid(pid, true);
+        for tid in tids {
+            match ptrace::attach(tid) {
+                Ok(()) => {}
+                Err(e) if tid == pid => return Err(e),
+                // the thread exited in the meantime
+                Err(_) => continue,
+            }
+            loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                    // some other signal arrived first; deliver it and wait for our SIGSTOP
+                    WaitStatus::Stopped(_, signal) => ptrace::cont(tid, signal)?,
+                    _ => return Err(nix::Error::ESRCH),
+                }
+            }
+            ptrace::setoptions(tid, trace_options())?;
+            inferior.add_thread(tid);
+        }
+        Ok(inferior)
+    }
+
+    fn from_pid(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
+            pid,
+            attached,
+            threads: Vec::new(),
+            next_thread_num: 1,
+            current: pid,
+            announced: pid,
+            hw_slots: [None; NUM_HW_WATCHPOINTS],
+            dr7: 0,
+            hw_watch_hit: None,
+            fork_policy: ForkPolicy::default(),
+            signal_policy: SignalTable::default(),
+            held: Vec::new(),
+            vfork_parent: None,
+            stopped_children: Vec::new(),
+            deferred_events: Vec::new(),
+        }
+    }
+
+    /// Returns the LWP id of the current thread.
+    pub fn current_tid(&self) -> Pid {
+        self.current
+    }
+
+    /// Returns the x87 and SSE registers of thread `tid`.
+    pub fn thread_fp_registers(&self, tid: Pid) -> Result<libc::user_fpregs_struct, nix::Error> {
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        // nix has no wrapper for PTRACE_GETFPREGS
+        let result = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                tid.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut fpregs as *mut libc::user_fpregs_struct,
+            )
+        };
+        nix::errno::Errno::result(result)?;
+        Ok(fpregs)
+    }
+
+    /// Sets the x87 and SSE registers of the current thread.
+    pub fn set_fp_registers(&mut self, mut fpregs: libc::user_fpregs_struct) -> Result<(), nix::Error> {
+        // nix has no wrapper for PTRACE_SETFPREGS
+        let result = unsafe {
+            libc::ptrace(
+                libc::PTRACE_SETFPREGS,
+                self.current.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut fpregs as *mut libc::user_fpregs_struct,
+            )
+        };
+        nix::errno::Errno::result(result)?;
+        Ok(())
+    }
+
+    /// Adds a stopped thread to the thread table and returns its number.
+    fn add_thread(&mut self, tid: Pid) -> usize {
+        let num = self.next_thread_num;
+        self.next_thread_num += 1;
+        self.threads.push(Thread::new(num, tid));
+        num
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Records a thread the inferior has just created. The thread must be stopped. Debug
+    /// registers are not inherited by new threads, so our watchpoints are set up in it here.
+    fn add_new_thread(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        let num = self.add_thread(tid);
+        println!("[New thread {} (LWP {})]", num, tid);
+        self.install_debug_regs(tid)
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(index) = self.thread_index(tid) {
+            let thread = self.threads.remove(index);
+            if tid != self.pid {
+                println!("[Thread {} (LWP {}) exited]", thread.num, tid);
+            }
+        }
+        if self.current == tid {
+            if let Some(thread) = self.threads.first() {
+                self.current = thread.tid;
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+        }
     }
 
-    /// continue to run the inferior
-    pub fn cont(&mut self, breakpoints: &HashMap<usize, Option<Breakpoint>>) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip = regs.rip as usize;
-        // check if we are at a breakpoint
-        if breakpoints.contains_key(&(rip as usize - 1)) {
-            // we are at a breakpoint, so we need to restore the original byte
-            let breakpoint = breakpoints.get(&(rip as usize - 1)).unwrap().as_ref().unwrap();
-            self.write_byte(breakpoint.addr, breakpoint.orig_byte).unwrap();
-            // set the instruction pointer to the breakpoint address
-            regs.rip = (rip - 1) as u64;
-            ptrace::setregs(self.pid(), regs).unwrap();
-            ptrace::step(self.pid(), None).unwrap();
-            match self.wait(None) {
-                Ok(status) => {
-                    match status {
-                        Status::Stopped(_, _) => {
-                            // write the breakpoint back
-                            self.write_byte(rip - 1, 0xcc)?;
+    /// Returns true if `tid` is a thread of this process, rather than a child process.
+    fn is_own_thread(&self, tid: Pid) -> bool {
+        std::path::Path::new(&format!("/proc/{}/task/{}", self.pid, tid)).exists()
+    }
+
+    fn thread_index(&self, tid: Pid) -> Option<usize> {
+        self.threads.iter().position(|thread| thread.tid == tid)
+    }
+
+    fn thread_ids(&self) -> Vec<Pid> {
+        self.threads.iter().map(|thread| thread.tid).collect()
+    }
+
+    fn set_running(&mut self, tid: Pid, running: bool) {
+        if let Some(index) = self.thread_index(tid) {
+            self.threads[index].running = running;
+        }
+    }
+
+    /// Returns true, and forgets about it, if a SIGSTOP we sent to `tid` was still on its way.
+    fn take_stop_expected(&mut self, tid: Pid) -> bool {
+        match self.thread_index(tid) {
+            Some(index) => std::mem::replace(&mut self.threads[index].stop_expected, false),
+            None => false,
+        }
+    }
+
+    /// Records the signal thread `tid` stopped with, for the next resume to deliver.
+    fn set_signal(&mut self, tid: Pid, signal: signal::Signal) {
+        self.set_resume_signal(tid, Some(signal));
+    }
+
+    /// Replaces the signal thread `tid` will be given when it next resumes. With None, the signal
+    /// it stopped with is discarded.
+    pub fn set_resume_signal(&mut self, tid: Pid, signal: Option<signal::Signal>) {
+        if let Some(index) = self.thread_index(tid) {
+            self.threads[index].signal = signal;
+        }
+    }
+
+    /// Resumes a stopped thread, single-stepping it if `step` is set. The signal it last stopped
+    /// with goes along, unless the program is not meant to see it.
+    fn resume_thread(&mut self, tid: Pid, step: bool) -> Result<(), nix::Error> {
+        let signal_policy = self.signal_policy;
+        let signal = self
+            .thread_index(tid)
+            .and_then(|index| self.threads[index].signal.take())
+            .filter(|signal| signal_policy.get(*signal).pass);
+        if step {
+            ptrace::step(tid, signal)?;
+        } else {
+            ptrace::cont(tid, signal)?;
+        }
+        self.set_running(tid, true);
+        Ok(())
+    }
+
+    /// True if `signal` should be handed to the program without stopping. Says so if the
+    /// signal is to be printed.
+    fn pass_through(&self, signal: signal::Signal) -> bool {
+        let policy = self.signal_policy.get(signal);
+        if signal == signal::Signal::SIGTRAP || policy.stop {
+            return false;
+        }
+        if policy.print {
+            println!("[Inferior received signal {}, {}]", signal, signals::description(signal));
+        }
+        true
+    }
+
+    /// Handles a PTRACE_EVENT_CLONE stop of thread `tid` by recording the new thread. Returns the
+    /// new thread, which is left stopped, unless it was already known.
+    fn handle_clone(&mut self, tid: Pid) -> Result<Option<Pid>, nix::Error> {
+        let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+        if self.thread_index(new_tid).is_some() {
+            return Ok(None);
+        }
+        // new threads start out with a SIGSTOP
+        loop {
+            match waitpid(new_tid, Some(WaitPidFlag::__WALL))? {
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(None),
+                _ => {}
+            }
+        }
+        self.add_new_thread(new_tid)?;
+        Ok(Some(new_tid))
+    }
+
+    /// Handles a fork or vfork by thread `tid` according to the fork policy. Returns the thread to
+    /// resume: `tid` if we stay with the parent, or the child if we follow it.
+    fn handle_fork(&mut self, tid: Pid, vfork: bool, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Pid, nix::Error> {
+        let child = Pid::from_raw(ptrace::getevent(tid)? as i32);
+        // the child starts out with a SIGSTOP, which we may have seen already
+        if let Some(index) = self.stopped_children.iter().position(|pid| *pid == child) {
+            self.stopped_children.remove(index);
+        } else {
+            loop {
+                match waitpid(child, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                    WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(tid),
+                    _ => {}
+                }
+            }
+        }
+        let mut child_inferior = Inferior::from_pid(child, self.attached);
+        child_inferior.add_thread(child);
+        child_inferior.fork_policy = self.fork_policy;
+        child_inferior.signal_policy = self.signal_policy;
+        child_inferior.hw_slots = self.hw_slots;
+        child_inferior.dr7 = self.dr7;
+
+        if !self.fork_policy.follow_child {
+            if self.fork_policy.detach_on_fork {
+                // the breakpoints are in the child's copy of our memory. After a vfork it is the
+                // same memory, and they are put back once the child is done with it.
+                println!("[Detaching after fork from child process {}]", child);
+                child_inferior.release(breakpoints)?;
+            } else {
+                println!("[Keeping child process {} stopped]", child);
+                child_inferior.install_debug_regs(child)?;
+                self.held.push(child_inferior);
+            }
+            return Ok(tid);
+        }
+
+        println!("[Attaching after process {} fork to child process {}]", self.pid, child);
+        // debug registers are not inherited by the child
+        child_inferior.install_debug_regs(child)?;
+        let mut parent = std::mem::replace(self, child_inferior);
+        self.held = std::mem::take(&mut parent.held);
+        parent.set_running(tid, false);
+        parent.stop_other_threads(breakpoints)?;
+        if !self.fork_policy.detach_on_fork {
+            self.held.push(parent);
+        } else if vfork {
+            self.vfork_parent = Some(Box::new(parent));
+        } else {
+            println!("[Detaching after fork from parent process {}]", parent.pid);
+            parent.release(breakpoints)?;
+        }
+        Ok(child)
+    }
+
+    /// Resets our view of the process after it has exec'd a new program. The other threads are
+    /// gone, and so are the breakpoints and the debug registers.
+    fn handle_exec(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        let pid = self.pid;
+        self.threads.retain(|thread| thread.tid == pid);
+        if self.threads.is_empty() {
+            self.add_thread(pid);
+        }
+        self.threads[0].running = false;
+        self.threads[0].stop_expected = false;
+        self.threads[0].pending = None;
+        self.threads[0].signal = None;
+        self.current = pid;
+        self.announced = pid;
+        self.hw_slots = [None; NUM_HW_WATCHPOINTS];
+        self.dr7 = 0;
+        self.hw_watch_hit = None;
+        if let Some(mut parent) = self.vfork_parent.take() {
+            // the parent has its own memory again, with our breakpoints still in it
+            println!("[Detaching after vfork from parent process {}]", parent.pid);
+            parent.release(breakpoints)?;
+        }
+        Ok(())
+    }
+
+    /// Waits until a thread stops for a reason the caller has to look at, or the process exits,
+    /// and makes that thread the current one. Thread creation and exit and the SIGSTOPs we sent
+    /// are dealt with here.
+    ///
+    /// If `stepping` is set, only that thread is running, single-stepped, and new threads are
+    /// left stopped. Otherwise all threads are running; once one of them stops, a breakpoint
+    /// hit is rewound and the other threads are stopped as well.
+    fn wait_for_event(&mut self, mut stepping: Option<Pid>, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        loop {
+            let wait_status = match self.deferred_events.pop() {
+                Some(wait_status) if stepping.is_none() => wait_status,
+                Some(wait_status) => {
+                    self.deferred_events.push(wait_status);
+                    waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))?
+                }
+                None => waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))?,
+            };
+            let tid = match wait_status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            match wait_status {
+                WaitStatus::Exited(_, exit_code) if tid == self.pid => {
+                    self.release_held(breakpoints)?;
+                    return Ok(Status::Exited(exit_code));
+                }
+                WaitStatus::Signaled(_, signal, _) if tid == self.pid => {
+                    self.release_held(breakpoints)?;
+                    return Ok(Status::Signaled(signal));
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.remove_thread(tid);
+                    if stepping == Some(tid) {
+                        // the stepped thread is gone, so let the rest of the process run
+                        stepping = None;
+                        for other in self.thread_ids() {
+                            self.resume_thread(other, false).ok();
                         }
-                        Status::Exited(exit_code) => {
-                            return Ok(Status::Exited(exit_code));
+                    }
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    if let Some(new_tid) = self.handle_clone(tid)? {
+                        if stepping.is_none() {
+                            self.resume_thread(new_tid, false)?;
                         }
-                        Status::Signaled(signal) => {
-                            return Ok(Status::Signaled(signal));
+                    }
+                    self.resume_thread(tid, stepping == Some(tid))?;
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK =>
+                {
+                    let step = stepping == Some(tid);
+                    let followed = self.handle_fork(tid, event == libc::PTRACE_EVENT_VFORK, breakpoints)?;
+                    if step {
+                        stepping = Some(followed);
+                    }
+                    self.resume_thread(followed, step)?;
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK_DONE) => {
+                    // a vfork child we let go has stopped using our memory, so the breakpoints
+                    // can go back in
+                    self.insert_breakpoint_bytes(breakpoints)?;
+                    self.resume_thread(tid, stepping == Some(tid))?;
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                    self.handle_exec(breakpoints)?;
+                    return Ok(Status::Exec);
+                }
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if self.thread_index(tid).is_none() => {
+                    if self.is_own_thread(tid) {
+                        // a new thread can report its first stop before its creator reports the clone
+                        self.add_new_thread(tid)?;
+                        if stepping.is_none() {
+                            self.resume_thread(tid, false)?;
                         }
+                    } else {
+                        // and a new process before its parent reports the fork
+                        self.stopped_children.push(tid);
+                    }
+                }
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if self.take_stop_expected(tid) => {
+                    self.resume_thread(tid, stepping == Some(tid))?;
+                }
+                WaitStatus::Stopped(_, signal) if self.pass_through(signal) => {
+                    self.set_signal(tid, signal);
+                    self.resume_thread(tid, stepping == Some(tid))?;
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    self.set_running(tid, false);
+                    self.current = tid;
+                    if signal != signal::Signal::SIGTRAP {
+                        self.set_signal(tid, signal);
+                    }
+                    let watch_hit = self.latch_hw_watchpoint_hit(tid)?;
+                    if watch_hit.is_some() {
+                        self.hw_watch_hit = watch_hit;
+                    }
+                    let mut rip = ptrace::getregs(tid)?.rip as usize;
+                    if stepping.is_none() {
+                        rip = self.rewind_breakpoint(tid, signal, watch_hit, breakpoints)?;
+                        self.stop_other_threads(breakpoints)?;
+                    }
+                    return Ok(Status::Stopped(signal, rip));
+                }
+                // stops for events we never asked ptrace for, which the thread can just carry on from
+                WaitStatus::PtraceEvent(..) | WaitStatus::PtraceSyscall(_) => {
+                    self.resume_thread(tid, stepping == Some(tid))?;
+                }
+                // only reported with WCONTINUED or WNOHANG, which aren't used here
+                WaitStatus::Continued(_) | WaitStatus::StillAlive => {}
+            }
+        }
+    }
+
+    /// Stops every thread that is still running, so that the whole process stays stopped while
+    /// the user looks at it. Stops that threads make for other reasons in the meantime are kept
+    /// as pending, for the next `cont` to report.
+    fn stop_other_threads(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        for thread in self.threads.iter_mut().filter(|thread| thread.running) {
+            thread.stop_expected = true;
+            unsafe {
+                libc::syscall(libc::SYS_tgkill, self.pid.as_raw(), thread.tid.as_raw(), libc::SIGSTOP);
+            }
+        }
+        while self.threads.iter().any(|thread| thread.running) {
+            let wait_status = waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))?;
+            let tid = match wait_status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            match wait_status {
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if self.thread_index(tid).is_none() => {
+                    if self.is_own_thread(tid) {
+                        self.add_new_thread(tid)?;
+                    } else {
+                        self.stopped_children.push(tid);
                     }
                 }
-                Err(e) => {
-                    println!("Error waiting for inferior: {:?}", e);
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if self.take_stop_expected(tid) => {
+                    self.set_running(tid, false);
+                }
+                WaitStatus::Stopped(_, signal) if self.pass_through(signal) => {
+                    // delivered when the thread is resumed, and our SIGSTOP is still queued
+                    self.set_running(tid, false);
+                    self.set_signal(tid, signal);
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    self.set_running(tid, false);
+                    let watch_hit = self.latch_hw_watchpoint_hit(tid)?;
+                    self.rewind_breakpoint(tid, signal, watch_hit, breakpoints)?;
+                    if let Some(index) = self.thread_index(tid) {
+                        self.threads[index].pending = Some((signal, watch_hit));
+                    }
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    // the thread is stopped now, but our SIGSTOP is still queued
+                    self.set_running(tid, false);
+                    self.handle_clone(tid)?;
+                }
+                WaitStatus::PtraceEvent(..) => {
+                    self.set_running(tid, false);
+                    self.deferred_events.push(wait_status);
                 }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => self.remove_thread(tid),
+                _ => {}
             }
         }
+        Ok(())
+    }
 
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    /// Makes the next pending stop the current one and returns it, skipping breakpoint hits on
+    /// breakpoints that have been removed since.
+    fn take_pending_stop(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<Status>, nix::Error> {
+        while let Some(index) = self.threads.iter().position(|thread| thread.pending.is_some()) {
+            let tid = self.threads[index].tid;
+            let (signal, watch_hit) = self.threads[index].pending.take().unwrap();
+            let watch_hit = watch_hit.filter(|slot| self.hw_slots[*slot].is_some());
+            let rip = ptrace::getregs(tid)?.rip as usize;
+            if signal == signal::Signal::SIGTRAP
+                && watch_hit.is_none()
+                && Inferior::installed_at(breakpoints, rip).is_none()
+            {
+                continue;
+            }
+            self.current = tid;
+            if watch_hit.is_some() {
+                self.hw_watch_hit = watch_hit;
+            }
+            if signal != signal::Signal::SIGTRAP {
+                self.threads[index].signal = Some(signal);
+            }
+            return Ok(Some(Status::Stopped(signal, rip)));
+        }
+        Ok(None)
     }
 
-    /// kill the inferior
-    pub fn kill(&mut self) {
-        println!("Killing inferior {}", self.pid());
-        self.child.kill().unwrap();
-        self.wait(None).unwrap();
+    /// If thread `tid` just trapped on one of our breakpoints, moves its instruction pointer back
+    /// onto the breakpoint. Returns the thread's instruction pointer.
+    fn rewind_breakpoint(&self, tid: Pid, signal: signal::Signal, watch_hit: Option<usize>, breakpoints: &HashMap<usize, Breakpoint>) -> Result<usize, nix::Error> {
+        let mut regs = ptrace::getregs(tid)?;
+        let rip = regs.rip as usize;
+        match rip.checked_sub(1) {
+            Some(addr)
+                if signal == signal::Signal::SIGTRAP
+                    && watch_hit.is_none()
+                    && Inferior::installed_at(breakpoints, addr).is_some() =>
+            {
+                regs.rip = addr as u64;
+                ptrace::setregs(tid, regs)?;
+                Ok(addr)
+            }
+            _ => Ok(rip),
+        }
     }
-    
-    /// print stack trace of the program
-    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error>  {
-        let regs = ptrace::getregs(self.pid())?;
-        let mut instr_ptr = regs.rip as usize;
-        let mut ebp = regs.rbp as usize; 
-        loop {
-            // println!("instr_ptr: {:#x}:", instr_ptr);
-            // println!("ebp: {:#x}:", ebp);
-            let line = debug_data.get_line_from_addr(instr_ptr).unwrap();
-            let func = debug_data.get_function_from_addr(instr_ptr).unwrap();
-            println!("{} ({})", func, line);
-            if func == "main" {
-                break;
+
+    /// If thread `tid` is stopped on a breakpoint, restore the original byte, execute that one
+    /// instruction, and write the breakpoint back. Returns None if there was nothing to step over.
+    fn step_over_breakpoint(&mut self, tid: Pid, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Option<Status>, nix::Error> {
+        let rip = ptrace::getregs(tid)?.rip as usize;
+        let orig_byte = match Inferior::installed_at(breakpoints, rip) {
+            Some(orig_byte) => orig_byte,
+            None => return Ok(None),
+        };
+        self.write_byte(rip, orig_byte)?;
+        self.resume_thread(tid, true)?;
+        let status = self.wait_for_event(Some(tid), breakpoints)?;
+        if let Status::Stopped(_, _) = status {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(Some(status))
+    }
+
+    /// Returns the original byte of the breakpoint installed at `addr`, if there is one.
+    fn installed_at(breakpoints: &HashMap<usize, Breakpoint>, addr: usize) -> Option<u8> {
+        breakpoints.get(&addr).and_then(|breakpoint| breakpoint.orig_byte)
+    }
+
+    /// Removes our breakpoints and watchpoints from this process and detaches from it, along
+    /// with the processes held with it.
+    fn release(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        self.release_held(breakpoints)?;
+        self.remove_breakpoint_bytes(breakpoints)?;
+        for slot in 0..NUM_HW_WATCHPOINTS {
+            if self.hw_slots[slot].is_some() {
+                self.remove_hw_watchpoint(slot)?;
             }
-            instr_ptr = ptrace::read(self.pid(), (ebp + 8) as ptrace::AddressType)? as usize;
-            ebp = ptrace::read(self.pid(), ebp as ptrace::AddressType)? as usize;
+        }
+        for thread in &self.threads {
+            if thread.stop_expected && !Inferior::consume_sigstop(thread.tid)? {
+                continue;
+            }
+            // signals held back while stopping the threads are delivered now
+            let signal = match thread.pending {
+                Some((signal::Signal::SIGTRAP, _)) | None => thread.signal,
+                Some((signal, _)) => Some(signal),
+            };
+            let signal = signal.filter(|signal| self.signal_policy.get(*signal).pass);
+            ptrace::detach(thread.tid, signal)?;
         }
         Ok(())
     }
 
-    pub fn set_breakpoint(&mut self, addr: usize) -> Result<u8, nix::Error> {
-        self.write_byte(addr, 0xcc)
+    /// Lets go of the processes kept stopped after forks.
+    fn release_held(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        let vfork_parent = self.vfork_parent.take().map(|parent| *parent);
+        for mut held in self.held.drain(..).chain(vfork_parent) {
+            println!("[Detaching from held process {}]", held.pid);
+            held.release(breakpoints)?;
+        }
+        Ok(())
+    }
+
+    /// Writes back the original bytes of all installed breakpoints, leaving `breakpoints` as is.
+    fn remove_breakpoint_bytes(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        for breakpoint in breakpoints.values() {
+            if let Some(orig_byte) = breakpoint.orig_byte {
+                self.write_byte(breakpoint.addr, orig_byte)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the 0xcc of all installed breakpoints again.
+    fn insert_breakpoint_bytes(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        for breakpoint in breakpoints.values() {
+            if breakpoint.orig_byte.is_some() {
+                self.write_byte(breakpoint.addr, 0xcc)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets the SIGSTOP we sent to a thread arrive, since it would stop the whole process after
+    /// we detach. Returns false if the thread exited instead.
+    fn consume_sigstop(tid: Pid) -> Result<bool, nix::Error> {
+        ptrace::cont(tid, None)?;
+        loop {
+            match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => return Ok(true),
+                WaitStatus::Stopped(_, signal) => ptrace::cont(tid, signal)?,
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(false),
+                _ => ptrace::cont(tid, None)?,
+            }
+        }
+    }
+    
+    fn read_debug_reg(&self, tid: Pid, num: usize) -> Result<u64, nix::Error> {
+        let offset = DEBUG_REGS_OFFSET + num * std::mem::size_of::<u64>();
+        Ok(ptrace::read_user(tid, offset as ptrace::AddressType)? as u64)
+    }
+
+    fn write_debug_reg(&self, tid: Pid, num: usize, value: u64) -> Result<(), nix::Error> {
+        let offset = DEBUG_REGS_OFFSET + num * std::mem::size_of::<u64>();
+        unsafe {
+            ptrace::write_user(
+                tid,
+                offset as ptrace::AddressType,
+                value as *mut std::ffi::c_void,
+            )
+        }
+    }
+
+    /// Copies our watchpoints into the debug registers of thread `tid`.
+    fn install_debug_regs(&self, tid: Pid) -> Result<(), nix::Error> {
+        if self.dr7 == 0 {
+            return Ok(());
+        }
+        for (slot, addr) in self.hw_slots.iter().enumerate() {
+            if let Some(addr) = addr {
+                self.write_debug_reg(tid, slot, *addr as u64)?;
+            }
+        }
+        self.write_debug_reg(tid, 7, self.dr7)
+    }
+
+    /// Reads DR6 of thread `tid` after a trap and returns the slot of the watchpoint that
+    /// triggered, if any. DR6 is then cleared, because the CPU never clears it itself.
+    fn latch_hw_watchpoint_hit(&self, tid: Pid) -> Result<Option<usize>, nix::Error> {
+        if self.hw_slots.iter().all(|used| used.is_none()) {
+            return Ok(None);
+        }
+        let dr6 = self.read_debug_reg(tid, 6)?;
+        let slot = (0..NUM_HW_WATCHPOINTS).find(|&slot| self.hw_slots[slot].is_some() && dr6 & (1 << slot) != 0);
+        if dr6 & 0xf != 0 {
+            self.write_debug_reg(tid, 6, 0)?;
+        }
+        Ok(slot)
+    }
+
+    /// read a word from process memory
+    fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        Ok(ptrace::read(self.current, addr as ptrace::AddressType)? as usize)
+    }
+
+    /// read process memory a word at a time with PTRACE_PEEKDATA
+    fn peek_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        // a range past the top of the address space can't be read
+        let end = addr.checked_add(len).ok_or(nix::Error::EFAULT)?;
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        while bytes.len() < len {
+            let word = self.read_word(word_addr)?.to_le_bytes();
+            for (i, byte) in word.iter().enumerate() {
+                if word_addr + i >= addr && word_addr + i < end {
+                    bytes.push(*byte);
+                }
+            }
+            word_addr = word_addr.wrapping_add(word.len());
+        }
+        Ok(bytes)
     }
 
     /// write byte to process memory
@@ -156,13 +830,13 @@ impl Inferior {
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let word = ptrace::read(self.current, aligned_addr as ptrace::AddressType)? as u64;
         let orig_byte = (word >> 8 * byte_offset) & 0xff;
         let masked_word = word & !(0xff << 8 * byte_offset);
         let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
         unsafe {
             ptrace::write(
-                self.pid(),
+                self.current,
                 aligned_addr as ptrace::AddressType,
                 updated_word as *mut std::ffi::c_void,
             )?;
@@ -171,3 +845,288 @@ impl Inferior {
     }
 
 }
+
+impl Process for Inferior {
+    /// Returns true if this inferior was attached to rather than started by us.
+    fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    fn set_fork_policy(&mut self, fork_policy: ForkPolicy) {
+        self.fork_policy = fork_policy;
+    }
+
+    fn set_signal_policy(&mut self, signal_policy: SignalTable) {
+        self.signal_policy = signal_policy;
+    }
+
+    /// Returns the threads of the inferior, in the order they were seen.
+    fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Returns the number of the current thread.
+    fn current_thread_num(&self) -> usize {
+        self.thread_index(self.current).map_or(0, |index| self.threads[index].num)
+    }
+
+    /// Makes thread `num` the current thread. Returns false if there is no such thread.
+    fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.num == num) {
+            Some(thread) => {
+                self.current = thread.tid;
+                self.announced = thread.tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// If the current thread has changed since the user was last told, returns its number and
+    /// LWP id so that the switch can be announced.
+    fn take_thread_switch(&mut self) -> Option<(usize, Pid)> {
+        if self.current == self.announced {
+            return None;
+        }
+        self.announced = self.current;
+        Some((self.current_thread_num(), self.current))
+    }
+
+    /// Returns the name the kernel has for thread `tid`.
+    fn thread_name(&self, tid: Pid) -> Option<String> {
+        let comm = std::fs::read_to_string(format!("/proc/{}/task/{}/comm", self.pid, tid)).ok()?;
+        Some(comm.trim_end().to_string())
+    }
+
+    /// Returns the registers of thread `tid`.
+    fn thread_registers(&self, tid: Pid) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(tid)
+    }
+
+    /// Sets the general-purpose registers of the current thread.
+    fn set_registers(&mut self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        ptrace::setregs(self.current, regs)
+    }
+
+    /// write `bytes` to process memory at `addr`, with a single process_vm_writev call if the
+    /// process itself could write them
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let remote = [RemoteIoVec { base: addr, len: bytes.len() }];
+        match process_vm_writev(self.current, &[IoSlice::new(bytes)], &remote) {
+            Ok(written) if written == bytes.len() => Ok(()),
+            // read-only pages such as code can only be written through ptrace
+            _ => {
+                for (i, byte) in bytes.iter().enumerate() {
+                    self.write_byte(addr + i, *byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, addr: usize) -> Result<u8, nix::Error> {
+        self.write_byte(addr, 0xcc)
+    }
+
+    /// remove a breakpoint by writing back the byte it replaced
+    fn remove_breakpoint(&mut self, addr: usize, orig_byte: u8) -> Result<(), nix::Error> {
+        self.write_byte(addr, orig_byte)?;
+        Ok(())
+    }
+
+    /// Programs a free debug register to trap when the `len` bytes at `addr` are accessed. `len`
+    /// must be 1, 2, 4 or 8 and `addr` must be aligned to it. Returns the slot used, or None if
+    /// all of DR0-DR3 are taken. Debug registers are per thread, so every thread gets the
+    /// watchpoint.
+    ///
+    /// x86 cannot trap on reads alone, so read watchpoints trap on any access.
+    fn set_hw_watchpoint(&mut self, addr: usize, len: usize, kind: WatchKind) -> Result<Option<usize>, nix::Error> {
+        let slot = match self.hw_slots.iter().position(|used| used.is_none()) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let len_bits: u64 = match len {
+            1 => 0b00,
+            2 => 0b01,
+            4 => 0b11,
+            8 => 0b10,
+            _ => return Err(nix::Error::EINVAL),
+        };
+        if addr % len != 0 {
+            return Err(nix::Error::EINVAL);
+        }
+        let rw_bits: u64 = match kind {
+            WatchKind::Write => 0b01,
+            WatchKind::Read | WatchKind::Access => 0b11,
+        };
+
+        // DR7 has a local enable bit per slot in its low byte, and 4 bits of R/W and LEN
+        // control per slot starting at bit 16
+        let control_shift = 16 + slot * 4;
+        self.dr7 &= !(0b1111 << control_shift);
+        self.dr7 |= (rw_bits | (len_bits << 2)) << control_shift;
+        self.dr7 |= 1 << (slot * 2);
+        self.hw_slots[slot] = Some(addr);
+        for tid in self.thread_ids() {
+            self.install_debug_regs(tid)?;
+        }
+        Ok(Some(slot))
+    }
+
+    /// Disables the watchpoint in debug register `slot`.
+    fn remove_hw_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        self.dr7 &= !(1 << (slot * 2));
+        self.dr7 &= !(0b1111 << (16 + slot * 4));
+        self.hw_slots[slot] = None;
+        for tid in self.thread_ids() {
+            self.write_debug_reg(tid, 7, self.dr7)?;
+            self.write_debug_reg(tid, slot, 0)?;
+        }
+        if self.hw_watch_hit == Some(slot) {
+            self.hw_watch_hit = None;
+        }
+        Ok(())
+    }
+
+    /// Returns the slot of the watchpoint that triggered since the last call, if any.
+    fn take_hw_watchpoint_hit(&mut self) -> Option<usize> {
+        self.hw_watch_hit.take()
+    }
+
+    /// Returns the address that caused the signal the current thread stopped with, from its
+    /// siginfo. Only meaningful for faults such as SIGSEGV.
+    fn fault_address(&self) -> Option<usize> {
+        let siginfo = ptrace::getsiginfo(self.current).ok()?;
+        Some(unsafe { siginfo.si_addr() } as usize)
+    }
+
+    /// continue to run the inferior
+    fn cont(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        // a stop held back while the threads were being stopped is reported before anything runs
+        if let Some(status) = self.take_pending_stop(breakpoints)? {
+            return Ok(status);
+        }
+
+        // every thread sitting on a breakpoint executes its original instruction first. Threads
+        // with a deferred event are resumed once the event has been handled.
+        let deferred: Vec<Pid> = self.deferred_events.iter().filter_map(|event| event.pid()).collect();
+        for tid in self.thread_ids() {
+            if self.thread_index(tid).is_none() || deferred.contains(&tid) {
+                continue;
+            }
+            match self.step_over_breakpoint(tid, breakpoints)? {
+                None | Some(Status::Stopped(signal::Signal::SIGTRAP, _)) => {}
+                Some(other) => return Ok(other),
+            }
+        }
+
+        for tid in self.thread_ids() {
+            if !deferred.contains(&tid) {
+                self.resume_thread(tid, false)?;
+            }
+        }
+        self.wait_for_event(None, breakpoints)
+    }
+
+    /// execute a single instruction in the current thread, leaving the other threads stopped
+    fn step_instruction(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        let tid = self.current;
+        if let Some(status) = self.step_over_breakpoint(tid, breakpoints)? {
+            return Ok(status);
+        }
+        self.resume_thread(tid, true)?;
+        self.wait_for_event(Some(tid), breakpoints)
+    }
+
+    /// kill the inferior
+    fn kill(&mut self) {
+        for held in self.held.iter_mut().chain(self.vfork_parent.as_deref_mut()) {
+            held.kill();
+        }
+        println!("Killing inferior {}", self.pid());
+        // ESRCH: the process is gone already, and there is nothing left to reap
+        if signal::kill(self.pid(), signal::Signal::SIGKILL).is_err() {
+            return;
+        }
+        // reap every thread; the main thread is reported last
+        loop {
+            match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Exited(tid, _) | WaitStatus::Signaled(tid, _, _)) if tid == self.pid => break,
+                Ok(_) => {}
+                // ECHILD, if something else reaped it
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Removes every breakpoint and watchpoint from the process and lets it run on untraced.
+    fn detach(&mut self, breakpoints: &mut HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        self.release(breakpoints)?;
+        for breakpoint in breakpoints.values_mut() {
+            breakpoint.orig_byte = None;
+        }
+        Ok(())
+    }
+
+    fn as_inferior(&self) -> Option<&Inferior> {
+        Some(self)
+    }
+}
+
+impl Target for Inferior {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// read `len` bytes of process memory starting at `addr`, with a single process_vm_readv
+    /// call if the process itself could read them
+    fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = vec![0; len];
+        let remote = [RemoteIoVec { base: addr, len }];
+        match process_vm_readv(self.current, &mut [IoSliceMut::new(&mut bytes)], &remote) {
+            Ok(read) if read == len => Ok(bytes),
+            // e.g. a page without read permission, which ptrace can still get at
+            _ => self.peek_bytes(addr, len),
+        }
+    }
+
+    fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.current)
+    }
+
+    fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        self.thread_fp_registers(self.current)
+    }
+
+    fn auxv(&self) -> Option<Vec<u8>> {
+        std::fs::read(format!("/proc/{}/auxv", self.pid)).ok()
+    }
+
+    fn mappings(&self) -> Vec<Mapping> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid)).unwrap_or_default();
+        target::parse_maps(&maps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_bytes() {
+        let mut inferior = Inferior::new("/bin/true", &Vec::new()).expect("could not start /bin/true");
+        let rsp = inferior.get_registers().unwrap().rsp as usize;
+        let stack = inferior.read_bytes(rsp, 16).unwrap();
+        assert_eq!(stack.len(), 16);
+        // the same bytes a word at a time, from an unaligned address
+        assert_eq!(inferior.peek_bytes(rsp + 3, 10).unwrap(), stack[3..13]);
+        assert_eq!(inferior.peek_bytes(rsp, 0).unwrap(), []);
+
+        assert!(inferior.read_bytes(0, 8).is_err());
+        // near and past the top of the address space
+        assert!(inferior.read_bytes(0xfffffffffffffff8, 8).is_err());
+        assert!(inferior.read_bytes(0xfffffffffffffff8, 16).is_err());
+        assert!(inferior.peek_bytes(usize::MAX, 2).is_err());
+        inferior.kill();
+    }
+}
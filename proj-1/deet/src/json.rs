@@ -0,0 +1,398 @@
+//! Just enough JSON for the front ends that drive deet: `--interpreter=json`, whose clients send
+//! commands and get events back one per line, and the Debug Adapter Protocol.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a complete JSON text, such as one line of input.
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.text.len() {
+            return Err(format!("Unexpected text after JSON value at offset {}", parser.pos));
+        }
+        Ok(value)
+    }
+
+    /// Builds an object from its members.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Returns the member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns a number that is a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(num) if *num >= 0.0 && num.fract() == 0.0 => Some(*num as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Json {
+        Json::String(text.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Json {
+        Json::String(text)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(num: usize) -> Json {
+        Json::Number(num as f64)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(num: i32) -> Json {
+        Json::Number(num as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json {
+        Json::Array(values)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+/// Writes compact JSON, with no newlines, so that every event fits on one line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(num) if num.is_finite() => write!(f, "{}", num),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.text.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn error<T>(&self, what: &str) -> Result<T, String> {
+        Err(format!("Expected {} at offset {}", what, self.pos))
+    }
+
+    /// Consumes `byte` if it is next, after any whitespace.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            self.error("a value")
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return self.error("':'");
+                    }
+                    members.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Ok(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return self.error("',' or '}'");
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    if self.eat(b']') {
+                        return Ok(Json::Array(values));
+                    }
+                    if !self.eat(b',') {
+                        return self.error("',' or ']'");
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.text.get(self.pos), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+                match number.parse::<f64>() {
+                    Ok(num) => Ok(Json::Number(num)),
+                    Err(_) => Err(format!("Invalid number {}", number)),
+                }
+            }
+            _ => self.error("a value"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.text.get(self.pos) != Some(&b'"') {
+            return self.error("a string");
+        }
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.text.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.text.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let unit = self.hex4()?;
+                            // a surrogate pair encodes one character outside the basic plane
+                            let code = if (0xd800..0xdc00).contains(&unit) && self.text[self.pos + 1..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                            } else {
+                                unit
+                            };
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return self.error("an escape sequence"),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(&byte) => bytes.push(byte),
+                None => return self.error("'\"'"),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        String::from_utf8(bytes).map_err(|_| "Invalid UTF-8 in string".to_string())
+    }
+
+    /// Reads the four hex digits of a \u escape, leaving `pos` on the last one.
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos + 1..self.pos + 5)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok());
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(unit) => {
+                self.pos += 4;
+                Ok(unit)
+            }
+            None => self.error("four hex digits"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value = Json::parse(r#" {"id": 1, "command": "break", "args": ["main", -2.5e1, true, false, null], "empty": {}} "#).unwrap();
+        assert_eq!(
+            value,
+            Json::object(vec![
+                ("id", Json::Number(1.0)),
+                ("command", "break".into()),
+                ("args", Json::Array(vec!["main".into(), Json::Number(-25.0), true.into(), false.into(), Json::Null])),
+                ("empty", Json::Object(Vec::new())),
+            ])
+        );
+        assert_eq!(value.get("id").and_then(Json::as_usize), Some(1));
+        assert_eq!(value.get("command").and_then(Json::as_str), Some("break"));
+        assert_eq!(value.get("args").and_then(Json::as_array).map(|args| args.len()), Some(5));
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(Json::parse("[]"), Ok(Json::Array(Vec::new())));
+        assert_eq!(Json::Number(1.5).as_usize(), None);
+        assert_eq!(Json::Number(-1.0).as_usize(), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("", "Expected a value at offset 0"),
+            ("{", "Expected a string at offset 1"),
+            ("{\"a\" 1}", "Expected ':' at offset 5"),
+            ("{\"a\": 1", "Expected ',' or '}' at offset 7"),
+            ("[1 2]", "Expected ',' or ']' at offset 3"),
+            ("\"abc", "Expected '\"' at offset 4"),
+            ("\"\\x\"", "Expected an escape sequence at offset 2"),
+            ("\"\\u12\"", "Expected four hex digits at offset 2"),
+            ("\"\\u+123\"", "Expected four hex digits at offset 2"),
+            ("nul", "Expected a value at offset 0"),
+            ("1 2", "Unexpected text after JSON value at offset 2"),
+            ("-", "Invalid number -"),
+            ("1e", "Invalid number 1e"),
+        ];
+        for (text, error) in cases {
+            assert_eq!(Json::parse(text), Err(error.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let value = Json::object(vec![
+            ("event", "stopped".into()),
+            ("thread", 2usize.into()),
+            ("signal", Json::from(None::<&str>)),
+            ("frames", Json::Array(vec![Json::Number(-1.5), Json::Bool(true)])),
+        ]);
+        assert_eq!(value.to_string(), r#"{"event":"stopped","thread":2,"signal":null,"frames":[-1.5,true]}"#);
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+        assert_eq!(Json::Number(f64::INFINITY).to_string(), "null");
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(Json::from("a\"b\\c").to_string(), r#""a\"b\\c""#);
+        assert_eq!(Json::from("\n\r\t\u{1}\u{1f}").to_string(), r#""\n\r\t\u0001\u001f""#);
+        assert_eq!(Json::from("caf\u{e9} \u{1f600}").to_string(), "\"caf\u{e9} \u{1f600}\"");
+        assert_eq!(Json::parse(r#""\/\b\f\u00e9\ud83d\ude00""#), Ok(Json::from("/\u{8}\u{c}\u{e9}\u{1f600}")));
+        // a lone surrogate can't be a character
+        assert_eq!(Json::parse(r#""\ud83d""#), Ok(Json::from("\u{fffd}")));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            Json::Null,
+            Json::Number(0.1),
+            Json::Number(-1e300),
+            Json::from("quotes \" and \\ and \n and \u{7} and \u{e9}"),
+            Json::object(vec![("key \"quoted\"", Json::Array(vec![Json::Null, Json::object(vec![("", 3usize.into())])]))]),
+        ];
+        for value in values {
+            assert_eq!(Json::parse(&value.to_string()), Ok(value.clone()), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Where variables live while the program runs. The debugging info describes a variable's
+//! location with a DWARF expression, a little stack machine program that can read registers and
+//! memory, so it is kept as it is and evaluated against a stopped frame each time the variable
+//! is read. In optimized code a variable moves around as the function runs: its location is then
+//! a list of expressions that each apply to a range of code, and it may be nowhere at all.
+
+use crate::target::Target;
+use crate::unwind::Frame;
+use gimli::{EvaluationResult, Value};
+use std::fmt;
+
+/// DWARF register numbers of xmm0-xmm15 on x86-64
+const XMM0: u16 = 17;
+const XMM15: u16 = 32;
+
+#[derive(Clone)]
+pub enum Location {
+    /// A fixed address, like that of a global variable
+    Address(usize),
+    Expression(Expression),
+    /// Expressions for ranges of code addresses, as (start, end, expression). Outside of them
+    /// the variable has no location.
+    List(Vec<(usize, usize, Expression)>),
+    /// A value the compiler knew at compile time, as little-endian bytes
+    Constant(Vec<u8>),
+    /// No location at all: the variable was optimized away
+    OptimizedOut,
+}
+
+#[derive(Clone)]
+pub struct Expression {
+    bytecode: Vec<u8>,
+    encoding: gimli::Encoding,
+    /// Added to the addresses written in the expression, for position-independent code
+    bias: usize,
+}
+
+/// Where a variable's value turned out to be.
+pub enum Storage {
+    Memory(usize),
+    /// The value itself, for one that isn't in memory: held in registers, computed by the
+    /// expression or known at compile time
+    Value(Vec<u8>),
+    OptimizedOut,
+}
+
+impl Expression {
+    pub fn new(bytecode: Vec<u8>, encoding: gimli::Encoding) -> Expression {
+        Expression {
+            bytecode,
+            encoding,
+            bias: 0,
+        }
+    }
+
+    fn evaluate(&self, target: &dyn Target, frame: &Frame, frame_base: Option<&Location>) -> Result<Storage, String> {
+        let bytecode = gimli::EndianSlice::new(&self.bytecode, gimli::LittleEndian);
+        let mut evaluation = gimli::Evaluation::new(bytecode, self.encoding);
+        let mut result = evaluation.evaluate();
+        loop {
+            let state = result.map_err(|e| format!("Cannot evaluate location expression: {}", e))?;
+            result = match state {
+                EvaluationResult::Complete => break,
+                EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let bytes = read_memory(target, address as usize, size as usize)?;
+                    evaluation.resume_with_memory(Value::Generic(decode(&bytes)))
+                }
+                EvaluationResult::RequiresRegister { register, .. } => match register_bytes(target, frame, register.0) {
+                    Some(bytes) => evaluation.resume_with_register(Value::Generic(decode(&bytes[..8]))),
+                    None => return Ok(Storage::OptimizedOut),
+                },
+                EvaluationResult::RequiresFrameBase => {
+                    // DW_OP_call_frame_cfa in practice, but older compilers name a register
+                    let base = match frame_base.map(|base| base.evaluate(target, frame, None)) {
+                        Some(Ok(Storage::Memory(addr))) => addr as u64,
+                        Some(Ok(Storage::Value(bytes))) => decode(&bytes),
+                        Some(Err(e)) => return Err(e),
+                        _ => return Ok(Storage::OptimizedOut),
+                    };
+                    evaluation.resume_with_frame_base(base)
+                }
+                EvaluationResult::RequiresCallFrameCfa => evaluation.resume_with_call_frame_cfa(frame.cfa as u64),
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address.wrapping_add(self.bias as u64))
+                }
+                // the value a parameter had on entry could only be recovered from the caller's
+                // call site, which isn't attempted
+                EvaluationResult::RequiresEntryValue(_) => return Ok(Storage::OptimizedOut),
+                EvaluationResult::RequiresTls(_) => return Err("Cannot access thread-local variables.".to_string()),
+                _ => return Err("Unsupported operation in location expression.".to_string()),
+            };
+        }
+        let pieces = evaluation.result();
+        // the common case of a variable that lives in memory as a whole
+        if let [gimli::Piece {
+            size_in_bits: None,
+            location: gimli::Location::Address { address },
+            ..
+        }] = pieces.as_slice()
+        {
+            return Ok(Storage::Memory(*address as usize));
+        }
+        let mut value = Vec::new();
+        for piece in pieces {
+            let len = piece.size_in_bits.map(|bits| (bits as usize).div_ceil(8));
+            let skip = piece.bit_offset.unwrap_or(0) as usize / 8;
+            let bytes = match piece.location {
+                gimli::Location::Empty => return Ok(Storage::OptimizedOut),
+                gimli::Location::Address { address } => {
+                    let len = len.ok_or_else(|| "Location expression without a size.".to_string())?;
+                    read_memory(target, address as usize + skip, len)?
+                }
+                gimli::Location::Register { register } => match register_bytes(target, frame, register.0) {
+                    Some(bytes) => bytes[skip.min(bytes.len())..].to_vec(),
+                    None => return Ok(Storage::OptimizedOut),
+                },
+                gimli::Location::Value { value } => {
+                    let bytes = match value {
+                        Value::F32(value) => value.to_le_bytes().to_vec(),
+                        Value::F64(value) => value.to_le_bytes().to_vec(),
+                        value => value.to_u64(!0).unwrap_or(0).to_le_bytes().to_vec(),
+                    };
+                    bytes[skip.min(bytes.len())..].to_vec()
+                }
+                gimli::Location::Bytes { value } => value.to_vec(),
+                gimli::Location::ImplicitPointer { .. } => return Ok(Storage::OptimizedOut),
+            };
+            match len {
+                Some(len) => value.extend((0..len).map(|i| bytes.get(i).copied().unwrap_or(0))),
+                None => value.extend(bytes),
+            }
+        }
+        Ok(Storage::Value(value))
+    }
+}
+
+impl Location {
+    /// Finds a variable at this location in a frame of the stopped program. `frame_base` is the
+    /// `DW_AT_frame_base` of the function the frame is running, which `DW_OP_fbreg` counts from.
+    pub fn evaluate(&self, target: &dyn Target, frame: &Frame, frame_base: Option<&Location>) -> Result<Storage, String> {
+        let expression = match self {
+            Location::Address(addr) => return Ok(Storage::Memory(*addr)),
+            Location::Expression(expression) => expression,
+            Location::List(entries) => {
+                let pc = frame.code_addr();
+                match entries.iter().find(|(start, end, _)| (*start..*end).contains(&pc)) {
+                    Some((_, _, expression)) => expression,
+                    None => return Ok(Storage::OptimizedOut),
+                }
+            }
+            Location::Constant(bytes) => return Ok(Storage::Value(bytes.clone())),
+            Location::OptimizedOut => return Ok(Storage::OptimizedOut),
+        };
+        expression.evaluate(target, frame, frame_base)
+    }
+
+    /// Returns the address of a variable that stays in one place, like a global, which can be
+    /// found without running the program.
+    pub fn static_address(&self) -> Option<usize> {
+        match self {
+            Location::Address(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// Moves the addresses in this location `delta` bytes up, for an object file loaded above its
+    /// link-time addresses.
+    pub fn relocate(&mut self, delta: usize) {
+        match self {
+            Location::Address(addr) => *addr = addr.wrapping_add(delta),
+            Location::Expression(expression) => expression.bias = expression.bias.wrapping_add(delta),
+            Location::List(entries) => {
+                for (start, end, expression) in entries {
+                    *start = start.wrapping_add(delta);
+                    *end = end.wrapping_add(delta);
+                    expression.bias = expression.bias.wrapping_add(delta);
+                }
+            }
+            Location::Constant(_) | Location::OptimizedOut => {}
+        }
+    }
+}
+
+fn read_memory(target: &dyn Target, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+    target
+        .read_bytes(addr, len)
+        .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+}
+
+/// Returns the contents of a DWARF register in a frame, or None if they can't be recovered.
+/// Vector registers are only known in the innermost frame, since callers don't preserve them.
+fn register_bytes(target: &dyn Target, frame: &Frame, num: u16) -> Option<Vec<u8>> {
+    if let Some(value) = frame.register(num as usize) {
+        return Some(value.to_le_bytes().to_vec());
+    }
+    if frame.level == 0 && (XMM0..=XMM15).contains(&num) {
+        let fpregs = target.get_fp_registers().ok()?;
+        let first = (num - XMM0) as usize * 4;
+        return Some(fpregs.xmm_space[first..first + 4].iter().flat_map(|word| word.to_le_bytes().to_vec()).collect());
+    }
+    None
+}
+
+/// Reads a little-endian number of up to 8 bytes.
+fn decode(bytes: &[u8]) -> u64 {
+    let mut word = [0u8; 8];
+    let len = bytes.len().min(8);
+    word[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(word)
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Address(addr) => write!(f, "Address({:#x})", addr),
+            Location::Expression(expression) => write!(f, "Expression({:02x?})", expression.bytecode),
+            Location::List(entries) => write!(f, "List({} ranges)", entries.len()),
+            Location::Constant(bytes) => write!(f, "Constant({:02x?})", bytes),
+            Location::OptimizedOut => write!(f, "OptimizedOut"),
+        }
+    }
+}
+
+impl fmt::Debug for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -1,25 +1,145 @@
 mod utils;
+mod breakpoint;
+mod core_file;
+mod dap;
 mod debugger;
 mod debugger_command;
+mod disassembler;
+mod expression;
+mod gdbserver;
 mod inferior;
+mod json;
+mod location;
+mod process;
 mod dwarf_data;
 mod gimli_wrapper;
+mod registers;
+mod remote;
+mod rsp;
+mod shared_library;
+mod signals;
+mod source;
+mod target;
+mod unwind;
 
 use crate::debugger::Debugger;
+use crate::inferior::Inferior;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::unistd::Pid;
 use std::env;
 
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [-batch] [--interpreter=json] [-x script]... <target program> [core file]", program);
+    println!("       {} [-batch] [--interpreter=json] [-x script]... -p <pid>", program);
+    println!("       {} --dap[=<port>]", program);
+    println!("       {} --gdbserver [host]:<port> <target program> [args]...", program);
+    println!("       {} -p <pid> --gdbserver [host]:<port>", program);
+    std::process::exit(1);
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let mut scripts = Vec::new();
+    let mut batch = false;
+    let mut json = false;
+    let mut dap = None;
+    let mut gdbserver = None;
+    let mut attach_pid = None;
+    let mut positional = Vec::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-x" => match rest.next() {
+                Some(script) => scripts.push(script.clone()),
+                None => usage(&args[0]),
+            },
+            "-batch" | "--batch" => batch = true,
+            "--interpreter=json" => json = true,
+            "--interpreter=console" => json = false,
+            // the Debug Adapter Protocol, on stdio or a TCP port
+            "--dap" => dap = Some(None),
+            arg if arg.starts_with("--dap=") => match arg["--dap=".len()..].parse::<u16>() {
+                Ok(port) => dap = Some(Some(port)),
+                Err(_) => usage(&args[0]),
+            },
+            // everything after the address is the program and its arguments
+            "--gdbserver" => match rest.next() {
+                Some(address) => {
+                    gdbserver = Some(address.clone());
+                    positional.extend(rest.by_ref().cloned());
+                }
+                None => usage(&args[0]),
+            },
+            "-p" => match rest.next() {
+                Some(pid) => match pid.parse::<i32>() {
+                    Ok(pid) => attach_pid = Some(pid),
+                    Err(_) => {
+                        println!("Invalid pid {}", pid);
+                        std::process::exit(1);
+                    }
+                },
+                None => usage(&args[0]),
+            },
+            _ => positional.push(arg.clone()),
+        }
     }
-    let target = &args[1];
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    // the client says which program to debug
+    if let Some(port) = dap {
+        let result = match port {
+            Some(port) => dap::serve_tcp(port),
+            None => dap::serve_stdio(),
+        };
+        if let Err(e) = result {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(address) = gdbserver {
+        let inferior = match (attach_pid, positional.split_first()) {
+            (Some(pid), None) => Inferior::attach(Pid::from_raw(pid)).map_err(|e| format!("Error attaching to process {}: {}", pid, e)),
+            (None, Some((target, target_args))) => match Inferior::new(target, &target_args.to_vec()) {
+                Some(inferior) => {
+                    println!("Process {} created; pid = {}", target, inferior.current_tid());
+                    Ok(inferior)
+                }
+                None => Err("Error starting subprocess".to_string()),
+            },
+            _ => usage(&args[0]),
+        };
+        if let Err(e) = inferior.and_then(|inferior| gdbserver::serve(&address, inferior)) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (target, core) = match (attach_pid, positional.len()) {
+        (Some(pid), 0) => (format!("/proc/{}/exe", pid), None),
+        (None, 1) => (positional[0].clone(), None),
+        (None, 2) => (positional[0].clone(), Some(positional[1].clone())),
+        _ => usage(&args[0]),
+    };
+
+    let mut debugger = Debugger::new(&target);
+    if json {
+        debugger.use_json_interpreter();
+    }
+    if let Some(pid) = attach_pid {
+        debugger.attach_inferior(pid);
+    }
+    if let Some(core) = core {
+        debugger.load_core(&core);
+    }
+    debugger.set_batch(batch);
+    for script in &scripts {
+        debugger.source_file(script);
+    }
+    std::process::exit(debugger.run());
 }
@@ -0,0 +1,215 @@
+//! A program deet can run and change, as opposed to just look at: the execution backend behind
+//! `run`, `continue`, stepping, breakpoints and watchpoints. An `Inferior` controls a local
+//! process with ptrace, and a `Remote` one that a gdbserver controls for us. Stepping by lines and
+//! `finish` are built from the primitives here, so both get them.
+
+use crate::breakpoint::Breakpoint;
+use crate::dwarf_data::DwarfData;
+use crate::inferior::{ForkPolicy, Inferior, Status, Thread, WatchKind};
+use crate::signals::SignalTable;
+use crate::target::Target;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::collections::HashMap;
+
+pub trait Process: Target {
+    /// Returns true if the process was already running when we took control of it, in which
+    /// case quitting lets it go rather than killing it.
+    fn is_attached(&self) -> bool;
+
+    fn set_fork_policy(&mut self, fork_policy: ForkPolicy);
+
+    fn set_signal_policy(&mut self, signal_policy: SignalTable);
+
+    /// Returns the threads of the process, in the order they were seen.
+    fn threads(&self) -> &[Thread];
+
+    /// Returns the number of the current thread.
+    fn current_thread_num(&self) -> usize;
+
+    /// Makes thread `num` the current thread. Returns false if there is no such thread.
+    fn select_thread(&mut self, num: usize) -> bool;
+
+    /// If the current thread has changed since the user was last told, returns its number and
+    /// LWP id so that the switch can be announced.
+    fn take_thread_switch(&mut self) -> Option<(usize, Pid)>;
+
+    fn thread_name(&self, tid: Pid) -> Option<String>;
+
+    fn thread_registers(&self, tid: Pid) -> Result<libc::user_regs_struct, nix::Error>;
+
+    /// Sets the general-purpose registers of the current thread.
+    fn set_registers(&mut self, regs: libc::user_regs_struct) -> Result<(), nix::Error>;
+
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error>;
+
+    /// Installs a breakpoint at `addr` and returns the byte the program has there.
+    fn set_breakpoint(&mut self, addr: usize) -> Result<u8, nix::Error>;
+
+    fn remove_breakpoint(&mut self, addr: usize, orig_byte: u8) -> Result<(), nix::Error>;
+
+    /// Watches `len` bytes at `addr` with a hardware watchpoint. Returns the slot it took, or
+    /// None if all are in use.
+    fn set_hw_watchpoint(&mut self, addr: usize, len: usize, kind: WatchKind) -> Result<Option<usize>, nix::Error>;
+
+    fn remove_hw_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error>;
+
+    /// Returns the slot of the watchpoint that stopped the program, once.
+    fn take_hw_watchpoint_hit(&mut self) -> Option<usize>;
+
+    /// Returns the address a fault such as a SIGSEGV was about, if the program is stopped by one.
+    fn fault_address(&self) -> Option<usize>;
+
+    /// Resumes every thread until the program stops, exits or dies. Threads sitting on one of
+    /// `breakpoints` get past it first.
+    fn cont(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error>;
+
+    /// Executes a single instruction in the current thread, leaving the other threads stopped.
+    fn step_instruction(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error>;
+
+    fn kill(&mut self);
+
+    /// Removes every breakpoint and watchpoint from the process and lets it run on its own.
+    fn detach(&mut self, breakpoints: &mut HashMap<usize, Breakpoint>) -> Result<(), nix::Error>;
+
+    /// Returns the local process, for what only ptrace and /proc can do, like writing a core
+    /// file.
+    fn as_inferior(&self) -> Option<&Inferior> {
+        None
+    }
+
+    /// Executes a single instruction in the current thread like `step_instruction`, except that
+    /// a call runs until it returns.
+    fn next_instruction(&mut self, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        let thread = self.current_thread_num();
+        let old_regs = self.get_registers()?;
+        let rip = match self.step_instruction(breakpoints)? {
+            Status::Stopped(Signal::SIGTRAP, rip) if self.current_thread_num() == thread => rip,
+            other => return Ok(other),
+        };
+        match self.called_from(&old_regs)? {
+            Some(ret_addr) => self.run_to(ret_addr, old_regs.rsp as usize, breakpoints),
+            None => Ok(Status::Stopped(Signal::SIGTRAP, rip)),
+        }
+    }
+
+    /// Runs until the instruction at `addr` is reached with a stack pointer of at least
+    /// `min_sp`, using a temporary breakpoint. The frame check keeps recursive calls from
+    /// stopping too early. Any other breakpoint that is hit on the way stops the program.
+    fn run_to(&mut self, addr: usize, min_sp: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        let mut breakpoints = breakpoints.clone();
+        let temp_orig_byte = if breakpoints.get(&addr).and_then(|breakpoint| breakpoint.orig_byte).is_some() {
+            None
+        } else {
+            let orig_byte = self.set_breakpoint(addr)?;
+            let mut breakpoint = Breakpoint::new(0, addr);
+            breakpoint.orig_byte = Some(orig_byte);
+            breakpoints.insert(addr, breakpoint);
+            Some(orig_byte)
+        };
+
+        // other threads may pass the temporary breakpoint too
+        let thread = self.current_thread_num();
+        let status = loop {
+            match self.cont(&breakpoints)? {
+                Status::Stopped(Signal::SIGTRAP, rip)
+                    if rip == addr
+                        && temp_orig_byte.is_some()
+                        && (self.current_thread_num() != thread || (self.get_registers()?.rsp as usize) < min_sp) => {}
+                status => break status,
+            }
+        };
+
+        if let (Some(orig_byte), Status::Stopped(_, _)) = (temp_orig_byte, &status) {
+            self.remove_breakpoint(addr, orig_byte)?;
+        }
+        Ok(status)
+    }
+
+    /// Steps until the program reaches the start of a different source line. Calls into
+    /// functions without line information are always stepped over; other calls are entered only
+    /// if `step_into` is set. Without line information where the program is, it only gets one
+    /// instruction further; the caller should `finish` out of such code instead. Returning into
+    /// code without line information stops there, as gdb does.
+    fn step_line(&mut self, debug_data: &DwarfData, breakpoints: &HashMap<usize, Breakpoint>, step_into: bool) -> Result<Status, nix::Error> {
+        let thread = self.current_thread_num();
+        let start_rip = self.get_registers()?.rip as usize;
+        let start_line = match debug_data.get_line_from_addr(start_rip) {
+            Some(line) => line,
+            None => return self.step_instruction(breakpoints),
+        };
+
+        loop {
+            let old_regs = self.get_registers()?;
+            // a stop in another thread ends the step
+            let mut rip = match self.step_instruction(breakpoints)? {
+                Status::Stopped(Signal::SIGTRAP, rip) if self.current_thread_num() == thread => rip,
+                other => return Ok(other),
+            };
+
+            if let Some(ret_addr) = self.called_from(&old_regs)? {
+                let in_debug_info = debug_data.get_line_from_addr(rip).is_some();
+                if step_into && in_debug_info {
+                    let body_addr = debug_data.get_addr_after_prologue(rip);
+                    if body_addr == rip {
+                        return Ok(Status::Stopped(Signal::SIGTRAP, rip));
+                    }
+                    return self.run_to(body_addr, 0, breakpoints);
+                }
+                match self.run_to(ret_addr, old_regs.rsp as usize, breakpoints)? {
+                    Status::Stopped(Signal::SIGTRAP, addr) if addr == ret_addr && self.current_thread_num() == thread => rip = addr,
+                    other => return Ok(other),
+                }
+            }
+
+            match debug_data.get_line_from_addr(rip) {
+                Some(line) => {
+                    let same_line = line.file == start_line.file && line.number == start_line.number;
+                    if !same_line && debug_data.is_line_start(rip) {
+                        return Ok(Status::Stopped(Signal::SIGTRAP, rip));
+                    }
+                }
+                // we returned into code without line information
+                None => return Ok(Status::Stopped(Signal::SIGTRAP, rip)),
+            }
+        }
+    }
+
+    /// Checks whether the instruction that was just stepped over (starting from `old_regs`) was a
+    /// call. If so, returns the return address it pushed.
+    fn called_from(&self, old_regs: &libc::user_regs_struct) -> Result<Option<usize>, nix::Error> {
+        let regs = self.get_registers()?;
+        if regs.rsp != old_regs.rsp.wrapping_sub(8) {
+            return Ok(None);
+        }
+        let ret_addr = read_word(self, regs.rsp as usize)?;
+        // x86 instructions are at most 15 bytes long
+        if ret_addr > old_regs.rip as usize && ret_addr <= old_regs.rip as usize + 15 && regs.rip as usize != ret_addr {
+            Ok(Some(ret_addr))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Runs until a function returns to its caller at `ret_addr`, which is when the stack is
+    /// back up to the CFA `cfa` of the function's frame. Both come from unwinding the stack, so
+    /// that this works from any frame and without frame pointers. Returns the stop status
+    /// together with whether the function did return, rather than the program stopping for
+    /// something else on the way, so that its return value can be read from the registers.
+    fn finish(&mut self, ret_addr: usize, cfa: usize, breakpoints: &HashMap<usize, Breakpoint>) -> Result<(Status, bool), nix::Error> {
+        let thread = self.current_thread_num();
+        let status = self.run_to(ret_addr, cfa, breakpoints)?;
+        let returned = match status {
+            Status::Stopped(Signal::SIGTRAP, rip) if rip == ret_addr && self.current_thread_num() == thread => {
+                self.get_registers()?.rsp as usize >= cfa
+            }
+            _ => false,
+        };
+        Ok((status, returned))
+    }
+}
+
+fn read_word<P: Process + ?Sized>(process: &P, addr: usize) -> Result<usize, nix::Error> {
+    let bytes = process.read_bytes(addr, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
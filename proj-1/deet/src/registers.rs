@@ -0,0 +1,173 @@
+//! Names and formatting of the x86-64 registers, as ptrace presents them in `user_regs_struct`
+//! and `user_fpregs_struct`.
+
+use crate::utils;
+use libc::{user_fpregs_struct, user_regs_struct};
+
+/// How a register's value is shown next to its hex value in `info registers`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RegisterKind {
+    Integer,
+    DataPointer,
+    CodePointer,
+    Flags,
+}
+
+pub struct Register {
+    pub name: &'static str,
+    /// The DWARF register number, for the registers that call frame information can recover
+    pub dwarf: Option<usize>,
+    pub kind: RegisterKind,
+    pub get: fn(&user_regs_struct) -> u64,
+    pub set: fn(&mut user_regs_struct, u64),
+}
+
+macro_rules! register {
+    ($name:ident, $dwarf:expr, $kind:ident) => {
+        Register {
+            name: stringify!($name),
+            dwarf: $dwarf,
+            kind: RegisterKind::$kind,
+            get: |regs| regs.$name,
+            set: |regs, value| regs.$name = value,
+        }
+    };
+}
+
+/// The general-purpose registers in the order `info registers` lists them.
+pub const REGISTERS: [Register; 26] = [
+    register!(rax, Some(0), Integer),
+    register!(rbx, Some(3), Integer),
+    register!(rcx, Some(2), Integer),
+    register!(rdx, Some(1), Integer),
+    register!(rsi, Some(4), Integer),
+    register!(rdi, Some(5), Integer),
+    register!(rbp, Some(6), DataPointer),
+    register!(rsp, Some(7), DataPointer),
+    register!(r8, Some(8), Integer),
+    register!(r9, Some(9), Integer),
+    register!(r10, Some(10), Integer),
+    register!(r11, Some(11), Integer),
+    register!(r12, Some(12), Integer),
+    register!(r13, Some(13), Integer),
+    register!(r14, Some(14), Integer),
+    register!(r15, Some(15), Integer),
+    // the return address column holds the pc of outer frames
+    register!(rip, Some(16), CodePointer),
+    register!(eflags, None, Flags),
+    register!(cs, None, Integer),
+    register!(ss, None, Integer),
+    register!(ds, None, Integer),
+    register!(es, None, Integer),
+    register!(fs, None, Integer),
+    register!(gs, None, Integer),
+    register!(fs_base, None, Integer),
+    register!(gs_base, None, Integer),
+];
+
+/// Returns the index in `REGISTERS` of a general-purpose register. `pc`, `sp` and `fp` stand for
+/// rip, rsp and rbp.
+pub fn find(name: &str) -> Option<usize> {
+    let name = match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        name => name,
+    };
+    REGISTERS.iter().position(|register| register.name == name)
+}
+
+/// Names the flags set in a flags register, highest bit first, e.g. `[ IF ZF PF ]`.
+fn format_flags(value: u64, flags: &[(u32, &str)]) -> String {
+    let set: Vec<&str> = flags
+        .iter()
+        .rev()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
+}
+
+pub fn format_eflags(value: u64) -> String {
+    const EFLAGS: [(u32, &str); 9] = [
+        (0, "CF"),
+        (2, "PF"),
+        (4, "AF"),
+        (6, "ZF"),
+        (7, "SF"),
+        (8, "TF"),
+        (9, "IF"),
+        (10, "DF"),
+        (11, "OF"),
+    ];
+    format_flags(value, &EFLAGS)
+}
+
+fn format_mxcsr(value: u64) -> String {
+    const MXCSR: [(u32, &str); 14] = [
+        (0, "IE"),
+        (1, "DE"),
+        (2, "ZE"),
+        (3, "OE"),
+        (4, "UE"),
+        (5, "PE"),
+        (6, "DAZ"),
+        (7, "IM"),
+        (8, "DM"),
+        (9, "ZM"),
+        (10, "OM"),
+        (11, "UM"),
+        (12, "PM"),
+        (15, "FZ"),
+    ];
+    format_flags(value, &MXCSR)
+}
+
+/// Lists the x87 and SSE registers as (name, value, natural representation), the way
+/// `info all-registers` shows them.
+pub fn fp_registers(fpregs: &user_fpregs_struct) -> Vec<(String, String, String)> {
+    let mut registers = Vec::new();
+    for (i, st) in fpregs.st_space.chunks(4).enumerate() {
+        let bytes: Vec<u8> = st.iter().flat_map(|word| word.to_le_bytes()).take(10).collect();
+        let raw: String = bytes.iter().rev().map(|byte| format!("{:02x}", byte)).collect();
+        registers.push((
+            format!("st{}", i),
+            format!("{:?}", utils::x87_extended_to_f64(&bytes)),
+            format!("(raw 0x{})", raw),
+        ));
+    }
+    let control = [
+        ("fctrl", fpregs.cwd as u64),
+        ("fstat", fpregs.swd as u64),
+        ("ftag", fpregs.ftw as u64),
+        ("fiseg", fpregs.rip >> 32),
+        ("fioff", fpregs.rip & 0xffff_ffff),
+        ("foseg", fpregs.rdp >> 32),
+        ("fooff", fpregs.rdp & 0xffff_ffff),
+        ("fop", fpregs.fop as u64),
+    ];
+    for (name, value) in control {
+        registers.push((name.to_string(), format!("{:#x}", value), value.to_string()));
+    }
+    for (i, xmm) in fpregs.xmm_space.chunks(4).enumerate() {
+        // Debug switches to exponents for very large and small values, which Display doesn't
+        let floats: Vec<String> = xmm.iter().map(|word| format!("{:?}", f32::from_bits(*word))).collect();
+        let low = xmm[0] as u64 | (xmm[1] as u64) << 32;
+        let high = xmm[2] as u64 | (xmm[3] as u64) << 32;
+        registers.push((
+            format!("xmm{}", i),
+            format!(
+                "{{v4_float = {{{}}}, v2_double = {{{:?}, {:?}}}, uint128 = 0x{:016x}{:016x}}}",
+                floats.join(", "),
+                f64::from_bits(low),
+                f64::from_bits(high),
+                high,
+                low
+            ),
+            String::new(),
+        ));
+    }
+    let mxcsr = fpregs.mxcsr as u64;
+    registers.push(("mxcsr".to_string(), format!("{:#x}", mxcsr), format_mxcsr(mxcsr)));
+    registers
+}
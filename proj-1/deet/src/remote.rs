@@ -0,0 +1,714 @@
+//! `target remote`: a program that a gdbserver (or `deet --gdbserver`) controls for us over the
+//! GDB remote serial protocol. The stub does the ptrace work and steps over its own breakpoints;
+//! deet keeps doing the symbolic side, reading memory and registers through the connection.
+
+use crate::breakpoint::Breakpoint;
+use crate::inferior::{ForkPolicy, Status, Thread, WatchKind, NUM_HW_WATCHPOINTS};
+use crate::process::Process;
+use crate::rsp::{self, from_hex, parse_hex, register_bytes, register_size, set_register_bytes, to_hex, unescape_binary, NUM_REGISTERS};
+use crate::signals::{self, SignalTable};
+use crate::target::{self, Mapping, Target};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// The packet size to assume until the stub says what it takes.
+const DEFAULT_PACKET_SIZE: usize = 0x400;
+
+/// The fewest bytes to ask for at a time, however small a packet size the stub claims.
+const MIN_CHUNK_SIZE: usize = 16;
+
+/// The connection to the stub.
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// Acknowledge every packet with `+`, until the stub agrees to stop doing so
+    ack: bool,
+    /// The largest packet the stub accepts
+    packet_size: usize,
+}
+
+impl Connection {
+    /// Sends a packet and returns the stub's reply.
+    fn request(&mut self, data: &str) -> Result<String, Errno> {
+        self.send(data)?;
+        self.receive()
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), Errno> {
+        let packet = rsp::packet(data);
+        loop {
+            self.stream.write_all(&packet).map_err(|_| Errno::EIO)?;
+            if !self.ack {
+                return Ok(());
+            }
+            // a `-` asks for the packet again
+            match self.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' => continue,
+                _ => return Err(Errno::EPROTO),
+            }
+        }
+    }
+
+    /// Reads the next packet the stub sends.
+    fn receive(&mut self) -> Result<String, Errno> {
+        loop {
+            while self.read_byte()? != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let packet = rsp::packet_data(&data, &checksum);
+            if self.ack {
+                let reply: &[u8] = if packet.is_some() { b"+" } else { b"-" };
+                self.stream.write_all(reply).map_err(|_| Errno::EIO)?;
+            }
+            if let Some(packet) = packet {
+                return Ok(packet);
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Errno> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(1) => Ok(byte[0]),
+            // the stub went away
+            _ => Err(Errno::ECONNRESET),
+        }
+    }
+
+    /// Sends a request whose reply is OK or an error.
+    fn command(&mut self, data: &str) -> Result<(), Errno> {
+        let reply = self.request(data)?;
+        check_reply(&reply)?;
+        if reply.is_empty() {
+            return Err(Errno::ENOTSUP);
+        }
+        Ok(())
+    }
+
+    /// Reads a whole object with `qXfer:object:read:annex:offset,length` requests.
+    fn read_object(&mut self, object: &str, annex: &str) -> Result<Vec<u8>, Errno> {
+        let mut data = Vec::new();
+        loop {
+            let reply = self.request(&format!("qXfer:{}:read:{}:{:x},{:x}", object, annex, data.len(), self.packet_size.saturating_sub(4).max(MIN_CHUNK_SIZE)))?;
+            check_reply(&reply)?;
+            match reply.split_at(reply.len().min(1)) {
+                ("m", chunk) => data.extend(unescape_binary(chunk)),
+                ("l", chunk) => {
+                    data.extend(unescape_binary(chunk));
+                    return Ok(data);
+                }
+                _ => return Err(Errno::ENOTSUP),
+            }
+        }
+    }
+
+    /// Reads a file on the stub's machine through gdb's host I/O requests.
+    fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Errno> {
+        let reply = self.request(&format!("vFile:open:{},0,0", to_hex(path.as_bytes())))?;
+        let fd = file_result(&reply)?;
+        let mut data = Vec::new();
+        let result = loop {
+            let reply = match self.request(&format!("vFile:pread:{:x},{:x},{:x}", fd, (self.packet_size / 2).max(MIN_CHUNK_SIZE), data.len())) {
+                Ok(reply) => reply,
+                Err(e) => break Err(e),
+            };
+            match file_result(&reply) {
+                Ok(0) => break Ok(()),
+                Ok(_) => data.extend(unescape_binary(reply.split_once(';').map_or("", |(_, chunk)| chunk))),
+                Err(e) => break Err(e),
+            }
+        };
+        self.request(&format!("vFile:close:{:x}", fd))?;
+        result.map(|_| data)
+    }
+}
+
+/// Turns an `Exx` reply into the error it carries.
+fn check_reply(reply: &str) -> Result<(), Errno> {
+    match reply.strip_prefix('E').and_then(|code| u8::from_str_radix(code, 16).ok()) {
+        Some(code) if reply.len() == 3 => Err(Errno::from_i32(code as i32)),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the result of a host I/O request: `Fresult` or `F-1,errno`.
+fn file_result(reply: &str) -> Result<usize, Errno> {
+    let result = reply.strip_prefix('F').ok_or(Errno::ENOTSUP)?;
+    let result = result.split(';').next().unwrap_or("");
+    match result.split_once(',') {
+        Some((_, errno)) => Err(Errno::from_i32(i32::from_str_radix(errno, 16).unwrap_or(libc::EIO))),
+        None => parse_hex(result).ok_or(Errno::EPROTO),
+    }
+}
+
+/// Parses a thread ID in a packet. -1 means all threads and 0 any thread, neither of which is a
+/// thread of its own.
+fn parse_thread(text: &str) -> Option<Pid> {
+    i32::from_str_radix(text, 16).ok().filter(|tid| *tid > 0).map(Pid::from_raw)
+}
+
+/// Why the program stopped, from a stop reply.
+enum StopReply {
+    Stopped {
+        signal: Signal,
+        thread: Option<Pid>,
+        /// The address of the watchpoint that triggered
+        watch_addr: Option<usize>,
+    },
+    Exited(i32),
+    Signaled(Signal),
+}
+
+fn parse_stop_reply(reply: &str) -> Result<StopReply, Errno> {
+    check_reply(reply)?;
+    let (kind, rest) = reply.split_at(reply.len().min(1));
+    let num = rest.get(..2).and_then(|num| u8::from_str_radix(num, 16).ok()).ok_or(Errno::EPROTO)?;
+    let signal = || signals::from_gdb(num).ok_or(Errno::EPROTO);
+    match kind {
+        "T" | "S" => {
+            let mut thread = None;
+            let mut watch_addr = None;
+            for field in rest[2..].split(';') {
+                match field.split_once(':') {
+                    Some(("thread", tid)) => thread = parse_thread(tid),
+                    Some(("watch" | "rwatch" | "awatch", addr)) => watch_addr = parse_hex(addr),
+                    _ => {}
+                }
+            }
+            Ok(StopReply::Stopped { signal: signal()?, thread, watch_addr })
+        }
+        "W" => Ok(StopReply::Exited(num as i32)),
+        "X" => Ok(StopReply::Signaled(signal()?)),
+        _ => Err(Errno::EPROTO),
+    }
+}
+
+pub struct Remote {
+    connection: RefCell<Connection>,
+    pid: Pid,
+    /// True if the stub attached to a process that was already running
+    attached: bool,
+    threads: Vec<Thread>,
+    next_thread_num: usize,
+    /// The thread whose registers are used and which gets stepped
+    current: Pid,
+    /// The thread the user was last told about
+    announced: Pid,
+    /// The thread the stub last made current for register requests, with `Hg`
+    register_thread: Cell<Option<Pid>>,
+    signal_policy: SignalTable,
+    /// The signal the current thread stopped with, to be delivered when it resumes if the signal
+    /// is passed to the program
+    signal: Option<Signal>,
+    /// What each watchpoint slot watches, as (address, length, kind)
+    hw_slots: [Option<(usize, usize, WatchKind)>; NUM_HW_WATCHPOINTS],
+    hw_watch_hit: Option<usize>,
+}
+
+impl Remote {
+    /// Connects to a stub at `address`, e.g. `:2345` or `host:2345`, and finds out where the
+    /// program it controls is stopped.
+    pub fn connect(address: &str) -> Result<Remote, String> {
+        let address = match address.strip_prefix(':') {
+            Some(port) => format!("127.0.0.1:{}", port),
+            None => address.to_string(),
+        };
+        let stream = TcpStream::connect(&address).map_err(|e| format!("{}: {}.", address, e))?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        let mut connection = Connection { stream, reader, ack: true, packet_size: DEFAULT_PACKET_SIZE };
+        let protocol_error = |e: Errno| format!("Remote communication error: {}", e.desc());
+
+        // both ends start by acknowledging every packet
+        connection.stream.write_all(b"+").map_err(|e| e.to_string())?;
+        let features = connection.request("qSupported:swbreak+;vContSupported+").map_err(protocol_error)?;
+        for feature in features.split(';') {
+            if let Some(size) = feature.strip_prefix("PacketSize=").and_then(parse_hex) {
+                connection.packet_size = size;
+            }
+        }
+        if features.split(';').any(|feature| feature == "QStartNoAckMode+") && connection.command("QStartNoAckMode").is_ok() {
+            connection.ack = false;
+        }
+
+        let thread = match parse_stop_reply(&connection.request("?").map_err(protocol_error)?) {
+            Ok(StopReply::Stopped { thread, .. }) => thread,
+            Ok(_) => return Err("The remote program is not being run.".to_string()),
+            Err(e) => return Err(protocol_error(e)),
+        };
+        let attached = connection.request("qAttached").map_err(protocol_error)? == "1";
+        let mut remote = Remote {
+            connection: RefCell::new(connection),
+            pid: Pid::from_raw(0),
+            attached,
+            threads: Vec::new(),
+            next_thread_num: 1,
+            current: Pid::from_raw(0),
+            announced: Pid::from_raw(0),
+            register_thread: Cell::new(None),
+            signal_policy: SignalTable::default(),
+            signal: None,
+            hw_slots: [None; NUM_HW_WATCHPOINTS],
+            hw_watch_hit: None,
+        };
+        remote.update_threads(false).map_err(protocol_error)?;
+        // the main thread, whose ID is the process ID, is listed first
+        let main = remote.threads.first().map(|thread| thread.tid).ok_or("The remote program has no threads.")?;
+        remote.pid = main;
+        remote.current = thread.unwrap_or(main);
+        remote.announced = remote.current;
+        Ok(remote)
+    }
+
+    /// Asks the stub which threads there are, numbering the new ones and forgetting those that
+    /// exited. The changes are announced unless this is the first look.
+    fn update_threads(&mut self, announce: bool) -> Result<(), Errno> {
+        let mut tids = Vec::new();
+        let mut reply = self.connection.get_mut().request("qfThreadInfo")?;
+        while let Some(list) = reply.strip_prefix('m') {
+            tids.extend(list.split(',').filter_map(parse_thread));
+            reply = self.connection.get_mut().request("qsThreadInfo")?;
+        }
+        if tids.is_empty() {
+            // a stub without thread support
+            tids.push(self.current);
+        }
+
+        self.threads.retain(|thread| {
+            let alive = tids.contains(&thread.tid);
+            if !alive && announce {
+                println!("[Thread {} (LWP {}) exited]", thread.num, thread.tid);
+            }
+            alive
+        });
+        for tid in tids {
+            if !self.threads.iter().any(|thread| thread.tid == tid) {
+                let num = self.next_thread_num;
+                self.next_thread_num += 1;
+                self.threads.push(Thread::new(num, tid));
+                if announce {
+                    println!("[New thread {} (LWP {})]", num, tid);
+                }
+            }
+        }
+        if !self.threads.iter().any(|thread| thread.tid == self.current) {
+            if let Some(thread) = self.threads.first() {
+                self.current = thread.tid;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads all registers of thread `tid` in the stub's `g` layout.
+    fn read_registers(&self, tid: Pid) -> Result<(libc::user_regs_struct, libc::user_fpregs_struct), Errno> {
+        let mut connection = self.connection.borrow_mut();
+        if self.register_thread.get() != Some(tid) {
+            connection.command(&format!("Hg{:x}", tid.as_raw()))?;
+            self.register_thread.set(Some(tid));
+        }
+        let reply = connection.request("g")?;
+        check_reply(&reply)?;
+        let bytes = from_hex(&reply).ok_or(Errno::EPROTO)?;
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let mut offset = 0;
+        for num in 0..NUM_REGISTERS {
+            let size = register_size(num);
+            // registers the stub leaves out of a short `g` reply read as zero
+            if offset + size > bytes.len() {
+                break;
+            }
+            set_register_bytes(num, &bytes[offset..offset + size], &mut regs, &mut fpregs);
+            offset += size;
+        }
+        Ok((regs, fpregs))
+    }
+
+    /// Resumes the program, stepping just the current thread if `step` is set, and waits for it
+    /// to stop. Signals the program is set to get without stopping are passed on here.
+    fn resume(&mut self, step: bool) -> Result<Status, Errno> {
+        loop {
+            let signal = self.signal.take().filter(|signal| self.signal_policy.get(*signal).pass);
+            let current = self.current.as_raw();
+            let request = match (step, signal) {
+                (true, Some(signal)) => format!("vCont;S{:02x}:{:x}", signals::to_gdb(signal), current),
+                (true, None) => format!("vCont;s:{:x}", current),
+                (false, Some(signal)) => format!("vCont;C{:02x}:{:x};c", signals::to_gdb(signal), current),
+                (false, None) => "vCont;c".to_string(),
+            };
+            let connection = self.connection.get_mut();
+            connection.send(&request)?;
+            let reply = loop {
+                let reply = connection.receive()?;
+                // console output from the program, which some stubs forward
+                match reply.strip_prefix('O') {
+                    Some(output) if reply != "OK" => {
+                        print!("{}", String::from_utf8_lossy(&from_hex(output).unwrap_or_default()));
+                    }
+                    _ => break reply,
+                }
+            };
+            self.register_thread.set(None);
+
+            match parse_stop_reply(&reply)? {
+                StopReply::Exited(code) => return Ok(Status::Exited(code)),
+                StopReply::Signaled(signal) => return Ok(Status::Signaled(signal)),
+                StopReply::Stopped { signal, thread, watch_addr } => {
+                    if let Some(thread) = thread {
+                        self.current = thread;
+                    }
+                    self.update_threads(true)?;
+                    self.hw_watch_hit = watch_addr.and_then(|addr| {
+                        self.hw_slots.iter().position(|slot| matches!(slot, Some((start, len, _)) if addr >= *start && addr < start + len))
+                    });
+                    if signal != Signal::SIGTRAP {
+                        self.signal = Some(signal);
+                    }
+                    if self.pass_through(signal) {
+                        continue;
+                    }
+                    let rip = self.get_registers()?.rip as usize;
+                    return Ok(Status::Stopped(signal, rip));
+                }
+            }
+        }
+    }
+
+    /// True if `signal` should be handed to the program without stopping. Says so if the
+    /// signal is to be printed.
+    fn pass_through(&self, signal: Signal) -> bool {
+        let policy = self.signal_policy.get(signal);
+        if signal == Signal::SIGTRAP || policy.stop {
+            return false;
+        }
+        if policy.print {
+            println!("[Inferior received signal {}, {}]", signal, signals::description(signal));
+        }
+        true
+    }
+}
+
+impl Process for Remote {
+    fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// What happens on fork is up to the stub.
+    fn set_fork_policy(&mut self, _fork_policy: ForkPolicy) {}
+
+    fn set_signal_policy(&mut self, signal_policy: SignalTable) {
+        self.signal_policy = signal_policy;
+    }
+
+    fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    fn current_thread_num(&self) -> usize {
+        self.threads.iter().find(|thread| thread.tid == self.current).map_or(0, |thread| thread.num)
+    }
+
+    fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.num == num) {
+            Some(thread) => {
+                self.current = thread.tid;
+                self.announced = thread.tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn take_thread_switch(&mut self) -> Option<(usize, Pid)> {
+        if self.current == self.announced {
+            return None;
+        }
+        self.announced = self.current;
+        Some((self.current_thread_num(), self.current))
+    }
+
+    fn thread_name(&self, tid: Pid) -> Option<String> {
+        let comm = self.connection.borrow_mut().read_file(&format!("/proc/{}/task/{}/comm", self.pid, tid)).ok()?;
+        Some(String::from_utf8_lossy(&comm).trim_end().to_string())
+    }
+
+    fn thread_registers(&self, tid: Pid) -> Result<libc::user_regs_struct, nix::Error> {
+        Ok(self.read_registers(tid)?.0)
+    }
+
+    fn set_registers(&mut self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        let (_, fpregs) = self.read_registers(self.current)?;
+        let data: String = (0..NUM_REGISTERS).map(|num| to_hex(&register_bytes(num, &regs, &fpregs))).collect();
+        self.connection.get_mut().command(&format!("G{}", data))
+    }
+
+    fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let connection = self.connection.get_mut();
+        // each byte takes two hex digits, after the header
+        let chunk_size = (connection.packet_size.saturating_sub(32) / 2).max(MIN_CHUNK_SIZE);
+        for (i, chunk) in bytes.chunks(chunk_size).enumerate() {
+            let start = addr + i * chunk_size;
+            connection.command(&format!("M{:x},{:x}:{}", start, chunk.len(), to_hex(chunk)))?;
+        }
+        Ok(())
+    }
+
+    /// The stub keeps the breakpoint out of what memory reads return, and steps over it itself.
+    fn set_breakpoint(&mut self, addr: usize) -> Result<u8, nix::Error> {
+        let orig_byte = self.read_bytes(addr, 1)?[0];
+        self.connection.get_mut().command(&format!("Z0,{:x},1", addr))?;
+        Ok(orig_byte)
+    }
+
+    fn remove_breakpoint(&mut self, addr: usize, _orig_byte: u8) -> Result<(), nix::Error> {
+        self.connection.get_mut().command(&format!("z0,{:x},1", addr))
+    }
+
+    fn set_hw_watchpoint(&mut self, addr: usize, len: usize, kind: WatchKind) -> Result<Option<usize>, nix::Error> {
+        let slot = match self.hw_slots.iter().position(|slot| slot.is_none()) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        self.connection.get_mut().command(&format!("Z{},{:x},{:x}", watch_type(kind), addr, len))?;
+        self.hw_slots[slot] = Some((addr, len, kind));
+        Ok(Some(slot))
+    }
+
+    fn remove_hw_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        if let Some((addr, len, kind)) = self.hw_slots[slot].take() {
+            self.connection.get_mut().command(&format!("z{},{:x},{:x}", watch_type(kind), addr, len))?;
+        }
+        Ok(())
+    }
+
+    fn take_hw_watchpoint_hit(&mut self) -> Option<usize> {
+        self.hw_watch_hit.take()
+    }
+
+    /// The protocol doesn't carry the fault address with the stop.
+    fn fault_address(&self) -> Option<usize> {
+        None
+    }
+
+    fn cont(&mut self, _breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        self.resume(false)
+    }
+
+    fn step_instruction(&mut self, _breakpoints: &HashMap<usize, Breakpoint>) -> Result<Status, nix::Error> {
+        self.resume(true)
+    }
+
+    fn kill(&mut self) {
+        println!("Killing inferior {}", self.pid);
+        let connection = self.connection.get_mut();
+        if connection.command(&format!("vKill;{:x}", self.pid.as_raw())).is_err() {
+            // `k` has no reply
+            connection.send("k").ok();
+        }
+    }
+
+    fn detach(&mut self, breakpoints: &mut HashMap<usize, Breakpoint>) -> Result<(), nix::Error> {
+        for breakpoint in breakpoints.values_mut() {
+            if let Some(orig_byte) = breakpoint.orig_byte.take() {
+                self.remove_breakpoint(breakpoint.addr, orig_byte)?;
+            }
+        }
+        for slot in 0..NUM_HW_WATCHPOINTS {
+            self.remove_hw_watchpoint(slot)?;
+        }
+        self.connection.get_mut().command("D")
+    }
+}
+
+impl Target for Remote {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Reads memory in chunks that fit in a reply. A stub may return less than was asked for,
+    /// where readable memory ends.
+    fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut connection = self.connection.borrow_mut();
+        let chunk_size = (connection.packet_size.saturating_sub(4) / 2).max(MIN_CHUNK_SIZE);
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let start = addr + bytes.len();
+            let reply = connection.request(&format!("m{:x},{:x}", start, chunk_size.min(len - bytes.len())))?;
+            check_reply(&reply).map_err(|_| Errno::EFAULT)?;
+            let chunk = from_hex(&reply).ok_or(Errno::EPROTO)?;
+            if chunk.is_empty() {
+                return Err(Errno::EFAULT);
+            }
+            bytes.extend(chunk);
+        }
+        Ok(bytes)
+    }
+
+    fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        Ok(self.read_registers(self.current)?.0)
+    }
+
+    fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        Ok(self.read_registers(self.current)?.1)
+    }
+
+    fn auxv(&self) -> Option<Vec<u8>> {
+        self.connection.borrow_mut().read_object("auxv", "").ok()
+    }
+
+    fn mappings(&self) -> Vec<Mapping> {
+        let maps = self.connection.borrow_mut().read_file(&format!("/proc/{}/maps", self.pid)).unwrap_or_default();
+        target::parse_maps(&String::from_utf8_lossy(&maps))
+    }
+}
+
+/// Returns the `Z` type of a watchpoint.
+fn watch_type(kind: WatchKind) -> u8 {
+    match kind {
+        WatchKind::Write => 2,
+        WatchKind::Read => 3,
+        WatchKind::Access => 4,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inferior::Inferior;
+
+    #[test]
+    fn test_check_reply() {
+        assert_eq!(check_reply("OK"), Ok(()));
+        assert_eq!(check_reply(""), Ok(()));
+        assert_eq!(check_reply("E0e"), Err(Errno::EFAULT));
+        assert_eq!(check_reply("E01"), Err(Errno::EPERM));
+        // memory that happens to start with 0xe, and things that only look like errors
+        assert_eq!(check_reply("e0ff"), Ok(()));
+        assert_eq!(check_reply("E0"), Ok(()));
+        assert_eq!(check_reply("E0e0f"), Ok(()));
+        assert_eq!(check_reply("Ezz"), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_stop_reply() {
+        match parse_stop_reply("T05thread:1f2;swbreak:;06:d0e0ffffff7f0000;") {
+            Ok(StopReply::Stopped { signal, thread, watch_addr }) => {
+                assert_eq!(signal, Signal::SIGTRAP);
+                assert_eq!(thread, Some(Pid::from_raw(0x1f2)));
+                assert_eq!(watch_addr, None);
+            }
+            _ => panic!("T05 is a stop"),
+        }
+        match parse_stop_reply("T05thread:1f3;watch:404028;") {
+            Ok(StopReply::Stopped { signal, thread, watch_addr }) => {
+                assert_eq!(signal, Signal::SIGTRAP);
+                assert_eq!(thread, Some(Pid::from_raw(0x1f3)));
+                assert_eq!(watch_addr, Some(0x404028));
+            }
+            _ => panic!("T05 is a stop"),
+        }
+        match parse_stop_reply("S0b") {
+            Ok(StopReply::Stopped { signal, thread, watch_addr }) => {
+                assert_eq!(signal, Signal::SIGSEGV);
+                assert_eq!(thread, None);
+                assert_eq!(watch_addr, None);
+            }
+            _ => panic!("S0b is a stop"),
+        }
+        assert!(matches!(parse_stop_reply("W00"), Ok(StopReply::Exited(0))));
+        assert!(matches!(parse_stop_reply("W2a;process:1f2"), Ok(StopReply::Exited(42))));
+        assert!(matches!(parse_stop_reply("X09"), Ok(StopReply::Signaled(Signal::SIGKILL))));
+        // GDB's numbers are not Linux's
+        assert!(matches!(parse_stop_reply("X1e"), Ok(StopReply::Signaled(Signal::SIGUSR1))));
+
+        assert!(matches!(parse_stop_reply("E16"), Err(Errno::EINVAL)));
+        assert!(matches!(parse_stop_reply(""), Err(Errno::EPROTO)));
+        assert!(matches!(parse_stop_reply("T"), Err(Errno::EPROTO)));
+        assert!(matches!(parse_stop_reply("Tzz"), Err(Errno::EPROTO)));
+        assert!(matches!(parse_stop_reply("T00"), Err(Errno::EPROTO)));
+        assert!(matches!(parse_stop_reply("OK"), Err(Errno::EPROTO)));
+        assert!(matches!(parse_stop_reply("Q05"), Err(Errno::EPROTO)));
+    }
+
+    /// Debugs /bin/true through deet's own stub, which has to run on this thread since it
+    /// traces the program.
+    #[test]
+    fn test_connect() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!(":{}", listener.local_addr().unwrap().port());
+        let inferior = Inferior::new("/bin/true", &Vec::new()).expect("could not start /bin/true");
+        let pid = inferior.pid();
+        let regs = inferior.get_registers().unwrap();
+        let fpregs = inferior.get_fp_registers().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut remote = Remote::connect(&address).unwrap();
+            assert_eq!(remote.pid(), pid);
+            assert!(!remote.is_attached());
+            assert_eq!(remote.threads().len(), 1);
+            assert_eq!(remote.current_thread_num(), 1);
+
+            let remote_regs = remote.get_registers().unwrap();
+            assert_eq!(remote_regs.rip, regs.rip);
+            assert_eq!(remote_regs.rsp, regs.rsp);
+            assert_eq!(remote_regs.rdx, regs.rdx);
+            assert_eq!(remote_regs.eflags, regs.eflags);
+            assert_eq!(remote_regs.cs, regs.cs);
+            assert_eq!(remote_regs.fs_base, regs.fs_base);
+            let remote_fpregs = remote.get_fp_registers().unwrap();
+            assert_eq!(remote_fpregs.cwd, fpregs.cwd);
+            assert_eq!(remote_fpregs.mxcsr, fpregs.mxcsr);
+
+            // a packet size this small splits everything below into many requests
+            remote.connection.get_mut().packet_size = 0x40;
+            let auxv = remote.auxv().unwrap();
+            assert_eq!(auxv, std::fs::read(format!("/proc/{}/auxv", pid)).unwrap());
+            let file = remote.connection.get_mut().read_file("/bin/true").unwrap();
+            assert_eq!(file, std::fs::read("/bin/true").unwrap());
+            assert_eq!(remote.connection.get_mut().read_file("/no/such/file"), Err(Errno::ENOENT));
+
+            let rip = regs.rip as usize;
+            let mappings = remote.mappings();
+            assert!(mappings.iter().any(|mapping| mapping.executable && mapping.start <= rip && rip < mapping.end));
+
+            // the stack below where the program starts is mapped but unused
+            let addr = regs.rsp as usize - 0x1000;
+            let data: Vec<u8> = (0..0x300).map(|i| (i * 7) as u8).collect();
+            remote.write_bytes(addr, &data).unwrap();
+            assert_eq!(remote.read_bytes(addr, data.len()).unwrap(), data);
+            let mut written = vec![0; data.len()];
+            let mem = std::fs::File::open(format!("/proc/{}/mem", pid)).unwrap();
+            std::os::unix::fs::FileExt::read_exact_at(&mem, &mut written, addr as u64).unwrap();
+            assert_eq!(written, data);
+            assert_eq!(remote.read_bytes(0, 0x100), Err(Errno::EFAULT));
+
+            let entry = auxv
+                .chunks_exact(16)
+                .find(|entry| u64::from_le_bytes(entry[..8].try_into().unwrap()) == libc::AT_ENTRY)
+                .map(|entry| u64::from_le_bytes(entry[8..].try_into().unwrap()) as usize)
+                .unwrap();
+            let breakpoints = HashMap::new();
+            let orig_byte = remote.set_breakpoint(entry).unwrap();
+            // the stub keeps its breakpoints out of memory reads
+            assert_eq!(remote.read_bytes(entry, 1).unwrap(), vec![orig_byte]);
+            assert!(matches!(remote.cont(&breakpoints), Ok(Status::Stopped(Signal::SIGTRAP, rip)) if rip == entry));
+            remote.remove_breakpoint(entry, orig_byte).unwrap();
+            assert!(matches!(remote.step_instruction(&breakpoints), Ok(Status::Stopped(Signal::SIGTRAP, rip)) if rip != entry));
+            assert!(matches!(remote.cont(&breakpoints), Ok(Status::Exited(0))));
+        });
+
+        crate::gdbserver::serve_listener(listener, inferior).unwrap();
+        client.join().unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+//! The GDB remote serial protocol, as spoken by both ends: `--gdbserver` and `target remote`.
+//! Packets are `$data#checksum`. Their data is mostly hex, but some replies carry raw bytes, so a
+//! packet's data is kept as a string with one char per byte, U+0000 to U+00FF.
+
+use crate::registers::REGISTERS;
+
+/// Gdb's register numbers of the x87, SSE and other registers that follow the general-purpose
+/// ones, in the order target.xml lists them.
+pub const ST0: usize = 24;
+const FCTRL: usize = 32;
+const XMM0: usize = 40;
+const MXCSR: usize = 56;
+const ORIG_RAX: usize = 57;
+const FS_BASE: usize = 58;
+pub const NUM_REGISTERS: usize = 60;
+
+/// Returns the size in bytes of gdb register `num`.
+pub fn register_size(num: usize) -> usize {
+    match num {
+        0..=16 => 8,
+        17..=23 => 4,
+        ST0..=31 => 10,
+        FCTRL..=39 => 4,
+        XMM0..=55 => 16,
+        MXCSR => 4,
+        _ => 8,
+    }
+}
+
+/// Returns the value of gdb register `num`, little-endian.
+pub fn register_bytes(num: usize, regs: &libc::user_regs_struct, fpregs: &libc::user_fpregs_struct) -> Vec<u8> {
+    let value: u64 = match num {
+        0..=23 => (REGISTERS[num].get)(regs),
+        ST0..=31 => {
+            let st = &fpregs.st_space[(num - ST0) * 4..(num - ST0) * 4 + 4];
+            return st.iter().flat_map(|word| word.to_le_bytes()).take(10).collect();
+        }
+        FCTRL => fpregs.cwd as u64,
+        33 => fpregs.swd as u64,
+        34 => full_tag_word(fpregs.ftw) as u64,
+        35 => fpregs.rip >> 32,
+        36 => fpregs.rip & 0xffff_ffff,
+        37 => fpregs.rdp >> 32,
+        38 => fpregs.rdp & 0xffff_ffff,
+        39 => fpregs.fop as u64,
+        XMM0..=55 => {
+            let xmm = &fpregs.xmm_space[(num - XMM0) * 4..(num - XMM0) * 4 + 4];
+            return xmm.iter().flat_map(|word| word.to_le_bytes()).collect();
+        }
+        MXCSR => fpregs.mxcsr as u64,
+        ORIG_RAX => regs.orig_rax,
+        _ => (REGISTERS[num - FS_BASE + 24].get)(regs),
+    };
+    value.to_le_bytes()[..register_size(num)].to_vec()
+}
+
+/// Sets gdb register `num` from its little-endian value.
+pub fn set_register_bytes(num: usize, bytes: &[u8], regs: &mut libc::user_regs_struct, fpregs: &mut libc::user_fpregs_struct) {
+    let mut value = [0; 8];
+    let len = bytes.len().min(8);
+    value[..len].copy_from_slice(&bytes[..len]);
+    let value = u64::from_le_bytes(value);
+    match num {
+        0..=23 => (REGISTERS[num].set)(regs, value),
+        ST0..=31 => {
+            let mut st = [0u8; 16];
+            st[..10].copy_from_slice(bytes);
+            for (i, word) in st.chunks(4).enumerate() {
+                fpregs.st_space[(num - ST0) * 4 + i] = u32::from_le_bytes(word.try_into().unwrap());
+            }
+        }
+        FCTRL => fpregs.cwd = value as u16,
+        33 => fpregs.swd = value as u16,
+        34 => fpregs.ftw = abridged_tag_word(value as u16),
+        35 => fpregs.rip = (fpregs.rip & 0xffff_ffff) | value << 32,
+        36 => fpregs.rip = (fpregs.rip & !0xffff_ffff) | (value & 0xffff_ffff),
+        37 => fpregs.rdp = (fpregs.rdp & 0xffff_ffff) | value << 32,
+        38 => fpregs.rdp = (fpregs.rdp & !0xffff_ffff) | (value & 0xffff_ffff),
+        39 => fpregs.fop = value as u16,
+        XMM0..=55 => {
+            for (i, word) in bytes.chunks(4).enumerate() {
+                fpregs.xmm_space[(num - XMM0) * 4 + i] = u32::from_le_bytes(word.try_into().unwrap());
+            }
+        }
+        MXCSR => fpregs.mxcsr = value as u32,
+        ORIG_RAX => regs.orig_rax = value,
+        _ => (REGISTERS[num - FS_BASE + 24].set)(regs, value),
+    }
+}
+
+/// FXSAVE keeps one bit per x87 register saying whether it's empty, where gdb expects the full
+/// tag word with two. Registers in use are tagged valid rather than classified further.
+fn full_tag_word(abridged: u16) -> u16 {
+    (0..8).fold(0, |tags, i| if abridged & (1 << i) != 0 { tags } else { tags | 3 << (i * 2) })
+}
+
+fn abridged_tag_word(full: u16) -> u16 {
+    (0..8).fold(0, |tags, i| if (full >> (i * 2)) & 3 != 3 { tags | 1 << i } else { tags })
+}
+
+/// Parses a hex number. Unlike `from_str_radix`, a sign isn't taken.
+pub fn parse_hex(text: &str) -> Option<usize> {
+    if !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    usize::from_str_radix(text, 16).ok()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|digits| match digits {
+            [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Escapes the bytes that can't appear as they are in the binary data of a packet.
+pub fn escape_binary(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &byte in bytes {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => {
+                text.push('}');
+                text.push((byte ^ 0x20) as char);
+            }
+            _ => text.push(byte as char),
+        }
+    }
+    text
+}
+
+/// Undoes `escape_binary`.
+pub fn unescape_binary(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' => bytes.extend(chars.next().map(|c| c as u8 ^ 0x20)),
+            c => bytes.push(c as u8),
+        }
+    }
+    bytes
+}
+
+/// Frames packet data for sending.
+pub fn packet(data: &str) -> Vec<u8> {
+    let bytes: Vec<u8> = data.chars().map(|c| c as u8).collect();
+    let mut packet = Vec::with_capacity(bytes.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(&bytes);
+    packet.extend_from_slice(format!("#{:02x}", checksum(&bytes)).as_bytes());
+    packet
+}
+
+/// Returns the data of a received packet, given what came between `$` and `#` and the two
+/// checksum digits, or None if it was damaged on the way.
+pub fn packet_data(bytes: &[u8], checksum_digits: &[u8]) -> Option<String> {
+    let expected = u8::from_str_radix(std::str::from_utf8(checksum_digits).ok()?, 16).ok()?;
+    if checksum(bytes) != expected {
+        return None;
+    }
+    Some(bytes.iter().map(|byte| *byte as char).collect())
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packet() {
+        assert_eq!(packet("OK"), b"$OK#9a");
+        assert_eq!(packet(""), b"$#00");
+        // bytes past 0x7f go out as themselves, not as UTF-8
+        assert_eq!(packet("\u{ff}\u{80}"), b"$\xff\x80#7f");
+    }
+
+    #[test]
+    fn test_packet_data() {
+        assert_eq!(packet_data(b"OK", b"9a"), Some("OK".to_string()));
+        assert_eq!(packet_data(b"OK", b"9A"), Some("OK".to_string()));
+        assert_eq!(packet_data(b"OK", b"9b"), None);
+        assert_eq!(packet_data(b"OK", b"9"), None);
+        assert_eq!(packet_data(b"OK", b"zz"), None);
+        assert_eq!(packet_data(b"\xff\x80", b"7f"), Some("\u{ff}\u{80}".to_string()));
+    }
+
+    #[test]
+    fn test_packet_round_trip() {
+        let data = escape_binary(&[0, b'$', b'#', b'}', b'*', 0x7f, 0x80, 0xff]);
+        let framed = packet(&data);
+        let (body, checksum) = framed[1..].split_at(framed.len() - 4);
+        assert_eq!(checksum[0], b'#');
+        let received = packet_data(body, &checksum[1..]).unwrap();
+        assert_eq!(unescape_binary(&received), [0, b'$', b'#', b'}', b'*', 0x7f, 0x80, 0xff]);
+    }
+
+    #[test]
+    fn test_escape_binary() {
+        assert_eq!(escape_binary(b"abc"), "abc");
+        assert_eq!(escape_binary(b"$#}*"), "}\u{4}}\u{3}}]}\n");
+        assert_eq!(escape_binary(&[0xcc]), "\u{cc}");
+        assert_eq!(unescape_binary("}\u{4}}\u{3}}]}\n"), b"$#}*");
+        assert_eq!(unescape_binary("a\u{cc}"), [b'a', 0xcc]);
+        // a trailing escape character has nothing to escape
+        assert_eq!(unescape_binary("a}"), b"a");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(parse_hex("7fff"), Some(0x7fff));
+        assert_eq!(parse_hex("FF"), Some(0xff));
+        assert_eq!(parse_hex(""), None);
+        assert_eq!(parse_hex("-1"), None);
+        assert_eq!(parse_hex("1g"), None);
+        assert_eq!(parse_hex("10000000000000000"), None);
+        assert_eq!(to_hex(&[0, 0xab, 0x10]), "00ab10");
+        assert_eq!(from_hex("00ab10"), Some(vec![0, 0xab, 0x10]));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("+1"), None);
+        assert_eq!(parse_hex("+1"), None);
+        assert_eq!(from_hex("\u{e9}1"), None);
+    }
+
+    #[test]
+    fn test_tag_words() {
+        assert_eq!(full_tag_word(0), 0xffff);
+        assert_eq!(full_tag_word(0xff), 0);
+        assert_eq!(full_tag_word(0x01), 0xfffc);
+        for abridged in [0, 0x01, 0x80, 0xa5, 0xff] {
+            assert_eq!(abridged_tag_word(full_tag_word(abridged)), abridged);
+        }
+    }
+
+    #[test]
+    fn test_register_bytes() {
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        for num in 0..NUM_REGISTERS {
+            // the x87 control registers are 16 bits in 32-bit slots
+            let value: Vec<u8> = match num {
+                FCTRL | 33 | 39 => vec![1, 2, 0, 0],
+                _ => (1..=register_size(num) as u8).collect(),
+            };
+            set_register_bytes(num, &value, &mut regs, &mut fpregs);
+            // the tag word only keeps whether each register is empty
+            if num != 34 {
+                assert_eq!(register_bytes(num, &regs, &fpregs), value, "register {}", num);
+            }
+        }
+    }
+}
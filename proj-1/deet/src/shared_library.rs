@@ -0,0 +1,207 @@
+//! Keeps track of the shared libraries loaded into the inferior.
+//!
+//! The dynamic linker publishes the list of loaded objects in its `r_debug` structure, which the
+//! executable's `DT_DEBUG` dynamic entry points to once the linker has run. Each object is a
+//! `link_map` node giving its path and the bias it was loaded at.
+
+use crate::dwarf_data::DwarfData;
+use crate::target::Target;
+use object::{Object, ObjectSection};
+use std::cell::OnceCell;
+use std::convert::TryInto;
+use std::fs;
+
+/// Tag of the dynamic entry holding the address of `r_debug`
+const DT_DEBUG: u64 = 21;
+const DT_NULL: u64 = 0;
+
+/// Offsets into `struct r_debug` and `struct link_map` from <link.h>
+const R_MAP: usize = 8;
+const L_ADDR: usize = 0;
+const L_NAME: usize = 8;
+const L_NEXT: usize = 24;
+
+pub struct SharedLibrary {
+    pub path: String,
+    /// Difference between the library's run-time and link-time addresses (`l_addr`)
+    pub bias: usize,
+    /// Run-time address range of the library's .text section
+    pub from: usize,
+    pub to: usize,
+    has_debug_info: bool,
+    debug_data: OnceCell<Option<DwarfData>>,
+}
+
+impl SharedLibrary {
+    fn load(path: &str, bias: usize) -> Option<SharedLibrary> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&*mmap).ok()?;
+        let text = object.section_by_name(".text")?;
+        let from = text.address() as usize + bias;
+        Some(SharedLibrary {
+            path: path.to_string(),
+            bias,
+            from,
+            to: from + text.size() as usize,
+            has_debug_info: object.section_by_name(".debug_info").is_some(),
+            debug_data: OnceCell::new(),
+        })
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.from && addr < self.to
+    }
+
+    pub fn has_debug_info(&self) -> bool {
+        self.has_debug_info
+    }
+
+    /// Returns true once the library's debugging info has been loaded.
+    pub fn symbols_loaded(&self) -> bool {
+        self.debug_data.get().is_some()
+    }
+
+    /// Returns the library's debugging info, reading it the first time it is needed.
+    pub fn debug_data(&self) -> Option<&DwarfData> {
+        if !self.has_debug_info {
+            return None;
+        }
+        self.debug_data
+            .get_or_init(|| {
+                let mut debug_data = DwarfData::from_file(&self.path).ok()?;
+                debug_data.set_load_bias(self.bias);
+                Some(debug_data)
+            })
+            .as_ref()
+    }
+}
+
+/// The shared libraries of one program, as last read from the dynamic linker.
+pub struct SharedLibraries {
+    /// Link-time address of the program's entry point
+    entry: usize,
+    /// Link-time address of the program's .dynamic section, if it is dynamically linked
+    dynamic: Option<usize>,
+    libraries: Vec<SharedLibrary>,
+}
+
+impl SharedLibraries {
+    pub fn new(executable: &str) -> SharedLibraries {
+        let mut shared_libraries = SharedLibraries {
+            entry: 0,
+            dynamic: None,
+            libraries: Vec::new(),
+        };
+        if let Ok(file) = fs::File::open(executable) {
+            if let Ok(mmap) = unsafe { memmap::Mmap::map(&file) } {
+                if let Ok(object) = object::File::parse(&*mmap) {
+                    shared_libraries.entry = object.entry() as usize;
+                    shared_libraries.dynamic = object.section_by_name(".dynamic").map(|section| section.address() as usize);
+                }
+            }
+        }
+        shared_libraries
+    }
+
+    /// Returns how far the kernel moved the program from its link-time addresses, which is only
+    /// nonzero for position-independent executables. The kernel passes the run-time entry point
+    /// in the auxiliary vector.
+    pub fn executable_bias(&self, target: &dyn Target) -> Option<usize> {
+        let auxv = target.auxv()?;
+        auxv.chunks_exact(16).find_map(|entry| {
+            let key = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(entry[8..].try_into().unwrap());
+            if key == libc::AT_ENTRY {
+                Some((value as usize).wrapping_sub(self.entry))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Reads the dynamic linker's list of loaded objects again, loading the libraries that were
+    /// mapped since the last update and forgetting those that were unmapped. `executable_bias`
+    /// is where the program itself was loaded.
+    pub fn update(&mut self, target: &dyn Target, executable_bias: usize) {
+        let loaded = match self.dynamic {
+            Some(dynamic) => read_link_map(target, dynamic + executable_bias),
+            None => Vec::new(),
+        };
+        let mut libraries = Vec::new();
+        for (path, bias) in loaded {
+            match self.libraries.iter().position(|lib| lib.path == path && lib.bias == bias) {
+                Some(index) => libraries.push(self.libraries.swap_remove(index)),
+                // the vDSO has a name but no file
+                None => libraries.extend(SharedLibrary::load(&path, bias)),
+            }
+        }
+        self.libraries = libraries;
+    }
+
+    pub fn libraries(&self) -> &[SharedLibrary] {
+        &self.libraries
+    }
+
+    /// Returns the library whose code contains `addr`.
+    pub fn find(&self, addr: usize) -> Option<&SharedLibrary> {
+        self.libraries.iter().find(|lib| lib.contains(addr))
+    }
+}
+
+/// Walks the `link_map` list of the dynamic linker, given the run-time address of the program's
+/// .dynamic section. Returns the path and bias of each shared object. The list is empty until the
+/// dynamic linker has filled in `DT_DEBUG`.
+fn read_link_map(target: &dyn Target, dynamic: usize) -> Vec<(String, usize)> {
+    let read_word = |addr: usize| -> Option<usize> {
+        let bytes = target.read_bytes(addr, 8).ok()?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let mut r_debug = None;
+    for entry in (dynamic..).step_by(16) {
+        match read_word(entry).map(|tag| tag as u64) {
+            Some(DT_DEBUG) => {
+                r_debug = read_word(entry + 8);
+                break;
+            }
+            Some(DT_NULL) | None => break,
+            _ => {}
+        }
+    }
+
+    let mut objects = Vec::new();
+    let mut link_map = match r_debug.filter(|addr| *addr != 0).and_then(|addr| read_word(addr + R_MAP)) {
+        Some(link_map) => link_map,
+        None => return objects,
+    };
+    while link_map != 0 {
+        let name = read_word(link_map + L_NAME).and_then(|addr| read_string(target, addr));
+        let bias = read_word(link_map + L_ADDR);
+        // the first entry is the program itself, which has an empty name
+        if let (Some(name), Some(bias)) = (name, bias) {
+            if !name.is_empty() {
+                objects.push((name, bias));
+            }
+        }
+        link_map = match read_word(link_map + L_NEXT) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    objects
+}
+
+/// Reads a NUL-terminated string from the target's memory.
+fn read_string(target: &dyn Target, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    loop {
+        let word = target.read_bytes(addr + bytes.len(), 8).ok()?;
+        match word.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&word[..end]);
+                return Some(String::from_utf8_lossy(&bytes).into_owned());
+            }
+            None => bytes.extend_from_slice(&word),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use nix::sys::signal::Signal;
+use std::ffi::CStr;
+use std::str::FromStr;
+
+/// What to do when the inferior receives a signal: stop and hand control to the user, print a
+/// notice, and deliver the signal to the program when it resumes.
+#[derive(Clone, Copy)]
+pub struct SignalPolicy {
+    pub stop: bool,
+    pub print: bool,
+    pub pass: bool,
+}
+
+impl SignalPolicy {
+    /// Applies one of the `handle` keywords. As in gdb, stopping implies printing and not
+    /// printing implies not stopping. Returns false if the keyword is not one of them.
+    pub fn apply(&mut self, keyword: &str) -> bool {
+        match keyword {
+            "stop" => {
+                self.stop = true;
+                self.print = true;
+            }
+            "nostop" => self.stop = false,
+            "print" => self.print = true,
+            "noprint" => {
+                self.print = false;
+                self.stop = false;
+            }
+            "pass" | "noignore" => self.pass = true,
+            "nopass" | "ignore" => self.pass = false,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The policy for every signal, indexed by signal number. The defaults are gdb's: signals that
+/// programs use in their normal course of business go straight through to them, SIGTRAP and
+/// SIGINT belong to the debugger, and everything else stops the program.
+#[derive(Clone, Copy)]
+pub struct SignalTable([SignalPolicy; 32]);
+
+impl Default for SignalTable {
+    fn default() -> SignalTable {
+        let mut table = SignalTable([SignalPolicy { stop: true, print: true, pass: true }; 32]);
+        for signal in [
+            Signal::SIGALRM,
+            Signal::SIGURG,
+            Signal::SIGCHLD,
+            Signal::SIGWINCH,
+            Signal::SIGIO,
+            Signal::SIGVTALRM,
+            Signal::SIGPROF,
+        ] {
+            *table.get_mut(signal) = SignalPolicy { stop: false, print: false, pass: true };
+        }
+        table.get_mut(Signal::SIGTRAP).pass = false;
+        table.get_mut(Signal::SIGINT).pass = false;
+        table
+    }
+}
+
+impl SignalTable {
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.0[signal as usize]
+    }
+
+    pub fn get_mut(&mut self, signal: Signal) -> &mut SignalPolicy {
+        &mut self.0[signal as usize]
+    }
+}
+
+/// Parses a signal given by name, such as SIGUSR1, or by number. The SIG prefix is required,
+/// since `stop` would otherwise be SIGSTOP.
+pub fn parse_signal(text: &str) -> Option<Signal> {
+    match text.parse::<i32>() {
+        Ok(num) => Signal::try_from(num).ok(),
+        Err(_) => Signal::from_str(&text.to_uppercase()).ok(),
+    }
+}
+
+/// Returns the C library's description of a signal, e.g. "Segmentation fault" for SIGSEGV.
+pub fn description(signal: Signal) -> String {
+    let text = unsafe { libc::strsignal(signal as libc::c_int) };
+    if text.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned()
+}
+
+/// True for the signals the processor raises on a bad instruction or memory access, which carry
+/// the faulting address in their siginfo.
+pub fn is_fault(signal: Signal) -> bool {
+    matches!(signal, Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGILL | Signal::SIGFPE)
+}
+
+/// Linux signals and the numbers GDB gives them, which its remote protocol uses. The first few
+/// agree, but most of the rest don't. SIGSTKFLT has no number of its own and is sent as GDB's
+/// "unknown signal".
+const GDB_SIGNALS: [(Signal, u8); 31] = [
+    (Signal::SIGHUP, 1),
+    (Signal::SIGINT, 2),
+    (Signal::SIGQUIT, 3),
+    (Signal::SIGILL, 4),
+    (Signal::SIGTRAP, 5),
+    (Signal::SIGABRT, 6),
+    (Signal::SIGBUS, 10),
+    (Signal::SIGFPE, 8),
+    (Signal::SIGKILL, 9),
+    (Signal::SIGUSR1, 30),
+    (Signal::SIGSEGV, 11),
+    (Signal::SIGUSR2, 31),
+    (Signal::SIGPIPE, 13),
+    (Signal::SIGALRM, 14),
+    (Signal::SIGTERM, 15),
+    (Signal::SIGSTKFLT, 143),
+    (Signal::SIGCHLD, 20),
+    (Signal::SIGCONT, 19),
+    (Signal::SIGSTOP, 17),
+    (Signal::SIGTSTP, 18),
+    (Signal::SIGTTIN, 21),
+    (Signal::SIGTTOU, 22),
+    (Signal::SIGURG, 16),
+    (Signal::SIGXCPU, 24),
+    (Signal::SIGXFSZ, 25),
+    (Signal::SIGVTALRM, 26),
+    (Signal::SIGPROF, 27),
+    (Signal::SIGWINCH, 28),
+    (Signal::SIGIO, 23),
+    (Signal::SIGPWR, 32),
+    (Signal::SIGSYS, 12),
+];
+
+/// Returns GDB's number for a signal.
+pub fn to_gdb(signal: Signal) -> u8 {
+    GDB_SIGNALS.iter().find(|(linux, _)| *linux == signal).map_or(143, |(_, gdb)| *gdb)
+}
+
+/// Returns the signal GDB means by a number, if Linux has it.
+pub fn from_gdb(num: u8) -> Option<Signal> {
+    GDB_SIGNALS.iter().find(|(_, gdb)| *gdb == num).map(|(linux, _)| *linux)
+}
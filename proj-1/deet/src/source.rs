@@ -0,0 +1,88 @@
+//! Finding and reading the source files that debugging info refers to. The paths recorded at
+//! compile time don't always exist here, e.g. for a program built inside a container, so files
+//! are also looked for in a list of directories set with `directory`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub struct SourceFiles {
+    /// Directories searched before the recorded path, most recently added first
+    directories: Vec<PathBuf>,
+    /// Lines of each file read so far, keyed by the path in the debugging info. None if the file
+    /// couldn't be found.
+    cache: HashMap<String, Option<Vec<String>>>,
+}
+
+impl SourceFiles {
+    pub fn new() -> SourceFiles {
+        SourceFiles::default()
+    }
+
+    /// Puts directories at the front of the search path, keeping them in the order given. A
+    /// directory already in the path moves to the front.
+    pub fn add_directories(&mut self, dirs: &[PathBuf]) {
+        for dir in dirs.iter().rev() {
+            self.directories.retain(|existing| existing != dir);
+            self.directories.insert(0, dir.clone());
+        }
+        // files that were missing may be in the new directories
+        self.cache.retain(|_, lines| lines.is_some());
+    }
+
+    /// Forgets the directories added with `add_directories`.
+    pub fn reset_directories(&mut self) {
+        self.directories.clear();
+        self.cache.retain(|_, lines| lines.is_some());
+    }
+
+    /// Describes the search path the way GDB does. $cdir is the compilation directory, which the
+    /// recorded paths already include, and $cwd is the current directory.
+    pub fn search_path(&self) -> String {
+        let mut entries: Vec<String> = self.directories.iter().map(|dir| dir.display().to_string()).collect();
+        entries.push("$cdir".to_string());
+        entries.push("$cwd".to_string());
+        entries.join(":")
+    }
+
+    /// Finds a source file recorded as `path`. Each search directory is tried with the whole
+    /// path and then with shorter and shorter trailing parts of it, down to the file name, so a
+    /// file built as /build/src/main.c is found as src/main.c under the directory holding the
+    /// same tree. The recorded path itself comes after the search directories, and the
+    /// current directory last.
+    pub fn find(&self, path: &str) -> Option<PathBuf> {
+        let recorded = Path::new(path);
+        let mut relative: Vec<&Path> = Vec::new();
+        let mut components = recorded.components();
+        while !components.as_path().as_os_str().is_empty() {
+            if components.as_path().is_relative() {
+                relative.push(components.as_path());
+            }
+            components.next();
+        }
+        let in_directory = |dir: &Path| {
+            relative.iter().map(|suffix| dir.join(suffix)).find(|candidate| candidate.is_file())
+        };
+        self.directories
+            .iter()
+            .find_map(|dir| in_directory(dir))
+            .or_else(|| Some(recorded.to_path_buf()).filter(|recorded| recorded.is_file()))
+            .or_else(|| in_directory(Path::new(".")))
+    }
+
+    /// Returns the lines of the source file recorded as `path`, reading it if this is the first
+    /// time it is needed.
+    pub fn lines(&mut self, path: &str) -> Option<&Vec<String>> {
+        if !self.cache.contains_key(path) {
+            let text = self.find(path).and_then(|found| std::fs::read(found).ok());
+            let lines = text.map(|text| String::from_utf8_lossy(&text).lines().map(|line| line.to_string()).collect());
+            self.cache.insert(path.to_string(), lines);
+        }
+        self.cache.get(path).unwrap().as_ref()
+    }
+
+    /// Returns the text of line `number` of a source file, counting from 1.
+    pub fn line(&mut self, path: &str, number: usize) -> Option<&str> {
+        self.lines(path)?.get(number.checked_sub(1)?).map(|line| line.as_str())
+    }
+}
@@ -0,0 +1,197 @@
+//! What deet looks at in a stopped program: its memory, the registers of the current thread and
+//! the files it has mapped. A live `Inferior` provides these through ptrace and /proc, a `Remote`
+//! one through a gdbserver, and a `CoreFile` from what the kernel saved when the program died, so
+//! that backtraces, variables and memory can be inspected in all of them without caring which it
+//! is.
+
+use nix::unistd::Pid;
+
+/// A file mapped into the address space of the program.
+#[derive(Clone, PartialEq)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// Offset in the file of the first mapped byte
+    pub offset: usize,
+    pub executable: bool,
+    pub path: String,
+}
+
+pub trait Target {
+    fn pid(&self) -> Pid;
+
+    fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error>;
+
+    /// Returns the general-purpose registers of the current thread.
+    fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error>;
+
+    /// Returns the x87, SSE and MXCSR state of the current thread.
+    fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error>;
+
+    /// Returns the auxiliary vector the kernel passed to the program, as raw (key, value) pairs.
+    fn auxv(&self) -> Option<Vec<u8>>;
+
+    /// Returns the files mapped into the program, in address order.
+    fn mappings(&self) -> Vec<Mapping>;
+
+    fn rip(&self) -> Option<usize> {
+        self.get_registers().ok().map(|regs| regs.rip as usize)
+    }
+}
+
+/// A line of /proc/<pid>/maps: some memory of the program, whether a file is mapped there or not.
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub offset: usize,
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+    /// The mapped file, a name like `[heap]`, or empty for anonymous memory
+    pub path: String,
+    /// How many bytes of it are private to the process rather than read from the file, e.g. the
+    /// pages of a library the loader has relocated. Only /proc/<pid>/smaps says.
+    pub anonymous: usize,
+}
+
+/// Reads every region out of the contents of /proc/<pid>/maps or /proc/<pid>/smaps. A path is
+/// the rest of its line, since it may contain spaces; a file deleted since it was mapped keeps
+/// the ` (deleted)` the kernel adds, so that a new file at its path isn't mistaken for it.
+pub fn parse_regions(maps: &str) -> Vec<Region> {
+    let mut regions: Vec<Region> = Vec::new();
+    for line in maps.lines() {
+        // smaps follows each region with lines such as "Anonymous:             8 kB"
+        if let Some(size) = line.strip_prefix("Anonymous:") {
+            if let (Some(region), Some(kb)) = (regions.last_mut(), size.trim().strip_suffix(" kB")) {
+                region.anonymous = kb.parse::<usize>().unwrap_or(0) * 1024;
+            }
+            continue;
+        }
+        // e.g. "7f1e2a828000-7f1e2a9bd000 r-xp 00028000 08:01 1835    /usr/lib/x86_64-linux-gnu/libc.so.6",
+        // with single spaces between the fields and padding before the path
+        let fields: Vec<&str> = line.splitn(6, ' ').collect();
+        if fields.len() < 5 {
+            continue;
+        }
+        let range = fields[0].split_once('-').and_then(|(start, end)| {
+            Some((usize::from_str_radix(start, 16).ok()?, usize::from_str_radix(end, 16).ok()?))
+        });
+        let (start, end, offset) = match (range, usize::from_str_radix(fields[2], 16)) {
+            (Some((start, end)), Ok(offset)) => (start, end, offset),
+            _ => continue,
+        };
+        regions.push(Region {
+            start,
+            end,
+            offset,
+            readable: fields[1].contains('r'),
+            writable: fields[1].contains('w'),
+            executable: fields[1].contains('x'),
+            path: fields.get(5).map_or("", |path| path.trim_start()).to_string(),
+            anonymous: 0,
+        });
+    }
+    regions
+}
+
+/// Reads the file mappings out of the contents of /proc/<pid>/maps.
+pub fn parse_maps(maps: &str) -> Vec<Mapping> {
+    parse_regions(maps)
+        .into_iter()
+        .filter(|region| region.path.starts_with('/'))
+        .map(|region| Mapping {
+            start: region.start,
+            end: region.end,
+            offset: region.offset,
+            executable: region.executable,
+            path: region.path,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAPS: &str = "\
+00400000-00401000 r--p 00000000 08:01 1835                               /tmp/t/prog
+00401000-00402000 r-xp 00001000 08:01 1835                               /tmp/t/prog
+01234000-01255000 rw-p 00000000 00:00 0                                  [heap]
+7f1e2a800000-7f1e2a828000 r--p 00000000 08:01 2001                       /opt/my libs/libfoo.so
+7f1e2a828000-7f1e2a9bd000 r-xp 00028000 08:01 2001                       /opt/my libs/libfoo.so
+7f1e2aa00000-7f1e2aa01000 r-xp 00000000 08:01 2002                       /tmp/libbar.so (deleted)
+7f1e2ab00000-7f1e2ab21000 rw-p 00000000 00:00 0 
+7ffd1c000000-7ffd1c021000 rw-p 00000000 00:00 0                          [stack]
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]
+";
+
+    #[test]
+    fn test_parse_maps() {
+        let mappings = parse_maps(MAPS);
+        let summary: Vec<(usize, usize, usize, bool, &str)> = mappings
+            .iter()
+            .map(|mapping| (mapping.start, mapping.end, mapping.offset, mapping.executable, mapping.path.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0x400000, 0x401000, 0, false, "/tmp/t/prog"),
+                (0x401000, 0x402000, 0x1000, true, "/tmp/t/prog"),
+                (0x7f1e2a800000, 0x7f1e2a828000, 0, false, "/opt/my libs/libfoo.so"),
+                (0x7f1e2a828000, 0x7f1e2a9bd000, 0x28000, true, "/opt/my libs/libfoo.so"),
+                (0x7f1e2aa00000, 0x7f1e2aa01000, 0, true, "/tmp/libbar.so (deleted)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_regions() {
+        let regions = parse_regions(MAPS);
+        let summary: Vec<(usize, &str, bool, bool, bool)> = regions
+            .iter()
+            .map(|region| (region.start, region.path.as_str(), region.readable, region.writable, region.executable))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0x400000, "/tmp/t/prog", true, false, false),
+                (0x401000, "/tmp/t/prog", true, false, true),
+                (0x1234000, "[heap]", true, true, false),
+                (0x7f1e2a800000, "/opt/my libs/libfoo.so", true, false, false),
+                (0x7f1e2a828000, "/opt/my libs/libfoo.so", true, false, true),
+                (0x7f1e2aa00000, "/tmp/libbar.so (deleted)", true, false, true),
+                (0x7f1e2ab00000, "", true, true, false),
+                (0x7ffd1c000000, "[stack]", true, true, false),
+                (0xffffffffff600000, "[vsyscall]", false, false, true),
+            ]
+        );
+        assert_eq!(regions[6].end, 0x7f1e2ab21000);
+    }
+
+    #[test]
+    fn test_parse_smaps() {
+        let smaps = "\
+00400000-00401000 r--p 00000000 08:01 1835                               /tmp/t/prog
+Size:                  4 kB
+Rss:                   4 kB
+Anonymous:             0 kB
+VmFlags: rd mr mw me dw
+00403000-00405000 r--p 00002000 08:01 1835                               /tmp/t/prog
+Size:                  8 kB
+Anonymous:             4 kB
+THPeligible:           0
+VmFlags: rd mr mw me dw ac
+";
+        let regions = parse_regions(smaps);
+        let summary: Vec<(usize, usize, usize)> = regions.iter().map(|region| (region.start, region.end, region.anonymous)).collect();
+        assert_eq!(summary, [(0x400000, 0x401000, 0), (0x403000, 0x405000, 0x1000)]);
+        assert_eq!(parse_maps(smaps).len(), 2);
+    }
+
+    #[test]
+    fn test_parse_maps_malformed() {
+        assert!(parse_maps("").is_empty());
+        assert!(parse_maps("garbage\n00400000 r-xp 0 08:01 1 /a\nzz-00401000 r-xp 0 08:01 1 /b").is_empty());
+        assert!(parse_maps("00400000-00401000 r-xp zz 08:01 1 /c").is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+//! Stack unwinding driven by the call frame information in `.eh_frame` and `.debug_frame`.
+//!
+//! Every object file mapped into the program (the program itself, libc, the dynamic loader...)
+//! carries its own CFI, so the unwinder loads it for each executable mapping of the target.
+//! Functions without CFI are unwound by assuming they keep a frame pointer.
+
+use crate::target::Target;
+use gimli::{CfaRule, Register, RegisterRule, UnwindSection};
+use object::{Object, ObjectSection, ObjectSegment, SymbolKind};
+use std::convert::TryInto;
+use std::fs;
+
+/// DWARF register numbers on x86-64. The return address column comes right after r15.
+const RBX: usize = 3;
+const RBP: usize = 6;
+const RSP: usize = 7;
+const RA: usize = 16;
+const NUM_REGS: usize = 17;
+
+/// Registers a callee must preserve. CFI only mentions them once they have been saved, so until
+/// then they still hold the caller's values.
+const CALLEE_SAVED: [usize; 6] = [RBX, RBP, 12, 13, 14, 15];
+
+/// Gives up on corrupted stacks that would otherwise seem to go on forever.
+const MAX_FRAMES: usize = 1024;
+
+/// One frame of the call stack, along with the registers that could be recovered for it.
+#[derive(Clone)]
+pub struct Frame {
+    /// 0 for the innermost frame, counting up towards `main`
+    pub level: usize,
+    /// Where execution is in this frame: the stop address for the innermost frame, and the
+    /// return address for its callers
+    pub pc: usize,
+    /// The canonical frame address, i.e. rsp just before the call that created this frame.
+    /// Frame bases in the DWARF info are relative to it.
+    pub cfa: usize,
+    regs: [Option<u64>; NUM_REGS],
+}
+
+impl Frame {
+    /// Returns an address inside the instruction being executed in this frame. For callers this
+    /// is the call instruction rather than the return address, which may already belong to the
+    /// next line or even the next function.
+    pub fn code_addr(&self) -> usize {
+        if self.level == 0 {
+            self.pc
+        } else {
+            self.pc - 1
+        }
+    }
+
+    /// Returns the value DWARF register `num` had in this frame, if it could be recovered.
+    pub fn register(&self, num: usize) -> Option<u64> {
+        self.regs.get(num).copied().flatten()
+    }
+}
+
+type RegisterRules<'a> = [RegisterRule<gimli::EndianSlice<'a, gimli::RunTimeEndian>>; NUM_REGS];
+
+/// The CFI and function symbols of one object file mapped into the inferior.
+struct Module {
+    start: usize,
+    end: usize,
+    /// Difference between run-time addresses and the addresses in the file
+    bias: usize,
+    endian: gimli::RunTimeEndian,
+    eh_frame: Vec<u8>,
+    eh_frame_addr: u64,
+    debug_frame: Vec<u8>,
+    text_addr: u64,
+    /// Function symbols as (start, end, name), at run-time addresses
+    symbols: Vec<(usize, usize, String)>,
+}
+
+impl Module {
+    fn load(path: &str, start: usize, end: usize, base: usize) -> Option<Module> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&*mmap).ok()?;
+        let endian = if object.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        // the first mapping of a file is its lowest segment, rounded down to a page
+        let first_segment = object.segments().map(|segment| segment.address()).min().unwrap_or(0);
+        let bias = base - (first_segment as usize & !0xfff);
+        let section_addr = |name| object.section_by_name(name).map_or(0, |section| section.address());
+        let section_data = |name| object.section_data_by_name(name).map_or(Vec::new(), |data| data.to_vec());
+
+        let mut symbols: Vec<(usize, usize, String)> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|(_, symbol)| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+            .filter_map(|(_, symbol)| {
+                let start = symbol.address() as usize + bias;
+                Some((start, start + symbol.size() as usize, symbol.name()?.to_string()))
+            })
+            .collect();
+        symbols.extend(plt_symbols(&object, bias));
+        symbols.sort();
+        symbols.dedup_by_key(|(start, _, _)| *start);
+
+        Some(Module {
+            start,
+            end,
+            bias,
+            endian,
+            eh_frame: section_data(".eh_frame"),
+            eh_frame_addr: section_addr(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            text_addr: section_addr(".text"),
+            symbols,
+        })
+    }
+
+    /// Returns the unwinding rules in effect at run-time address `addr`, as (CFA rule, rule for
+    /// each register), or None if the module has no CFI covering it.
+    fn unwind_rules(&self, addr: usize) -> Option<((usize, i64), RegisterRules<'_>)> {
+        let addr = (addr - self.bias) as u64;
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_addr)
+            .set_text(self.text_addr);
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let row = match eh_frame.unwind_info_for_address(&bases, &mut ctx, addr, gimli::EhFrame::cie_from_offset) {
+            Ok(row) => row,
+            Err(_) => {
+                let debug_frame = gimli::DebugFrame::new(&self.debug_frame, self.endian);
+                debug_frame
+                    .unwind_info_for_address(&bases, &mut ctx, addr, gimli::DebugFrame::cie_from_offset)
+                    .ok()?
+            }
+        };
+        // CFA expressions only show up in hand-written assembly such as signal trampolines
+        let cfa = match *row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => (register.0 as usize, offset),
+            CfaRule::Expression(_) => return None,
+        };
+        let rules = (0..NUM_REGS)
+            .map(|reg| row.register(Register(reg as u16)))
+            .collect::<Vec<_>>()
+            .try_into()
+            .ok()?;
+        Some((cfa, rules))
+    }
+
+    fn symbol_for(&self, addr: usize) -> Option<(&str, usize, usize)> {
+        let index = match self.symbols.binary_search_by_key(&addr, |(start, _, _)| *start) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let (start, end, name) = &self.symbols[index];
+        // some assembly symbols have no size
+        if addr < *end || start == end {
+            Some((name, *start, *end))
+        } else {
+            None
+        }
+    }
+}
+
+/// Names the PLT entries of an object file after the functions they jump to, e.g. `puts@plt`.
+/// Entry `i` of the PLT serves the `i`th relocation in `.rela.plt`. With IBT the entries that
+/// calls go to are in `.plt.sec`; otherwise they are in `.plt`, after its lazy-binding header.
+fn plt_symbols(object: &object::File, bias: usize) -> Vec<(usize, usize, String)> {
+    const PLT_ENTRY_SIZE: usize = 16;
+    const RELA_SIZE: usize = 24;
+    const SYMBOL_SIZE: usize = 24;
+    const R_X86_64_JUMP_SLOT: u64 = 7;
+    let first_entry = match (object.section_by_name(".plt.sec"), object.section_by_name(".plt")) {
+        (Some(plt_sec), _) => plt_sec.address() as usize,
+        (None, Some(plt)) => plt.address() as usize + PLT_ENTRY_SIZE,
+        _ => return Vec::new(),
+    };
+    let (relocations, dynsym, dynstr) = match (
+        object.section_data_by_name(".rela.plt"),
+        object.section_data_by_name(".dynsym"),
+        object.section_data_by_name(".dynstr"),
+    ) {
+        (Some(relocations), Some(dynsym), Some(dynstr)) => (relocations, dynsym, dynstr),
+        _ => return Vec::new(),
+    };
+    let word = |bytes: &[u8], offset: usize| -> Option<u64> {
+        Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+    };
+    let mut symbols = Vec::new();
+    for (i, relocation) in relocations.chunks_exact(RELA_SIZE).enumerate() {
+        let info = match word(relocation, 8) {
+            Some(info) if info & 0xffff_ffff == R_X86_64_JUMP_SLOT => info,
+            _ => continue,
+        };
+        let symbol = (info >> 32) as usize * SYMBOL_SIZE;
+        let name_offset = match dynsym.get(symbol..symbol + 4) {
+            Some(bytes) => u32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+            None => continue,
+        };
+        let name = match dynstr.get(name_offset..).and_then(|names| names.split(|byte| *byte == 0).next()) {
+            Some(name) if !name.is_empty() => String::from_utf8_lossy(name),
+            _ => continue,
+        };
+        let start = first_entry + i * PLT_ENTRY_SIZE + bias;
+        symbols.push((start, start + PLT_ENTRY_SIZE, format!("{}@plt", name)));
+    }
+    symbols
+}
+
+/// Unwinds the stacks of one process, using the object files it had mapped when the unwinder
+/// was created.
+pub struct Unwinder {
+    objects: Vec<(String, usize, usize, usize)>,
+    modules: Vec<Module>,
+}
+
+impl Unwinder {
+    pub fn new(target: &dyn Target) -> Unwinder {
+        let objects = mapped_objects(target);
+        let modules = objects
+            .iter()
+            .filter_map(|(path, start, end, base)| Module::load(path, *start, *end, *base))
+            .collect();
+        Unwinder { objects, modules }
+    }
+
+    /// Returns false if the process has mapped or unmapped object files since the unwinder was
+    /// created, e.g. by exec'ing or calling `dlopen`.
+    pub fn is_current(&self, target: &dyn Target) -> bool {
+        mapped_objects(target) == self.objects
+    }
+
+    fn module_for(&self, addr: usize) -> Option<&Module> {
+        self.modules.iter().find(|module| addr >= module.start && addr < module.end)
+    }
+
+    /// Returns the name and start address of the function symbol containing `addr`, for code
+    /// without debugging info.
+    pub fn symbol_for(&self, addr: usize) -> Option<(&str, usize)> {
+        self.symbol_bounds(addr).map(|(name, start, _)| (name, start))
+    }
+
+    /// Like `symbol_for`, also returning the end of the symbol. Symbols without a size end where
+    /// they start.
+    pub fn symbol_bounds(&self, addr: usize) -> Option<(&str, usize, usize)> {
+        self.module_for(addr)?.symbol_for(addr)
+    }
+
+    /// Walks the stack of the current thread of the target, innermost frame first. The walk
+    /// ends at `_start`, at a null return address, or when the CFI says there is no caller.
+    pub fn frames(&self, target: &dyn Target) -> Vec<Frame> {
+        let regs = match target.get_registers() {
+            Ok(regs) => regs,
+            Err(_) => return Vec::new(),
+        };
+        let mut frame = Frame {
+            level: 0,
+            pc: regs.rip as usize,
+            cfa: 0,
+            regs: [
+                Some(regs.rax), Some(regs.rdx), Some(regs.rcx), Some(regs.rbx),
+                Some(regs.rsi), Some(regs.rdi), Some(regs.rbp), Some(regs.rsp),
+                Some(regs.r8), Some(regs.r9), Some(regs.r10), Some(regs.r11),
+                Some(regs.r12), Some(regs.r13), Some(regs.r14), Some(regs.r15),
+                Some(regs.rip),
+            ],
+        };
+        let mut frames: Vec<Frame> = Vec::new();
+        loop {
+            let caller = self.unwind_frame(&mut frame, target);
+            // callers always live further up the stack, anything else means it is corrupted
+            if frame.cfa != 0 && frames.last().map_or(false, |callee| frame.cfa <= callee.cfa) {
+                break;
+            }
+            let at_start = self.symbol_for(frame.code_addr()).map(|(name, _)| name) == Some("_start");
+            frames.push(frame);
+            frame = match caller {
+                Some(caller) if !at_start && caller.pc != 0 && frames.len() < MAX_FRAMES => caller,
+                _ => break,
+            };
+        }
+        frames
+    }
+
+    /// Computes the CFA of `frame` and recovers the registers of its caller. Returns None if
+    /// there is no caller, or its return address can't be found.
+    fn unwind_frame(&self, frame: &mut Frame, target: &dyn Target) -> Option<Frame> {
+        let read = |addr: u64| -> Option<u64> {
+            let bytes = target.read_bytes(addr as usize, 8).ok()?;
+            Some(u64::from_le_bytes(bytes.try_into().ok()?))
+        };
+        let mut caller = [None; NUM_REGS];
+        match self.module_for(frame.code_addr()).and_then(|module| module.unwind_rules(frame.code_addr())) {
+            Some(((cfa_reg, cfa_offset), rules)) => {
+                let cfa = (frame.regs.get(cfa_reg).copied().flatten()? as i64 + cfa_offset) as u64;
+                frame.cfa = cfa as usize;
+                for (reg, rule) in rules.iter().enumerate() {
+                    caller[reg] = match *rule {
+                        RegisterRule::Undefined if CALLEE_SAVED.contains(&reg) => frame.regs[reg],
+                        RegisterRule::SameValue => frame.regs[reg],
+                        RegisterRule::Offset(offset) => read((cfa as i64 + offset) as u64),
+                        RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as u64),
+                        RegisterRule::Register(other) => frame.regs.get(other.0 as usize).copied().flatten(),
+                        _ => None,
+                    };
+                }
+                caller[RSP] = Some(cfa);
+            }
+            None => {
+                // no CFI: assume the function pushed rbp and points it at the saved value
+                let rbp = frame.regs[RBP].filter(|rbp| *rbp != 0)?;
+                frame.cfa = rbp as usize + 16;
+                caller.copy_from_slice(&frame.regs);
+                caller[RBP] = read(rbp);
+                caller[RA] = read(rbp + 8);
+                caller[RSP] = Some(rbp + 16);
+            }
+        }
+        // an undefined return address marks the outermost frame
+        let pc = caller[RA]? as usize;
+        Some(Frame {
+            level: frame.level + 1,
+            pc,
+            cfa: 0,
+            regs: caller,
+        })
+    }
+}
+
+/// Lists the object files with executable mappings in the target as (path, start of the code,
+/// end of the code, address the file is loaded at).
+fn mapped_objects(target: &dyn Target) -> Vec<(String, usize, usize, usize)> {
+    let mut objects: Vec<(String, usize, usize, usize)> = Vec::new();
+    let mut base = 0;
+    for mapping in target.mappings() {
+        if mapping.offset == 0 {
+            base = mapping.start;
+        }
+        if mapping.executable {
+            objects.push((mapping.path, mapping.start, mapping.end, base));
+        }
+    }
+    objects
+}
@@ -1,4 +1,6 @@
+use std::fs::File;
 use std::mem::size_of;
+use std::os::unix::io::FromRawFd;
 pub fn parse_address(addr: &str) -> Option<usize> {
     let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
         &addr[2..]
@@ -8,6 +10,99 @@ pub fn parse_address(addr: &str) -> Option<usize> {
     usize::from_str_radix(addr_without_0x, 16).ok()
 }
 
+/// Parses an address or other integer written in decimal, or in hex with a 0x prefix.
+pub fn parse_integer(text: &str) -> Option<usize> {
+    if text.to_lowercase().starts_with("0x") {
+        parse_address(text)
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parses a numeric literal: a decimal or 0x-prefixed hex integer, a float, or a character
+/// literal such as 'a'.
+pub fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if digits.to_lowercase().starts_with("0x") {
+        parse_address(digits)? as f64
+    } else if digits.len() == 3 && digits.starts_with('\'') && digits.ends_with('\'') {
+        digits.as_bytes()[1] as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
 pub fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
+
+/// Formats a byte the way C would write it as a character literal.
+pub fn format_char(byte: u8) -> String {
+    match byte {
+        b'\0' => "'\\0'".to_string(),
+        b'\n' => "'\\n'".to_string(),
+        b'\t' => "'\\t'".to_string(),
+        b'\r' => "'\\r'".to_string(),
+        b'\'' => "'\\''".to_string(),
+        b'\\' => "'\\\\'".to_string(),
+        0x20..=0x7e => format!("'{}'", byte as char),
+        _ => format!("'\\{:03o}'", byte),
+    }
+}
+
+/// Formats bytes the way C would write them as a string literal.
+pub fn format_string(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' => text.push_str("\\\""),
+            b'\'' => text.push('\''),
+            _ => text.push_str(format_char(byte).trim_matches('\'')),
+        }
+    }
+    text.push('"');
+    text
+}
+
+/// Converts an x87 80-bit extended precision float (as used for `long double`) to an f64.
+pub fn x87_extended_to_f64(bytes: &[u8]) -> f64 {
+    let mantissa = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let sign_exp = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let sign = if sign_exp & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (sign_exp & 0x7fff) as i32;
+    if exponent == 0 && mantissa == 0 {
+        return sign * 0.0;
+    }
+    if exponent == 0x7fff {
+        return if mantissa << 1 == 0 { sign * f64::INFINITY } else { f64::NAN };
+    }
+    // the mantissa has an explicit integer bit, so it is scaled by 2^-63
+    sign * (mantissa as f64) * 2f64.powi(exponent - 16383 - 63)
+}
+
+/// Moves standard output to a new descriptor, which programs deet runs don't inherit, and points
+/// standard output at standard error. Returns the new descriptor, for a front end's messages
+/// alone; everything else deet or the program prints goes to standard error.
+pub fn take_stdout() -> File {
+    let fd = nix::fcntl::fcntl(libc::STDOUT_FILENO, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(0))
+        .expect("Error duplicating standard output");
+    nix::unistd::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO).expect("Error redirecting standard output");
+    unsafe { File::from_raw_fd(fd) }
+}
+
+/// Moves standard input to a new descriptor, which programs deet runs don't inherit, and gives
+/// them /dev/null in its place, so that they can't read a front end's messages.
+pub fn take_stdin() -> File {
+    let fd = nix::fcntl::fcntl(libc::STDIN_FILENO, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(0))
+        .expect("Error duplicating standard input");
+    let null = File::open("/dev/null").expect("Error opening /dev/null");
+    nix::unistd::dup2(std::os::unix::io::AsRawFd::as_raw_fd(&null), libc::STDIN_FILENO)
+        .expect("Error redirecting standard input");
+    unsafe { File::from_raw_fd(fd) }
+}